@@ -1,11 +1,21 @@
+use crate::esruntime_utils::priority_tasks::PriorityTaskManager;
+use crate::esruntime_utils::promises;
 use crate::esruntimebuilder::EsRuntimeBuilder;
-use crate::esvalue::EsValueFacade;
+use crate::esvalue::{EsValueConvertible, EsValueFacade, ES_UNDEFINED};
 use crate::features;
+use crate::features::console::ConsoleCapture;
+use crate::features::fetch::cache::FetchCache;
 use crate::features::fetch::request::FetchRequest;
 use crate::features::fetch::response::FetchResponse;
-use crate::quickjs_utils::{functions, objects};
-use crate::quickjscontext::QuickJsContext;
-use crate::quickjsruntime::{NativeModuleLoaderAdapter, QuickJsRuntime, ScriptModuleLoaderAdapter};
+use crate::features::storage::{MemoryStorageBackend, StorageBackend};
+use crate::quickjs_utils;
+use crate::quickjs_utils::profiler::ProfilingHandle;
+use crate::quickjs_utils::{compile, errors, functions, modules, objects, reflect};
+use crate::quickjscontext::{EvalOptions, QuickJsContext};
+use crate::quickjsruntime::{
+    CallFrame, CompiledModuleLoaderAdapter, NativeModuleLoader, NativeModuleLoaderAdapter,
+    QuickJsRuntime, SandboxConfig, ScriptModuleLoaderAdapter, QJS_RT,
+};
 use crate::valueref::JSValueRef;
 use hirofa_utils::eventloop::EventLoop;
 use hirofa_utils::js_utils::adapters::JsRealmAdapter;
@@ -14,12 +24,16 @@ use hirofa_utils::js_utils::JsError;
 use hirofa_utils::js_utils::Script;
 use hirofa_utils::task_manager::TaskManager;
 use libquickjs_sys as q;
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 use tokio::task::JoinError;
+use tokio_util::sync::CancellationToken;
 
 lazy_static! {
     /// a static Multithreaded task manager used to run rust ops async and multithreaded ( in at least 2 threads)
@@ -29,6 +43,147 @@ lazy_static! {
 pub type FetchResponseProvider =
     dyn Fn(&FetchRequest) -> Box<dyn FetchResponse + Send> + Send + Sync + 'static;
 
+pub type TaskCompleteCallback = dyn Fn(TaskInfo) + Send + Sync + 'static;
+/// a task which was added with [EsRuntime::add_rt_task_to_tick_queue]
+type TickTask = Box<dyn FnOnce(&QuickJsRuntime) + Send + 'static>;
+
+pub type ExceptionHandler = dyn Fn(&JsError) + Send + Sync + 'static;
+
+/// a filter which is applied to every error returned by the eval and call methods of an EsRuntime,
+/// see [EsRuntimeBuilder::exception_filter]
+pub type ExceptionFilter =
+    dyn Fn(JsError) -> Result<JsError, EsValueFacade> + Send + Sync + 'static;
+
+/// the type of a task which was run in the event queue of an EsRuntime
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskType {
+    Eval,
+    CallFunction,
+    Gc,
+    UserTask,
+}
+
+/// timing info of a task which was run in the event queue of an EsRuntime, see [EsRuntimeBuilder::on_task_complete]
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub queue_time: Instant,
+    pub start_time: Instant,
+    pub end_time: Instant,
+    pub task_type: TaskType,
+}
+
+/// the result of [EsRuntime::eval_with_diagnostics_sync]
+pub struct EvalResult {
+    pub value: Result<EsValueFacade, JsError>,
+    /// the time it took to evaluate the script and run the pending jobs in microseconds
+    pub duration_us: u64,
+    /// the difference in allocated bytes of the runtime before and after evaluating, this may be negative when
+    /// the garbage collector ran during evaluation
+    pub memory_delta_bytes: i64,
+    /// the number of pending jobs (e.g. promise reactions) which were run after evaluating the script
+    pub jobs_executed: u32,
+}
+
+type NamespaceMethod<T> =
+    Arc<dyn Fn(&T, Vec<EsValueFacade>) -> Result<EsValueFacade, JsError> + Send + Sync + 'static>;
+
+/// used to add methods to an object installed with [EsRuntime::install_object]
+pub struct NamespaceBuilder<T> {
+    methods: Arc<Mutex<Vec<(String, NamespaceMethod<T>)>>>,
+}
+
+impl<T> NamespaceBuilder<T> {
+    /// add a method, the method is called with the installed object and the arguments of the call
+    pub fn method<F>(self, name: &str, method: F) -> Self
+    where
+        F: Fn(&T, Vec<EsValueFacade>) -> Result<EsValueFacade, JsError> + Send + Sync + 'static,
+    {
+        {
+            let methods = &mut *self.methods.lock().unwrap();
+            methods.push((name.to_string(), Arc::new(method)));
+        }
+        self
+    }
+}
+
+/// counts the tasks which were added to and completed in the event queue
+#[derive(Default)]
+struct TaskCounters {
+    added: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+impl TaskCounters {
+    fn todo_count(&self) -> usize {
+        // completed is read first so it can never be larger than added
+        let completed = self.completed.load(Ordering::SeqCst);
+        let added = self.added.load(Ordering::SeqCst);
+        added.saturating_sub(completed)
+    }
+}
+
+/// passes the errors returned by the eval and call methods of an EsRuntime to its exception handler and applies its
+/// exception filter (if any), this is cloned into the tasks of methods which need to filter in the worker thread
+#[derive(Clone, Default)]
+struct ResultFilter {
+    handler: Option<Arc<ExceptionHandler>>,
+    filter: Option<Arc<ExceptionFilter>>,
+}
+
+impl ResultFilter {
+    fn apply(&self, res: Result<EsValueFacade, JsError>) -> Result<EsValueFacade, JsError> {
+        let err = match res {
+            Err(err) => err,
+            ok => return ok,
+        };
+        if let Some(handler) = &self.handler {
+            handler(&err);
+        }
+        match &self.filter {
+            Some(filter) => match filter(err) {
+                Ok(err) => Err(err),
+                Err(value) => Ok(value),
+            },
+            None => Err(err),
+        }
+    }
+
+    /// apply the filter to the result of a JsRuntimeFacade method, this has to be called in the worker thread because
+    /// a substituted value is converted to a JsValueFacade
+    fn apply_to_facade(
+        &self,
+        q_ctx: &QuickJsContext,
+        res: Result<Box<dyn JsValueFacade>, JsError>,
+    ) -> Result<Box<dyn JsValueFacade>, JsError> {
+        match res {
+            Err(err) => {
+                let mut value = self.apply(Err(err))?;
+                let value_ref = value.as_js_value(q_ctx)?;
+                Ok(q_ctx.to_js_value_facade(&value_ref))
+            }
+            res => res,
+        }
+    }
+}
+
+/// used to report a TaskInfo from the worker thread when a task completes
+struct TaskTelemetry {
+    callback: Arc<TaskCompleteCallback>,
+    task_type: TaskType,
+    queue_time: Instant,
+}
+
+impl TaskTelemetry {
+    fn complete(self, start_time: Instant) {
+        (self.callback)(TaskInfo {
+            queue_time: self.queue_time,
+            start_time,
+            end_time: Instant::now(),
+            task_type: self.task_type,
+        });
+    }
+}
+
 impl Drop for EsRuntime {
     fn drop(&mut self) {
         log::trace!("> EsRuntime::drop");
@@ -37,6 +192,62 @@ impl Drop for EsRuntime {
     }
 }
 
+/// a CancelToken can be used to interrupt a script which was started with [EsRuntime::eval_async_with_cancel]
+#[derive(Clone)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    fn new() -> Self {
+        Self {
+            flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    /// cancel the script, if it is still running it will be interrupted
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+    /// check if cancel was called
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// a weak reference to an EsRuntime, this can be moved into closures which may outlive the EsRuntime
+/// all methods silently do nothing when the EsRuntime was dropped
+#[derive(Clone)]
+pub struct WeakEsRuntime {
+    inner: Weak<EsRuntime>,
+}
+
+impl WeakEsRuntime {
+    /// get a strong reference to the EsRuntime if it was not dropped
+    pub fn upgrade(&self) -> Option<Arc<EsRuntime>> {
+        self.inner.upgrade()
+    }
+
+    /// add a task to the event loop of the EsRuntime (if it was not dropped), see [EsRuntime::add_rt_task_to_event_loop_void]
+    pub fn add_rt_task_to_event_loop_void<C>(&self, consumer: C)
+    where
+        C: FnOnce(&QuickJsRuntime) + Send + 'static,
+    {
+        if let Some(es_rt) = self.upgrade() {
+            es_rt.add_rt_task_to_event_loop_void(consumer)
+        }
+    }
+
+    /// run a task in the event loop of the EsRuntime and return its result, returns None if the EsRuntime was dropped, see [EsRuntime::exe_rt_task_in_event_loop]
+    pub fn exe_rt_task_in_event_loop<C, R>(&self, consumer: C) -> Option<R>
+    where
+        C: FnOnce(&QuickJsRuntime) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.upgrade()
+            .map(|es_rt| es_rt.exe_rt_task_in_event_loop(consumer))
+    }
+}
+
 /// EsRuntime is the main public struct representing a JavaScript runtime.
 /// You can construct a new EsRuntime by using the [EsRuntimeBuilder] struct
 /// # Example
@@ -47,18 +258,51 @@ impl Drop for EsRuntime {
 pub struct EsRuntime {
     event_loop: EventLoop,
     fetch_response_provider: Option<Box<FetchResponseProvider>>,
+    fetch_cache: Option<Box<dyn FetchCache + Send + Sync>>,
+    local_storage: Box<dyn StorageBackend + Send + Sync>,
+    session_storage: MemoryStorageBackend,
+    task_complete_callback: Option<Arc<TaskCompleteCallback>>,
+    task_counters: Arc<TaskCounters>,
+    tick_queue: Mutex<VecDeque<TickTask>>,
+    helper_task_threads: Option<usize>,
+    helper_tasks: Mutex<Option<Arc<TaskManager>>>,
+    priority_tasks: Mutex<Option<Arc<PriorityTaskManager>>>,
     js_contexts: HashSet<String>,
+    result_filter: ResultFilter,
 }
 
 impl EsRuntime {
     pub(crate) fn new(mut builder: EsRuntimeBuilder) -> Arc<Self> {
         let fetch_response_provider =
             std::mem::replace(&mut builder.opt_fetch_response_provider, None);
+        let fetch_cache = builder.opt_fetch_cache.take();
+        let local_storage = builder
+            .opt_local_storage_backend
+            .take()
+            .unwrap_or_else(|| Box::new(MemoryStorageBackend::new()));
+
+        let task_complete_callback = builder
+            .opt_task_complete_callback
+            .take()
+            .map(Arc::<TaskCompleteCallback>::from);
 
         let ret = Arc::new(Self {
             event_loop: EventLoop::new(),
             fetch_response_provider,
+            fetch_cache,
+            local_storage,
+            session_storage: MemoryStorageBackend::new(),
+            task_complete_callback,
+            task_counters: Arc::new(TaskCounters::default()),
+            tick_queue: Mutex::new(VecDeque::new()),
+            helper_task_threads: builder.opt_helper_task_threads,
+            helper_tasks: Mutex::new(None),
+            priority_tasks: Mutex::new(None),
             js_contexts: Default::default(),
+            result_filter: ResultFilter {
+                handler: builder.exception_handler.take().map(Arc::from),
+                filter: builder.exception_filter.take().map(Arc::from),
+            },
         });
 
         ret.exe_task(|| {
@@ -82,6 +326,34 @@ impl EsRuntime {
             panic!("could not init features: {}", res.err().unwrap());
         }
 
+        if builder.std_polyfills {
+            let res = ret.exe_rt_task_in_event_loop(features::polyfills::init);
+            if res.is_err() {
+                panic!("could not install polyfills: {}", res.err().unwrap());
+            }
+        }
+
+        if !builder.disabled_builtins.is_empty() {
+            let disabled_builtins = std::mem::take(&mut builder.disabled_builtins);
+            let res = ret.exe_rt_task_in_event_loop(move |q_js_rt| {
+                q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                    let global_ref = quickjs_utils::get_global_q(q_ctx);
+                    for name in &disabled_builtins {
+                        if !objects::delete_property_q(q_ctx, &global_ref, name.as_str())? {
+                            return Err(JsError::new_string(format!(
+                                "built-in {} can not be removed",
+                                name
+                            )));
+                        }
+                    }
+                    Ok(())
+                })
+            });
+            if res.is_err() {
+                panic!("could not disable builtins: {}", res.err().unwrap());
+            }
+        }
+
         if let Some(interval) = builder.opt_gc_interval {
             let e_ref: Weak<EsRuntime> = Arc::downgrade(&ret);
             std::thread::spawn(move || loop {
@@ -104,12 +376,19 @@ impl EsRuntime {
                         native_module_loader,
                     ));
                 }
+                for compiled_module_loader in builder.compiled_module_loaders {
+                    q_js_rt.add_compiled_module_loader(CompiledModuleLoaderAdapter::new(
+                        compiled_module_loader,
+                    ));
+                }
                 for script_module_loader in builder.script_module_loaders {
                     q_js_rt.add_script_module_loader(ScriptModuleLoaderAdapter::new(
                         script_module_loader,
                     ));
                 }
                 q_js_rt.script_pre_processors = builder.script_pre_processors;
+                q_js_rt.module_cache = builder.module_cache;
+                q_js_rt.structured_console_handler = builder.structured_console_handler;
 
                 if let Some(limit) = builder.opt_memory_limit_bytes {
                     unsafe {
@@ -125,10 +404,19 @@ impl EsRuntime {
                     unsafe {
                         q::JS_SetMaxStackSize(q_js_rt.runtime, stack_size as _);
                     }
+                    q_js_rt.max_stack_size.set(stack_size);
                 }
                 if let Some(interrupt_handler) = builder.interrupt_handler {
                     q_js_rt.set_interrupt_handler(interrupt_handler);
                 }
+                if let Some(max_jobs) = builder.opt_max_jobs_per_tick {
+                    q_js_rt.max_jobs_per_tick = max_jobs;
+                }
+                q_js_rt.default_strict_mode = builder.default_strict_mode;
+                q_js_rt.max_eval_depth = builder.opt_max_eval_depth;
+                q_js_rt.module_base_path = builder.opt_module_base_path;
+                q_js_rt.module_specifier_normalizer = builder.module_specifier_normalizer;
+                q_js_rt.typescript_transpiler = builder.typescript_transpiler;
             })
         });
 
@@ -160,46 +448,117 @@ impl EsRuntime {
     where
         C: FnOnce() + Send + 'static,
     {
+        self.add_typed_task_to_event_loop_void(TaskType::UserTask, task)
+    }
+
+    pub fn exe_task_in_event_loop<C, R: Send + 'static>(&self, task: C) -> R
+    where
+        C: FnOnce() -> R + Send + 'static,
+    {
+        self.exe_typed_task_in_event_loop(TaskType::UserTask, task)
+    }
+
+    pub fn add_task_to_event_loop<C, R: Send + 'static>(&self, task: C) -> impl Future<Output = R>
+    where
+        C: FnOnce() -> R + Send + 'static,
+    {
+        self.add_typed_task_to_event_loop(TaskType::UserTask, task)
+    }
+
+    fn task_telemetry(&self, task_type: TaskType) -> Option<TaskTelemetry> {
+        self.task_complete_callback
+            .as_ref()
+            .map(|callback| TaskTelemetry {
+                callback: callback.clone(),
+                task_type,
+                queue_time: Instant::now(),
+            })
+    }
+
+    fn add_typed_task_to_event_loop_void<C>(&self, task_type: TaskType, task: C)
+    where
+        C: FnOnce() + Send + 'static,
+    {
+        let telemetry = self.task_telemetry(task_type);
+        let counters = self.task_counters.clone();
+        counters.added.fetch_add(1, Ordering::SeqCst);
         self.event_loop.add_void(move || {
+            let start_time = Instant::now();
             task();
-            EventLoop::add_local_void(|| {
-                QuickJsRuntime::do_with(|q_js_rt| {
-                    q_js_rt.run_pending_jobs_if_any();
-                })
-            })
+            EventLoop::add_local_void(run_pending_jobs_tick);
+            if let Some(telemetry) = telemetry {
+                telemetry.complete(start_time);
+            }
+            counters.completed.fetch_add(1, Ordering::SeqCst);
         });
     }
 
-    pub fn exe_task_in_event_loop<C, R: Send + 'static>(&self, task: C) -> R
+    fn exe_typed_task_in_event_loop<C, R: Send + 'static>(&self, task_type: TaskType, task: C) -> R
     where
         C: FnOnce() -> R + Send + 'static,
     {
+        let telemetry = self.task_telemetry(task_type);
+        let counters = self.task_counters.clone();
+        counters.added.fetch_add(1, Ordering::SeqCst);
         self.event_loop.exe(move || {
+            let start_time = Instant::now();
             let res = task();
-            EventLoop::add_local_void(|| {
-                QuickJsRuntime::do_with(|q_js_rt| {
-                    q_js_rt.run_pending_jobs_if_any();
-                })
-            });
+            EventLoop::add_local_void(run_pending_jobs_tick);
+            if let Some(telemetry) = telemetry {
+                telemetry.complete(start_time);
+            }
+            counters.completed.fetch_add(1, Ordering::SeqCst);
             res
         })
     }
 
-    pub fn add_task_to_event_loop<C, R: Send + 'static>(&self, task: C) -> impl Future<Output = R>
+    fn add_typed_task_to_event_loop<C, R: Send + 'static>(
+        &self,
+        task_type: TaskType,
+        task: C,
+    ) -> impl Future<Output = R>
     where
         C: FnOnce() -> R + Send + 'static,
     {
+        let telemetry = self.task_telemetry(task_type);
+        let counters = self.task_counters.clone();
+        counters.added.fetch_add(1, Ordering::SeqCst);
         self.event_loop.add(move || {
+            let start_time = Instant::now();
             let res = task();
-            EventLoop::add_local_void(|| {
-                QuickJsRuntime::do_with(|q_js_rt| {
-                    q_js_rt.run_pending_jobs_if_any();
-                });
-            });
+            EventLoop::add_local_void(run_pending_jobs_tick);
+            if let Some(telemetry) = telemetry {
+                telemetry.complete(start_time);
+            }
+            counters.completed.fetch_add(1, Ordering::SeqCst);
             res
         })
     }
 
+    fn exe_typed_rt_task_in_event_loop<C, R>(&self, task_type: TaskType, consumer: C) -> R
+    where
+        C: FnOnce(&QuickJsRuntime) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.exe_typed_task_in_event_loop(task_type, || QuickJsRuntime::do_with(consumer))
+    }
+
+    fn add_typed_rt_task_to_event_loop<C, R: Send + 'static>(
+        &self,
+        task_type: TaskType,
+        consumer: C,
+    ) -> impl Future<Output = R>
+    where
+        C: FnOnce(&QuickJsRuntime) -> R + Send + 'static,
+    {
+        self.add_typed_task_to_event_loop(task_type, || QuickJsRuntime::do_with(consumer))
+    }
+
+    /// pass an error result through the exception filter of this runtime (if any)
+    fn filter_result(&self, res: Result<EsValueFacade, JsError>) -> Result<EsValueFacade, JsError> {
+        self.result_filter.apply(res)
+    }
+
     /// this is how you add a closure to the worker thread which has an instance of the QuickJsRuntime
     /// this will run asynchronously
     /// # example
@@ -228,7 +587,7 @@ impl EsRuntime {
         self.add_task_to_event_loop_void(|| QuickJsRuntime::do_with(consumer))
     }
 
-    /// used to add tasks from the worker threads which require run_pending_jobs_if_any to run after it
+    /// used to add tasks from the worker threads which require pending jobs to run after it
     pub(crate) fn add_local_task_to_event_loop<C>(consumer: C)
     where
         C: FnOnce(&QuickJsRuntime) + 'static,
@@ -237,19 +596,102 @@ impl EsRuntime {
             QuickJsRuntime::do_with(|q_js_rt| {
                 consumer(q_js_rt);
             });
-            EventLoop::add_local_void(|| {
-                QuickJsRuntime::do_with(|q_js_rt| {
-                    q_js_rt.run_pending_jobs_if_any();
-                })
-            })
+            EventLoop::add_local_void(run_pending_jobs_tick)
         });
     }
 
+    /// get a weak reference to this EsRuntime
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let weak_rt = rt.weak_ref();
+    /// assert_eq!(weak_rt.exe_rt_task_in_event_loop(|_q_js_rt| 1), Some(1));
+    /// ```
+    pub fn weak_ref(self: &Arc<Self>) -> WeakEsRuntime {
+        WeakEsRuntime {
+            inner: Arc::downgrade(self),
+        }
+    }
+
     #[allow(clippy::borrowed_box)]
     pub fn get_fetch_response_provider(&self) -> Option<&Box<FetchResponseProvider>> {
         self.fetch_response_provider.as_ref()
     }
 
+    #[allow(clippy::borrowed_box)]
+    pub fn get_fetch_cache(&self) -> Option<&Box<dyn FetchCache + Send + Sync>> {
+        self.fetch_cache.as_ref()
+    }
+
+    /// get a snapshot of the call stack of the runtime, see [QuickJsRuntime::get_call_stack]
+    /// please note that this runs as a task in the event loop so when called from another thread it will only run after
+    /// the currently running script has finished
+    pub fn get_call_stack_sync(&self) -> Vec<CallFrame> {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.get_call_stack())
+    }
+
+    /// add a breakpoint, the debug hook of the runtime is called when the line is about to be executed
+    /// see [QuickJsRuntime::set_debug_hook]
+    pub fn add_breakpoint(&self, filename: &str, line: u32) {
+        let filename = filename.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt.add_breakpoint(filename.as_str(), line)
+        })
+    }
+
+    pub fn remove_breakpoint(&self, filename: &str, line: u32) {
+        let filename = filename.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt.remove_breakpoint(filename.as_str(), line)
+        })
+    }
+
+    /// start profiling the scripts of this runtime, profiling stops when the returned handle is stopped or dropped
+    /// see [profiler](crate::quickjs_utils::profiler) for which functions are profiled
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let handle = rt.start_profiling();
+    /// rt.eval_sync(Script::new("profile.es", "function a() {return 1;}; a(); a();")).ok().expect("script failed");
+    /// let report = handle.stop();
+    /// assert_eq!(report.get_function("a").unwrap().call_count, 2);
+    /// ```
+    pub fn start_profiling(self: &Arc<Self>) -> ProfilingHandle {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.start_profiling())
+            .ok()
+            .expect("could not start profiling");
+        ProfilingHandle::new(self.clone())
+    }
+
+    /// capture all console output of this runtime until the returned ConsoleCapture is dropped, this is useful for
+    /// asserting what was logged when testing scripts
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::features::console::ConsoleLevel;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let capture = rt.capture_console_output();
+    /// rt.eval_sync(Script::new("log.es", "console.log('hello');")).ok().expect("script failed");
+    /// assert_eq!(capture.take_messages(), vec![(ConsoleLevel::Log, "hello".to_string())]);
+    /// ```
+    pub fn capture_console_output(self: &Arc<Self>) -> ConsoleCapture {
+        ConsoleCapture::new(self)
+    }
+
+    /// get the backend of localStorage
+    pub fn get_local_storage(&self) -> &(dyn StorageBackend + Send + Sync) {
+        self.local_storage.as_ref()
+    }
+
+    /// get the backend of sessionStorage
+    pub fn get_session_storage(&self) -> &(dyn StorageBackend + Send + Sync) {
+        &self.session_storage
+    }
+
     pub fn builder() -> EsRuntimeBuilder {
         EsRuntimeBuilder::new()
     }
@@ -265,15 +707,100 @@ impl EsRuntime {
 
     /// Evaluate a script asynchronously
     pub async fn eval(&self, script: Script) -> Result<EsValueFacade, JsError> {
-        self.add_rt_task_to_event_loop(|q_js_rt| {
+        let res = self
+            .add_typed_rt_task_to_event_loop(TaskType::Eval, |q_js_rt| {
+                let q_ctx = q_js_rt.get_main_context();
+                let res = q_ctx.eval(script);
+                match res {
+                    Ok(js) => EsValueFacade::from_jsval(q_ctx, &js),
+                    Err(e) => Err(e),
+                }
+            })
+            .await;
+        self.filter_result(res)
+    }
+
+    /// Evaluate a script asynchronously and get a CancelToken which may be used to interrupt the script
+    /// when the script is cancelled the future will complete with an Err with name CancelledError
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use futures::executor::block_on;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let (fut, cancel_token) = rt.eval_async_with_cancel(Script::new("my_file.es", "while(true){}"));
+    /// cancel_token.cancel();
+    /// assert!(block_on(fut).is_err());
+    /// ```
+    pub fn eval_async_with_cancel(
+        &self,
+        script: Script,
+    ) -> (
+        impl Future<Output = Result<EsValueFacade, JsError>>,
+        CancelToken,
+    ) {
+        let cancel_token = CancelToken::new();
+        let flag = cancel_token.flag.clone();
+        let result_filter = self.result_filter.clone();
+        let fut = self.add_typed_rt_task_to_event_loop(TaskType::Eval, move |q_js_rt| {
+            let cancelled_err = || {
+                JsError::new(
+                    "CancelledError".to_string(),
+                    "script was cancelled".to_string(),
+                    "".to_string(),
+                )
+            };
+            if flag.load(Ordering::SeqCst) {
+                return result_filter.apply(Err(cancelled_err()));
+            }
+            q_js_rt.add_cancel_flag(flag.clone());
             let q_ctx = q_js_rt.get_main_context();
             let res = q_ctx.eval(script);
-            match res {
+            q_js_rt.remove_cancel_flag(&flag);
+            result_filter.apply(match res {
                 Ok(js) => EsValueFacade::from_jsval(q_ctx, &js),
+                Err(_) if flag.load(Ordering::SeqCst) => Err(cancelled_err()),
                 Err(e) => Err(e),
+            })
+        });
+        (fut, cancel_token)
+    }
+
+    /// Evaluate a script asynchronously, the script is interrupted when the CancellationToken is cancelled
+    /// when the script is cancelled the future will complete with an Err with name CancelledError
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use futures::executor::block_on;
+    /// use tokio_util::sync::CancellationToken;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let cancel = CancellationToken::new();
+    /// let fut = rt.eval_with_cancel_token_async(Script::new("my_file.es", "while(true){}"), cancel.clone());
+    /// cancel.cancel();
+    /// assert!(block_on(fut).is_err());
+    /// ```
+    pub fn eval_with_cancel_token_async(
+        &self,
+        script: Script,
+        cancel: CancellationToken,
+    ) -> impl Future<Output = Result<EsValueFacade, JsError>> {
+        let (fut, cancel_token) = self.eval_async_with_cancel(script);
+        // the watch token is cancelled when either the script completes or the CancellationToken is cancelled
+        let watch_token = cancel.child_token();
+        // the guard is owned by the returned future so the watcher also completes when that future is dropped before
+        // it completes or without being polled at all
+        let done_guard = watch_token.clone().drop_guard();
+        let _ = self.add_runtime_helper_task_async(async move {
+            watch_token.cancelled().await;
+            if cancel.is_cancelled() {
+                cancel_token.cancel();
             }
-        })
-        .await
+        });
+        async move {
+            let _done_guard = done_guard;
+            fut.await
+        }
     }
 
     /// Evaluate a script and return the result synchronously
@@ -287,104 +814,559 @@ impl EsRuntime {
     /// assert_eq!(res.get_i32(), 27);
     /// ```
     pub fn eval_sync(&self, script: Script) -> Result<EsValueFacade, JsError> {
-        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
             let q_ctx = q_js_rt.get_main_context();
             let res = q_ctx.eval(script);
             match res {
                 Ok(val_ref) => EsValueFacade::from_jsval(q_ctx, &val_ref),
                 Err(e) => Err(e),
             }
-        })
-    }
-
-    /// run the garbage collector asynchronously
-    pub async fn gc(&self) {
-        self.add_rt_task_to_event_loop(|q_js_rt| q_js_rt.gc()).await
-    }
-
-    /// run the garbage collector and wait for it to be done
-    pub fn gc_sync(&self) {
-        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.gc())
+        });
+        self.filter_result(res)
     }
 
-    /// call a function in the engine and await the result
+    /// evaluate a script, run all pending jobs and return the result along with info on how long it took and how much
+    /// memory was allocated, this is useful for profiling user supplied scripts
     /// # example
     /// ```rust
     /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
-    /// use quickjs_runtime::es_args;
-    /// use quickjs_runtime::esvalue::EsValueConvertible;
-    /// use quickjs_runtime::esvalue::EsValueFacade;
     /// use hirofa_utils::js_utils::Script;
     /// let rt = EsRuntimeBuilder::new().build();
-    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b, someStr, someBool){return a*b;}}};");
-    /// rt.eval_sync(script).ok().expect("script failed");
-    /// let res = rt.call_function_sync(vec!["com", "my"], "methodA", vec![7i32.to_es_value_facade(), 5i32.to_es_value_facade(), "abc".to_string().to_es_value_facade(), true.to_es_value_facade()]).ok().expect("func failed");
-    /// assert_eq!(res.get_i32(), 35);
+    /// let res = rt.eval_with_diagnostics_sync(Script::new("diag.es", "[1, 2, 3].length;"));
+    /// println!("eval took {} us and allocated {} bytes", res.duration_us, res.memory_delta_bytes);
+    /// assert_eq!(res.value.ok().unwrap().get_i32(), 3);
     /// ```
-    pub fn call_function_sync(
-        &self,
-        namespace: Vec<&'static str>,
-        func_name: &str,
-        mut arguments: Vec<EsValueFacade>,
-    ) -> Result<EsValueFacade, JsError> {
-        let func_name_string = func_name.to_string();
-
-        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+    pub fn eval_with_diagnostics_sync(&self, script: Script) -> EvalResult {
+        let mut res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            let memory_before = quickjs_utils::memory_usage(q_js_rt).malloc_size;
+            let start = Instant::now();
             let q_ctx = q_js_rt.get_main_context();
-
-            let mut q_args = vec![];
-            for arg in &mut arguments {
-                q_args.push(arg.as_js_value(q_ctx)?);
-            }
-
-            let res = q_ctx.call_function(namespace, func_name_string.as_str(), q_args);
-            match res {
-                Ok(val_ref) => EsValueFacade::from_jsval(q_ctx, &val_ref),
-                Err(e) => Err(e),
+            let value = q_ctx
+                .eval(script)
+                .and_then(|val_ref| EsValueFacade::from_jsval(q_ctx, &val_ref));
+            let jobs_executed = q_js_rt.run_pending_jobs_limit(usize::MAX);
+            let duration_us = start.elapsed().as_micros() as u64;
+            let memory_after = quickjs_utils::memory_usage(q_js_rt).malloc_size;
+            EvalResult {
+                value,
+                duration_us,
+                memory_delta_bytes: memory_after as i64 - memory_before as i64,
+                jobs_executed: jobs_executed as u32,
             }
-        })
+        });
+        res.value = self.filter_result(res.value);
+        res
     }
 
-    /// call a function in the engine asynchronously
-    /// N.B. func_name is not a &str because of https://github.com/rust-lang/rust/issues/56238 (i think)
+    /// evaluate a script with resource limits, the limits are only applied while the script and the pending jobs
+    /// (e.g. promise reactions) it added run, timers set by the script run without limits
+    /// when a timeout or the maximum number of objects is exceeded the script is interrupted and an Err with name
+    /// SandboxError is returned
     /// # example
     /// ```rust
     /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
-    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use quickjs_runtime::quickjsruntime::SandboxConfig;
     /// use hirofa_utils::js_utils::Script;
     /// let rt = EsRuntimeBuilder::new().build();
-    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b){return a*b;}}};");
-    /// rt.eval_sync(script).ok().expect("script failed");
-    /// rt.call_function(vec!["com", "my"], "methodA".to_string(), vec![7.to_es_value_facade(), 5.to_es_value_facade()]);
+    /// let config = SandboxConfig {
+    ///     timeout_ms: Some(10),
+    ///     ..Default::default()
+    /// };
+    /// let res = rt.eval_sandboxed(Script::new("sandbox.es", "while(true){}"), config);
+    /// assert_eq!(res.err().unwrap().get_name(), "SandboxError");
     /// ```
-    pub async fn call_function(
+    pub fn eval_sandboxed(
         &self,
-        namespace: Vec<&'static str>,
-        func_name: String,
+        script: Script,
+        config: SandboxConfig,
+    ) -> Result<EsValueFacade, JsError> {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            q_js_rt.with_sandbox(&config, || {
+                let q_ctx = q_js_rt.get_main_context();
+                let val_ref = q_ctx.eval(script)?;
+                EsValueFacade::from_jsval(q_ctx, &val_ref)
+            })
+        });
+        self.filter_result(res)
+    }
+
+    /// evaluate multiple scripts in a single task in the event loop so no other tasks run in between
+    /// evaluation stops at the first script which fails, the error names the index and path of that script
+    /// when the exception filter of the runtime substitutes a value for the error that value is used as the result of
+    /// the failed script and evaluation continues
+    /// please note that the side effects of the scripts evaluated before the failing script are not rolled back
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let res = rt.eval_batch_sync(vec![
+    ///     Script::new("a.es", "globalThis.a = 1;"),
+    ///     Script::new("b.es", "a + 1;"),
+    /// ]).ok().expect("batch failed");
+    /// assert_eq!(res[1].get_i32(), 2);
+    /// ```
+    pub fn eval_batch_sync(&self, scripts: Vec<Script>) -> Result<Vec<EsValueFacade>, JsError> {
+        let result_filter = self.result_filter.clone();
+        self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let mut results = vec![];
+            for (index, script) in scripts.into_iter().enumerate() {
+                let path = script.get_path().to_string();
+                let res = q_ctx
+                    .eval(script)
+                    .and_then(|val_ref| EsValueFacade::from_jsval(q_ctx, &val_ref));
+                let esvf = result_filter.apply(res).map_err(|err| {
+                    JsError::new(
+                        err.get_name().to_string(),
+                        format!("script {} ({}) failed: {}", index, path, err.get_message()),
+                        err.get_stack().to_string(),
+                    )
+                })?;
+                results.push(esvf);
+            }
+            Ok(results)
+        })
+    }
+
+    /// evaluate a script in strict mode and return the result synchronously
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let res = rt.eval_strict_sync(Script::new("strict.es", "undeclared_var = 1;"));
+    /// assert!(res.is_err());
+    /// ```
+    pub fn eval_strict_sync(&self, script: Script) -> Result<EsValueFacade, JsError> {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let val_ref = q_ctx.eval_strict(script)?;
+            EsValueFacade::from_jsval(q_ctx, &val_ref)
+        });
+        self.filter_result(res)
+    }
+
+    /// evaluate a script or module with specific options and return the result synchronously
+    /// when compile_only is set the script is only compiled and the result is undefined
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::quickjscontext::{EvalOptions, EvalType};
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let options = EvalOptions {
+    ///     strict: true,
+    ///     ..Default::default()
+    /// };
+    /// let res = rt.eval_sync_with_options(Script::new("strict.es", "undeclared_var = 1;"), options);
+    /// assert!(res.is_err());
+    /// ```
+    pub fn eval_sync_with_options(
+        &self,
+        script: Script,
+        options: EvalOptions,
+    ) -> Result<EsValueFacade, JsError> {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let val_ref = q_ctx.eval_with_options(script, &options)?;
+            if options.compile_only {
+                Ok(ES_UNDEFINED.to_es_value_facade())
+            } else {
+                EsValueFacade::from_jsval(q_ctx, &val_ref)
+            }
+        });
+        self.filter_result(res)
+    }
+
+    /// register a source map for a script path, errors thrown from that script will report the original source locations in their stack
+    /// inline source maps (`//# sourceMappingURL=data:application/json;base64,...`) are detected and registered automatically
+    pub fn add_source_map(&self, path: &str, source_map_json: &str) -> Result<(), JsError> {
+        let path = path.to_string();
+        let source_map_json = source_map_json.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt.add_source_map(path.as_str(), source_map_json.as_str())
+        })
+    }
+
+    /// remove the source map of a script path, returns false if no source map was registered for the path
+    pub fn remove_source_map(&self, path: &str) -> bool {
+        let path = path.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| q_js_rt.remove_source_map(path.as_str()))
+    }
+
+    /// run the garbage collector asynchronously
+    pub async fn gc(&self) {
+        self.add_typed_rt_task_to_event_loop(TaskType::Gc, |q_js_rt| q_js_rt.gc())
+            .await
+    }
+
+    /// run the garbage collector and wait for it to be done
+    pub fn gc_sync(&self) {
+        self.exe_typed_rt_task_in_event_loop(TaskType::Gc, |q_js_rt| q_js_rt.gc())
+    }
+
+    /// run the garbage collector repeatedly until at least target_freed_bytes have been freed or no more memory could be freed
+    /// returns the number of bytes which were freed
+    pub fn gc_incremental_sync(&self, target_freed_bytes: usize) -> usize {
+        self.exe_typed_rt_task_in_event_loop(TaskType::Gc, move |q_js_rt| {
+            let mut freed: usize = 0;
+            loop {
+                let before = q_js_rt.memory_usage().memory_used_size;
+                q_js_rt.gc();
+                let after = q_js_rt.memory_usage().memory_used_size;
+                if after >= before {
+                    break;
+                }
+                freed += (before - after) as usize;
+                if freed >= target_freed_bytes {
+                    break;
+                }
+            }
+            log::trace!("gc_incremental_sync freed {} bytes", freed);
+            freed
+        })
+    }
+
+    /// get memory usage statistics of the runtime
+    pub fn get_memory_usage(&self) -> q::JSMemoryUsage {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.memory_usage())
+    }
+
+    /// wait until all tasks which are currently in the event queue have completed
+    /// this adds a sentinel task to the queue and blocks until it is run, tasks which are added later
+    /// (e.g. by timeouts) are not awaited
+    pub fn drain_event_queue_sync(&self) {
+        self.exe_task_in_event_loop(|| {
+            log::trace!("EsRuntime::drain_event_queue_sync sentinel reached");
+        });
+    }
+
+    /// get the number of tasks which were added to the event queue and have not completed yet
+    pub fn get_todo_count(&self) -> usize {
+        self.task_counters.todo_count()
+    }
+
+    /// add a task to the tick queue, tasks in the tick queue are not run by the event loop on its own but only when
+    /// [EsRuntime::run_event_loop_once] is called, this is meant for embedders which drive their own event loop
+    pub fn add_rt_task_to_tick_queue<C>(&self, consumer: C)
+    where
+        C: FnOnce(&QuickJsRuntime) + Send + 'static,
+    {
+        self.tick_queue
+            .lock()
+            .unwrap()
+            .push_back(Box::new(consumer));
+    }
+
+    /// run the tasks which are currently in the tick queue (see [EsRuntime::add_rt_task_to_tick_queue]) in the event
+    /// loop and return the number of tasks which were run
+    /// this does not wait for new tasks, tasks which are added while the current tasks are running (e.g. by one of
+    /// those tasks) are left in the queue for the next call
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.add_rt_task_to_tick_queue(|_q_js_rt| {
+    ///     println!("running in the event loop");
+    /// });
+    /// assert_eq!(rt.run_event_loop_once(), 1);
+    /// assert_eq!(rt.run_event_loop_once(), 0);
+    /// ```
+    pub fn run_event_loop_once(&self) -> usize {
+        let tasks: Vec<TickTask> = {
+            let queue = &mut *self.tick_queue.lock().unwrap();
+            let len = queue.len();
+            queue.drain(..len).collect()
+        };
+        let count = tasks.len();
+        if count > 0 {
+            self.exe_rt_task_in_event_loop(move |q_js_rt| {
+                for task in tasks {
+                    task(q_js_rt);
+                }
+            });
+        }
+        log::trace!("EsRuntime::run_event_loop_once ran {} tasks", count);
+        count
+    }
+
+    /// check if there are pending jobs (e.g. promise reactions) in the runtime
+    /// pending jobs are run after every task in the event loop so this will usually be false
+    pub fn has_pending_jobs(&self) -> bool {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.has_pending_jobs())
+    }
+
+    /// get the approximate number of pending jobs and tasks, this is the number of tasks in the event queue which have
+    /// not completed yet plus 1 if there are pending jobs in the runtime (QuickJS does not expose the length of its
+    /// job queue), the tasks are counted when this is called so tasks which complete while waiting for the
+    /// event loop are still included
+    pub fn pending_job_count(&self) -> usize {
+        let queued = self.get_todo_count();
+        if self.has_pending_jobs() {
+            queued + 1
+        } else {
+            queued
+        }
+    }
+
+    /// wait until the event queue is empty and there are no more pending jobs (e.g. promise reactions), this is polled
+    /// every millisecond until the timeout is reached
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::time::Duration;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync(Script::new("idle.es", "Promise.resolve(1).then((a) => {globalThis.a = a;});")).ok().unwrap();
+    /// rt.wait_for_idle(Duration::from_secs(1)).ok().expect("runtime did not become idle");
+    /// ```
+    pub fn wait_for_idle(&self, timeout: Duration) -> Result<(), JsError> {
+        let start = Instant::now();
+        loop {
+            if self.get_todo_count() == 0 && !self.has_pending_jobs() {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(JsError::new_string(format!(
+                    "runtime was not idle after {}ms",
+                    timeout.as_millis()
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// list the names of the modules provided by the registered native module loaders
+    pub fn list_native_modules(&self) -> Vec<String> {
+        self.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.list_native_modules())
+    }
+
+    /// call a function in the engine and await the result
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::es_args;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use quickjs_runtime::esvalue::EsValueFacade;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b, someStr, someBool){return a*b;}}};");
+    /// rt.eval_sync(script).ok().expect("script failed");
+    /// let res = rt.call_function_sync(vec!["com", "my"], "methodA", vec![7i32.to_es_value_facade(), 5i32.to_es_value_facade(), "abc".to_string().to_es_value_facade(), true.to_es_value_facade()]).ok().expect("func failed");
+    /// assert_eq!(res.get_i32(), 35);
+    /// ```
+    pub fn call_function_sync(
+        &self,
+        namespace: Vec<&'static str>,
+        func_name: &str,
         mut arguments: Vec<EsValueFacade>,
     ) -> Result<EsValueFacade, JsError> {
         let func_name_string = func_name.to_string();
 
-        self.add_rt_task_to_event_loop(move |q_js_rt| {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::CallFunction, move |q_js_rt| {
             let q_ctx = q_js_rt.get_main_context();
+
             let mut q_args = vec![];
             for arg in &mut arguments {
-                match arg.as_js_value(q_ctx) {
-                    Ok(js_arg) => q_args.push(js_arg),
-                    Err(err) => log::error!(
-                        "error occurred in async esruntime::call_function closure: {}",
-                        err
-                    ),
-                }
+                q_args.push(arg.as_js_value(q_ctx)?);
             }
 
             let res = q_ctx.call_function(namespace, func_name_string.as_str(), q_args);
             match res {
-                Ok(js_ref) => EsValueFacade::from_jsval(q_ctx, &js_ref),
+                Ok(val_ref) => EsValueFacade::from_jsval(q_ctx, &val_ref),
                 Err(e) => Err(e),
             }
+        });
+        self.filter_result(res)
+    }
+
+    /// call a function which is exported by a module, if the module was not loaded yet it will be loaded by the module loaders
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_module_sync(Script::new("my_math.mes", "export function add(a, b) {return a + b;}")).ok().expect("module failed");
+    /// let res = rt.call_module_function_sync("my_math.mes", "add", vec![1.to_es_value_facade(), 2.to_es_value_facade()]).ok().expect("call failed");
+    /// assert_eq!(res.get_i32(), 3);
+    /// ```
+    pub fn call_module_function_sync(
+        &self,
+        module_name: &str,
+        func_name: &str,
+        mut arguments: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        let module_name = module_name.to_string();
+        let func_name = func_name.to_string();
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::CallFunction, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let ns_ref = q_ctx.get_module_namespace(module_name.as_str())?;
+            let func_ref = objects::get_property_q(q_ctx, &ns_ref, func_name.as_str())?;
+            if !functions::is_function_q(q_ctx, &func_ref) {
+                return Err(JsError::new_string(format!(
+                    "module {} does not export a function named {}",
+                    module_name, func_name
+                )));
+            }
+
+            let mut q_args = vec![];
+            for arg in &mut arguments {
+                q_args.push(arg.as_js_value(q_ctx)?);
+            }
+
+            let res = functions::call_function_q(q_ctx, &func_ref, q_args, None)?;
+            EsValueFacade::from_jsval(q_ctx, &res)
+        });
+        self.filter_result(res)
+    }
+
+    /// copy all properties of the sources to the object at target_namespace, like `Object.assign(target, ...sources);`
+    /// the target object is created if it does not exist, later sources overwrite properties set by earlier sources
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::{EsValueConvertible, EsValueFacade};
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::collections::HashMap;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let mut props: HashMap<String, EsValueFacade> = HashMap::new();
+    /// props.insert("a".to_string(), 1.to_es_value_facade());
+    /// rt.merge_objects(vec!["com", "my", "config"], vec![props.to_es_value_facade()]).ok().expect("merge failed");
+    /// let res = rt.eval_sync(Script::new("merge.es", "com.my.config.a;")).ok().expect("script failed");
+    /// assert_eq!(res.get_i32(), 1);
+    /// ```
+    pub fn merge_objects(
+        &self,
+        target_namespace: Vec<&'static str>,
+        mut sources: Vec<EsValueFacade>,
+    ) -> Result<(), JsError> {
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let target_ref = objects::get_namespace_q(q_ctx, target_namespace, true)?;
+            let mut source_refs = vec![];
+            for source in &mut sources {
+                source_refs.push(source.as_js_value(q_ctx)?);
+            }
+            let source_refs: Vec<&JSValueRef> = source_refs.iter().collect();
+            objects::merge_q(q_ctx, &target_ref, source_refs.as_slice())
         })
-        .await
+    }
+
+    /// get the prototype object of a class, if the class does not have a prototype object yet (e.g. a Proxy class) one is created
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync(Script::new("proto.es", "this.com = {my: {Greeter: class Greeter {}}};")).ok().expect("script failed");
+    /// let proto = rt.get_class_prototype_sync(vec!["com", "my"], "Greeter").ok().expect("could not get prototype");
+    /// assert!(proto.is_object());
+    /// ```
+    pub fn get_class_prototype_sync(
+        &self,
+        namespace: Vec<&'static str>,
+        class_name: &str,
+    ) -> Result<EsValueFacade, JsError> {
+        let class_name = class_name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let proto_ref = get_class_prototype(q_ctx, namespace, class_name.as_str())?;
+            EsValueFacade::from_jsval(q_ctx, &proto_ref)
+        })
+    }
+
+    /// add a method to the prototype of a class, the method will be available on all instances of the class
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync(Script::new("proto.es", "this.com = {my: {Greeter: class Greeter {}}};")).ok().expect("script failed");
+    /// rt.set_class_method_sync(vec!["com", "my"], "Greeter", "greet", |_q_ctx, _this, _args| {
+    ///     Ok("hello".to_string().to_es_value_facade())
+    /// }).ok().expect("could not set method");
+    /// let res = rt.eval_sync(Script::new("greet.es", "new com.my.Greeter().greet();")).ok().expect("script failed");
+    /// assert_eq!(res.get_str(), "hello");
+    /// ```
+    pub fn set_class_method_sync<F>(
+        &self,
+        namespace: Vec<&'static str>,
+        class_name: &str,
+        method_name: &str,
+        method: F,
+    ) -> Result<(), JsError>
+    where
+        F: Fn(&QuickJsContext, &JSValueRef, Vec<EsValueFacade>) -> Result<EsValueFacade, JsError>
+            + Send
+            + 'static,
+    {
+        let class_name = class_name.to_string();
+        let method_name = method_name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let proto_ref = get_class_prototype(q_ctx, namespace, class_name.as_str())?;
+            let func_ref = functions::new_function_q(
+                q_ctx,
+                method_name.as_str(),
+                move |q_ctx, this_ref, args| {
+                    let mut args_facades = vec![];
+                    for arg_ref in args {
+                        args_facades.push(EsValueFacade::from_jsval(q_ctx, &arg_ref)?);
+                    }
+                    let mut res = method(q_ctx, this_ref, args_facades)?;
+                    res.as_js_value(q_ctx)
+                },
+                1,
+            )?;
+            objects::set_property2_q(q_ctx, &proto_ref, method_name.as_str(), &func_ref, 0)
+        })
+    }
+
+    /// call a function in the engine asynchronously
+    /// N.B. func_name is not a &str because of https://github.com/rust-lang/rust/issues/56238 (i think)
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let script = Script::new("my_file.es", "this.com = {my: {methodA: function(a, b){return a*b;}}};");
+    /// rt.eval_sync(script).ok().expect("script failed");
+    /// rt.call_function(vec!["com", "my"], "methodA".to_string(), vec![7.to_es_value_facade(), 5.to_es_value_facade()]);
+    /// ```
+    pub async fn call_function(
+        &self,
+        namespace: Vec<&'static str>,
+        func_name: String,
+        mut arguments: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        let func_name_string = func_name.to_string();
+
+        let res = self
+            .add_typed_rt_task_to_event_loop(TaskType::CallFunction, move |q_js_rt| {
+                let q_ctx = q_js_rt.get_main_context();
+                let mut q_args = vec![];
+                for arg in &mut arguments {
+                    match arg.as_js_value(q_ctx) {
+                        Ok(js_arg) => q_args.push(js_arg),
+                        Err(err) => log::error!(
+                            "error occurred in async esruntime::call_function closure: {}",
+                            err
+                        ),
+                    }
+                }
+
+                let res = q_ctx.call_function(namespace, func_name_string.as_str(), q_args);
+                match res {
+                    Ok(js_ref) => EsValueFacade::from_jsval(q_ctx, &js_ref),
+                    Err(e) => Err(e),
+                }
+            })
+            .await;
+        self.filter_result(res)
     }
 
     /// evaluate a module, you need if you want to compile a script that contains static imports
@@ -418,12 +1400,13 @@ impl EsRuntime {
     /// rt.eval_module(script);
     /// ```
     pub async fn eval_module(&self, script: Script) {
-        self.add_rt_task_to_event_loop(|q_js_rt| {
+        let result_filter = self.result_filter.clone();
+        self.add_typed_rt_task_to_event_loop(TaskType::Eval, move |q_js_rt| {
             let q_ctx = q_js_rt.get_main_context();
-            let res = q_ctx.eval_module(script);
-            match res {
-                Ok(_) => {}
-                Err(e) => log::error!("error in async eval {}", e),
+            if let Err(e) = q_ctx.eval_module(script) {
+                if let Err(e) = result_filter.apply(Err(e)) {
+                    log::error!("error in async eval {}", e);
+                }
             }
         })
         .await
@@ -431,41 +1414,287 @@ impl EsRuntime {
 
     /// evaluate a module and return result synchronously
     pub fn eval_module_sync(&self, script: Script) -> Result<EsValueFacade, JsError> {
-        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
             let q_ctx = q_js_rt.get_main_context();
             let res = q_ctx.eval_module(script);
             match res {
                 Ok(val_ref) => EsValueFacade::from_jsval(q_ctx, &val_ref),
                 Err(e) => Err(e),
             }
-        })
+        });
+        self.filter_result(res)
     }
 
-    /// this is how you add a closure to the worker thread which has an instance of the QuickJsRuntime
-    /// this will run and return synchronously
+    /// evaluate a module and return its exports
     /// # example
     /// ```rust
     /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
     /// use hirofa_utils::js_utils::Script;
-    /// use quickjs_runtime::quickjs_utils::primitives;
     /// let rt = EsRuntimeBuilder::new().build();
-    /// let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
-    ///     let q_ctx = q_js_rt.get_main_context();
-    ///     // here you are in the worker thread and you can use the quickjs_utils
-    ///     let val_ref = q_ctx.eval(Script::new("test.es", "(11 * 6);")).ok().expect("script failed");
-    ///     primitives::to_i32(&val_ref).ok().expect("could not get i32")
-    /// });
-    /// assert_eq!(res, 66);
+    /// let exports = rt.eval_module_sync_exports(Script::new("exports.mes", "export const a = 1;")).ok().expect("module failed");
+    /// assert_eq!(exports.get("a").expect("no a").get_i32(), 1);
     /// ```
-    pub fn exe_rt_task_in_event_loop<C, R>(&self, consumer: C) -> R
-    where
-        C: FnOnce(&QuickJsRuntime) -> R + Send + 'static,
-        R: Send + 'static,
-    {
-        self.exe_task_in_event_loop(|| QuickJsRuntime::do_with(consumer))
+    pub fn eval_module_sync_exports(
+        &self,
+        script: Script,
+    ) -> Result<HashMap<String, EsValueFacade>, JsError> {
+        self.exe_typed_rt_task_in_event_loop(TaskType::Eval, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let module_name = script.get_path().to_string();
+            q_ctx.eval_module(script)?;
+            let ns_ref = modules::get_module_namespace_q(q_ctx, module_name.as_str())?;
+            let mut exports = HashMap::new();
+            for export_name in objects::get_property_names_q(q_ctx, &ns_ref)? {
+                let export_ref = objects::get_property_q(q_ctx, &ns_ref, export_name.as_str())?;
+                exports.insert(export_name, EsValueFacade::from_jsval(q_ctx, &export_ref)?);
+            }
+            Ok(exports)
+        })
     }
 
-    /// this adds a rust function to JavaScript, it is added for all current and future contexts
+    /// call a function in the main context by a dot separated name (e.g. `math.add`) with JSON params and return the
+    /// result as JSON, this is a lightweight RPC interface for e.g. HTTP handlers which call script logic
+    /// when params is an array its elements are passed as arguments, null means no arguments and any other value is
+    /// passed as a single argument
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync(Script::new("math.es", "this.math = {add: function(a, b) {return a + b;}};")).ok().expect("script failed");
+    /// let res = rt.dispatch_sync("math.add", serde_json::json!([3, 4])).ok().expect("dispatch failed");
+    /// assert_eq!(res, serde_json::json!(7));
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn dispatch_sync(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, JsError> {
+        let mut arguments = match params {
+            serde_json::Value::Array(elements) => elements
+                .into_iter()
+                .map(EsValueFacade::from_typed)
+                .collect::<Result<Vec<EsValueFacade>, JsError>>()?,
+            serde_json::Value::Null => vec![],
+            param => vec![EsValueFacade::from_typed(param)?],
+        };
+        let method = method.to_string();
+        let res = self.exe_typed_rt_task_in_event_loop(TaskType::CallFunction, move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let mut path: Vec<&str> = method.split('.').collect();
+            let func_name = path.pop().unwrap_or_default();
+            let mut this_ref = quickjs_utils::get_global_q(q_ctx);
+            for part in path {
+                this_ref = objects::get_property_q(q_ctx, &this_ref, part)?;
+                if !this_ref.is_object() {
+                    return Err(JsError::new_string(format!("no such method: {}", method)));
+                }
+            }
+            let func_ref = objects::get_property_q(q_ctx, &this_ref, func_name)?;
+            if !functions::is_function_q(q_ctx, &func_ref) {
+                return Err(JsError::new_string(format!("no such method: {}", method)));
+            }
+            let mut q_args = vec![];
+            for arg in &mut arguments {
+                q_args.push(arg.as_js_value(q_ctx)?);
+            }
+            let res_ref = functions::call_function_q(q_ctx, &func_ref, q_args, Some(&this_ref))?;
+            EsValueFacade::from_jsval(q_ctx, &res_ref)
+        });
+        // the result is converted outside of the event queue because JS backed objects are read via the event queue
+        self.filter_result(res)?.into_typed()
+    }
+
+    /// get the globalThis object of the main context, the facade references the object so its properties may be read
+    /// and written with [EsValueFacade::get_property_sync] and [EsValueFacade::set_property_sync]
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let global = rt.get_global_this_sync().ok().expect("could not get globalThis");
+    /// global.set_property_sync("answer", 42.to_es_value_facade()).ok().expect("could not set property");
+    /// let res = rt.eval_sync(Script::new("global.es", "answer;")).ok().expect("script failed");
+    /// assert_eq!(res.get_i32(), 42);
+    /// ```
+    pub fn get_global_this_sync(&self) -> Result<EsValueFacade, JsError> {
+        self.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let global_ref = quickjs_utils::get_global_q(q_ctx);
+            EsValueFacade::from_jsval(q_ctx, &global_ref)
+        })
+    }
+
+    /// get an export of a module in the main context, if the module was already loaded it is not evaluated again
+    pub fn get_module_export(
+        &self,
+        module_name: &str,
+        export_name: &str,
+    ) -> Result<EsValueFacade, JsError> {
+        let module_name = module_name.to_string();
+        let export_name = export_name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let ns_ref = q_ctx.get_module_namespace(module_name.as_str())?;
+            let export_ref = objects::get_property_q(q_ctx, &ns_ref, export_name.as_str())?;
+            EsValueFacade::from_jsval(q_ctx, &export_ref)
+        })
+    }
+
+    /// compile a script without running it, this can be used to check a script for syntax errors
+    /// the ScriptPreProcessors are called for the script (so they should not have side effects) but an inline source
+    /// map is not registered and the script is not instrumented for the profiler or the debugger
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// assert!(rt.validate_script(Script::new("valid.es", "let a = 1;")).is_ok());
+    /// assert!(rt.validate_script(Script::new("invalid.es", "let a = ;")).is_err());
+    /// ```
+    pub fn validate_script(&self, script: Script) -> Result<(), JsError> {
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let script = QuickJsRuntime::pre_process_for_validation(script)?;
+            unsafe { compile::compile(q_ctx.context, script) }.map(|_| ())
+        })
+    }
+
+    /// parse a module without running it, this can be used to check a module for syntax errors
+    /// please note that imports are not resolved and the compiled module is not kept
+    /// the script is pre processed like in [EsRuntime::validate_script]
+    pub fn validate_module(&self, script: Script) -> Result<(), JsError> {
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let script = QuickJsRuntime::pre_process_for_validation(script)?;
+            unsafe { modules::validate_module(q_ctx.context, script) }
+        })
+    }
+
+    /// compile a module and store its bytecode in the ModuleCache without running it, when the module is imported
+    /// later (in any context) the cached bytecode is used
+    /// this fails when no ModuleCache was set in the EsRuntimeBuilder
+    pub fn preload_module(&self, script: Script) -> Result<(), JsError> {
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let cache = q_js_rt
+                .module_cache
+                .as_ref()
+                .ok_or_else(|| JsError::new_str("no module cache was configured"))?;
+            let q_ctx = q_js_rt.get_main_context();
+            let script = QuickJsRuntime::pre_process(script)?;
+            let path = script.get_path().to_string();
+            let compiled_module = unsafe { modules::compile_module(q_ctx.context, script)? };
+            let bytecode = unsafe { compile::to_bytecode(q_ctx.context, &compiled_module) };
+            cache.put(path.as_str(), bytecode);
+            Ok(())
+        })
+    }
+
+    /// this is how you add a closure to the worker thread which has an instance of the QuickJsRuntime
+    /// this will run and return synchronously
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use quickjs_runtime::quickjs_utils::primitives;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///     let q_ctx = q_js_rt.get_main_context();
+    ///     // here you are in the worker thread and you can use the quickjs_utils
+    ///     let val_ref = q_ctx.eval(Script::new("test.es", "(11 * 6);")).ok().expect("script failed");
+    ///     primitives::to_i32(&val_ref).ok().expect("could not get i32")
+    /// });
+    /// assert_eq!(res, 66);
+    /// ```
+    pub fn exe_rt_task_in_event_loop<C, R>(&self, consumer: C) -> R
+    where
+        C: FnOnce(&QuickJsRuntime) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.exe_task_in_event_loop(|| QuickJsRuntime::do_with(consumer))
+    }
+
+    /// run a closure in the worker thread with the main context and its global object (globalThis)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::quickjs_utils::{objects, primitives};
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.with_global_this(|q_ctx, global_this| {
+    ///     objects::set_property_q(q_ctx, global_this, "myVar", &primitives::from_i32(12)).ok().expect("set prop failed");
+    /// });
+    /// ```
+    pub fn with_global_this<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&QuickJsContext, &JSValueRef) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let global_this = q_ctx
+                .get_global_this()
+                .ok()
+                .expect("could not get globalThis");
+            f(q_ctx, &global_this)
+        })
+    }
+
+    /// run a closure with a set of temporary global variables in the main context
+    /// the variables are removed when the closure returns or panics, a variable may not have the same name as an existing global
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::{EsValueConvertible, EsValueFacade};
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::collections::HashMap;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let mut scope: HashMap<String, EsValueFacade> = HashMap::new();
+    /// scope.insert("price".to_string(), 10.to_es_value_facade());
+    /// scope.insert("amount".to_string(), 3.to_es_value_facade());
+    /// let res = rt.with_scope_sync(scope, |rt| {
+    ///     rt.eval_sync(Script::new("formula.es", "price * amount;"))
+    /// }).ok().expect("could not set scope");
+    /// assert_eq!(res.ok().expect("script failed").get_i32(), 30);
+    /// ```
+    pub fn with_scope_sync<R, F>(
+        &self,
+        scope: HashMap<String, EsValueFacade>,
+        f: F,
+    ) -> Result<R, JsError>
+    where
+        F: FnOnce(&EsRuntime) -> R,
+    {
+        // the guard is created first so the variables which were set are also removed when setting another one fails
+        let names = Arc::new(Mutex::new(vec![]));
+        let _guard = ScopeGuard {
+            rt: self,
+            names: names.clone(),
+        };
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let global_ref = quickjs_utils::get_global_q(q_ctx);
+            for name in scope.keys() {
+                if reflect::has_q(q_ctx, &global_ref, name.as_str())? {
+                    return Err(JsError::new_string(format!(
+                        "scope variable {} would overwrite an existing global",
+                        name
+                    )));
+                }
+            }
+            for (name, mut value) in scope {
+                let value_ref = value.as_js_value(q_ctx)?;
+                objects::set_property_q(q_ctx, &global_ref, name.as_str(), &value_ref)?;
+                names.lock().unwrap().push(name);
+            }
+            Ok(())
+        })?;
+
+        Ok(f(self))
+    }
+
+    /// this adds a rust function to JavaScript, it is added for all current and future contexts
     /// # Example
     /// ```rust
     /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
@@ -529,99 +1758,556 @@ impl EsRuntime {
         })
     }
 
-    /// add a task the the "helper" thread pool
-    pub fn add_helper_task<T>(task: T)
+    /// create an object in the global scope of all current and future contexts, every context gets its own copy of the
+    /// properties so changes made by a script in one context are not visible in other contexts
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::collections::HashMap;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let mut props = HashMap::new();
+    /// props.insert("version".to_string(), "1.2.3".to_string().to_es_value_facade());
+    /// rt.create_shared_namespace("appConfig", props).ok().expect("could not create namespace");
+    /// let res = rt.eval_sync(Script::new("ns.es", "appConfig.version;")).ok().expect("script failed");
+    /// assert_eq!(res.get_str(), "1.2.3");
+    /// ```
+    pub fn create_shared_namespace(
+        &self,
+        name: &str,
+        properties: HashMap<String, EsValueFacade>,
+    ) -> Result<(), JsError> {
+        let name = name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let properties = RefCell::new(properties);
+            q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                let ns_ref = objects::create_object_q(q_ctx)?;
+                let properties = &mut *properties.borrow_mut();
+                for (prop_name, prop_esvf) in properties.iter_mut() {
+                    let prop_ref = prop_esvf.as_js_value(q_ctx)?;
+                    objects::set_property_q(q_ctx, &ns_ref, prop_name.as_str(), &prop_ref)?;
+                }
+                objects::set_property_q(
+                    q_ctx,
+                    &quickjs_utils::get_global_q(q_ctx),
+                    name.as_str(),
+                    &ns_ref,
+                )
+            })
+        })
+    }
+
+    /// create a ReadableStream in the main context which is fed by the items received from a channel
+    /// the stream is closed when all senders of the channel are dropped and errored when an item can not be converted
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::sync::mpsc::channel;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let (sender, receiver) = channel();
+    /// let stream = rt.channel_to_readable_stream(receiver).ok().expect("could not create stream");
+    /// sender.send("a".to_string()).unwrap();
+    /// drop(sender);
+    /// let read_func = rt.eval_sync(Script::new("read.es", "(async function(stream) {let r = []; for await (const x of stream) {r.push(x);} return r.join(',');});")).ok().unwrap();
+    /// let prom = read_func.invoke_function_sync(vec![stream]).ok().unwrap();
+    /// assert_eq!(prom.get_promise_result_sync().ok().unwrap().get_str(), "a");
+    /// ```
+    pub fn channel_to_readable_stream<T>(
+        self: &Arc<Self>,
+        receiver: std::sync::mpsc::Receiver<T>,
+    ) -> Result<EsValueFacade, JsError>
     where
-        T: FnOnce() + Send + 'static,
+        T: EsValueConvertible + Send + 'static,
     {
-        log::trace!("adding a helper task");
-        HELPER_TASKS.add_task(task);
+        features::streams::channel_to_readable_stream(self, receiver)
     }
 
-    /// add an async task the the "helper" thread pool
-    pub fn add_helper_task_async<R: Send + 'static, T: Future<Output = R> + Send + 'static>(
-        task: T,
-    ) -> impl Future<Output = Result<R, JoinError>> {
-        log::trace!("adding an async helper task");
-        HELPER_TASKS.add_task_async(task)
+    /// read all chunks of a ReadableStream and return them as a rust Stream
+    /// every chunk is converted by the converter function, which is called in the worker thread of the runtime
+    /// the Stream ends when the ReadableStream is closed or errored
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use futures::executor::block_on;
+    /// use futures::StreamExt;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let stream = rt.eval_sync(Script::new("stream.es", "new ReadableStream({start(c) {c.enqueue(1); c.close();}});")).ok().unwrap();
+    /// let chunks: Vec<i32> = block_on(rt.readable_stream_to_rust(stream, |chunk| chunk.get_i32()).collect());
+    /// assert_eq!(chunks, vec![1]);
+    /// ```
+    pub fn readable_stream_to_rust<T, C>(
+        &self,
+        stream_facade: EsValueFacade,
+        converter: C,
+    ) -> impl futures::Stream<Item = T>
+    where
+        T: Send + 'static,
+        C: Fn(EsValueFacade) -> T + Send + 'static,
+    {
+        features::streams::readable_stream_to_rust(self, stream_facade, converter)
     }
 
-    /// create a new context besides the always existing main_context
-    /// # todo
-    /// EsRuntime needs some more pub methods using context like eval / call_func
+    /// define a class which extends Error in the global scope of all contexts
+    /// rust functions (see [EsRuntime::set_function]) which return a JsError with the name of the class throw an
+    /// instance of the class
     /// # Example
-    /// ```
+    /// ```rust
     /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
     /// use hirofa_utils::js_utils::Script;
     /// let rt = EsRuntimeBuilder::new().build();
-    /// rt.create_context("my_context");
-    /// rt.exe_rt_task_in_event_loop(|q_js_rt| {
-    ///    let my_ctx = q_js_rt.get_context("my_context");
-    ///    my_ctx.eval(Script::new("ctx_test.es", "this.myVar = 'only exists in my_context';"));
-    /// });
+    /// rt.define_error_class("ValidationError").ok().expect("could not define class");
+    /// let res = rt.eval_sync(Script::new("err.es", "new ValidationError('oops') instanceof Error;")).ok().unwrap();
+    /// assert!(res.get_boolean());
     /// ```
-    pub fn create_context(&self, id: &str) -> Result<(), JsError> {
-        let id = id.to_string();
-        self.event_loop
-            .exe(move || QuickJsRuntime::create_context(id.as_str()))
+    pub fn define_error_class(&self, name: &str) -> Result<(), JsError> {
+        let name = name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                let class_ref = errors::define_custom_error_class_q(q_ctx, name.as_str())?;
+                objects::set_property2_q(
+                    q_ctx,
+                    &quickjs_utils::get_global_q(q_ctx),
+                    name.as_str(),
+                    &class_ref,
+                    (q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE) as i32,
+                )
+            })
+        })
     }
 
-    /// drop a context which was created earlier with a call to [create_context()](struct.EsRuntime.html#method.create_context)
-    pub fn drop_context(&self, id: &str) {
-        let id = id.to_string();
-        self.event_loop
-            .exe(move || QuickJsRuntime::remove_context(id.as_str()))
-    }
-}
+    /// add a rust function which returns a Future to a namespace in the global scope, calling the function from script
+    /// returns a Promise which is resolved with the output of the Future
+    /// the Future is spawned in the helper thread pool, when it fails the Promise is rejected with an Error which has
+    /// the name, message and stack of the JsError
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.set_async_function(vec!["com", "mycompany"], "double", |_q_ctx, args| {
+    ///     let a = args[0].get_i32();
+    ///     async move { Ok((a * 2).to_es_value_facade()) }
+    /// }).ok().expect("set_async_function failed");
+    /// let prom = rt.eval_sync(Script::new("async.es", "com.mycompany.double(21);")).ok().expect("script failed");
+    /// assert_eq!(prom.get_promise_result_sync().ok().unwrap().get_i32(), 42);
+    /// ```
+    pub fn set_async_function<F, Fut>(
+        &self,
+        namespace: Vec<&'static str>,
+        name: &str,
+        function: F,
+    ) -> Result<(), JsError>
+    where
+        F: Fn(&QuickJsContext, Vec<EsValueFacade>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<EsValueFacade, JsError>> + Send + 'static,
+    {
+        let name = name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let func_rc = Rc::new(function);
 
-impl JsRuntimeFacade for EsRuntime {
-    type JsRuntimeAdapterType = QuickJsRuntime;
+            q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                let ns = objects::get_namespace_q(q_ctx, namespace.clone(), true)?;
 
-    fn js_realm_create(&mut self, name: &str) -> Result<(), JsError> {
-        self.create_context(name).map(|_| {
-            self.js_contexts.insert(name.to_string());
-        })
-    }
+                let func_rc = func_rc.clone();
 
-    fn js_realm_destroy(&mut self, _name: &str) -> Result<(), JsError> {
-        todo!()
-    }
+                let func = functions::new_function_q(
+                    q_ctx,
+                    name.as_str(),
+                    move |q_ctx, _this_ref, args| {
+                        let mut args_facades = vec![];
 
-    fn js_realm_has(&mut self, name: &str) -> Result<bool, JsError> {
-        Ok(self.js_contexts.contains(name))
-    }
+                        for arg_ref in args {
+                            args_facades.push(EsValueFacade::from_jsval(q_ctx, arg_ref)?);
+                        }
 
-    fn js_loop_sync<
-        R: Send + 'static,
-        C: FnOnce(&Self::JsRuntimeAdapterType) -> R + Send + 'static,
-    >(
-        &self,
-        consumer: C,
-    ) -> R {
-        self.exe_rt_task_in_event_loop(consumer)
-    }
+                        let fut = func_rc(q_ctx, args_facades);
+                        let es_rt = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref())
+                            .ok_or_else(|| JsError::new_str("Runtime was dropped"))?;
 
-    fn js_loop<R: Send + 'static, C: FnOnce(&Self::JsRuntimeAdapterType) -> R + Send + 'static>(
-        &self,
-        consumer: C,
-    ) -> Pin<Box<dyn Future<Output = R> + Send>> {
-        Box::pin(self.add_rt_task_to_event_loop(consumer))
-    }
+                        promises::new_resolving_promise_async(
+                            q_ctx,
+                            fut,
+                            |q_ctx, mut val_esvf| val_esvf.as_js_value(q_ctx),
+                            es_rt,
+                        )
+                    },
+                    1,
+                )?;
 
-    fn js_loop_void<C: FnOnce(&Self::JsRuntimeAdapterType) + Send + 'static>(&self, consumer: C) {
-        self.add_rt_task_to_event_loop_void(consumer)
+                objects::set_property2_q(q_ctx, &ns, name.as_str(), &func, 0)?;
+
+                Ok(())
+            })
+        })
     }
 
-    #[allow(clippy::type_complexity)]
+    /// install a rust object as a JS object with methods, the object is installed in all contexts and shared by them
+    /// this can be used to expose a stateful service (e.g. a connection pool) to scripts
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// struct Greeter {
+    ///     greeting: String,
+    /// }
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.install_object(vec!["services"], "greeter", Greeter{greeting: "hello".to_string()}, |_greeter, builder| {
+    ///     builder.method("greet", |greeter, args| {
+    ///         Ok(format!("{} {}", greeter.greeting, args[0].get_str()).to_es_value_facade())
+    ///     });
+    /// }).ok().expect("install failed");
+    /// let res = rt.eval_sync(Script::new("greet.es", "services.greeter.greet('world');")).ok().expect("script failed");
+    /// assert_eq!(res.get_str(), "hello world");
+    /// ```
+    pub fn install_object<T, S>(
+        &self,
+        namespace: Vec<&'static str>,
+        name: &str,
+        obj: T,
+        setup: S,
+    ) -> Result<(), JsError>
+    where
+        T: Send + Sync + 'static,
+        S: FnOnce(&T, NamespaceBuilder<T>),
+    {
+        let methods = Arc::new(Mutex::new(vec![]));
+        setup(
+            &obj,
+            NamespaceBuilder {
+                methods: methods.clone(),
+            },
+        );
+        let methods: Vec<(String, NamespaceMethod<T>)> =
+            std::mem::take(&mut *methods.lock().unwrap());
+        let obj = Arc::new(obj);
+        let name = name.to_string();
+        self.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                let ns = objects::get_namespace_q(q_ctx, namespace.clone(), true)?;
+                let obj_ref = objects::create_object_q(q_ctx)?;
+                for (method_name, method) in &methods {
+                    let method = method.clone();
+                    let obj = obj.clone();
+                    let func = functions::new_function_q(
+                        q_ctx,
+                        method_name.as_str(),
+                        move |q_ctx, _this_ref, args| {
+                            let mut args_facades = vec![];
+                            for arg_ref in args {
+                                args_facades.push(EsValueFacade::from_jsval(q_ctx, arg_ref)?);
+                            }
+                            let mut res = method(&*obj, args_facades)?;
+                            res.as_js_value(q_ctx)
+                        },
+                        1,
+                    )?;
+                    objects::set_property2_q(q_ctx, &obj_ref, method_name.as_str(), &func, 0)?;
+                }
+                objects::set_property2_q(q_ctx, &ns, name.as_str(), &obj_ref, 0)
+            })
+        })
+    }
+
+    /// add a task the the "helper" thread pool
+    pub fn add_helper_task<T>(task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        log::trace!("adding a helper task");
+        HELPER_TASKS.add_task(task);
+    }
+
+    /// add an async task the the "helper" thread pool
+    pub fn add_helper_task_async<R: Send + 'static, T: Future<Output = R> + Send + 'static>(
+        task: T,
+    ) -> impl Future<Output = Result<R, JoinError>> {
+        log::trace!("adding an async helper task");
+        HELPER_TASKS.add_task_async(task)
+    }
+
+    /// get the helper thread pool of this runtime, this is only created if the number of threads was configured
+    /// with [EsRuntimeBuilder::helper_task_threads]
+    fn get_helper_tasks(&self) -> Option<Arc<TaskManager>> {
+        let thread_count = self.helper_task_threads?;
+        let helper_tasks = &mut *self.helper_tasks.lock().unwrap();
+        Some(
+            helper_tasks
+                .get_or_insert_with(|| Arc::new(TaskManager::new(thread_count)))
+                .clone(),
+        )
+    }
+
+    /// get the priority helper thread pool of this runtime, it uses the thread count configured with
+    /// [EsRuntimeBuilder::helper_task_threads] or at least 2 threads
+    fn get_priority_tasks(&self) -> Arc<PriorityTaskManager> {
+        let thread_count = self
+            .helper_task_threads
+            .unwrap_or_else(|| std::cmp::max(2, num_cpus::get()));
+        let priority_tasks = &mut *self.priority_tasks.lock().unwrap();
+        priority_tasks
+            .get_or_insert_with(|| Arc::new(PriorityTaskManager::new(thread_count)))
+            .clone()
+    }
+
+    /// add a helper task which is run before all tasks added with [EsRuntime::add_low_priority_task]
+    pub fn add_high_priority_task<T>(&self, task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        self.get_priority_tasks().add_high_priority_task(task);
+    }
+
+    /// add a helper task which is only run when there are no high priority tasks waiting
+    pub fn add_low_priority_task<T>(&self, task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        self.get_priority_tasks().add_low_priority_task(task);
+    }
+
+    /// add a task to the "helper" thread pool of this runtime, if no thread count was configured with
+    /// [EsRuntimeBuilder::helper_task_threads] the static helper thread pool is used
+    pub fn add_runtime_helper_task<T>(&self, task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        match self.get_helper_tasks() {
+            Some(helper_tasks) => helper_tasks.add_task(task),
+            None => Self::add_helper_task(task),
+        }
+    }
+
+    /// add an async task to the "helper" thread pool of this runtime, if no thread count was configured with
+    /// [EsRuntimeBuilder::helper_task_threads] the static helper thread pool is used
+    pub fn add_runtime_helper_task_async<
+        R: Send + 'static,
+        T: Future<Output = R> + Send + 'static,
+    >(
+        &self,
+        task: T,
+    ) -> impl Future<Output = Result<R, JoinError>> {
+        log::trace!("adding an async runtime helper task");
+        match self.get_helper_tasks() {
+            Some(helper_tasks) => helper_tasks.add_task_async(task),
+            None => HELPER_TASKS.add_task_async(task),
+        }
+    }
+
+    /// get the EsRuntime if the current thread is the worker thread of an EsRuntime
+    fn current_runtime() -> Option<Arc<EsRuntime>> {
+        QJS_RT
+            .try_with(|qjs_rc| {
+                qjs_rc.try_borrow().ok().and_then(|qjs_rt_opt| {
+                    qjs_rt_opt.as_ref().and_then(|q_js_rt| q_js_rt.get_rt_ref())
+                })
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// add a task to the "helper" thread pool of the runtime whose worker thread is the current thread, the static
+    /// helper thread pool is used when called from any other thread
+    pub(crate) fn add_current_runtime_helper_task<T>(task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        match Self::current_runtime() {
+            Some(rt) => rt.add_runtime_helper_task(task),
+            None => Self::add_helper_task(task),
+        }
+    }
+
+    /// add an async task to the "helper" thread pool of the runtime whose worker thread is the current thread, the
+    /// static helper thread pool is used when called from any other thread
+    pub(crate) fn add_current_runtime_helper_task_async<
+        R: Send + 'static,
+        T: Future<Output = R> + Send + 'static,
+    >(
+        task: T,
+    ) {
+        match Self::current_runtime() {
+            Some(rt) => {
+                let _ = rt.add_runtime_helper_task_async(task);
+            }
+            None => {
+                let _ = Self::add_helper_task_async(task);
+            }
+        }
+    }
+
+    /// create a new context besides the always existing main_context
+    /// # todo
+    /// EsRuntime needs some more pub methods using context like eval / call_func
+    /// # Example
+    /// ```
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.create_context("my_context");
+    /// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///    let my_ctx = q_js_rt.get_context("my_context");
+    ///    my_ctx.eval(Script::new("ctx_test.es", "this.myVar = 'only exists in my_context';"));
+    /// });
+    /// ```
+    pub fn create_context(&self, id: &str) -> Result<(), JsError> {
+        let id = id.to_string();
+        self.event_loop
+            .exe(move || QuickJsRuntime::create_context(id.as_str()))
+    }
+
+    /// create a new context with a copy of the globals of an existing context, see
+    /// [QuickJsRuntime::clone_context](crate::quickjsruntime::QuickJsRuntime::clone_context)
+    pub fn clone_context(&self, src_id: &str, dst_id: &str) -> Result<(), JsError> {
+        let src_id = src_id.to_string();
+        let dst_id = dst_id.to_string();
+        self.event_loop
+            .exe(move || QuickJsRuntime::clone_context(src_id.as_str(), dst_id.as_str()))
+    }
+
+    /// add a NativeModuleLoader which only provides modules to a single context, the modules can not be imported
+    /// from other contexts, the loaders of a context are checked before the loaders of the runtime
+    pub fn add_context_native_module_loader<M>(
+        &self,
+        ctx_id: &str,
+        loader: M,
+    ) -> Result<(), JsError>
+    where
+        M: NativeModuleLoader + Send + 'static,
+    {
+        let ctx_id = ctx_id.to_string();
+        self.event_loop.exe(move || {
+            QuickJsRuntime::do_with_mut(|q_js_rt| {
+                q_js_rt.add_context_native_module_loader(
+                    ctx_id.as_str(),
+                    NativeModuleLoaderAdapter::new(Box::new(loader)),
+                )
+            })
+        })
+    }
+
+    /// drop a context which was created earlier with a call to [create_context()](struct.EsRuntime.html#method.create_context)
+    pub fn drop_context(&self, id: &str) {
+        let id = id.to_string();
+        self.event_loop
+            .exe(move || QuickJsRuntime::remove_context(id.as_str()))
+    }
+}
+
+/// run at most max_jobs_per_tick pending jobs, if more jobs are pending a new tick is added to the
+/// event queue so other tasks get a chance to run in between
+fn run_pending_jobs_tick() {
+    let more_pending = QuickJsRuntime::do_with(|q_js_rt| {
+        q_js_rt.run_pending_jobs_limit(q_js_rt.max_jobs_per_tick);
+        q_js_rt.has_pending_jobs()
+    });
+    if more_pending {
+        EventLoop::add_local_void(run_pending_jobs_tick);
+    }
+}
+
+/// removes the temporary globals set by [EsRuntime::with_scope_sync] when dropped
+struct ScopeGuard<'a> {
+    rt: &'a EsRuntime,
+    /// the names of the variables which were set
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        let names = std::mem::take(&mut *self.names.lock().unwrap());
+        if names.is_empty() {
+            return;
+        }
+        self.rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let global_ref = quickjs_utils::get_global_q(q_ctx);
+            for name in names {
+                if let Err(e) = reflect::delete_property_q(q_ctx, &global_ref, name.as_str()) {
+                    log::error!("could not remove scope variable {}: {}", name, e);
+                }
+            }
+        });
+    }
+}
+
+fn get_class_prototype(
+    q_ctx: &QuickJsContext,
+    namespace: Vec<&str>,
+    class_name: &str,
+) -> Result<JSValueRef, JsError> {
+    let ns_ref = objects::get_namespace_q(q_ctx, namespace, false)?;
+    let class_ref = objects::get_property_q(q_ctx, &ns_ref, class_name)?;
+    if !functions::is_function_q(q_ctx, &class_ref) {
+        return Err(JsError::new_string(format!(
+            "{} is not a class",
+            class_name
+        )));
+    }
+    let proto_ref = objects::get_property_q(q_ctx, &class_ref, "prototype")?;
+    if proto_ref.is_object() {
+        Ok(proto_ref)
+    } else {
+        let proto_ref = objects::create_object_q(q_ctx)?;
+        objects::set_property2_q(q_ctx, &class_ref, "prototype", &proto_ref, 0)?;
+        Ok(proto_ref)
+    }
+}
+
+impl JsRuntimeFacade for EsRuntime {
+    type JsRuntimeAdapterType = QuickJsRuntime;
+
+    fn js_realm_create(&mut self, name: &str) -> Result<(), JsError> {
+        self.create_context(name).map(|_| {
+            self.js_contexts.insert(name.to_string());
+        })
+    }
+
+    fn js_realm_destroy(&mut self, _name: &str) -> Result<(), JsError> {
+        todo!()
+    }
+
+    fn js_realm_has(&mut self, name: &str) -> Result<bool, JsError> {
+        Ok(self.js_contexts.contains(name))
+    }
+
+    fn js_loop_sync<
+        R: Send + 'static,
+        C: FnOnce(&Self::JsRuntimeAdapterType) -> R + Send + 'static,
+    >(
+        &self,
+        consumer: C,
+    ) -> R {
+        self.exe_rt_task_in_event_loop(consumer)
+    }
+
+    fn js_loop<R: Send + 'static, C: FnOnce(&Self::JsRuntimeAdapterType) -> R + Send + 'static>(
+        &self,
+        consumer: C,
+    ) -> Pin<Box<dyn Future<Output = R> + Send>> {
+        Box::pin(self.add_rt_task_to_event_loop(consumer))
+    }
+
+    fn js_loop_void<C: FnOnce(&Self::JsRuntimeAdapterType) + Send + 'static>(&self, consumer: C) {
+        self.add_rt_task_to_event_loop_void(consumer)
+    }
+
+    #[allow(clippy::type_complexity)]
     fn js_eval(
         &self,
         realm_name: Option<&str>,
         script: Script,
     ) -> Pin<Box<dyn Future<Output = Result<Box<dyn JsValueFacade>, JsError>>>> {
-        self.js_loop_realm(realm_name, |_rt, realm| {
-            realm
+        let result_filter = self.result_filter.clone();
+        self.js_loop_realm(realm_name, move |_rt, realm| {
+            let res = realm
                 .js_eval(script)
-                .map(|jsvr| realm.to_js_value_facade(&jsvr))
+                .map(|jsvr| realm.to_js_value_facade(&jsvr));
+            result_filter.apply_to_facade(realm, res)
         })
     }
 
@@ -635,6 +2321,7 @@ impl JsRuntimeFacade for EsRuntime {
     ) -> Result<Box<dyn JsValueFacade>, JsError> {
         let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
         let movable_method_name = method_name.to_string();
+        let result_filter = self.result_filter.clone();
 
         self.js_loop_realm_sync(realm_name, move |_rt, realm| {
             let args_adapters: Vec<JSValueRef> = args
@@ -660,7 +2347,7 @@ impl JsRuntimeFacade for EsRuntime {
                 )
                 .map(|jsvr| realm.to_js_value_facade(&jsvr));
 
-            res
+            result_filter.apply_to_facade(realm, res)
         })
     }
 
@@ -674,6 +2361,7 @@ impl JsRuntimeFacade for EsRuntime {
     ) -> Pin<Box<dyn Future<Output = Result<Box<dyn JsValueFacade>, JsError>>>> {
         let movable_namespace: Vec<String> = namespace.iter().map(|s| s.to_string()).collect();
         let movable_method_name = method_name.to_string();
+        let result_filter = self.result_filter.clone();
 
         self.js_loop_realm(realm_name, move |_rt, realm| {
             let args_adapters: Vec<JSValueRef> = args
@@ -699,7 +2387,7 @@ impl JsRuntimeFacade for EsRuntime {
                 )
                 .map(|jsvr| realm.to_js_value_facade(&jsvr));
 
-            res
+            result_filter.apply_to_facade(realm, res)
         })
     }
 
@@ -758,11 +2446,17 @@ impl JsRuntimeFacade for EsRuntime {
 
 #[cfg(test)]
 pub mod tests {
-    use crate::esruntime::EsRuntime;
-    use crate::esvalue::{EsValueConvertible, EsValueFacade};
-    use crate::quickjs_utils::{primitives, promises};
-    use crate::quickjscontext::QuickJsContext;
-    use crate::quickjsruntime::{NativeModuleLoader, ScriptModuleLoader};
+    use crate::esruntime::{EsRuntime, TaskInfo, TaskType};
+    use crate::esruntimebuilder::{EsRuntimeBuilder, GcStrategy};
+    use crate::esvalue::{EsPromise, EsValueConvertible, EsValueFacade, ES_NULL};
+    use crate::quickjs_utils::sourcemaps::SourceMapScript;
+    use crate::quickjs_utils::{compile, modules, objects, primitives, promises};
+    use crate::quickjscontext::{EvalOptions, EvalType, QuickJsContext};
+    use crate::quickjsruntime::{
+        CompiledModuleLoader, MemoryModuleCache, NativeModuleLoader, SandboxConfig,
+        ScriptModuleLoader,
+    };
+    use crate::reflection::Proxy;
     use crate::valueref::JSValueRef;
     use backtrace::Backtrace;
     use futures::executor::block_on;
@@ -770,9 +2464,12 @@ pub mod tests {
     use hirofa_utils::js_utils::Script;
     use log::debug;
     use log::LevelFilter;
+    use std::collections::HashMap;
     use std::panic;
-    use std::sync::Arc;
-    use std::time::Duration;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio_util::sync::CancellationToken;
 
     struct TestNativeModuleLoader {}
     struct TestScriptModuleLoader {}
@@ -801,6 +2498,14 @@ pub mod tests {
                 ("c", primitives::from_i32(333)),
             ]
         }
+
+        fn list_modules(&self) -> Vec<String> {
+            vec![
+                "greco://one".to_string(),
+                "greco://two".to_string(),
+                "greco://three".to_string(),
+            ]
+        }
     }
 
     impl ScriptModuleLoader for TestScriptModuleLoader {
@@ -1052,4 +2757,1479 @@ pub mod tests {
     fn test_macro() {
         let _args = es_args!(1, 2i32, true, "sdf".to_string());
     }
+
+    #[test]
+    fn test_with_global_this() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new("test_wgt.es", "this.wgt_a = 'abc';"))
+            .ok()
+            .expect("script failed");
+        let a = rt.with_global_this(|q_ctx, global_this| {
+            let a_ref = objects::get_property_q(q_ctx, global_this, "wgt_a")
+                .ok()
+                .expect("get prop failed");
+            objects::set_property_q(q_ctx, global_this, "wgt_b", &primitives::from_i32(7))
+                .ok()
+                .expect("set prop failed");
+            primitives::to_string_q(q_ctx, &a_ref)
+                .ok()
+                .expect("not a string")
+        });
+        assert_eq!(a, "abc");
+        let b = rt
+            .eval_sync(Script::new("test_wgt2.es", "(wgt_b * 6);"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(b.get_i32(), 42);
+    }
+
+    #[test]
+    fn test_validate() {
+        let rt = init_test_rt();
+        rt.validate_script(Script::new("test_validate.es", "this.validatedA = 1;"))
+            .ok()
+            .expect("valid script did not validate");
+        let res = rt.validate_script(Script::new("test_validate2.es", "let a = ;"));
+        let err = res.err().expect("invalid script validated");
+        assert!(err.get_name().contains("SyntaxError"));
+        let type_of = rt
+            .eval_sync(Script::new("test_validate3.es", "(typeof validatedA);"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(type_of.get_str(), "undefined");
+
+        rt.validate_module(Script::new(
+            "test_validate4.mes",
+            "export const validatedB = 1;",
+        ))
+        .ok()
+        .expect("valid module did not validate");
+        let res = rt.validate_module(Script::new(
+            "test_validate5.mes",
+            "export const = 1;",
+        ));
+        assert!(res.is_err());
+        // imports are not resolved
+        rt.validate_module(Script::new(
+            "test_validate6.mes",
+            "import {x} from 'test_validate_missing.mes';\nexport const y = x;",
+        ))
+        .ok()
+        .expect("module with an unknown import did not validate");
+    }
+
+    #[test]
+    fn test_validate_pre_process() {
+        let rt = EsRuntime::builder()
+            .add_script_preprocessor(Box::new(|script: Script| {
+                Script::new(
+                    script.get_path(),
+                    script.get_code().replace("@@", "1").as_str(),
+                )
+            }))
+            .build();
+        // the preprocessors are applied to the validated script, without them it would be a syntax error
+        rt.validate_script(Script::new("test_validate_pp.es", "let a = @@ + @@;"))
+            .ok()
+            .expect("pre processed script did not validate");
+
+        // an inline source map is not registered by validating
+        let script = Script::new("test_validate_pp2.es", "let a = 1;").with_source_map(
+            "{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA\"}",
+        );
+        rt.validate_script(script)
+            .ok()
+            .expect("script with source map did not validate");
+        assert!(!rt.remove_source_map("test_validate_pp2.es"));
+    }
+
+    #[test]
+    fn test_validate_module_is_not_kept() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(CountingScriptModuleLoader {
+                loads: loads.clone(),
+            }))
+            .build();
+        rt.validate_module(Script::new(
+            "cached_test.mes",
+            "export const foo = 'validated';",
+        ))
+        .ok()
+        .expect("valid module did not validate");
+        rt.eval_module_sync(Script::new(
+            "test_validate_module_is_not_kept.es",
+            "import {foo} from 'cached_test.mes';\nglobalThis.validatedFoo = foo;",
+        ))
+        .ok()
+        .expect("module failed");
+        let foo = rt
+            .eval_sync(Script::new("test_validate7.es", "(validatedFoo);"))
+            .ok()
+            .expect("script failed");
+        // the module was loaded by the loader instead of using the validated module
+        assert_eq!(foo.get_str(), "cached");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_eval_async_with_cancel() {
+        let rt = init_test_rt();
+        let (fut, cancel_token) = rt.eval_async_with_cancel(Script::new(
+            "test_cancel.es",
+            "let cancelX = 0; while(true){cancelX++;}",
+        ));
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cancel_token.cancel();
+        });
+        let res = block_on(fut);
+        let err = res.err().expect("script was not cancelled");
+        assert_eq!(err.get_name(), "CancelledError");
+
+        // rt should still be usable
+        let res = rt
+            .eval_sync(Script::new("test_cancel2.es", "(1 + 1);"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 2);
+    }
+
+    #[test]
+    fn test_gc_incremental() {
+        let rt = EsRuntime::builder()
+            .gc_strategy(GcStrategy::Manual)
+            .build();
+        rt.eval_sync(Script::new(
+            "test_gc_incremental.es",
+            "this.gcObjs = []; for (let x = 0; x < 100000; x++) {this.gcObjs.push({a: x, b: 'abc' + x, c: [x, x]});}",
+        ))
+        .ok()
+        .expect("script failed");
+        let before = rt.get_memory_usage().memory_used_size;
+        assert!(before > 10 * 1024 * 1024);
+        rt.eval_sync(Script::new("test_gc_incremental2.es", "this.gcObjs = null;"))
+            .ok()
+            .expect("script failed");
+        let freed = rt.gc_incremental_sync(1024 * 1024);
+        assert!(freed > 0);
+        let after = rt.get_memory_usage().memory_used_size;
+        assert!(after < before);
+    }
+
+    #[test]
+    fn test_gc_manual() {
+        let rt = EsRuntime::builder().gc_strategy(GcStrategy::Manual).build();
+        // cyclic garbage is only freed by the GC, with the default threshold QuickJS would collect it while allocating
+        rt.eval_sync(Script::new(
+            "test_gc_manual.es",
+            "for (let x = 0; x < 100000; x++) {let a = {x}; let b = {a}; a.b = b;}",
+        ))
+        .ok()
+        .expect("script failed");
+        let before = rt.get_memory_usage().memory_used_size;
+        rt.gc_sync();
+        let after = rt.get_memory_usage().memory_used_size;
+        assert!(before > after + 5 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_weak_ref() {
+        let rt = init_test_rt();
+        let weak_rt = rt.weak_ref();
+        assert!(weak_rt.upgrade().is_some());
+        assert_eq!(weak_rt.exe_rt_task_in_event_loop(|_q_js_rt| 3), Some(3));
+        drop(rt);
+        assert!(weak_rt.upgrade().is_none());
+        assert!(weak_rt.exe_rt_task_in_event_loop(|_q_js_rt| 3).is_none());
+        weak_rt.add_rt_task_to_event_loop_void(|_q_js_rt| {
+            panic!("should not run");
+        });
+    }
+
+    #[test]
+    fn test_context_native_module_loader() {
+        struct ContextModuleLoader {}
+        impl NativeModuleLoader for ContextModuleLoader {
+            fn has_module(&self, _q_ctx: &QuickJsContext, module_name: &str) -> bool {
+                module_name.eq("ctx://private")
+            }
+
+            fn get_module_export_names(
+                &self,
+                _q_ctx: &QuickJsContext,
+                _module_name: &str,
+            ) -> Vec<&str> {
+                vec!["secret"]
+            }
+
+            fn get_module_exports(
+                &self,
+                _q_ctx: &QuickJsContext,
+                _module_name: &str,
+            ) -> Vec<(&str, JSValueRef)> {
+                vec![("secret", primitives::from_i32(42))]
+            }
+        }
+
+        let rt = init_test_rt();
+        rt.create_context("ctx_with_loader")
+            .ok()
+            .expect("could not create context");
+        rt.create_context("ctx_without_loader")
+            .ok()
+            .expect("could not create context");
+        rt.add_context_native_module_loader("ctx_with_loader", ContextModuleLoader {})
+            .ok()
+            .expect("could not add loader");
+        assert!(rt
+            .add_context_native_module_loader("no_such_ctx", ContextModuleLoader {})
+            .is_err());
+
+        let (with_res, without_res) = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let import = |ctx_id: &str| {
+                let q_ctx = q_js_rt.get_context(ctx_id);
+                q_ctx.eval_module(Script::new(
+                    format!("test_context_native_module_loader_{}.mes", ctx_id).as_str(),
+                    "import {secret} from 'ctx://private';\n\
+                     if (secret !== 42) {throw Error('secret was ' + secret);}",
+                ))
+            };
+            (
+                import("ctx_with_loader").is_ok(),
+                import("ctx_without_loader").is_ok(),
+            )
+        });
+        assert!(with_res);
+        assert!(!without_res);
+
+        rt.drop_context("ctx_with_loader");
+        rt.drop_context("ctx_without_loader");
+    }
+
+    #[test]
+    fn test_list_native_modules() {
+        let rt = init_test_rt();
+        let modules = rt.list_native_modules();
+        assert!(modules.contains(&"greco://one".to_string()));
+        assert!(modules.contains(&"greco://two".to_string()));
+        assert!(modules.contains(&"greco://three".to_string()));
+    }
+
+    struct TestCompiledModuleLoader {
+        loads: Arc<AtomicUsize>,
+    }
+
+    impl CompiledModuleLoader for TestCompiledModuleLoader {
+        fn normalize_path(
+            &self,
+            _q_ctx: &QuickJsContext,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            if path.eq("compiled_test.mes") {
+                Some(path.to_string())
+            } else {
+                None
+            }
+        }
+
+        fn load_module(
+            &self,
+            q_ctx: &QuickJsContext,
+            absolute_path: &str,
+        ) -> Result<Vec<u8>, JsError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            let script = Script::new(absolute_path, "export const foo = 'compiled';");
+            unsafe {
+                let compiled = modules::compile_module(q_ctx.context, script)?;
+                Ok(compile::to_bytecode(q_ctx.context, &compiled))
+            }
+        }
+    }
+
+    #[test]
+    fn test_compiled_module_loader() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(TestScriptModuleLoader {}))
+            .compiled_module_loader(Box::new(TestCompiledModuleLoader {
+                loads: loads.clone(),
+            }))
+            .build();
+        rt.eval_module_sync(Script::new(
+            "test_compiled_module_loader.es",
+            "import {foo} from 'compiled_test.mes';\nglobalThis.compiledFoo = foo;",
+        ))
+        .ok()
+        .expect("module eval failed");
+        let res = rt
+            .eval_sync(Script::new(
+                "test_compiled_module_loader2.es",
+                "(compiledFoo);",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "compiled");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    struct CountingScriptModuleLoader {
+        loads: Arc<AtomicUsize>,
+    }
+
+    impl ScriptModuleLoader for CountingScriptModuleLoader {
+        fn normalize_path(&self, _ref_path: &str, path: &str) -> Option<String> {
+            if path.eq("cached_test.mes") {
+                Some(path.to_string())
+            } else {
+                None
+            }
+        }
+
+        fn load_module(&self, _absolute_path: &str) -> String {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            "export const foo = 'cached';".to_string()
+        }
+    }
+
+    #[test]
+    fn test_module_cache() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(CountingScriptModuleLoader {
+                loads: loads.clone(),
+            }))
+            .module_cache(Box::new(MemoryModuleCache::new()))
+            .build();
+        rt.eval_module_sync(Script::new(
+            "test_module_cache.es",
+            "import {foo} from 'cached_test.mes';\nglobalThis.cachedFoo = foo;",
+        ))
+        .ok()
+        .expect("module eval failed");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        rt.create_context("module_cache_ctx")
+            .ok()
+            .expect("could not create context");
+        let foo = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("module_cache_ctx");
+            q_ctx
+                .eval_module(Script::new(
+                    "test_module_cache2.es",
+                    "import {foo} from 'cached_test.mes';\nglobalThis.cachedFoo = foo;",
+                ))
+                .ok()
+                .expect("module eval failed");
+            let foo_ref = q_ctx
+                .eval(Script::new("test_module_cache3.es", "(cachedFoo);"))
+                .ok()
+                .expect("script failed");
+            primitives::to_string_q(q_ctx, &foo_ref)
+                .ok()
+                .expect("not a string")
+        });
+        assert_eq!(foo, "cached");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        rt.drop_context("module_cache_ctx");
+    }
+
+    #[test]
+    fn test_preload_module() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(CountingScriptModuleLoader {
+                loads: loads.clone(),
+            }))
+            .module_cache(Box::new(MemoryModuleCache::new()))
+            .build();
+        rt.preload_module(Script::new(
+            "cached_test.mes",
+            "export const foo = 'preloaded';",
+        ))
+        .ok()
+        .expect("preload failed");
+
+        rt.create_context("preload_ctx_a")
+            .ok()
+            .expect("could not create context");
+        rt.create_context("preload_ctx_b")
+            .ok()
+            .expect("could not create context");
+        for ctx_id in ["preload_ctx_a", "preload_ctx_b"] {
+            let foo = rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+                let q_ctx = q_js_rt.get_context(ctx_id);
+                q_ctx
+                    .eval_module(Script::new(
+                        "test_preload_module.es",
+                        "import {foo} from 'cached_test.mes';\nglobalThis.preloadedFoo = foo;",
+                    ))
+                    .ok()
+                    .expect("module eval failed");
+                let foo_ref = q_ctx
+                    .eval(Script::new("test_preload_module2.es", "(preloadedFoo);"))
+                    .ok()
+                    .expect("script failed");
+                primitives::to_string_q(q_ctx, &foo_ref)
+                    .ok()
+                    .expect("not a string")
+            });
+            assert_eq!(foo, "preloaded");
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+        rt.drop_context("preload_ctx_a");
+        rt.drop_context("preload_ctx_b");
+
+        let rt_no_cache = EsRuntime::builder().build();
+        assert!(rt_no_cache
+            .preload_module(Script::new("cached_test.mes", "export const foo = 1;"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_eval_module_sync_exports() {
+        let rt = init_test_rt();
+        let exports = rt
+            .eval_module_sync_exports(Script::new(
+                "test_eval_module_sync_exports.mes",
+                "export const a = 1;\nexport const b = 2;",
+            ))
+            .ok()
+            .expect("module failed");
+        assert_eq!(exports.len(), 2);
+        assert_eq!(exports.get("a").expect("no a").get_i32(), 1);
+        assert_eq!(exports.get("b").expect("no b").get_i32(), 2);
+
+        // the evaluated module can be imported by its name without a module loader
+        let res = rt
+            .eval_module_sync(Script::new(
+                "test_eval_module_sync_exports2.mes",
+                "import {a, b} from 'test_eval_module_sync_exports.mes';\nglobalThis.evaluatedSum = a + b;",
+            ))
+            .map(|_| ());
+        assert!(res.is_ok());
+        let sum = rt
+            .eval_sync(Script::new(
+                "test_eval_module_sync_exports3.es",
+                "evaluatedSum;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(sum.get_i32(), 3);
+    }
+
+    #[test]
+    fn test_get_module_export() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(CountingScriptModuleLoader {
+                loads: loads.clone(),
+            }))
+            .build();
+        rt.eval_module_sync(Script::new(
+            "test_get_module_export.es",
+            "import {foo} from 'cached_test.mes';\nconsole.log(foo);",
+        ))
+        .ok()
+        .expect("module eval failed");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        let foo = rt
+            .get_module_export("cached_test.mes", "foo")
+            .ok()
+            .expect("could not get export");
+        assert_eq!(foo.get_str(), "cached");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_call_module_function_sync() {
+        let rt = init_test_rt();
+        rt.eval_module_sync(Script::new(
+            "test_call_module_function.mes",
+            "export function mul(a, b) {return a * b;}\nexport const notAFunction = 1;",
+        ))
+        .ok()
+        .expect("module eval failed");
+        let res = rt
+            .call_module_function_sync(
+                "test_call_module_function.mes",
+                "mul",
+                vec![6.to_es_value_facade(), 7.to_es_value_facade()],
+            )
+            .ok()
+            .expect("call failed");
+        assert_eq!(res.get_i32(), 42);
+
+        assert!(rt
+            .call_module_function_sync("test_call_module_function.mes", "notAFunction", vec![])
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_primitive() {
+        let rt = init_test_rt();
+        let num = rt
+            .eval_sync(Script::new("test_is_primitive.es", "(21 * 2);"))
+            .ok()
+            .expect("script failed");
+        let obj = rt
+            .eval_sync(Script::new("test_is_primitive2.es", "({a: 1});"))
+            .ok()
+            .expect("script failed");
+        assert!(num.is_primitive());
+        assert!(!obj.is_primitive());
+        assert!(ES_NULL.to_es_value_facade().is_primitive());
+        assert!("a".to_string().to_es_value_facade().is_primitive());
+        assert_eq!(num.to_json_string(), Ok("42".to_string()));
+        assert_eq!(obj.to_json_string(), Ok("{\"a\":1}".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_cancel_token_async() {
+        let rt = init_test_rt();
+        let cancel = CancellationToken::new();
+        let fut = rt.eval_with_cancel_token_async(
+            Script::new("test_eval_with_cancel_token.es", "while(true){}"),
+            cancel.clone(),
+        );
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        match block_on(fut) {
+            Ok(_) => panic!("script should have been cancelled"),
+            Err(e) => assert_eq!(e.get_name(), "CancelledError"),
+        }
+
+        // a token which is never cancelled does not affect the script
+        let res = block_on(rt.eval_with_cancel_token_async(
+            Script::new("test_eval_with_cancel_token2.es", "(6 * 7);"),
+            CancellationToken::new(),
+        ))
+        .ok()
+        .expect("script failed");
+        assert_eq!(res.get_i32(), 42);
+    }
+
+    #[test]
+    fn test_wait_for_idle() {
+        let rt = init_test_rt();
+        rt.add_rt_task_to_event_loop_void(|q_js_rt| {
+            q_js_rt
+                .get_main_context()
+                .eval(Script::new(
+                    "test_wait_for_idle.es",
+                    "globalThis.idleSteps = [];\n\
+                     let p = Promise.resolve(0);\n\
+                     for (let i = 1; i <= 5; i++) {\n\
+                         p = p.then(() => {idleSteps.push(i);});\n\
+                     }",
+                ))
+                .ok()
+                .expect("script failed");
+        });
+        rt.wait_for_idle(Duration::from_secs(1))
+            .ok()
+            .expect("runtime was not idle");
+        let steps = rt
+            .exe_rt_task_in_event_loop(|q_js_rt| {
+                let q_ctx = q_js_rt.get_main_context();
+                let steps_ref = q_ctx.eval(Script::new(
+                    "test_wait_for_idle2.es",
+                    "idleSteps.join(',');",
+                ))?;
+                primitives::to_string_q(q_ctx, &steps_ref)
+            })
+            .ok()
+            .expect("could not get steps");
+        assert_eq!(steps, "1,2,3,4,5");
+    }
+
+    #[test]
+    fn test_has_pending_jobs() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            q_js_rt.run_pending_jobs_if_any();
+            assert!(!q_js_rt.has_pending_jobs());
+            q_ctx
+                .eval(Script::new(
+                    "test_has_pending_jobs.es",
+                    "Promise.resolve(1).then((a) => {globalThis.pendingJobsA = a;});",
+                ))
+                .ok()
+                .expect("script failed");
+            assert!(q_js_rt.has_pending_jobs());
+            // the job queue and the task which is currently running
+            assert_eq!(q_js_rt.pending_job_count(), 2);
+            q_js_rt.run_pending_jobs_if_any();
+            assert!(!q_js_rt.has_pending_jobs());
+            assert_eq!(q_js_rt.pending_job_count(), 1);
+        });
+        assert!(!rt.has_pending_jobs());
+        assert_eq!(rt.pending_job_count(), 0);
+        let a = rt
+            .eval_sync(Script::new("test_has_pending_jobs2.es", "(pendingJobsA);"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(a.get_i32(), 1);
+    }
+
+    #[test]
+    fn test_pending_job_count() {
+        // no gc_interval so no gc tasks are added to the event queue while counting
+        let rt = EsRuntime::builder().build();
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        rt.add_task_to_event_loop_void(move || {
+            rx.recv().expect("recv failed");
+        });
+        rt.add_task_to_event_loop_void(|| {});
+        rt.add_task_to_event_loop_void(|| {});
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            tx.send(()).expect("send failed");
+        });
+        // the blocked task and the two tasks waiting behind it
+        assert_eq!(rt.pending_job_count(), 3);
+        rt.drain_event_queue_sync();
+        assert_eq!(rt.pending_job_count(), 0);
+    }
+
+    #[test]
+    fn test_drain_event_queue_sync() {
+        let rt = init_test_rt();
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..5 {
+            let done = done.clone();
+            rt.add_task_to_event_loop_void(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        rt.drain_event_queue_sync();
+        assert_eq!(done.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_task_telemetry() {
+        let infos: Arc<Mutex<Vec<TaskInfo>>> = Arc::new(Mutex::new(vec![]));
+        let infos2 = infos.clone();
+        let rt = EsRuntime::builder()
+            .on_task_complete(Box::new(move |info| {
+                infos2.lock().unwrap().push(info);
+            }))
+            .build();
+        infos.lock().unwrap().clear();
+
+        for x in 0..10 {
+            rt.exe_task(move || {
+                std::thread::sleep(Duration::from_millis(5));
+                x
+            });
+        }
+        rt.eval_sync(Script::new("test_task_telemetry.es", "(1 + 1);"))
+            .ok()
+            .expect("script failed");
+        rt.gc_sync();
+
+        let infos = &*infos.lock().unwrap();
+        let user_tasks: Vec<&TaskInfo> = infos
+            .iter()
+            .filter(|i| i.task_type == TaskType::UserTask)
+            .collect();
+        assert_eq!(user_tasks.len(), 10);
+        for info in user_tasks {
+            assert!(info.start_time >= info.queue_time);
+            assert!(info.end_time >= info.start_time);
+            assert!(info.end_time - info.start_time >= Duration::from_millis(5));
+        }
+        assert_eq!(
+            infos
+                .iter()
+                .filter(|i| i.task_type == TaskType::Eval)
+                .count(),
+            1
+        );
+        assert_eq!(
+            infos.iter().filter(|i| i.task_type == TaskType::Gc).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_run_event_loop_once() {
+        let rt = init_test_rt();
+        assert_eq!(rt.run_event_loop_once(), 0);
+
+        let done = Arc::new(AtomicUsize::new(0));
+        for x in 0..3 {
+            let done = done.clone();
+            rt.add_rt_task_to_tick_queue(move |q_js_rt| {
+                done.fetch_add(1, Ordering::SeqCst);
+                if x == 2 {
+                    // the 4th task is added while the tick is running
+                    let done = done.clone();
+                    let es_rt = q_js_rt.get_rt_ref().expect("runtime was dropped");
+                    es_rt.add_rt_task_to_tick_queue(move |_q_js_rt| {
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            });
+        }
+        assert_eq!(rt.run_event_loop_once(), 3);
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+
+        // the 4th task is not run by the event loop on its own
+        rt.drain_event_queue_sync();
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+
+        assert_eq!(rt.run_event_loop_once(), 1);
+        assert_eq!(done.load(Ordering::SeqCst), 4);
+        assert_eq!(rt.run_event_loop_once(), 0);
+    }
+
+    #[test]
+    fn test_helper_task_threads() {
+        let rt = EsRuntime::builder().helper_task_threads(1).build();
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let mut futures = vec![];
+        for _ in 0..5 {
+            let active = active.clone();
+            let max_active = max_active.clone();
+            futures.push(rt.add_runtime_helper_task_async(async move {
+                let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max_active.fetch_max(now_active, Ordering::SeqCst);
+                // block the only helper thread
+                std::thread::sleep(Duration::from_millis(20));
+                active.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+        for fut in futures {
+            block_on(fut).ok().expect("task failed");
+        }
+        assert_eq!(max_active.load(Ordering::SeqCst), 1);
+
+        // the resolvers of EsPromises which are created in the runtime also run in its helper thread pool
+        max_active.store(0, Ordering::SeqCst);
+        rt.set_function(vec!["com", "helpers"], "sleep", move |_q_ctx, _args| {
+            let active = active.clone();
+            let max_active = max_active.clone();
+            Ok(EsPromise::new(move || {
+                let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max_active.fetch_max(now_active, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                active.fetch_sub(1, Ordering::SeqCst);
+                Ok((now_active as i32).to_es_value_facade())
+            })
+            .to_es_value_facade())
+        })
+        .ok()
+        .expect("set_function failed");
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_helper_task_threads.es",
+                "Promise.all([com.helpers.sleep(), com.helpers.sleep(), com.helpers.sleep()]).then((res) => Math.max(...res));",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom.get_promise_result_sync().ok().expect("promise failed");
+        assert_eq!(res.get_i32(), 1);
+    }
+
+    #[test]
+    fn test_todo_count() {
+        let rt = EsRuntime::builder().build();
+        assert_eq!(rt.get_todo_count(), 0);
+        let max_seen = Arc::new(AtomicUsize::new(0));
+        let mut threads = vec![];
+        for _ in 0..4 {
+            let rt = rt.clone();
+            let max_seen = max_seen.clone();
+            threads.push(std::thread::spawn(move || {
+                for _ in 0..5 {
+                    rt.add_task_to_event_loop_void(|| {
+                        std::thread::sleep(Duration::from_millis(2));
+                    });
+                    max_seen.fetch_max(rt.get_todo_count(), Ordering::SeqCst);
+                }
+            }));
+        }
+        for t in threads {
+            t.join().expect("thread failed");
+        }
+        assert!(max_seen.load(Ordering::SeqCst) <= 20);
+        rt.drain_event_queue_sync();
+        assert_eq!(rt.get_todo_count(), 0);
+    }
+
+    #[test]
+    fn test_merge_objects() {
+        let rt = init_test_rt();
+        let mut sources = vec![];
+        for (idx, keys) in [["a", "b"], ["b", "c"], ["c", "d"]].iter().enumerate() {
+            let mut props: HashMap<String, EsValueFacade> = HashMap::new();
+            for key in keys {
+                props.insert(key.to_string(), (idx as i32).to_es_value_facade());
+            }
+            sources.push(props.to_es_value_facade());
+        }
+        rt.merge_objects(vec!["test_merge_objects"], sources)
+            .ok()
+            .expect("merge failed");
+        let res = rt
+            .eval_sync(Script::new(
+                "test_merge_objects.es",
+                "JSON.stringify(test_merge_objects, Object.keys(test_merge_objects).sort());",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), r#"{"a":0,"b":1,"c":2,"d":2}"#);
+    }
+
+    #[test]
+    fn test_set_class_method() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            Proxy::new()
+                .namespace(vec!["test_set_class_method"])
+                .name("Counter")
+                .constructor(|_q_ctx, _id, _args| Ok(()))
+                .install(q_ctx, true)
+                .ok()
+                .expect("install failed");
+        });
+
+        let proto = rt
+            .get_class_prototype_sync(vec!["test_set_class_method"], "Counter")
+            .ok()
+            .expect("could not get prototype");
+        assert!(proto.is_object());
+
+        rt.set_class_method_sync(
+            vec!["test_set_class_method"],
+            "Counter",
+            "twice",
+            |_q_ctx, _this_ref, args| Ok((args[0].get_i32() * 2).to_es_value_facade()),
+        )
+        .ok()
+        .expect("could not set method");
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_set_class_method.es",
+                "new test_set_class_method.Counter().twice(21);",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 42);
+    }
+
+    #[test]
+    fn test_with_scope_sync() {
+        let rt = init_test_rt();
+        let mut scope: HashMap<String, EsValueFacade> = HashMap::new();
+        scope.insert("x".to_string(), 10.to_es_value_facade());
+        scope.insert("y".to_string(), 20.to_es_value_facade());
+        let res = rt
+            .with_scope_sync(scope, |rt| {
+                rt.eval_sync(Script::new("test_with_scope_sync.es", "x * y;"))
+            })
+            .ok()
+            .expect("could not set scope")
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 200);
+
+        let outside = rt
+            .eval_sync(Script::new(
+                "test_with_scope_sync2.es",
+                "typeof x + '_' + typeof y;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(outside.get_str(), "undefined_undefined");
+
+        // the scope is also cleaned up when the closure panics
+        let mut scope: HashMap<String, EsValueFacade> = HashMap::new();
+        scope.insert("z".to_string(), 1.to_es_value_facade());
+        let panic_res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _ = rt.with_scope_sync(scope, |_rt| panic!("fail in scope"));
+        }));
+        assert!(panic_res.is_err());
+        let outside = rt
+            .eval_sync(Script::new("test_with_scope_sync3.es", "typeof z;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(outside.get_str(), "undefined");
+
+        // variables which were set are removed when setting another variable fails
+        rt.create_context("scope_ctx")
+            .ok()
+            .expect("could not create context");
+        let foreign = rt
+            .exe_rt_task_in_event_loop(|q_js_rt| {
+                let q_ctx = q_js_rt.get_context("scope_ctx");
+                let obj_ref = q_ctx.eval(Script::new("test_with_scope_sync4.es", "({});"))?;
+                EsValueFacade::from_jsval(q_ctx, &obj_ref)
+            })
+            .ok()
+            .expect("script failed");
+        let mut scope: HashMap<String, EsValueFacade> = HashMap::new();
+        scope.insert("a".to_string(), 1.to_es_value_facade());
+        scope.insert("b".to_string(), foreign);
+        assert!(rt.with_scope_sync(scope, |_rt| ()).is_err());
+        let outside = rt
+            .eval_sync(Script::new(
+                "test_with_scope_sync5.es",
+                "typeof a + '_' + typeof b;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(outside.get_str(), "undefined_undefined");
+        rt.drop_context("scope_ctx");
+    }
+
+    #[test]
+    fn test_create_shared_namespace() {
+        let rt = init_test_rt();
+        let mut nested = HashMap::new();
+        nested.insert("b".to_string(), 2.to_es_value_facade());
+        let mut props = HashMap::new();
+        props.insert("a".to_string(), 1.to_es_value_facade());
+        props.insert("nested".to_string(), nested.to_es_value_facade());
+        rt.create_shared_namespace("sharedNs", props)
+            .ok()
+            .expect("could not create namespace");
+
+        rt.create_context("shared_ctx")
+            .ok()
+            .expect("could not create context");
+        let res = rt
+            .eval_sync(Script::new(
+                "test_shared_ns.es",
+                "sharedNs.a = 10; sharedNs.nested.b = 20; sharedNs.a + sharedNs.nested.b;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 30);
+
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("shared_ctx");
+            let res_ref = q_ctx
+                .eval(Script::new(
+                    "test_shared_ns2.es",
+                    "sharedNs.a + sharedNs.nested.b;",
+                ))
+                .ok()
+                .expect("script failed");
+            primitives::to_i32(&res_ref).ok().expect("not an i32")
+        });
+        assert_eq!(res, 3);
+        rt.drop_context("shared_ctx");
+    }
+
+    #[test]
+    fn test_set_async_function() {
+        let rt = init_test_rt();
+        rt.set_async_function(vec!["test", "async"], "slowDouble", |_q_ctx, args| {
+            let a = args[0].get_i32();
+            async move {
+                std::thread::sleep(Duration::from_millis(50));
+                if a < 0 {
+                    Err(JsError::new(
+                        "RangeError".to_string(),
+                        "negative".to_string(),
+                        "".to_string(),
+                    ))
+                } else {
+                    Ok((a * 2).to_es_value_facade())
+                }
+            }
+        })
+        .ok()
+        .expect("set_async_function failed");
+
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_set_async_function.es",
+                "(async function() {\n\
+                 let p = test.async.slowDouble(21);\n\
+                 let isPromise = p instanceof Promise;\n\
+                 let res = await p;\n\
+                 let err = await test.async.slowDouble(-1).catch((e) => e);\n\
+                 return isPromise + ':' + res + ':' + (err instanceof Error) + ':' + err.name + ':' + err.message;\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "true:42:true:RangeError:negative");
+    }
+
+    #[test]
+    fn test_set_async_function_concurrent() {
+        // with a single helper thread a pending Future must not block the Futures of other calls
+        let rt = EsRuntime::builder().helper_task_threads(1).build();
+        let (tx, rx) = futures::channel::oneshot::channel::<i32>();
+        let rx = Arc::new(Mutex::new(Some(rx)));
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        rt.set_async_function(vec!["test"], "waitForSignal", move |_q_ctx, _args| {
+            let rx = rx.lock().unwrap().take();
+            async move {
+                let rx = rx.ok_or_else(|| JsError::new_str("already waiting"))?;
+                let val = rx
+                    .await
+                    .map_err(|_| JsError::new_str("signal was dropped"))?;
+                Ok(val.to_es_value_facade())
+            }
+        })
+        .ok()
+        .expect("set_async_function failed");
+        rt.set_async_function(vec!["test"], "signal", move |_q_ctx, args| {
+            let tx = tx.lock().unwrap().take();
+            let val = args[0].get_i32();
+            async move {
+                let tx = tx.ok_or_else(|| JsError::new_str("already signalled"))?;
+                let _ = tx.send(val);
+                Ok(true.to_es_value_facade())
+            }
+        })
+        .ok()
+        .expect("set_async_function failed");
+
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_set_async_function_concurrent.es",
+                "Promise.all([test.waitForSignal(), test.signal(7)]).then((res) => res.join(':'));",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "7:true");
+    }
+
+    #[test]
+    fn test_install_object() {
+        struct Counter {
+            n: AtomicI32,
+        }
+        let rt = init_test_rt();
+        rt.install_object(
+            vec!["test", "services"],
+            "counter",
+            Counter {
+                n: AtomicI32::new(0),
+            },
+            |_counter, builder| {
+                builder
+                    .method("increment", |counter, _args| {
+                        Ok((counter.n.fetch_add(1, Ordering::SeqCst) + 1).to_es_value_facade())
+                    })
+                    .method("value", |counter, _args| {
+                        Ok(counter.n.load(Ordering::SeqCst).to_es_value_facade())
+                    });
+            },
+        )
+        .ok()
+        .expect("install_object failed");
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_install_object.es",
+                "test.services.counter.increment(); test.services.counter.increment();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 2);
+
+        rt.create_context("counter_ctx")
+            .ok()
+            .expect("could not create context");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("counter_ctx");
+            let res_ref = q_ctx
+                .eval(Script::new(
+                    "test_install_object2.es",
+                    "test.services.counter.increment(); test.services.counter.value();",
+                ))
+                .ok()
+                .expect("script failed");
+            primitives::to_i32(&res_ref).ok().expect("not an i32")
+        });
+        assert_eq!(res, 3);
+        rt.drop_context("counter_ctx");
+    }
+
+    #[test]
+    fn test_eval_with_diagnostics_sync() {
+        let rt = init_test_rt();
+        let res = rt.eval_with_diagnostics_sync(Script::new(
+            "test_diagnostics.es",
+            "globalThis.diag_objs = [];\n\
+             for (let x = 0; x < 1000; x++) {diag_objs.push({x});}\n\
+             Promise.resolve(1).then((v) => {globalThis.diag_then = v;});\n\
+             diag_objs.length;",
+        ));
+        assert_eq!(res.value.ok().expect("script failed").get_i32(), 1000);
+        assert!(res.duration_us > 0);
+        assert!(res.jobs_executed >= 1);
+        assert!(res.memory_delta_bytes > 0);
+
+        let res = rt.eval_with_diagnostics_sync(Script::new(
+            "test_diagnostics2.es",
+            "throw Error('diag failed');",
+        ));
+        assert_eq!(
+            res.value.err().expect("script did not fail").get_message(),
+            "diag failed"
+        );
+    }
+
+    #[test]
+    fn test_eval_sandboxed() {
+        let rt = init_test_rt();
+        let config = SandboxConfig {
+            memory_bytes: Some(10 * 1024 * 1024),
+            ..Default::default()
+        };
+        let res = rt.eval_sandboxed(
+            Script::new("test_sandbox1.es", "Array(1e9).fill(0);"),
+            config,
+        );
+        let err = res.err().expect("memory bomb did not fail");
+        assert!(err.get_message().contains("out of memory"));
+
+        let config = SandboxConfig {
+            timeout_ms: Some(50),
+            ..Default::default()
+        };
+        let res = rt.eval_sandboxed(Script::new("test_sandbox2.es", "while(true){}"), config);
+        let err = res.err().expect("endless loop did not fail");
+        assert_eq!(err.get_name(), "SandboxError");
+        assert_eq!(err.get_message(), "script exceeded the timeout of 50 ms");
+
+        let config = SandboxConfig {
+            max_objects: Some(10000),
+            ..Default::default()
+        };
+        let res = rt.eval_sandboxed(
+            Script::new(
+                "test_sandbox3.es",
+                "let objs = []; while(true) {objs.push({});}",
+            ),
+            config,
+        );
+        assert_eq!(res.err().expect("did not fail").get_name(), "SandboxError");
+
+        // promise jobs added by the script run within the limits
+        let config = SandboxConfig {
+            timeout_ms: Some(50),
+            ..Default::default()
+        };
+        let res = rt.eval_sandboxed(
+            Script::new(
+                "test_sandbox_jobs.es",
+                "Promise.resolve().then(() => {while(true){}}); 1;",
+            ),
+            config,
+        );
+        let err = res.err().expect("endless job did not fail");
+        assert_eq!(err.get_name(), "SandboxError");
+
+        // a smaller stack allows less recursion
+        let recursion_script = "(function() {let depth = 0; function recurse() {depth++; recurse();} try {recurse();} catch(e) {} return depth;})();";
+        let unlimited_depth = rt
+            .eval_sync(Script::new("test_sandbox_stack1.es", recursion_script))
+            .ok()
+            .expect("script failed")
+            .get_i32();
+        let config = SandboxConfig {
+            stack_bytes: Some(128 * 1024),
+            ..Default::default()
+        };
+        let limited_depth = rt
+            .eval_sandboxed(
+                Script::new("test_sandbox_stack2.es", recursion_script),
+                config,
+            )
+            .ok()
+            .expect("script failed")
+            .get_i32();
+        assert!(limited_depth > 0);
+        assert!(limited_depth < unlimited_depth);
+
+        // a nested sandbox restores the stack limit of the outer sandbox
+        let (inner_depth, outer_depth) = rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let eval_depth = |name: &str| {
+                q_js_rt
+                    .get_main_context()
+                    .eval(Script::new(name, recursion_script))
+                    .and_then(|depth_ref| primitives::to_i32(&depth_ref))
+            };
+            let outer_config = SandboxConfig {
+                stack_bytes: Some(128 * 1024),
+                ..Default::default()
+            };
+            let inner_config = SandboxConfig {
+                stack_bytes: Some(512 * 1024),
+                ..Default::default()
+            };
+            q_js_rt
+                .with_sandbox(&outer_config, || {
+                    let inner_depth = q_js_rt
+                        .with_sandbox(&inner_config, || eval_depth("test_sandbox_stack3.es"))?;
+                    let outer_depth = eval_depth("test_sandbox_stack4.es")?;
+                    Ok((inner_depth, outer_depth))
+                })
+                .ok()
+                .expect("nested sandbox failed")
+        });
+        assert!(outer_depth < inner_depth);
+        assert!(outer_depth < unlimited_depth);
+
+        // the limits are removed after the sandboxed eval
+        let res = rt
+            .eval_sync(Script::new(
+                "test_sandbox4.es",
+                "let big = new Array(3 * 1024 * 1024).fill(0); big.length;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_eval_batch_sync() {
+        let rt = init_test_rt();
+        let err = rt
+            .eval_batch_sync(vec![
+                Script::new("test_batch1.es", "globalThis.batch_a = 1;"),
+                Script::new("test_batch2.es", "throw Error('batch failed');"),
+                Script::new("test_batch3.es", "globalThis.batch_c = 3;"),
+            ])
+            .err()
+            .expect("batch did not fail");
+        assert_eq!(
+            err.get_message(),
+            "script 1 (test_batch2.es) failed: batch failed"
+        );
+        let res = rt
+            .eval_sync(Script::new(
+                "test_batch4.es",
+                "typeof globalThis.batch_a + ':' + typeof globalThis.batch_c;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "number:undefined");
+    }
+
+    #[test]
+    fn test_max_jobs_per_tick() {
+        let rt = EsRuntimeBuilder::new().max_jobs_per_tick(5).build();
+        let first_tick = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            q_ctx
+                .eval(Script::new(
+                    "test_max_jobs_per_tick.es",
+                    "this.jobs_run = 0; for (let i = 0; i < 100; i++) {Promise.resolve().then(() => {jobs_run++;});}",
+                ))
+                .ok()
+                .expect("script failed");
+            let ran = q_js_rt.run_pending_jobs_limit(q_js_rt.max_jobs_per_tick);
+            let jobs_run = q_ctx
+                .eval(Script::new("test_max_jobs_per_tick2.es", "jobs_run;"))
+                .ok()
+                .expect("script failed");
+            (ran, primitives::to_i32(&jobs_run).ok().unwrap())
+        });
+        assert_eq!(first_tick, (5, 5));
+
+        // the remaining jobs are run in later ticks
+        for _ in 0..100 {
+            let jobs_run = rt
+                .eval_sync(Script::new("test_max_jobs_per_tick3.es", "jobs_run;"))
+                .ok()
+                .expect("script failed");
+            if jobs_run.get_i32() == 100 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("not all jobs were run");
+    }
+
+    #[test]
+    fn test_max_jobs_per_tick_event_loop() {
+        let rt = EsRuntimeBuilder::new().max_jobs_per_tick(5).build();
+        // the first job adds a task to the event queue, that task runs after the first tick of 5 jobs and before the
+        // tick which runs the next jobs
+        rt.eval_sync(Script::new(
+            "test_max_jobs_per_tick_event_loop.es",
+            "this.jobs_run = 0; this.seen_jobs_run = -1;\n\
+             Promise.resolve().then(() => {setImmediate(() => {seen_jobs_run = jobs_run;});});\n\
+             for (let i = 0; i < 100; i++) {Promise.resolve().then(() => {jobs_run++;});}",
+        ))
+        .ok()
+        .expect("script failed");
+        for _ in 0..100 {
+            let seen = rt
+                .eval_sync(Script::new(
+                    "test_max_jobs_per_tick_event_loop2.es",
+                    "seen_jobs_run;",
+                ))
+                .ok()
+                .expect("script failed");
+            if seen.get_i32() != -1 {
+                assert_eq!(seen.get_i32(), 4);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("setImmediate task did not run");
+    }
+
+    #[test]
+    fn test_eval_sync_with_options() {
+        let rt = init_test_rt();
+
+        let module_options = EvalOptions {
+            eval_type: EvalType::Module,
+            ..Default::default()
+        };
+        let global_options = EvalOptions::default();
+
+        let export_code = "export const test_eval_options = 1;";
+        assert!(rt
+            .eval_sync_with_options(
+                Script::new("test_eval_options.mes", export_code),
+                module_options.clone()
+            )
+            .is_ok());
+        assert!(rt
+            .eval_sync_with_options(
+                Script::new("test_eval_options.es", export_code),
+                global_options.clone()
+            )
+            .is_err());
+
+        // await is not allowed at the top level of a global script
+        assert!(rt
+            .eval_sync_with_options(
+                Script::new("test_eval_options_await.es", "await Promise.resolve(1);"),
+                global_options.clone()
+            )
+            .is_err());
+
+        let sloppy = rt.eval_sync_with_options(
+            Script::new("test_eval_options_sloppy.es", "test_eval_sloppy = 1;"),
+            global_options,
+        );
+        assert!(sloppy.is_ok());
+        let strict = rt.eval_sync_with_options(
+            Script::new("test_eval_options_strict.es", "test_eval_strict = 1;"),
+            EvalOptions {
+                strict: true,
+                ..Default::default()
+            },
+        );
+        assert!(strict.is_err());
+
+        let compiled = rt
+            .eval_sync_with_options(
+                Script::new(
+                    "test_eval_options_compile.es",
+                    "this.test_eval_compiled = 1;",
+                ),
+                EvalOptions {
+                    compile_only: true,
+                    ..Default::default()
+                },
+            )
+            .ok()
+            .expect("compile failed");
+        assert!(compiled.is_undefined());
+        let not_run = rt
+            .eval_sync(Script::new(
+                "test_eval_options_compile2.es",
+                "typeof test_eval_compiled;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(not_run.get_str(), "undefined");
+    }
+
+    #[test]
+    fn test_get_global_this_sync() {
+        let rt = init_test_rt();
+        let global = rt
+            .get_global_this_sync()
+            .ok()
+            .expect("could not get globalThis");
+        assert!(global.is_object());
+        global
+            .set_property_sync("myProp", "from rust".to_string().to_es_value_facade())
+            .ok()
+            .expect("could not set property");
+        let res = rt
+            .eval_sync(Script::new("test_get_global_this_sync.es", "myProp;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "from rust");
+
+        rt.eval_sync(Script::new(
+            "test_get_global_this_sync2.es",
+            "globalThis.fromJs = 123;",
+        ))
+        .ok()
+        .expect("script failed");
+        let res = global
+            .get_property_sync("fromJs")
+            .ok()
+            .expect("could not get property");
+        assert_eq!(res.get_i32(), 123);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_dispatch_sync() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new(
+            "test_dispatch_sync.es",
+            "this.math = {base: 100, add: function(a, b) {return a + b;}, rebase: function(o) {return {sum: this.base + o.x};}};",
+        ))
+        .ok()
+        .expect("script failed");
+        let request = serde_json::json!({"method": "math.add", "params": [3, 4]});
+        let res = rt
+            .dispatch_sync(
+                request["method"].as_str().unwrap(),
+                request["params"].clone(),
+            )
+            .ok()
+            .expect("dispatch failed");
+        assert_eq!(res, serde_json::json!(7));
+
+        let res = rt
+            .dispatch_sync("math.rebase", serde_json::json!({"x": 5}))
+            .ok()
+            .expect("dispatch failed");
+        assert_eq!(res, serde_json::json!({"sum": 105}));
+
+        assert!(rt
+            .dispatch_sync("math.subtract", serde_json::json!([1, 2]))
+            .is_err());
+        assert!(rt
+            .dispatch_sync("nomath.add", serde_json::json!([1, 2]))
+            .is_err());
+    }
 }
@@ -1 +1,2 @@
+pub mod priority_tasks;
 pub mod promises;
@@ -0,0 +1,133 @@
+//! a thread pool which runs high priority tasks before low priority tasks
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Default)]
+struct Queues {
+    high: VecDeque<Task>,
+    low: VecDeque<Task>,
+    shutdown: bool,
+}
+
+/// a thread pool with two queues, worker threads always take tasks from the high priority queue first
+pub struct PriorityTaskManager {
+    queues: Arc<(Mutex<Queues>, Condvar)>,
+}
+
+impl PriorityTaskManager {
+    pub fn new(thread_count: usize) -> Self {
+        assert!(thread_count > 0);
+        let queues = Arc::new((Mutex::new(Queues::default()), Condvar::new()));
+        for x in 0..thread_count {
+            let queues = queues.clone();
+            std::thread::Builder::new()
+                .name(format!("priority_task_thread_{}", x))
+                .spawn(move || Self::work(&*queues))
+                .expect("could not spawn priority task thread");
+        }
+        Self { queues }
+    }
+
+    fn work(queues: &(Mutex<Queues>, Condvar)) {
+        let (mtx, cvar) = queues;
+        loop {
+            let task = {
+                let mut lck = mtx.lock().unwrap();
+                loop {
+                    if let Some(task) = lck.high.pop_front() {
+                        break task;
+                    }
+                    if let Some(task) = lck.low.pop_front() {
+                        break task;
+                    }
+                    if lck.shutdown {
+                        return;
+                    }
+                    lck = cvar.wait(lck).unwrap();
+                }
+            };
+            task();
+        }
+    }
+
+    fn add(&self, task: Task, high_priority: bool) {
+        let (mtx, cvar) = &*self.queues;
+        let lck = &mut *mtx.lock().unwrap();
+        if high_priority {
+            lck.high.push_back(task);
+        } else {
+            lck.low.push_back(task);
+        }
+        cvar.notify_one();
+    }
+
+    /// add a task which will be run before all low priority tasks
+    pub fn add_high_priority_task<T>(&self, task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        self.add(Box::new(task), true);
+    }
+
+    /// add a task which will only be run when there are no high priority tasks
+    pub fn add_low_priority_task<T>(&self, task: T)
+    where
+        T: FnOnce() + Send + 'static,
+    {
+        self.add(Box::new(task), false);
+    }
+}
+
+impl Drop for PriorityTaskManager {
+    fn drop(&mut self) {
+        // worker threads finish the remaining tasks and then stop
+        let (mtx, cvar) = &*self.queues;
+        mtx.lock().unwrap().shutdown = true;
+        cvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime_utils::priority_tasks::PriorityTaskManager;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_priority() {
+        let tm = PriorityTaskManager::new(1);
+        let order = Arc::new(Mutex::new(vec![]));
+
+        // block the only worker so the backlog builds up
+        let (gate_tx, gate_rx) = channel::<()>();
+        tm.add_low_priority_task(move || {
+            gate_rx.recv().ok();
+        });
+        for x in 0..20 {
+            let order = order.clone();
+            tm.add_low_priority_task(move || {
+                order.lock().unwrap().push(format!("low{}", x));
+            });
+        }
+        let (done_tx, done_rx) = channel::<()>();
+        let order2 = order.clone();
+        tm.add_high_priority_task(move || {
+            order2.lock().unwrap().push("high".to_string());
+        });
+        tm.add_low_priority_task(move || {
+            done_tx.send(()).ok();
+        });
+        gate_tx.send(()).ok();
+        done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("tasks did not complete");
+
+        let order = &*order.lock().unwrap();
+        assert_eq!(order.len(), 21);
+        assert_eq!(order[0], "high");
+    }
+}
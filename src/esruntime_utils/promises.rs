@@ -1,12 +1,13 @@
 use crate::esruntime::EsRuntime;
-use crate::quickjs_utils::primitives;
 use crate::quickjs_utils::promises::new_promise_q;
 use crate::quickjs_utils::promises::PromiseRef;
+use crate::quickjs_utils::{errors, primitives};
 use crate::quickjscontext::QuickJsContext;
 use crate::valueref::JSValueRef;
 use hirofa_utils::auto_id_map::AutoIdMap;
 use hirofa_utils::js_utils::JsError;
 use std::cell::RefCell;
+use std::future::Future;
 use std::sync::Arc;
 thread_local! {
     static RESOLVING_PROMISES: RefCell<AutoIdMap<PromiseRef>> = RefCell::new(AutoIdMap::new());
@@ -82,7 +83,8 @@ where
 
     let ctx_id = q_ctx.id.clone();
     // go async
-    EsRuntime::add_helper_task(move || {
+    let helper_rt = es_rt.clone();
+    helper_rt.add_runtime_helper_task(move || {
         // in helper thread, produce result
         let produced_result = producer();
         es_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
@@ -136,6 +138,67 @@ where
     Ok(return_ref)
 }
 
+/// create a new promise which is resolved with the output of a Future
+/// the Future is spawned in the helper thread pool of the runtime, so it does not hold a helper thread while it is
+/// waiting, its output is mapped to a JSValueRef by the mapper in the EventQueue
+/// when the Future or the mapper fails the promise is rejected with an Error which has the name, message and stack of
+/// the JsError
+pub fn new_resolving_promise_async<P, R, M>(
+    q_ctx: &QuickJsContext,
+    producer: P,
+    mapper: M,
+    es_rt: Arc<EsRuntime>,
+) -> Result<JSValueRef, JsError>
+where
+    R: Send + 'static,
+    P: Future<Output = Result<R, JsError>> + Send + 'static,
+    M: FnOnce(&QuickJsContext, R) -> Result<JSValueRef, JsError> + Send + 'static,
+{
+    // create promise
+    let promise_ref = new_promise_q(q_ctx)?;
+    let return_ref = promise_ref.get_promise_obj_ref();
+
+    // add to map and keep id
+    let id = RESOLVING_PROMISES.with(|map_rc| {
+        let map = &mut *map_rc.borrow_mut();
+        map.insert(promise_ref)
+    });
+
+    let ctx_id = q_ctx.id.clone();
+    // go async
+    let helper_rt = es_rt.clone();
+    let _ = helper_rt.add_runtime_helper_task_async(async move {
+        // in helper thread, produce result
+        let produced_result = producer.await;
+        es_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_context(ctx_id.as_str());
+            // in q_js_rt worker thread, resolve promise
+            let prom_ref = RESOLVING_PROMISES.with(|map_rc| {
+                let map = &mut *map_rc.borrow_mut();
+                map.remove(&id)
+            });
+
+            let settle_res = match produced_result.and_then(|res| mapper(q_ctx, res)) {
+                Ok(val_ref) => prom_ref.resolve_q(q_ctx, val_ref),
+                Err(err) => unsafe {
+                    errors::new_error(
+                        q_ctx.context,
+                        err.get_name(),
+                        err.get_message(),
+                        err.get_stack(),
+                    )
+                }
+                .and_then(|err_ref| prom_ref.reject_q(q_ctx, err_ref)),
+            };
+            if let Err(e) = settle_res {
+                log::error!("could not settle promise: {}", e);
+            }
+        });
+    });
+
+    Ok(return_ref)
+}
+
 #[cfg(test)]
 
 pub mod tests {
@@ -1,15 +1,50 @@
-use crate::esruntime::{EsRuntime, FetchResponseProvider};
+use crate::esruntime::{
+    EsRuntime, ExceptionFilter, ExceptionHandler, FetchResponseProvider, TaskCompleteCallback,
+};
+use crate::esvalue::EsValueFacade;
+use crate::features::console::{ConsoleLevel, StructuredConsoleHandler};
+use crate::features::fetch::cache::FetchCache;
 use crate::features::fetch::request::FetchRequest;
 use crate::features::fetch::response::FetchResponse;
-use crate::quickjsruntime::{NativeModuleLoader, QuickJsRuntime, ScriptModuleLoader};
+use crate::features::storage::StorageBackend;
+use crate::quickjsruntime::{
+    CompiledModuleLoader, ModuleCache, ModuleSpecifierNormalizer, NativeModuleLoader,
+    QuickJsRuntime, ScriptModuleLoader, TypeScriptTranspiler,
+};
+use crate::reflection::ClassDefinition;
 use hirofa_utils::js_utils::JsError;
+use hirofa_utils::js_utils::Script;
 use hirofa_utils::js_utils::ScriptPreProcessor;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 pub type EsRuntimeInitHooks =
     Vec<Box<dyn FnOnce(&EsRuntime) -> Result<(), JsError> + Send + 'static>>;
 
+/// the strategy used to trigger the garbage collector
+pub enum GcStrategy {
+    /// run a full GC every interval
+    Interval(Duration),
+    /// run the GC when the number of allocated bytes exceeds the threshold
+    Threshold(usize),
+    /// only run the GC when requested by calling gc() or gc_sync(), this sets the GC threshold of QuickJS to its
+    /// maximum so QuickJS never triggers the GC on its own while allocating
+    Manual,
+}
+
+struct FnScriptPreProcessor {
+    preprocessor: Box<dyn Fn(Script) -> Script + Send + Sync>,
+}
+
+impl ScriptPreProcessor for FnScriptPreProcessor {
+    fn process(&self, script: &mut Script) -> Result<(), JsError> {
+        let copy = Script::new(script.get_path(), script.get_code());
+        *script = (self.preprocessor)(copy);
+        Ok(())
+    }
+}
+
 /// the EsRuntimeBuilder is used to init an EsRuntime
 /// # Example
 /// ```rust
@@ -21,8 +56,25 @@ pub type EsRuntimeInitHooks =
 /// ```
 pub struct EsRuntimeBuilder {
     pub(crate) script_module_loaders: Vec<Box<dyn ScriptModuleLoader + Send>>,
+    pub(crate) compiled_module_loaders: Vec<Box<dyn CompiledModuleLoader + Send>>,
     pub(crate) native_module_loaders: Vec<Box<dyn NativeModuleLoader + Send>>,
+    pub(crate) module_cache: Option<Box<dyn ModuleCache + Send + Sync>>,
+    pub(crate) structured_console_handler: Option<Box<StructuredConsoleHandler>>,
+    pub(crate) exception_handler: Option<Box<ExceptionHandler>>,
+    pub(crate) exception_filter: Option<Box<ExceptionFilter>>,
     pub(crate) opt_fetch_response_provider: Option<Box<FetchResponseProvider>>,
+    pub(crate) opt_fetch_cache: Option<Box<dyn FetchCache + Send + Sync>>,
+    pub(crate) opt_local_storage_backend: Option<Box<dyn StorageBackend + Send + Sync>>,
+    pub(crate) opt_task_complete_callback: Option<Box<TaskCompleteCallback>>,
+    pub(crate) opt_helper_task_threads: Option<usize>,
+    pub(crate) opt_max_jobs_per_tick: Option<usize>,
+    pub(crate) default_strict_mode: bool,
+    pub(crate) opt_max_eval_depth: Option<usize>,
+    pub(crate) opt_module_base_path: Option<PathBuf>,
+    pub(crate) module_specifier_normalizer: Option<Box<ModuleSpecifierNormalizer>>,
+    pub(crate) typescript_transpiler: Option<Box<TypeScriptTranspiler>>,
+    pub(crate) disabled_builtins: Vec<String>,
+    pub(crate) std_polyfills: bool,
     pub(crate) opt_memory_limit_bytes: Option<u64>,
     pub(crate) opt_gc_threshold: Option<u64>,
     pub(crate) opt_max_stack_size: Option<u64>,
@@ -42,8 +94,25 @@ impl EsRuntimeBuilder {
     pub fn new() -> Self {
         Self {
             script_module_loaders: vec![],
+            compiled_module_loaders: vec![],
             native_module_loaders: vec![],
+            module_cache: None,
+            structured_console_handler: None,
+            exception_handler: None,
+            exception_filter: None,
             opt_fetch_response_provider: None,
+            opt_fetch_cache: None,
+            opt_local_storage_backend: None,
+            opt_task_complete_callback: None,
+            opt_helper_task_threads: None,
+            opt_max_jobs_per_tick: None,
+            default_strict_mode: false,
+            opt_max_eval_depth: None,
+            opt_module_base_path: None,
+            module_specifier_normalizer: None,
+            typescript_transpiler: None,
+            disabled_builtins: vec![],
+            std_polyfills: false,
             opt_memory_limit_bytes: None,
             opt_gc_threshold: None,
             opt_max_stack_size: None,
@@ -85,7 +154,183 @@ impl EsRuntimeBuilder {
         self
     }
 
+    /// add a CompiledModuleLoader, compiled module loaders provide modules as bytecode and are tried before script module loaders
+    pub fn compiled_module_loader<M: CompiledModuleLoader + Send + 'static>(
+        mut self,
+        loader: Box<M>,
+    ) -> Self {
+        self.compiled_module_loaders.push(loader);
+        self
+    }
+
+    /// set a ModuleCache, the bytecode of script modules is stored in the cache after they are compiled and modules
+    /// which are in the cache are not loaded again (e.g. when imported in a new context)
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::quickjsruntime::MemoryModuleCache;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .module_cache(Box::new(MemoryModuleCache::new()))
+    ///     .build();
+    /// ```
+    pub fn module_cache(mut self, cache: Box<dyn ModuleCache + Send + Sync>) -> Self {
+        self.module_cache = Some(cache);
+        self
+    }
+
+    /// set the number of threads in the helper thread pool of this runtime, see [EsRuntime::add_runtime_helper_task]
+    /// when this is not set the static helper thread pool (with at least 2 threads) is used
+    /// the runtime runs its own helper tasks (e.g. the resolvers of EsPromises and of native promises) in this pool too
+    pub fn helper_task_threads(mut self, thread_count: usize) -> Self {
+        assert!(thread_count > 0);
+        self.opt_helper_task_threads = Some(thread_count);
+        self
+    }
+
+    /// register a Proxy class which is installed in every context of the runtime, including contexts which are created later
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::reflection::{ClassDefinition, Proxy};
+    /// use quickjs_runtime::quickjs_utils::primitives;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .register_class(vec!["com", "my"], ClassDefinition::new(|| {
+    ///         Proxy::new()
+    ///             .name("Greeter")
+    ///             .constructor(|_q_ctx, _id, _args| Ok(()))
+    ///             .method("greet", |q_ctx, _id, _args| primitives::from_string_q(q_ctx, "hello"))
+    ///     }))
+    ///     .build();
+    /// let res = rt.eval_sync(Script::new("greet.es", "new com.my.Greeter().greet();")).ok().expect("script failed");
+    /// assert_eq!(res.get_str(), "hello");
+    /// ```
+    pub fn register_class(self, namespace: Vec<&'static str>, definition: ClassDefinition) -> Self {
+        self.runtime_init_hook(move |rt| {
+            rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+                q_js_rt.add_context_init_hook(move |_q_js_rt, q_ctx| {
+                    definition
+                        .create_proxy()
+                        .namespace(namespace.clone())
+                        .install(q_ctx, true)
+                        .map(|_| ())
+                })
+            })
+        })
+    }
+
+    /// set the maximum number of pending jobs (e.g. promise reactions) which are run after a task in the event queue
+    /// when more jobs are pending they are run in a later tick so other tasks are not starved, defaults to 1024
+    pub fn max_jobs_per_tick(mut self, max_jobs: usize) -> Self {
+        assert!(max_jobs > 0);
+        self.opt_max_jobs_per_tick = Some(max_jobs);
+        self
+    }
+
+    /// evaluate all scripts in strict mode, including those evaluated with [EsRuntime::eval_sync] and
+    /// [QuickJsContext::eval](crate::quickjscontext::QuickJsContext::eval), defaults to false
+    pub fn default_strict_mode(mut self, strict: bool) -> Self {
+        self.default_strict_mode = strict;
+        self
+    }
+
+    /// set the maximum nesting depth of evals, e.g. a native function which is called from script and evaluates
+    /// another script is a nested eval
+    /// when the depth is exceeded the eval fails with a RangeError instead of overflowing the stack
+    pub fn max_eval_depth(mut self, max_depth: usize) -> Self {
+        assert!(max_depth > 0);
+        self.opt_max_eval_depth = Some(max_depth);
+        self
+    }
+
+    /// set the base path used to resolve relative module imports (e.g. `import {a} from './a.mes';`) of scripts
+    /// which do not have an absolute path themselves, the joined path is passed to the module loaders
+    pub fn module_base_path(mut self, path: PathBuf) -> Self {
+        self.opt_module_base_path = Some(path);
+        self
+    }
+
+    /// set a function which normalizes module specifiers before they are passed to the module loaders
+    /// the function is called with the name of the importing module and the specifier as used in the import statement,
+    /// the returned String is used as the name of the module, modules with the same name are only loaded once
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .module_specifier_normalizer(Box::new(|_ref_name, specifier| {
+    ///         specifier.trim_start_matches("./").to_string()
+    ///     }))
+    ///     .build();
+    /// ```
+    pub fn module_specifier_normalizer(
+        mut self,
+        normalizer: Box<ModuleSpecifierNormalizer>,
+    ) -> Self {
+        self.module_specifier_normalizer = Some(normalizer);
+        self
+    }
+
+    /// set a transpiler which converts TypeScript to JavaScript, it is called for all scripts and modules with a path
+    /// ending in `.ts` or `.tsx` before they are passed to QuickJS (and before the script pre-processors are run)
+    /// no transpiler is built in, you may use a crate like swc or oxc to implement one
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .typescript_transpiler(Box::new(|code| Ok(code.replace(": number", ""))))
+    ///     .build();
+    /// let res = rt.eval_sync(Script::new("typed.ts", "let a: number = 1; a;")).ok().expect("script failed");
+    /// assert_eq!(res.get_i32(), 1);
+    /// ```
+    pub fn typescript_transpiler(mut self, transpiler: Box<TypeScriptTranspiler>) -> Self {
+        self.typescript_transpiler = Some(transpiler);
+        self
+    }
+
+    /// remove a built-in (e.g. `eval`, `Function` or `Proxy`) from the global scope of all contexts
+    /// building the runtime panics when a built-in can not be removed (e.g. `NaN` which is not configurable)
+    /// please note that this is not a security boundary, a removed built-in may still be reachable in other ways
+    /// (e.g. the Function constructor through `(function(){}).constructor`)
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().disable_builtin("eval").build();
+    /// let res = rt.eval_sync(Script::new("no_eval.es", "typeof eval;")).ok().unwrap();
+    /// assert_eq!(res.get_str(), "undefined");
+    /// ```
+    pub fn disable_builtin(mut self, name: &str) -> Self {
+        self.disabled_builtins.push(name.to_string());
+        self
+    }
+
+    /// install polyfills for modern globals which QuickJS lacks (structuredClone, queueMicrotask and
+    /// performance.now), see [features::polyfills](crate::features::polyfills)
+    pub fn install_std_polyfills(mut self) -> Self {
+        self.std_polyfills = true;
+        self
+    }
+
+    /// set a callback which is called with timing info every time a task in the event queue completes
+    /// the callback is called from the event queue thread so it should return quickly
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .on_task_complete(Box::new(|info| {
+    ///         println!("{:?} task took {:?}", info.task_type, info.end_time - info.start_time);
+    ///     }))
+    ///     .build();
+    /// ```
+    pub fn on_task_complete(mut self, callback: Box<TaskCompleteCallback>) -> Self {
+        self.opt_task_complete_callback = Some(callback);
+        self
+    }
+
     /// add a ScriptPreProcessor which will be called for all scripts which are evaluated and compiled
+    /// it is also called for scripts which are only validated (see [EsRuntime::validate_script]) so it should not have
+    /// side effects
     pub fn script_pre_processor<S: ScriptPreProcessor + Send + 'static>(
         mut self,
         processor: S,
@@ -94,6 +339,31 @@ impl EsRuntimeBuilder {
         self
     }
 
+    /// add a function which transforms all scripts before they are evaluated or compiled, functions and
+    /// ScriptPreProcessors are applied in the order in which they were added
+    /// like other ScriptPreProcessors the function is also called for scripts which are only validated so it should
+    /// not have side effects
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .add_script_preprocessor(Box::new(|script: Script| {
+    ///         Script::new(script.get_path(), script.get_code().replace("__DEBUG__", "false").as_str())
+    ///     }))
+    ///     .build();
+    /// let res = rt.eval_sync(Script::new("pp.es", "__DEBUG__;")).ok().expect("script failed");
+    /// assert!(!res.get_boolean());
+    /// ```
+    pub fn add_script_preprocessor(
+        mut self,
+        preprocessor: Box<dyn Fn(Script) -> Script + Send + Sync>,
+    ) -> Self {
+        self.script_pre_processors
+            .push(Box::new(FnScriptPreProcessor { preprocessor }));
+        self
+    }
+
     pub fn runtime_init_hook<H>(mut self, hook: H) -> Self
     where
         H: FnOnce(&EsRuntime) -> Result<(), JsError> + Send + 'static,
@@ -216,6 +486,93 @@ impl EsRuntimeBuilder {
         self
     }
 
+    /// set a cache for the responses of the fetch api, when a cached response is found the FetchResponseProvider is not called
+    /// please note that when a cache is set responses are read completely before being passed to script
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::features::fetch::cache::MemoryFetchCache;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .fetch_cache(Box::new(MemoryFetchCache::new()))
+    ///     .build();
+    /// ```
+    pub fn fetch_cache(mut self, cache: Box<dyn FetchCache + Send + Sync>) -> Self {
+        self.opt_fetch_cache = Some(cache);
+        self
+    }
+
+    /// set a handler which receives the arguments of console calls as EsValueFacades instead of a formatted string
+    /// when this is set console messages are no longer logged by the log crate
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .structured_console_handler(Box::new(|level, values| {
+    ///         println!("{:?}: {} values", level, values.len());
+    ///     }))
+    ///     .build();
+    /// ```
+    pub fn structured_console_handler(
+        mut self,
+        handler: Box<dyn Fn(ConsoleLevel, Vec<EsValueFacade>) + Send + Sync>,
+    ) -> Self {
+        self.structured_console_handler = Some(handler);
+        self
+    }
+
+    /// set a handler which is called for every error returned by the eval and call methods of the EsRuntime, e.g. when
+    /// a script which is evaluated or a function which is called throws, the handler is called before the
+    /// [exception filter](EsRuntimeBuilder::exception_filter) is applied
+    /// exceptions which are caught in script or by the runtime itself are not passed to the handler
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .on_exception(Box::new(|err| {
+    ///         println!("script failed: {}", err.get_message());
+    ///     }))
+    ///     .build();
+    /// ```
+    pub fn on_exception(mut self, handler: Box<dyn Fn(&JsError) + Send + Sync>) -> Self {
+        self.exception_handler = Some(handler);
+        self
+    }
+
+    /// set a filter which intercepts every error returned by the eval and call methods of the EsRuntime before it
+    /// reaches the caller
+    /// when the filter returns Ok(err) the (possibly transformed) error is returned to the caller, when it returns
+    /// Err(value) the value is returned to the caller as a successful result instead
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::{JsError, Script};
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .exception_filter(Box::new(|err| {
+    ///         Ok(JsError::new("AppError".to_string(), err.get_message().to_string(), "".to_string()))
+    ///     }))
+    ///     .build();
+    /// let err = rt.eval_sync(Script::new("filter.es", "throw Error('oops');")).err().unwrap();
+    /// assert_eq!(err.get_name(), "AppError");
+    /// ```
+    pub fn exception_filter(mut self, filter: Box<ExceptionFilter>) -> Self {
+        self.exception_filter = Some(filter);
+        self
+    }
+
+    /// set the backend for localStorage, when this is not set a MemoryStorageBackend is used
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::features::storage::MemoryStorageBackend;
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .local_storage_backend(Box::new(MemoryStorageBackend::new()))
+    ///     .build();
+    /// ```
+    pub fn local_storage_backend(mut self, backend: Box<dyn StorageBackend + Send + Sync>) -> Self {
+        self.opt_local_storage_backend = Some(backend);
+        self
+    }
+
     /// set max memory the runtime may use
     pub fn memory_limit(mut self, bytes: u64) -> Self {
         self.opt_memory_limit_bytes = Some(bytes);
@@ -240,6 +597,31 @@ impl EsRuntimeBuilder {
         self
     }
 
+    /// set the strategy which is used to trigger the garbage collector
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::{EsRuntimeBuilder, GcStrategy};
+    /// use std::time::Duration;
+    /// let rt = EsRuntimeBuilder::new()
+    /// .gc_strategy(GcStrategy::Interval(Duration::from_secs(10)))
+    /// .build();
+    /// ```
+    pub fn gc_strategy(mut self, strategy: GcStrategy) -> Self {
+        match strategy {
+            GcStrategy::Interval(interval) => {
+                self.opt_gc_interval = Some(interval);
+            }
+            GcStrategy::Threshold(size) => {
+                self.opt_gc_threshold = Some(size as u64);
+            }
+            GcStrategy::Manual => {
+                self.opt_gc_interval = None;
+                self.opt_gc_threshold = Some(usize::MAX as u64);
+            }
+        }
+        self
+    }
+
     /// add an interrupt handler, this will be called several times during script execution and may be used to cancel a running script
     pub fn set_interrupt_handler<I: Fn(&QuickJsRuntime) -> bool + Send + 'static>(
         mut self,
@@ -259,8 +641,14 @@ impl Default for EsRuntimeBuilder {
 #[cfg(test)]
 pub mod tests {
     use crate::esruntimebuilder::EsRuntimeBuilder;
-    use crate::quickjsruntime::ScriptModuleLoader;
-    use hirofa_utils::js_utils::Script;
+    use crate::esvalue::EsValueConvertible;
+    use crate::quickjs_utils::primitives;
+    use crate::quickjsruntime::{SandboxConfig, ScriptModuleLoader};
+    use crate::reflection::{ClassDefinition, Proxy};
+    use hirofa_utils::js_utils::facades::JsRuntimeFacade;
+    use hirofa_utils::js_utils::{JsError, Script};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_module_loader() {
@@ -286,4 +674,318 @@ pub mod tests {
             Err(e) => panic!("script failed {}", e),
         }
     }
+
+    #[test]
+    fn test_module_base_path() {
+        struct RecordingModuleLoader {
+            paths: Arc<Mutex<Vec<String>>>,
+        }
+        impl ScriptModuleLoader for RecordingModuleLoader {
+            fn normalize_path(&self, _ref_path: &str, path: &str) -> Option<String> {
+                self.paths.lock().unwrap().push(path.to_string());
+                Some(path.to_string())
+            }
+
+            fn load_module(&self, _absolute_path: &str) -> String {
+                "export const foo = 12;".to_string()
+            }
+        }
+
+        let paths = Arc::new(Mutex::new(vec![]));
+        let rt = EsRuntimeBuilder::new()
+            .module_base_path(PathBuf::from("/app/modules"))
+            .script_module_loader(Box::new(RecordingModuleLoader {
+                paths: paths.clone(),
+            }))
+            .build();
+        match rt.eval_module_sync(Script::new(
+            "test_module_base_path.es",
+            "import {foo} from './lib/foo.mes';\nimport {foo as bar} from '../shared/../modules/./lib/bar.mes';\nif (foo !== 12 || bar !== 12) {throw Error('foo was ' + foo);}",
+        )) {
+            Ok(_) => {}
+            Err(e) => panic!("script failed {}", e),
+        }
+        let paths = &*paths.lock().unwrap();
+        for path in &["lib/foo.mes", "lib/bar.mes"] {
+            let expected = PathBuf::from("/app/modules")
+                .join(path)
+                .to_string_lossy()
+                .to_string();
+            assert!(paths.contains(&expected), "{} was not loaded", expected);
+        }
+        assert!(!paths.iter().any(|path| path.contains("..")));
+    }
+
+    #[test]
+    fn test_module_specifier_normalizer() {
+        struct CountingModuleLoader {
+            loads: Arc<Mutex<Vec<String>>>,
+        }
+        impl ScriptModuleLoader for CountingModuleLoader {
+            fn normalize_path(&self, _ref_path: &str, path: &str) -> Option<String> {
+                Some(path.to_string())
+            }
+
+            fn load_module(&self, absolute_path: &str) -> String {
+                self.loads.lock().unwrap().push(absolute_path.to_string());
+                "export const foo = {};".to_string()
+            }
+        }
+
+        let loads = Arc::new(Mutex::new(vec![]));
+        let rt = EsRuntimeBuilder::new()
+            .module_specifier_normalizer(Box::new(|_ref_name, specifier| {
+                specifier.trim_start_matches("./").to_string()
+            }))
+            .script_module_loader(Box::new(CountingModuleLoader {
+                loads: loads.clone(),
+            }))
+            .build();
+        match rt.eval_module_sync(Script::new(
+            "test_module_specifier_normalizer.es",
+            "import {foo as a} from './foo.mes';\n\
+             import {foo as b} from 'foo.mes';\n\
+             if (a !== b) {throw Error('modules differ');}",
+        )) {
+            Ok(_) => {}
+            Err(e) => panic!("script failed {}", e),
+        }
+        assert_eq!(*loads.lock().unwrap(), vec!["foo.mes".to_string()]);
+    }
+
+    #[test]
+    fn test_disable_builtin() {
+        let rt = EsRuntimeBuilder::new().disable_builtin("Function").build();
+        let res = rt.eval_sync(Script::new(
+            "test_disable_builtin.es",
+            "new Function('return 1');",
+        ));
+        match res {
+            Ok(_) => panic!("Function should not be defined"),
+            Err(e) => {
+                assert_eq!(e.get_name(), "ReferenceError");
+                assert!(e.get_message().contains("Function"));
+                assert!(e.get_message().ends_with("is not defined"));
+            }
+        }
+        let res = rt
+            .eval_sync(Script::new(
+                "test_disable_builtin2.es",
+                "(function() {return 1;})();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 1);
+
+        // built-ins which are not configurable can not be removed
+        let res =
+            std::panic::catch_unwind(|| EsRuntimeBuilder::new().disable_builtin("NaN").build());
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_register_class() {
+        let rt = EsRuntimeBuilder::new()
+            .register_class(
+                vec!["com", "test"],
+                ClassDefinition::new(|| {
+                    Proxy::new()
+                        .name("Greeter")
+                        .constructor(|_q_ctx, _id, _args| Ok(()))
+                        .method("greet", |q_ctx, _id, _args| {
+                            primitives::from_string_q(q_ctx, "hello")
+                        })
+                }),
+            )
+            .build();
+
+        rt.create_context("test_register_class_ctx")
+            .ok()
+            .expect("could not create context");
+
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("test_register_class_ctx");
+            let res_ref = q_ctx
+                .eval(Script::new(
+                    "test_register_class.es",
+                    "new com.test.Greeter().greet();",
+                ))
+                .ok()
+                .expect("script failed");
+            primitives::to_string_q(q_ctx, &res_ref)
+                .ok()
+                .expect("not a string")
+        });
+        assert_eq!(res, "hello");
+
+        let main_res = rt
+            .eval_sync(Script::new(
+                "test_register_class.es",
+                "new com.test.Greeter().greet();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(main_res.get_str(), "hello");
+    }
+
+    #[test]
+    fn test_add_script_preprocessor() {
+        let rt = EsRuntimeBuilder::new()
+            .add_script_preprocessor(Box::new(|script: Script| {
+                Script::new(
+                    script.get_path(),
+                    script.get_code().replace("__VERSION__", "\"1.0\"").as_str(),
+                )
+            }))
+            .add_script_preprocessor(Box::new(|script: Script| {
+                Script::new(
+                    script.get_path(),
+                    format!("{} + '-final'", script.get_code()).as_str(),
+                )
+            }))
+            .build();
+        let res = rt
+            .eval_sync(Script::new("test_pp.es", "__VERSION__"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1.0-final");
+    }
+
+    #[test]
+    fn test_on_exception() {
+        let errors = Arc::new(Mutex::new(vec![]));
+        let errors2 = errors.clone();
+        let rt = EsRuntimeBuilder::new()
+            .on_exception(Box::new(move |err| {
+                errors2.lock().unwrap().push(err.get_message().to_string());
+            }))
+            .build();
+        let _ = rt.eval_sync(Script::new("test_ex1.es", "throw Error('first');"));
+        let _ = rt.eval_sync(Script::new("test_ex2.es", "1 + 1;"));
+        let _ = rt.eval_sync(Script::new(
+            "test_ex3.es",
+            "try {throw Error('caught');} catch(e) {}; null.foo;",
+        ));
+        // errors of internal operations (like stringifying a facade) are not reported
+        let circular = rt
+            .eval_sync(Script::new("test_ex4.es", "let o = {}; o.o = o; o;"))
+            .ok()
+            .expect("script failed");
+        assert!(circular.stringify().is_err());
+        let errors = &*errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].as_str(), "first");
+        assert!(errors[1].contains("null"));
+    }
+
+    #[test]
+    fn test_exception_filter() {
+        let rt = EsRuntimeBuilder::new()
+            .exception_filter(Box::new(|err| {
+                if err.get_name() == "SyntaxError" {
+                    Ok(JsError::new(
+                        "DomainError".to_string(),
+                        "E_SYNTAX".to_string(),
+                        err.get_stack().to_string(),
+                    ))
+                } else if err.get_message() == "use default" {
+                    Err(12.to_es_value_facade())
+                } else {
+                    Ok(err)
+                }
+            }))
+            .build();
+        let err = rt
+            .eval_sync(Script::new("test_filter1.es", "let a = ;"))
+            .err()
+            .expect("script should have failed");
+        assert_eq!(err.get_name(), "DomainError");
+        assert_eq!(err.get_message(), "E_SYNTAX");
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_filter2.es",
+                "throw Error('use default');",
+            ))
+            .ok()
+            .expect("error was not substituted");
+        assert_eq!(res.get_i32(), 12);
+
+        let err = rt
+            .eval_sync(Script::new("test_filter3.es", "throw TypeError('other');"))
+            .err()
+            .expect("script should have failed");
+        assert_eq!(err.get_name(), "TypeError");
+        assert_eq!(err.get_message(), "other");
+
+        // the filter applies to the other eval and call methods too
+        let res = rt.eval_with_diagnostics_sync(Script::new(
+            "test_filter4.es",
+            "throw Error('use default');",
+        ));
+        assert_eq!(
+            res.value.ok().expect("error was not substituted").get_i32(),
+            12
+        );
+
+        let err = rt
+            .eval_sandboxed(
+                Script::new("test_filter5.es", "let b = ;"),
+                SandboxConfig::default(),
+            )
+            .err()
+            .expect("script should have failed");
+        assert_eq!(err.get_name(), "DomainError");
+
+        let res = rt
+            .eval_batch_sync(vec![
+                Script::new("test_filter6.es", "throw Error('use default');"),
+                Script::new("test_filter7.es", "13;"),
+            ])
+            .ok()
+            .expect("error was not substituted");
+        assert_eq!(res[0].get_i32(), 12);
+        assert_eq!(res[1].get_i32(), 13);
+
+        rt.eval_sync(Script::new(
+            "test_filter8.es",
+            "this.filterTest = {fail: function() {throw Error('use default');}};",
+        ))
+        .ok()
+        .expect("script failed");
+        assert!(rt
+            .js_function_invoke_sync(None, &["filterTest"], "fail", vec![])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_typescript_transpiler() {
+        let rt = EsRuntimeBuilder::new()
+            .typescript_transpiler(Box::new(|code| {
+                if code.contains("interface ") {
+                    return Err(JsError::new_str("interfaces are not supported"));
+                }
+                Ok(code.replace("const x: number = 5;", "const x = 5;"))
+            }))
+            .build();
+        let res = rt
+            .eval_sync(Script::new(
+                "test_typescript.ts",
+                "const x: number = 5; x * 2;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 10);
+
+        // scripts which are not TypeScript are not transpiled
+        assert!(rt
+            .eval_sync(Script::new("test_typescript.js", "const y: number = 5;"))
+            .is_err());
+
+        let err = rt
+            .eval_sync(Script::new("test_typescript2.tsx", "interface A {}"))
+            .err()
+            .expect("transpiler should have failed");
+        assert_eq!(err.get_message(), "interfaces are not supported");
+    }
 }
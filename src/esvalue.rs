@@ -4,7 +4,9 @@ use crate::quickjs_utils::dates::is_date_q;
 use crate::quickjs_utils::errors::{error_to_js_error, is_error_q};
 use crate::quickjs_utils::functions::{is_function_q, new_function_q};
 use crate::quickjs_utils::json::stringify_q;
-use crate::quickjs_utils::objects::{get_property_names_q, get_property_q};
+use crate::quickjs_utils::objects::{
+    delete_property_q, get_property_names_q, get_property_q, set_property_q,
+};
 use crate::quickjs_utils::primitives::to_string_q;
 use crate::quickjs_utils::promises::{is_promise_q, PromiseRef};
 use crate::quickjs_utils::{functions, new_null_ref, promises};
@@ -17,8 +19,11 @@ use futures::task::{Context, Poll};
 use hirofa_utils::auto_id_map::AutoIdMap;
 use hirofa_utils::debug_mutex::DebugMutex;
 use hirofa_utils::js_utils::JsError;
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::fmt::{Debug, Error, Formatter};
 use std::future::Future;
 use std::pin::Pin;
@@ -26,6 +31,7 @@ use std::rc::Rc;
 use std::sync::mpsc::{sync_channel, Receiver, SendError, SyncSender};
 use std::sync::{Arc, Mutex, Weak};
 use std::task::Waker;
+use std::time::Duration;
 
 pub struct TaskFutureResolver<R> {
     sender: SyncSender<R>,
@@ -100,6 +106,7 @@ pub type PromiseReactionType =
     Option<Box<dyn Fn(EsValueFacade) -> Result<EsValueFacade, JsError> + Send + 'static>>;
 
 pub trait EsValueConvertible {
+    /// convert the value to a JSValueRef in the given context, values are always created in the context they are passed to
     fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError>;
 
     #[allow(clippy::wrong_self_convention)]
@@ -144,6 +151,12 @@ pub trait EsValueConvertible {
     fn get_f64(&self) -> f64 {
         panic!("i am not an f64");
     }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn is_infinity(&self) -> bool {
+        false
+    }
     fn is_function(&self) -> bool {
         false
     }
@@ -188,12 +201,64 @@ pub trait EsValueConvertible {
     fn get_object(&self) -> Result<HashMap<String, EsValueFacade>, JsError> {
         panic!("i am not an object");
     }
+    #[cfg(feature = "indexmap")]
+    fn get_object_ordered(&self) -> Result<IndexMap<String, EsValueFacade>, JsError> {
+        panic!("i am not an object");
+    }
+    /// the methods which access a single property of a JS object return an error for values which are not objects
+    fn get_property_sync(&self, _name: &str) -> Result<EsValueFacade, JsError> {
+        Err(JsError::new_str("value is not an object"))
+    }
+    fn set_property_sync(&self, _name: &str, _value: EsValueFacade) -> Result<(), JsError> {
+        Err(JsError::new_str("value is not an object"))
+    }
+    fn delete_property_sync(&self, _name: &str) -> Result<bool, JsError> {
+        Err(JsError::new_str("value is not an object"))
+    }
+    fn call_method_sync(
+        &self,
+        _method: &str,
+        _args: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        Err(JsError::new_str("value is not an object"))
+    }
     fn is_array(&self) -> bool {
         false
     }
     fn get_array(&self) -> Result<Vec<EsValueFacade>, JsError> {
         panic!("i am not an array");
     }
+    /// get a property of a value which was created in rust (e.g. a HashMap), this does not access the runtime
+    fn get_materialized_property(&self, _name: &str) -> Option<&EsValueFacade> {
+        None
+    }
+    fn get_materialized_property_mut(&mut self, _name: &str) -> Option<&mut EsValueFacade> {
+        None
+    }
+    /// set a property of a value which was created in rust, returns false if the value has no properties
+    fn set_materialized_property(&mut self, _name: &str, _value: EsValueFacade) -> bool {
+        false
+    }
+    /// get all properties of a value which was created in rust (e.g. a HashMap), this does not access the runtime
+    fn get_materialized_properties(&self) -> Option<Vec<(&str, &EsValueFacade)>> {
+        None
+    }
+    /// get all elements of a value which was created in rust (e.g. a Vec), this does not access the runtime
+    fn get_materialized_elements(&self) -> Option<&[EsValueFacade]> {
+        None
+    }
+    /// get an element of a value which was created in rust (e.g. a Vec), this does not access the runtime
+    fn get_materialized_element(&self, _index: usize) -> Option<&EsValueFacade> {
+        None
+    }
+    fn get_materialized_element_mut(&mut self, _index: usize) -> Option<&mut EsValueFacade> {
+        None
+    }
+    /// set an element of a value which was created in rust, an index equal to the length appends the value
+    /// returns false if the value has no elements or the index is out of bounds
+    fn set_materialized_element(&mut self, _index: usize, _value: EsValueFacade) -> bool {
+        false
+    }
     fn supports_stringify(&self) -> bool {
         false
     }
@@ -206,6 +271,12 @@ pub trait EsValueConvertible {
     fn get_error(&self) -> JsError {
         unimplemented!()
     }
+    fn get_error_name(&self) -> &str {
+        panic!("i am not an error");
+    }
+    fn get_error_message(&self) -> &str {
+        panic!("i am not an error");
+    }
 }
 
 pub struct EsUndefinedValue {}
@@ -214,6 +285,13 @@ pub struct EsNullValue {}
 pub const ES_NULL: EsNullValue = EsNullValue {};
 pub const ES_UNDEFINED: EsUndefinedValue = EsUndefinedValue {};
 
+/// represents the JS NaN value
+pub struct EsNaNValue {}
+/// represents the JS Infinity value, the bool indicates if it is positive (Infinity) or negative (-Infinity)
+pub struct EsInfinityValue(pub bool);
+
+pub const ES_NAN: EsNaNValue = EsNaNValue {};
+
 pub struct EsProxyInstance {
     class_name: &'static str,
     instance_id: usize,
@@ -278,6 +356,66 @@ impl EsValueConvertible for EsUndefinedValue {
     }
 }
 
+impl EsValueConvertible for EsNaNValue {
+    fn as_js_value(&mut self, _q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        Ok(crate::quickjs_utils::primitives::from_f64(f64::NAN))
+    }
+
+    fn is_f64(&self) -> bool {
+        true
+    }
+
+    fn get_f64(&self) -> f64 {
+        f64::NAN
+    }
+
+    fn is_nan(&self) -> bool {
+        true
+    }
+
+    fn supports_stringify(&self) -> bool {
+        true
+    }
+
+    fn stringify(&self) -> Result<String, JsError> {
+        Ok("NaN".to_string())
+    }
+}
+
+impl EsValueConvertible for EsInfinityValue {
+    fn as_js_value(&mut self, _q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        Ok(crate::quickjs_utils::primitives::from_f64(self.get_f64()))
+    }
+
+    fn is_f64(&self) -> bool {
+        true
+    }
+
+    fn get_f64(&self) -> f64 {
+        if self.0 {
+            f64::INFINITY
+        } else {
+            f64::NEG_INFINITY
+        }
+    }
+
+    fn is_infinity(&self) -> bool {
+        true
+    }
+
+    fn supports_stringify(&self) -> bool {
+        true
+    }
+
+    fn stringify(&self) -> Result<String, JsError> {
+        if self.0 {
+            Ok("Infinity".to_string())
+        } else {
+            Ok("-Infinity".to_string())
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum EsType {
     Promise,
@@ -300,7 +438,7 @@ struct CachedJSValueRef {
 impl CachedJSValueRef {
     fn new(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> Self {
         log::trace!("> CachedJSValueRef::new");
-        let es_rt = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref().unwrap());
+        let es_rt = q_ctx.get_runtime_ref().expect("runtime was dropped");
         let cached_obj_id = q_ctx.cache_object(value_ref.clone());
 
         let es_type = if value_ref.is_big_int() {
@@ -474,6 +612,13 @@ fn pipe_promise_resolution_to_sender(
 
 impl EsValueConvertible for CachedJSValueRef {
     fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        // the cached object lives in the context it was created in, object ids are not valid in other contexts
+        if q_ctx.id != self.context_id {
+            return Err(JsError::new_string(format!(
+                "object belongs to context {} and can not be used in context {}",
+                self.context_id, q_ctx.id
+            )));
+        }
         Ok(q_ctx.with_cached_obj(self.cached_obj_id, |obj_ref| obj_ref))
     }
 
@@ -533,7 +678,7 @@ impl EsValueConvertible for CachedJSValueRef {
             for mut args in batch_args {
                 let mut ref_args = vec![];
                 for arg in args.iter_mut() {
-                    ref_args.push(arg.as_js_value(q_ctx).ok().expect("to_js_value failed"));
+                    ref_args.push(arg.as_js_value(q_ctx).ok().expect("as_js_value failed"));
                 }
 
                 let res = crate::quickjs_utils::functions::call_function_q(
@@ -681,26 +826,6 @@ impl EsValueConvertible for CachedJSValueRef {
         Ok(())
     }
 
-    fn is_object(&self) -> bool {
-        self.es_type == EsType::Object
-    }
-
-    fn get_object(&self) -> Result<HashMap<String, EsValueFacade>, JsError> {
-        assert!(self.is_object());
-        self.do_with_sync(|_q_js_rt, q_ctx, obj_ref| {
-            let mut ret = HashMap::new();
-
-            for prop_name in get_property_names_q(q_ctx, &obj_ref)? {
-                log::trace!("EsValueConvertible::get_object > prop: {}", prop_name);
-                let prop_ref = get_property_q(q_ctx, &obj_ref, prop_name.as_str())?;
-                let prop_esvf = EsValueFacade::from_jsval(q_ctx, &prop_ref)?;
-                ret.insert(prop_name, prop_esvf);
-            }
-            log::trace!("EsValueConvertible::get_object > return");
-            Ok(ret)
-        })
-    }
-
     fn is_array(&self) -> bool {
         self.es_type == EsType::Array
     }
@@ -745,6 +870,195 @@ impl EsValueConvertible for CachedJSValueRef {
     }
 }
 
+/// a JS object which is held by reference, its properties are only converted when they are accessed
+#[allow(clippy::upper_case_acronyms)]
+struct CachedJSObject {
+    cached_ref: CachedJSValueRef,
+}
+
+impl CachedJSObject {
+    fn new(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> Self {
+        Self {
+            cached_ref: CachedJSValueRef::new(q_ctx, value_ref),
+        }
+    }
+}
+
+impl EsValueConvertible for CachedJSObject {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        self.cached_ref.as_js_value(q_ctx)
+    }
+
+    fn is_object(&self) -> bool {
+        true
+    }
+
+    fn get_object(&self) -> Result<HashMap<String, EsValueFacade>, JsError> {
+        self.cached_ref.do_with_sync(|_q_js_rt, q_ctx, obj_ref| {
+            let mut ret = HashMap::new();
+
+            for prop_name in get_property_names_q(q_ctx, &obj_ref)? {
+                log::trace!("EsValueConvertible::get_object > prop: {}", prop_name);
+                let prop_ref = get_property_q(q_ctx, &obj_ref, prop_name.as_str())?;
+                let prop_esvf = EsValueFacade::from_jsval(q_ctx, &prop_ref)?;
+                ret.insert(prop_name, prop_esvf);
+            }
+            log::trace!("EsValueConvertible::get_object > return");
+            Ok(ret)
+        })
+    }
+
+    #[cfg(feature = "indexmap")]
+    fn get_object_ordered(&self) -> Result<IndexMap<String, EsValueFacade>, JsError> {
+        self.cached_ref.do_with_sync(|_q_js_rt, q_ctx, obj_ref| {
+            let mut ret = IndexMap::new();
+            // property names are returned in definition order
+            for prop_name in get_property_names_q(q_ctx, &obj_ref)? {
+                let prop_ref = get_property_q(q_ctx, &obj_ref, prop_name.as_str())?;
+                let prop_esvf = EsValueFacade::from_jsval(q_ctx, &prop_ref)?;
+                ret.insert(prop_name, prop_esvf);
+            }
+            Ok(ret)
+        })
+    }
+
+    fn get_property_sync(&self, name: &str) -> Result<EsValueFacade, JsError> {
+        let name = name.to_string();
+        self.cached_ref
+            .do_with_sync(move |_q_js_rt, q_ctx, obj_ref| {
+                let prop_ref = get_property_q(q_ctx, &obj_ref, name.as_str())?;
+                EsValueFacade::from_jsval(q_ctx, &prop_ref)
+            })
+    }
+
+    fn set_property_sync(&self, name: &str, mut value: EsValueFacade) -> Result<(), JsError> {
+        let name = name.to_string();
+        self.cached_ref
+            .do_with_sync(move |_q_js_rt, q_ctx, obj_ref| {
+                let value_ref = value.as_js_value(q_ctx)?;
+                set_property_q(q_ctx, &obj_ref, name.as_str(), &value_ref)
+            })
+    }
+
+    fn delete_property_sync(&self, name: &str) -> Result<bool, JsError> {
+        let name = name.to_string();
+        self.cached_ref
+            .do_with_sync(move |_q_js_rt, q_ctx, obj_ref| {
+                delete_property_q(q_ctx, &obj_ref, name.as_str())
+            })
+    }
+
+    fn call_method_sync(
+        &self,
+        method: &str,
+        mut args: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        let method = method.to_string();
+        self.cached_ref
+            .do_with_sync(move |_q_js_rt, q_ctx, obj_ref| {
+                let mut ref_args = vec![];
+                for arg in args.iter_mut() {
+                    ref_args.push(arg.as_js_value(q_ctx)?);
+                }
+                functions::invoke_member_function_q(q_ctx, &obj_ref, method.as_str(), ref_args)
+                    .and_then(|js_val| EsValueFacade::from_jsval(q_ctx, &js_val))
+            })
+    }
+
+    fn supports_stringify(&self) -> bool {
+        true
+    }
+
+    fn stringify(&self) -> Result<String, JsError> {
+        self.cached_ref.stringify()
+    }
+}
+
+/// a JS Error object, the name, message and stack are read when the facade is created so they can be inspected
+/// without using the event queue
+#[allow(clippy::upper_case_acronyms)]
+struct CachedJSError {
+    cached_obj: CachedJSObject,
+    name: String,
+    message: String,
+    stack: String,
+}
+
+impl CachedJSError {
+    fn new(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> Self {
+        let js_error = unsafe { error_to_js_error(q_ctx.context, value_ref) };
+        Self {
+            cached_obj: CachedJSObject::new(q_ctx, value_ref),
+            name: js_error.get_name().to_string(),
+            message: js_error.get_message().to_string(),
+            stack: js_error.get_stack().to_string(),
+        }
+    }
+}
+
+impl EsValueConvertible for CachedJSError {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        self.cached_obj.as_js_value(q_ctx)
+    }
+
+    fn is_object(&self) -> bool {
+        true
+    }
+
+    fn get_object(&self) -> Result<HashMap<String, EsValueFacade>, JsError> {
+        self.cached_obj.get_object()
+    }
+
+    #[cfg(feature = "indexmap")]
+    fn get_object_ordered(&self) -> Result<IndexMap<String, EsValueFacade>, JsError> {
+        self.cached_obj.get_object_ordered()
+    }
+
+    fn get_property_sync(&self, name: &str) -> Result<EsValueFacade, JsError> {
+        self.cached_obj.get_property_sync(name)
+    }
+
+    fn set_property_sync(&self, name: &str, value: EsValueFacade) -> Result<(), JsError> {
+        self.cached_obj.set_property_sync(name, value)
+    }
+
+    fn delete_property_sync(&self, name: &str) -> Result<bool, JsError> {
+        self.cached_obj.delete_property_sync(name)
+    }
+
+    fn call_method_sync(
+        &self,
+        method: &str,
+        args: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        self.cached_obj.call_method_sync(method, args)
+    }
+
+    fn is_error(&self) -> bool {
+        true
+    }
+
+    fn get_error(&self) -> JsError {
+        JsError::new(self.name.clone(), self.message.clone(), self.stack.clone())
+    }
+
+    fn get_error_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn get_error_message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    fn supports_stringify(&self) -> bool {
+        true
+    }
+
+    fn stringify(&self) -> Result<String, JsError> {
+        self.cached_obj.stringify()
+    }
+}
+
 impl EsValueConvertible for String {
     fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
         crate::quickjs_utils::primitives::from_string_q(q_ctx, self.as_str())
@@ -832,6 +1146,28 @@ impl EsValueConvertible for f64 {
     }
 }
 
+/// Duration is converted to a number of milliseconds
+impl EsValueConvertible for Duration {
+    fn as_js_value(&mut self, _q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        Ok(crate::quickjs_utils::primitives::from_f64(self.get_f64()))
+    }
+    fn is_f64(&self) -> bool {
+        true
+    }
+
+    fn get_f64(&self) -> f64 {
+        self.as_secs_f64() * 1000.0
+    }
+
+    fn supports_stringify(&self) -> bool {
+        true
+    }
+
+    fn stringify(&self) -> Result<String, JsError> {
+        Ok(format!("{}", self.get_f64()))
+    }
+}
+
 impl EsValueConvertible for Vec<EsValueFacade> {
     fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
         // create the array
@@ -850,57 +1186,184 @@ impl EsValueConvertible for Vec<EsValueFacade> {
         }
         Ok(arr)
     }
-}
-
-impl EsValueConvertible for HashMap<String, EsValueFacade> {
-    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
-        // create new obj
-        let obj_ref = crate::quickjs_utils::objects::create_object_q(q_ctx)
-            .ok()
-            .unwrap();
 
-        for prop in self {
-            let prop_name = prop.0;
-            let prop_esvf = prop.1;
+    fn get_materialized_elements(&self) -> Option<&[EsValueFacade]> {
+        Some(self.as_slice())
+    }
 
-            // set prop in obj
+    fn get_materialized_element(&self, index: usize) -> Option<&EsValueFacade> {
+        self.get(index)
+    }
 
-            let property_value_ref = prop_esvf.as_js_value(q_ctx)?;
+    fn get_materialized_element_mut(&mut self, index: usize) -> Option<&mut EsValueFacade> {
+        self.get_mut(index)
+    }
 
-            crate::quickjs_utils::objects::set_property_q(
-                q_ctx,
-                &obj_ref,
-                prop_name.as_str(),
-                &property_value_ref,
-            )?;
+    fn set_materialized_element(&mut self, index: usize, value: EsValueFacade) -> bool {
+        if index < self.len() {
+            self[index] = value;
+            true
+        } else if index == self.len() {
+            self.push(value);
+            true
+        } else {
+            false
         }
-
-        Ok(obj_ref)
     }
 }
 
-pub type EsPromiseResolver = Box<dyn FnOnce() -> Result<EsValueFacade, String> + Send + 'static>;
-
-thread_local! {
-    static ESPROMISE_REFS: RefCell<AutoIdMap<PromiseRef>> = RefCell::new(AutoIdMap::new());
+/// Vec<u8> is converted to a Uint8Array
+#[cfg(feature = "vec_u8_as_uint8array")]
+impl EsValueConvertible for Vec<u8> {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        crate::quickjs_utils::typedarrays::new_uint8_array_q(q_ctx, self.as_slice())
+    }
 }
 
-struct EsPromiseResolvableHandleInfo {
-    weak_es_rt: Weak<EsRuntime>,
-    id: usize,
-    context_id: String,
+/// tuples are converted to fixed length arrays
+macro_rules! impl_tuple_convertible {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: EsValueConvertible + Send + 'static),+> EsValueConvertible for ($($name,)+) {
+            fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+                let arr = crate::quickjs_utils::arrays::create_array_q(q_ctx)?;
+                $(
+                    let item_val_ref = self.$idx.as_js_value(q_ctx)?;
+                    crate::quickjs_utils::arrays::set_element_q(q_ctx, &arr, $idx, item_val_ref)?;
+                )+
+                Ok(arr)
+            }
+        }
+    };
 }
 
-struct EsPromiseResolvableHandleInner {
-    js_info: Option<EsPromiseResolvableHandleInfo>,
-    resolution: Option<Result<EsValueFacade, EsValueFacade>>,
-}
+impl_tuple_convertible!(A 0, B 1);
+impl_tuple_convertible!(A 0, B 1, C 2);
+impl_tuple_convertible!(A 0, B 1, C 2, D 3);
+impl_tuple_convertible!(A 0, B 1, C 2, D 3, E 4);
+impl_tuple_convertible!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_tuple_convertible!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_tuple_convertible!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// create a new object and set all properties in the order of the iterator
+fn props_as_js_value<'a, I>(q_ctx: &QuickJsContext, props: I) -> Result<JSValueRef, JsError>
+where
+    I: Iterator<Item = (&'a String, &'a mut EsValueFacade)>,
+{
+    // create new obj
+    let obj_ref = crate::quickjs_utils::objects::create_object_q(q_ctx)
+        .ok()
+        .unwrap();
 
-pub struct EsPromiseResolvableHandle {
-    inner: DebugMutex<EsPromiseResolvableHandleInner>,
-}
+    for (prop_name, prop_esvf) in props {
+        // set prop in obj
 
-impl EsPromiseResolvableHandle {
+        let property_value_ref = prop_esvf.as_js_value(q_ctx)?;
+
+        crate::quickjs_utils::objects::set_property_q(
+            q_ctx,
+            &obj_ref,
+            prop_name.as_str(),
+            &property_value_ref,
+        )?;
+    }
+
+    Ok(obj_ref)
+}
+
+impl EsValueConvertible for HashMap<String, EsValueFacade> {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        props_as_js_value(q_ctx, self.iter_mut())
+    }
+
+    fn get_materialized_properties(&self) -> Option<Vec<(&str, &EsValueFacade)>> {
+        Some(self.iter().map(|(k, v)| (k.as_str(), v)).collect())
+    }
+
+    fn get_materialized_property(&self, name: &str) -> Option<&EsValueFacade> {
+        self.get(name)
+    }
+
+    fn get_materialized_property_mut(&mut self, name: &str) -> Option<&mut EsValueFacade> {
+        self.get_mut(name)
+    }
+
+    fn set_materialized_property(&mut self, name: &str, value: EsValueFacade) -> bool {
+        self.insert(name.to_string(), value);
+        true
+    }
+}
+
+/// properties are set in alphabetical order
+impl EsValueConvertible for BTreeMap<String, EsValueFacade> {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        props_as_js_value(q_ctx, self.iter_mut())
+    }
+
+    fn get_materialized_properties(&self) -> Option<Vec<(&str, &EsValueFacade)>> {
+        Some(self.iter().map(|(k, v)| (k.as_str(), v)).collect())
+    }
+
+    fn get_materialized_property(&self, name: &str) -> Option<&EsValueFacade> {
+        self.get(name)
+    }
+
+    fn get_materialized_property_mut(&mut self, name: &str) -> Option<&mut EsValueFacade> {
+        self.get_mut(name)
+    }
+
+    fn set_materialized_property(&mut self, name: &str, value: EsValueFacade) -> bool {
+        self.insert(name.to_string(), value);
+        true
+    }
+}
+
+/// properties are set in insertion order
+#[cfg(feature = "indexmap")]
+impl EsValueConvertible for IndexMap<String, EsValueFacade> {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        props_as_js_value(q_ctx, self.iter_mut())
+    }
+
+    fn get_materialized_properties(&self) -> Option<Vec<(&str, &EsValueFacade)>> {
+        Some(self.iter().map(|(k, v)| (k.as_str(), v)).collect())
+    }
+
+    fn get_materialized_property(&self, name: &str) -> Option<&EsValueFacade> {
+        self.get(name)
+    }
+
+    fn get_materialized_property_mut(&mut self, name: &str) -> Option<&mut EsValueFacade> {
+        self.get_mut(name)
+    }
+
+    fn set_materialized_property(&mut self, name: &str, value: EsValueFacade) -> bool {
+        self.insert(name.to_string(), value);
+        true
+    }
+}
+
+pub type EsPromiseResolver = Box<dyn FnOnce() -> Result<EsValueFacade, String> + Send + 'static>;
+
+thread_local! {
+    static ESPROMISE_REFS: RefCell<AutoIdMap<PromiseRef>> = RefCell::new(AutoIdMap::new());
+}
+
+struct EsPromiseResolvableHandleInfo {
+    weak_es_rt: Weak<EsRuntime>,
+    id: usize,
+    context_id: String,
+}
+
+struct EsPromiseResolvableHandleInner {
+    js_info: Option<EsPromiseResolvableHandleInfo>,
+    resolution: Option<Result<EsValueFacade, EsValueFacade>>,
+}
+
+pub struct EsPromiseResolvableHandle {
+    inner: DebugMutex<EsPromiseResolvableHandleInner>,
+}
+
+impl EsPromiseResolvableHandle {
     fn new() -> Self {
         Self {
             inner: DebugMutex::new(
@@ -1187,7 +1650,7 @@ impl EsPromise {
         let ret = Self::new_unresolving();
 
         let handle = ret.get_handle();
-        EsRuntime::add_helper_task(move || {
+        EsRuntime::add_current_runtime_helper_task(move || {
             let val = resolver();
             match val {
                 Ok(v) => {
@@ -1244,7 +1707,7 @@ impl EsPromise {
 
         let handle = ret.get_handle();
 
-        let _ = EsRuntime::add_helper_task_async(async move {
+        EsRuntime::add_current_runtime_helper_task_async(async move {
             let val = resolver.await;
             match val {
                 Ok(v) => {
@@ -1297,7 +1760,7 @@ impl EsPromise {
 
 impl EsValueConvertible for EsPromise {
     fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
-        log::trace!("EsPromise::to_js_value");
+        log::trace!("EsPromise::as_js_value");
 
         let prom_ref = promises::new_promise_q(q_ctx)?;
 
@@ -1306,7 +1769,9 @@ impl EsValueConvertible for EsPromise {
             let map = &mut *rc.borrow_mut();
             map.insert(prom_ref)
         });
-        let es_rt = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref().unwrap());
+        let es_rt = q_ctx
+            .get_runtime_ref()
+            .ok_or_else(|| JsError::new_str("runtime was dropped"))?;
 
         self.handle.set_info(&es_rt, id, q_ctx.id.as_str())?;
 
@@ -1314,6 +1779,80 @@ impl EsValueConvertible for EsPromise {
     }
 }
 
+/// convert a serde_json Value, numbers are converted to i32 when they are integers which fit in an i32
+impl TryFrom<serde_json::Value> for EsValueFacade {
+    type Error = JsError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Ok(match value {
+            serde_json::Value::Null => ES_NULL.to_es_value_facade(),
+            serde_json::Value::Bool(b) => b.to_es_value_facade(),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) if i >= i32::MIN as i64 && i <= i32::MAX as i64 => {
+                    (i as i32).to_es_value_facade()
+                }
+                _ => n
+                    .as_f64()
+                    .ok_or_else(|| JsError::new_string(format!("invalid number: {}", n)))?
+                    .to_es_value_facade(),
+            },
+            serde_json::Value::String(s) => s.to_es_value_facade(),
+            serde_json::Value::Array(items) => items
+                .into_iter()
+                .map(EsValueFacade::try_from)
+                .collect::<Result<Vec<_>, _>>()?
+                .to_es_value_facade(),
+            serde_json::Value::Object(props) => {
+                let mut map = HashMap::new();
+                for (name, prop) in props {
+                    map.insert(name, EsValueFacade::try_from(prop)?);
+                }
+                map.to_es_value_facade()
+            }
+        })
+    }
+}
+
+enum PathSegment<'a> {
+    Property(&'a str),
+    Index(usize),
+}
+
+/// parse a path like `a.b[1].c` into its segments
+fn parse_path(path: &str) -> Option<Vec<PathSegment<'_>>> {
+    let mut segments = vec![];
+    for part in path.split('.') {
+        let (name, mut rest) = match part.find('[') {
+            Some(idx) => (&part[..idx], &part[idx..]),
+            None => (part, ""),
+        };
+        if !name.is_empty() {
+            segments.push(PathSegment::Property(name));
+        } else if rest.is_empty() {
+            return None;
+        }
+        while !rest.is_empty() {
+            if !rest.starts_with('[') {
+                return None;
+            }
+            let end = rest.find(']')?;
+            segments.push(PathSegment::Index(rest[1..end].trim().parse().ok()?));
+            rest = &rest[end + 1..];
+        }
+    }
+    Some(segments)
+}
+
+/// a value which can be passed between rust and the runtime
+///
+/// properties of objects are accessed in one of two ways
+/// * values which were created in rust (e.g. a HashMap) hold their properties themselves, these are read with
+///   [get_property](EsValueFacade::get_property) and [get_path](EsValueFacade::get_path) and changed with
+///   [set_path](EsValueFacade::set_path) without accessing the runtime
+/// * JS objects are held by reference, their properties are read and changed in the event queue of the runtime with
+///   [get_property_sync](EsValueFacade::get_property_sync), [set_property_sync](EsValueFacade::set_property_sync),
+///   [delete_property_sync](EsValueFacade::delete_property_sync) and
+///   [call_method_sync](EsValueFacade::call_method_sync)
 pub struct EsValueFacade {
     convertible: Box<dyn EsValueConvertible + Send + 'static>,
 }
@@ -1329,7 +1868,7 @@ impl EsValueFacade {
         self.convertible.as_js_value(q_ctx)
     }
 
-    /// convert a JSValueRef to an EsValueFacade
+    /// convert a JSValueRef to an EsValueFacade, the EsRuntime of the facade is derived from the given context
     pub fn from_jsval(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> Result<Self, JsError> {
         log::trace!("EsValueFacade::from_jsval: tag:{}", value_ref.get_tag());
 
@@ -1366,12 +1905,29 @@ impl EsValueFacade {
                 let val: f64 = crate::quickjs_utils::primitives::to_f64(value_ref)
                     .ok()
                     .expect("could not convert to f64");
-                Ok(val.to_es_value_facade())
+                if val.is_nan() {
+                    Ok(ES_NAN.to_es_value_facade())
+                } else if val.is_infinite() {
+                    Ok(EsInfinityValue(val.is_sign_positive()).to_es_value_facade())
+                } else {
+                    Ok(val.to_es_value_facade())
+                }
             }
 
             // Object.
-            TAG_OBJECT => Ok(CachedJSValueRef::new(q_ctx, value_ref).to_es_value_facade())
-            ,
+            TAG_OBJECT => {
+                if is_error_q(q_ctx, value_ref) {
+                    Ok(CachedJSError::new(q_ctx, value_ref).to_es_value_facade())
+                } else {
+                    let cached_ref = CachedJSValueRef::new(q_ctx, value_ref);
+                    if cached_ref.es_type == EsType::Object {
+                        // plain objects are held by reference, their properties are converted when they are accessed
+                        Ok(CachedJSObject { cached_ref }.to_es_value_facade())
+                    } else {
+                        Ok(cached_ref.to_es_value_facade())
+                    }
+                }
+            }
             // BigIn
             TAG_BIG_INT => Ok(CachedJSValueRef::new(q_ctx, value_ref).to_es_value_facade()),
             x => Err(JsError::new_string(format!(
@@ -1411,6 +1967,236 @@ impl EsValueFacade {
         self.convertible.get_object()
     }
 
+    /// get the object value with its properties in definition order
+    #[cfg(feature = "indexmap")]
+    pub fn get_object_ordered(&self) -> Result<IndexMap<String, EsValueFacade>, JsError> {
+        self.convertible.get_object_ordered()
+    }
+
+    /// get a single property of an object value which was created in rust (e.g. a HashMap), this does not access the
+    /// runtime
+    /// returns None if there is no such property or if the value is not backed by rust, use
+    /// [get_property_from_js](EsValueFacade::get_property_from_js) for objects which are backed by a JS object
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esvalue::{EsValueConvertible, EsValueFacade};
+    /// use std::collections::HashMap;
+    /// let mut map: HashMap<String, EsValueFacade> = HashMap::new();
+    /// map.insert("a".to_string(), 12.to_es_value_facade());
+    /// let esvf = map.to_es_value_facade();
+    /// assert_eq!(esvf.get_property("a").unwrap().get_i32(), 12);
+    /// assert!(esvf.get_property("b").is_none());
+    /// ```
+    pub fn get_property(&self, name: &str) -> Option<&EsValueFacade> {
+        self.convertible.get_materialized_property(name)
+    }
+
+    /// convert the value to a serde_json Value, values which were created in rust are converted without accessing the
+    /// runtime, JS backed objects and arrays are converted with get_object and get_array
+    /// NaN and Infinity are converted to null (like JSON.stringify does), functions, promises and errors can not be
+    /// converted
+    pub fn to_json_value(&self) -> Result<serde_json::Value, &'static str> {
+        const NOT_SERIALIZABLE: &str = "not serializable";
+        if self.is_primitive() {
+            Ok(self.primitive_to_json_value())
+        } else if self.is_function() || self.is_promise() || self.is_error() {
+            Err(NOT_SERIALIZABLE)
+        } else if let Some(elements) = self.convertible.get_materialized_elements() {
+            elements.iter().map(|e| e.to_json_value()).collect()
+        } else if let Some(props) = self.convertible.get_materialized_properties() {
+            let mut map = serde_json::Map::new();
+            for (name, value) in props {
+                map.insert(name.to_string(), value.to_json_value()?);
+            }
+            Ok(serde_json::Value::Object(map))
+        } else if self.is_array() {
+            let elements = self.get_array().map_err(|_| NOT_SERIALIZABLE)?;
+            elements.iter().map(|e| e.to_json_value()).collect()
+        } else if self.is_object() {
+            let props = self.get_object().map_err(|_| NOT_SERIALIZABLE)?;
+            let mut map = serde_json::Map::new();
+            for (name, value) in props {
+                map.insert(name, value.to_json_value()?);
+            }
+            Ok(serde_json::Value::Object(map))
+        } else {
+            Err(NOT_SERIALIZABLE)
+        }
+    }
+
+    fn primitive_to_json_value(&self) -> serde_json::Value {
+        if self.is_boolean() {
+            serde_json::Value::Bool(self.get_boolean())
+        } else if self.is_i32() {
+            serde_json::Value::from(self.get_i32())
+        } else if self.is_f64() {
+            // NaN and Infinity are serialized as null, like JSON.stringify does
+            serde_json::Number::from_f64(self.get_f64())
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null)
+        } else if self.is_string() {
+            serde_json::Value::String(self.get_str().to_string())
+        } else {
+            serde_json::Value::Null
+        }
+    }
+
+    /// serialize the value to compact JSON without using the runtime, see [EsValueFacade::to_json_value]
+    /// object keys are sorted alphabetically
+    pub fn to_json_string(&self) -> Result<String, &'static str> {
+        serde_json::to_string(&self.to_json_value()?).map_err(|_| "not serializable")
+    }
+
+    /// serialize the value to JSON indented with 2 spaces without using the runtime, see [EsValueFacade::to_json_value]
+    /// object keys are sorted alphabetically
+    pub fn to_pretty_json_string(&self) -> Result<String, &'static str> {
+        serde_json::to_string_pretty(&self.to_json_value()?).map_err(|_| "not serializable")
+    }
+
+    /// convert the value to a rust type which implements serde's Deserialize, the value is converted with
+    /// [EsValueFacade::to_json_value] first
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let esvf = rt.eval_sync(Script::new("point.es", "({x: 1, y: 2});")).ok().expect("script failed");
+    /// let point: Point = esvf.into_typed().ok().expect("could not convert");
+    /// assert_eq!(point.x + point.y, 3);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn into_typed<T: serde::de::DeserializeOwned>(self) -> Result<T, JsError> {
+        let value = self.to_json_value().map_err(JsError::new_str)?;
+        serde_json::from_value(value).map_err(|e| JsError::new_string(format!("{}", e)))
+    }
+
+    /// create an EsValueFacade from a rust type which implements serde's Serialize, the value is serialized to a
+    /// serde_json Value first so this can be used to pass structs to script functions
+    #[cfg(feature = "serde")]
+    pub fn from_typed<T: serde::Serialize>(val: T) -> Result<EsValueFacade, JsError> {
+        let value = serde_json::to_value(val).map_err(|e| JsError::new_string(format!("{}", e)))?;
+        EsValueFacade::try_from(value)
+    }
+
+    /// get a nested value by path (e.g. `data.items[0].name`) from a value which was created in rust (e.g. a
+    /// HashMap of Vecs), this does not work for values which are backed by JS objects, use get_object or get_array
+    /// to convert those first
+    /// returns None if the path is invalid or a part of the path does not exist
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esvalue::{EsValueConvertible, EsValueFacade};
+    /// use std::collections::HashMap;
+    /// let mut item = HashMap::new();
+    /// item.insert("name".to_string(), "foo".to_string().to_es_value_facade());
+    /// let mut data = HashMap::new();
+    /// data.insert("items".to_string(), vec![item.to_es_value_facade()].to_es_value_facade());
+    /// let data = data.to_es_value_facade();
+    /// assert_eq!(data.get_path("items[0].name").unwrap().get_str(), "foo");
+    /// ```
+    pub fn get_path(&self, path: &str) -> Option<&EsValueFacade> {
+        let mut current = self;
+        for segment in parse_path(path)? {
+            current = match segment {
+                PathSegment::Property(name) => {
+                    current.convertible.get_materialized_property(name)?
+                }
+                PathSegment::Index(index) => current.convertible.get_materialized_element(index)?,
+            };
+        }
+        Some(current)
+    }
+
+    /// set a nested value by path in a value which was created in rust, see [EsValueFacade::get_path]
+    /// all parts of the path but the last must exist, setting an index equal to the length of a Vec appends the value
+    pub fn set_path(&mut self, path: &str, value: EsValueFacade) -> Result<(), JsError> {
+        let mut segments = parse_path(path)
+            .ok_or_else(|| JsError::new_string(format!("invalid path: {}", path)))?;
+        let last = segments
+            .pop()
+            .ok_or_else(|| JsError::new_str("path is empty"))?;
+        let mut current = self;
+        for segment in segments {
+            current = match segment {
+                PathSegment::Property(name) => {
+                    current.convertible.get_materialized_property_mut(name)
+                }
+                PathSegment::Index(index) => {
+                    current.convertible.get_materialized_element_mut(index)
+                }
+            }
+            .ok_or_else(|| JsError::new_string(format!("path not found: {}", path)))?;
+        }
+        let set = match last {
+            PathSegment::Property(name) => {
+                current.convertible.set_materialized_property(name, value)
+            }
+            PathSegment::Index(index) => current.convertible.set_materialized_element(index, value),
+        };
+        if set {
+            Ok(())
+        } else {
+            Err(JsError::new_string(format!("could not set path: {}", path)))
+        }
+    }
+
+    /// get a single property of a JS backed object value, objects are held by reference so only the requested
+    /// property is converted, this runs in the event queue of the runtime which owns the object
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let obj = rt.eval_sync(Script::new("obj.es", "({a: {b: 12}});")).ok().expect("script failed");
+    /// let a = obj.get_property_sync("a").ok().expect("could not get a");
+    /// assert_eq!(a.get_property_sync("b").ok().expect("could not get b").get_i32(), 12);
+    /// ```
+    pub fn get_property_sync(&self, name: &str) -> Result<EsValueFacade, JsError> {
+        self.convertible.get_property_sync(name)
+    }
+
+    /// get a single property of a JS backed object value, see [EsValueFacade::get_property_sync]
+    pub fn get_property_from_js(&self, name: &str) -> Result<EsValueFacade, JsError> {
+        self.get_property_sync(name)
+    }
+
+    /// call a method of a JS backed object value with the object as this, this runs in the event queue of the runtime
+    /// which owns the object
+    pub fn call_method_sync(
+        &self,
+        method: &str,
+        arguments: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, JsError> {
+        self.convertible.call_method_sync(method, arguments)
+    }
+
+    /// set a single property of a JS backed object value, this runs in the event queue of the runtime which owns the object
+    pub fn set_property_sync(&self, name: &str, value: EsValueFacade) -> Result<(), JsError> {
+        self.convertible.set_property_sync(name, value)
+    }
+
+    /// delete a single property of a JS backed object value, returns false if the property could not be deleted
+    /// (e.g. because it is not configurable), this runs in the event queue of the runtime which owns the object
+    pub fn delete_property_sync(&self, name: &str) -> Result<bool, JsError> {
+        self.convertible.delete_property_sync(name)
+    }
+
+    /// check if the value is a primitive (a String, i32, f64, bool, null or undefined), the value of a primitive is
+    /// stored in the facade itself so reading it never uses the event queue of the runtime
+    pub fn is_primitive(&self) -> bool {
+        self.is_string()
+            || self.is_i32()
+            || self.is_f64()
+            || self.is_boolean()
+            || self.is_null()
+            || self.is_undefined()
+    }
+
     /// check if the value is a String
     pub fn is_string(&self) -> bool {
         self.convertible.is_str()
@@ -1461,6 +2247,16 @@ impl EsValueFacade {
         self.convertible.is_undefined()
     }
 
+    /// check if the value is NaN
+    pub fn is_nan(&self) -> bool {
+        self.convertible.is_nan()
+    }
+
+    /// check if the value is Infinity or -Infinity
+    pub fn is_infinity(&self) -> bool {
+        self.convertible.is_infinity()
+    }
+
     /// invoke the Function represented by this EsValueFacade
     pub fn invoke_function_sync(
         &self,
@@ -1519,6 +2315,54 @@ impl EsValueFacade {
         self.convertible.get_promise_result()
     }
 
+    /// await the result of a Promise and map its resolved value
+    /// # example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use hirofa_utils::js_utils::Script;
+    /// use futures::executor::block_on;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let esvf = rt.eval_sync(Script::new("then_async.es", "(Promise.resolve(5));")).ok().expect("script failed");
+    /// let res = block_on(esvf.then_async(|v| (v.get_i32() * 2).to_es_value_facade()));
+    /// assert_eq!(res.ok().expect("promise was rejected").get_i32(), 10);
+    /// ```
+    pub fn then_async<F>(self, f: F) -> impl Future<Output = Result<EsValueFacade, EsValueFacade>>
+    where
+        F: FnOnce(EsValueFacade) -> EsValueFacade + Send + 'static,
+    {
+        assert!(self.is_promise());
+        let fut = self.get_promise_result();
+        async move { fut.await.map(f) }
+    }
+
+    /// await the result of a Promise and map its rejection value to a resolved value
+    pub fn catch_async<F>(self, f: F) -> impl Future<Output = Result<EsValueFacade, EsValueFacade>>
+    where
+        F: FnOnce(EsValueFacade) -> EsValueFacade + Send + 'static,
+    {
+        assert!(self.is_promise());
+        let fut = self.get_promise_result();
+        async move { fut.await.or_else(|rejection| Ok(f(rejection))) }
+    }
+
+    /// await the result of a Promise and run a closure regardless of the outcome
+    pub fn finally_async<F>(
+        self,
+        f: F,
+    ) -> impl Future<Output = Result<EsValueFacade, EsValueFacade>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        assert!(self.is_promise());
+        let fut = self.get_promise_result();
+        async move {
+            let res = fut.await;
+            f();
+            res
+        }
+    }
+
     pub fn is_error(&self) -> bool {
         self.convertible.is_error()
     }
@@ -1526,6 +2370,20 @@ impl EsValueFacade {
     pub fn get_error(&self) -> JsError {
         self.convertible.get_error()
     }
+
+    /// get the name of the Error (e.g. "TypeError"), this does not use the event queue
+    /// # Panics
+    /// if this is not an Error, check with [EsValueFacade::is_error] first
+    pub fn get_error_name(&self) -> &str {
+        self.convertible.get_error_name()
+    }
+
+    /// get the message of the Error, this does not use the event queue
+    /// # Panics
+    /// if this is not an Error, check with [EsValueFacade::is_error] first
+    pub fn get_error_message(&self) -> &str {
+        self.convertible.get_error_message()
+    }
 }
 
 impl Debug for EsValueFacade {
@@ -1561,9 +2419,14 @@ pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntime::EsRuntime;
     use crate::esruntimebuilder::EsRuntimeBuilder;
-    use crate::esvalue::{EsPromise, EsValueConvertible, EsValueFacade};
+    use crate::esvalue::{
+        EsInfinityValue, EsPromise, EsValueConvertible, EsValueFacade, ES_NAN, ES_NULL,
+    };
+    use crate::quickjs_utils::objects::set_property_q;
+    use crate::quickjs_utils::{get_global_q, primitives};
     use futures::executor::block_on;
     use hirofa_utils::js_utils::Script;
+    use std::collections::{BTreeMap, HashMap};
     use std::sync::{Arc, Weak};
     use std::time::Duration;
 
@@ -1729,4 +2592,616 @@ pub mod tests {
         let es_error = err_esvf.get_error();
         assert!(es_error.get_message().contains("poof"));
     }
+
+    #[test]
+    fn test_caught_error() {
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_caught_error.es",
+                "(function() {\n\
+                 try {\n\
+                     null.foo();\n\
+                 } catch(e) {\n\
+                     return e;\n\
+                 }\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(esvf.is_error());
+        assert!(esvf.is_object());
+        assert_eq!(esvf.get_error_name(), "TypeError");
+        assert!(!esvf.get_error_message().is_empty());
+        assert_eq!(esvf.get_error().get_name(), "TypeError");
+
+        // the properties of an error can be used like those of any other object
+        esvf.set_property_sync("code", 42.to_es_value_facade())
+            .ok()
+            .expect("could not set code");
+        let code = esvf
+            .get_property_from_js("code")
+            .ok()
+            .expect("could not get code");
+        assert_eq!(code.get_i32(), 42);
+        assert!(esvf
+            .get_object()
+            .ok()
+            .expect("could not get object")
+            .contains_key("code"));
+
+        let not_err = rt
+            .eval_sync(Script::new(
+                "test_caught_error2.es",
+                "({message: 'no error'});",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(!not_err.is_error());
+        assert!(not_err.is_object());
+    }
+
+    #[test]
+    fn test_as_js_value_multi_ctx() {
+        let rt = init_test_rt();
+        rt.create_context("esvf_ctx")
+            .ok()
+            .expect("could not create context");
+        let ok = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("esvf_ctx");
+            let mut map = HashMap::new();
+            map.insert("a".to_string(), 12.to_es_value_facade());
+            let mut esvf = map.to_es_value_facade();
+            let obj_ref = esvf.as_js_value(q_ctx).ok().expect("conversion failed");
+            let global = get_global_q(q_ctx);
+            set_property_q(q_ctx, &global, "esvfObj", &obj_ref)
+                .ok()
+                .expect("set prop failed");
+            let res = q_ctx
+                .eval(Script::new("test_as_js_value_multi_ctx.es", "(esvfObj.a);"))
+                .ok()
+                .expect("script failed");
+            primitives::to_i32(&res).ok().expect("not an i32") == 12
+        });
+        assert!(ok);
+        rt.drop_context("esvf_ctx");
+    }
+
+    #[test]
+    fn test_as_js_value_wrong_ctx() {
+        let rt = init_test_rt();
+        rt.create_context("esvf_ctx_a")
+            .ok()
+            .expect("could not create context");
+        rt.create_context("esvf_ctx_b")
+            .ok()
+            .expect("could not create context");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let ctx_a = q_js_rt.get_context("esvf_ctx_a");
+            let obj_ref = ctx_a
+                .eval(Script::new("test_as_js_value_wrong_ctx.es", "({a: 1});"))
+                .ok()
+                .expect("script failed");
+            let mut esvf = EsValueFacade::from_jsval(ctx_a, &obj_ref)
+                .ok()
+                .expect("conversion failed");
+            let same_ctx_ok = esvf.as_js_value(ctx_a).is_ok();
+            let ctx_b = q_js_rt.get_context("esvf_ctx_b");
+            let other_ctx_err = esvf.as_js_value(ctx_b).is_err();
+            same_ctx_ok && other_ctx_err
+        });
+        assert!(res);
+        rt.drop_context("esvf_ctx_a");
+        rt.drop_context("esvf_ctx_b");
+    }
+
+    #[test]
+    fn test_get_property_sync_lazy() {
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_get_property_sync_lazy.es",
+                "(function() {\
+                     let items = {};\
+                     for (let x = 0; x < 10000; x++) {items['item' + x] = {id: x, nested: {label: 'label' + x}};}\
+                     return {items, count: 10000, label: function(x) {return this.items['item' + x].nested.label;}};\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(esvf.is_object());
+        let count = esvf
+            .get_property_sync("count")
+            .ok()
+            .expect("could not get count");
+        assert_eq!(count.get_i32(), 10000);
+        assert_eq!(
+            esvf.get_property_from_js("count")
+                .ok()
+                .expect("could not get count")
+                .get_i32(),
+            10000
+        );
+        let label = esvf
+            .get_property_sync("items")
+            .and_then(|items| items.get_property_sync("item4321"))
+            .and_then(|item| item.get_property_sync("nested"))
+            .and_then(|nested| nested.get_property_sync("label"))
+            .ok()
+            .expect("could not get nested label");
+        assert_eq!(label.get_str(), "label4321");
+        let label = esvf
+            .call_method_sync("label", vec![1234.to_es_value_facade()])
+            .ok()
+            .expect("could not call method");
+        assert_eq!(label.get_str(), "label1234");
+        assert!(esvf.call_method_sync("noSuchMethod", vec![]).is_err());
+
+        // values which are not objects return an error instead of panicking
+        let func = esvf
+            .get_property_sync("label")
+            .ok()
+            .expect("could not get label");
+        assert!(func.get_property_sync("name").is_err());
+        assert!(func.call_method_sync("call", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_set_and_delete_property_sync() {
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_set_and_delete_property_sync.es",
+                "this.heldObj = {a: 1, b: 2}; Object.defineProperty(heldObj, 'fixed', {value: 3}); heldObj;",
+            ))
+            .ok()
+            .expect("script failed");
+        esvf.set_property_sync("c", "three".to_string().to_es_value_facade())
+            .ok()
+            .expect("could not set c");
+        esvf.set_property_sync("a", 10.to_es_value_facade())
+            .ok()
+            .expect("could not set a");
+        assert!(esvf
+            .delete_property_sync("b")
+            .ok()
+            .expect("could not delete b"));
+        assert!(!esvf
+            .delete_property_sync("fixed")
+            .ok()
+            .expect("could not delete fixed"));
+        let res = rt
+            .eval_sync(Script::new(
+                "test_set_and_delete_property_sync2.es",
+                "JSON.stringify(heldObj) + ':' + heldObj.fixed;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "{\"a\":10,\"c\":\"three\"}:3");
+
+        let arr = rt
+            .eval_sync(Script::new(
+                "test_set_and_delete_property_sync3.es",
+                "[1, 2];",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(arr.set_property_sync("a", 1.to_es_value_facade()).is_err());
+        assert!(arr.delete_property_sync("0").is_err());
+    }
+
+    #[test]
+    fn test_get_property() {
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_get_property.es",
+                "({a: 1, b: 'two', c: {d: true}});",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(esvf.is_object());
+        // the JS object is not backed by rust
+        assert!(esvf.get_property("a").is_none());
+
+        // the same object converted to a HashMap
+        let map = esvf
+            .get_object()
+            .ok()
+            .expect("get_object failed")
+            .to_es_value_facade();
+
+        let a = map.get_property("a").expect("no a");
+        let a_js = esvf
+            .get_property_from_js("a")
+            .ok()
+            .expect("get_property_from_js failed");
+        assert_eq!(a.get_i32(), a_js.get_i32());
+
+        let b = map.get_property("b").expect("no b");
+        let b_js = esvf
+            .get_property_from_js("b")
+            .ok()
+            .expect("get_property_from_js failed");
+        assert_eq!(b.get_str(), b_js.get_str());
+
+        let c_js = esvf
+            .get_property_from_js("c")
+            .ok()
+            .expect("get_property_from_js failed");
+        assert!(c_js.is_object());
+        let d_js = c_js
+            .get_property_from_js("d")
+            .ok()
+            .expect("get_property_from_js failed");
+        assert!(d_js.get_boolean());
+
+        assert!(map.get_property("e").is_none());
+        assert!(esvf
+            .get_property_from_js("e")
+            .ok()
+            .expect("get_property_from_js failed")
+            .is_undefined());
+
+        assert!(1.to_es_value_facade().get_property("a").is_none());
+    }
+
+    #[test]
+    fn test_btree_map() {
+        let rt = init_test_rt();
+        let mut map = BTreeMap::new();
+        map.insert("c".to_string(), 3.to_es_value_facade());
+        map.insert("a".to_string(), 1.to_es_value_facade());
+        map.insert("b".to_string(), 2.to_es_value_facade());
+        let res = rt
+            .call_function_sync(vec!["Object"], "keys", vec![map.to_es_value_facade()])
+            .ok()
+            .expect("call failed");
+        let keys: Vec<String> = res
+            .get_array()
+            .ok()
+            .expect("not an array")
+            .iter()
+            .map(|k| k.get_str().to_string())
+            .collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn test_index_map() {
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_index_map.es",
+                "({zeta: 1, alpha: 2, mu: 3, beta: 4, omega: 5});",
+            ))
+            .ok()
+            .expect("script failed");
+        let map = esvf.get_object_ordered().ok().expect("not an object");
+        let keys: Vec<&str> = map.keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, vec!["zeta", "alpha", "mu", "beta", "omega"]);
+
+        let res = rt
+            .call_function_sync(vec!["Object"], "keys", vec![map.to_es_value_facade()])
+            .ok()
+            .expect("call failed");
+        let keys2: Vec<String> = res
+            .get_array()
+            .ok()
+            .expect("not an array")
+            .iter()
+            .map(|k| k.get_str().to_string())
+            .collect();
+        assert_eq!(keys2, vec!["zeta", "alpha", "mu", "beta", "omega"]);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new(
+            "test_tuple.es",
+            "this.tupleFunc = function(args) {return args.length + ':' + (args[0] + args[1]) + ':' + args[2];};",
+        ))
+        .ok()
+        .expect("script failed");
+        let tuple = (12, "ab".to_string(), true);
+        let res = rt
+            .call_function_sync(vec![], "tupleFunc", vec![tuple.to_es_value_facade()])
+            .ok()
+            .expect("call failed");
+        assert_eq!(res.get_str(), "3:12ab:true");
+    }
+
+    #[cfg(feature = "vec_u8_as_uint8array")]
+    #[test]
+    fn test_vec_u8() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new(
+            "test_vec_u8.es",
+            "this.checkBytes = function(arr) {return arr instanceof Uint8Array && arr.length === 3 && arr[2] === 255;};",
+        ))
+        .ok()
+        .expect("script failed");
+        let res = rt
+            .call_function_sync(
+                vec![],
+                "checkBytes",
+                vec![vec![0u8, 1, 255].to_es_value_facade()],
+            )
+            .ok()
+            .expect("call failed");
+        assert!(res.get_boolean());
+    }
+
+    #[test]
+    fn test_duration() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new(
+            "test_duration.es",
+            "this.delayed = function(ms) {let start = Date.now(); return new Promise((res) => {setTimeout(() => {res(Date.now() - start);}, ms);});};",
+        ))
+        .ok()
+        .expect("script failed");
+        assert_eq!(
+            Duration::from_millis(1500).to_es_value_facade().get_f64(),
+            1500.0
+        );
+        let prom = rt
+            .call_function_sync(
+                vec![],
+                "delayed",
+                vec![Duration::from_millis(200).to_es_value_facade()],
+            )
+            .ok()
+            .expect("call failed");
+        let elapsed = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        let elapsed = if elapsed.is_i32() {
+            elapsed.get_i32() as f64
+        } else {
+            elapsed.get_f64()
+        };
+        assert!(elapsed >= 190.0);
+        assert!(elapsed < 2000.0);
+    }
+
+    #[test]
+    fn test_then_async() {
+        let rt = init_test_rt();
+        let prom = rt
+            .eval_sync(Script::new("test_then_async.es", "(Promise.resolve(5));"))
+            .ok()
+            .expect("script failed");
+        let res = block_on(prom.then_async(|v| (v.get_i32() * 2).to_es_value_facade()));
+        assert_eq!(res.ok().expect("promise was rejected").get_i32(), 10);
+
+        let prom = rt
+            .eval_sync(Script::new("test_then_async2.es", "(Promise.reject(3));"))
+            .ok()
+            .expect("script failed");
+        let res = block_on(prom.catch_async(|v| (v.get_i32() + 1).to_es_value_facade()));
+        assert_eq!(res.ok().expect("catch did not recover").get_i32(), 4);
+
+        let called = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let called2 = called.clone();
+        let prom = rt
+            .eval_sync(Script::new("test_then_async3.es", "(Promise.reject(7));"))
+            .ok()
+            .expect("script failed");
+        let res = block_on(prom.finally_async(move || {
+            called2.store(true, std::sync::atomic::Ordering::SeqCst);
+        }));
+        assert_eq!(res.err().expect("promise should be rejected").get_i32(), 7);
+        assert!(called.load(std::sync::atomic::Ordering::SeqCst));
+
+        // chain by awaiting the nested futures
+        let prom = rt
+            .eval_sync(Script::new("test_then_async4.es", "(Promise.resolve(1));"))
+            .ok()
+            .expect("script failed");
+        let res = block_on(async move {
+            let first = prom
+                .then_async(|v| (v.get_i32() + 1).to_es_value_facade())
+                .await?;
+            Ok::<i32, EsValueFacade>(first.get_i32() * 10)
+        });
+        assert_eq!(res.ok().expect("chain failed"), 20);
+    }
+
+    #[test]
+    fn test_nan_infinity() {
+        let rt = init_test_rt();
+        let nan = rt
+            .eval_sync(Script::new("test_nan.es", "NaN + 1;"))
+            .ok()
+            .expect("script failed");
+        assert!(nan.is_nan());
+        assert!(!nan.is_infinity());
+        assert!(nan.get_f64().is_nan());
+
+        let inf = rt
+            .eval_sync(Script::new("test_inf.es", "-1 / 0;"))
+            .ok()
+            .expect("script failed");
+        assert!(inf.is_infinity());
+        assert!(!inf.is_nan());
+        assert_eq!(inf.get_f64(), f64::NEG_INFINITY);
+
+        let num = rt
+            .eval_sync(Script::new("test_num.es", "1.5;"))
+            .ok()
+            .expect("script failed");
+        assert!(!num.is_nan());
+        assert!(!num.is_infinity());
+
+        rt.eval_sync(Script::new(
+            "test_nan_infinity.es",
+            "this.check_nan_inf = function(a, b) {return Number.isNaN(a) && b === Infinity;};",
+        ))
+        .ok()
+        .expect("script failed");
+        let res = rt
+            .call_function_sync(
+                vec![],
+                "check_nan_inf",
+                vec![
+                    ES_NAN.to_es_value_facade(),
+                    EsInfinityValue(true).to_es_value_facade(),
+                ],
+            )
+            .ok()
+            .expect("call failed");
+        assert!(res.get_boolean());
+    }
+
+    #[test]
+    fn test_get_set_path() {
+        let mut d = HashMap::new();
+        d.insert("d".to_string(), "found".to_string().to_es_value_facade());
+        let c = vec![
+            1.to_es_value_facade(),
+            d.to_es_value_facade(),
+            3.to_es_value_facade(),
+        ];
+        let mut b = HashMap::new();
+        b.insert("c".to_string(), c.to_es_value_facade());
+        let mut a = BTreeMap::new();
+        a.insert("b".to_string(), b.to_es_value_facade());
+        let mut root = HashMap::new();
+        root.insert("a".to_string(), a.to_es_value_facade());
+        let mut root = root.to_es_value_facade();
+
+        assert_eq!(root.get_path("a.b.c[1].d").unwrap().get_str(), "found");
+        assert_eq!(root.get_path("a.b.c[2]").unwrap().get_i32(), 3);
+        assert!(root.get_path("a.b.c[3]").is_none());
+        assert!(root.get_path("a.x").is_none());
+        assert!(root.get_path("a.b.c[1").is_none());
+        assert!(root.get_path("a..b").is_none());
+
+        root.set_path("a.b.c[1].e", 42.to_es_value_facade())
+            .ok()
+            .expect("set_path failed");
+        root.set_path("a.b.c[3]", true.to_es_value_facade())
+            .ok()
+            .expect("set_path failed");
+        assert_eq!(root.get_path("a.b.c[1].e").unwrap().get_i32(), 42);
+        assert!(root.get_path("a.b.c[3]").unwrap().get_boolean());
+        assert!(root.set_path("a.x.y", 1.to_es_value_facade()).is_err());
+    }
+
+    #[test]
+    fn test_to_json_string() {
+        let mut inner = HashMap::new();
+        inner.insert("b".to_string(), true.to_es_value_facade());
+        inner.insert("a".to_string(), ES_NULL.to_es_value_facade());
+        let mut obj = HashMap::new();
+        obj.insert("name".to_string(), "x\"y".to_string().to_es_value_facade());
+        obj.insert(
+            "list".to_string(),
+            vec![1.to_es_value_facade(), 1.5.to_es_value_facade()].to_es_value_facade(),
+        );
+        obj.insert("inner".to_string(), inner.to_es_value_facade());
+        let obj = obj.to_es_value_facade();
+
+        assert_eq!(
+            obj.to_json_string().ok().expect("could not serialize"),
+            r#"{"inner":{"a":null,"b":true},"list":[1,1.5],"name":"x\"y"}"#
+        );
+        assert_eq!(
+            obj.to_pretty_json_string().ok().expect("could not serialize"),
+            "{\n  \"inner\": {\n    \"a\": null,\n    \"b\": true\n  },\n  \"list\": [\n    1,\n    1.5\n  ],\n  \"name\": \"x\\\"y\"\n}"
+        );
+
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_to_json_string.es",
+                "({a: [1, 'two'], f: function() {}});",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(esvf.to_json_string(), Err("not serializable"));
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_to_json_string2.es",
+                "({b: [1, 'two'], a: NaN});",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(
+            esvf.to_json_string().ok().expect("could not serialize"),
+            r#"{"a":null,"b":[1,"two"]}"#
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_into_typed() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct User {
+            name: String,
+            age: u32,
+        }
+        let rt = init_test_rt();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_into_typed.es",
+                "(function getUser() {return {name: 'Alice', age: 30};})();",
+            ))
+            .ok()
+            .expect("script failed");
+        let user: User = esvf.into_typed().ok().expect("could not convert");
+        assert_eq!(
+            user,
+            User {
+                name: "Alice".to_string(),
+                age: 30
+            }
+        );
+
+        let esvf = rt
+            .eval_sync(Script::new("test_into_typed2.es", "({name: 'Bob'});"))
+            .ok()
+            .expect("script failed");
+        assert!(esvf.into_typed::<User>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_typed() {
+        #[derive(serde::Serialize)]
+        struct User {
+            name: String,
+            age: u32,
+        }
+        let users = vec![
+            User {
+                name: "Alice".to_string(),
+                age: 30,
+            },
+            User {
+                name: "Bob".to_string(),
+                age: 40,
+            },
+        ];
+        let rt = init_test_rt();
+        let func = rt
+            .eval_sync(Script::new(
+                "test_from_typed.es",
+                "(function(arr) {return arr[0].name + ':' + arr[1].age + ':' + arr.length;});",
+            ))
+            .ok()
+            .expect("script failed");
+        let arg = EsValueFacade::from_typed(users)
+            .ok()
+            .expect("could not convert");
+        let res = func
+            .invoke_function_sync(vec![arg])
+            .ok()
+            .expect("function failed");
+        assert_eq!(res.get_str(), "Alice:40:2");
+    }
 }
@@ -6,6 +6,9 @@
 //! * console.error()
 //! * console.warning()
 //! * console.trace()
+//! * console.time() and console.timeEnd()
+//! * console.table(), formats an array (or object) of objects as an ASCII table
+//! * console.assert(), logs an error only when the first argument is falsy
 //!
 //! The methods use rust's log crate to output messages. e.g. console.info() uses the log::info!() macro
 //! so the console messages should appear in the log you initialized from rust
@@ -41,19 +44,33 @@
 //! which will result in a log entry like
 //! ```[00:00:00.012] (7f44e7d24700) INFO   the quick brown fox jumped over 32 fences with a accuracy of 0.51```
 
+use crate::esruntime::{EsRuntime, WeakEsRuntime};
+use crate::esvalue::{EsValueConvertible, EsValueFacade};
 use crate::quickjs_utils;
 use crate::quickjs_utils::functions::call_to_string;
-use crate::quickjs_utils::{functions, json, parse_args, primitives};
+use crate::quickjs_utils::{arrays, functions, json, objects, parse_args, primitives};
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::QuickJsRuntime;
 use crate::reflection::Proxy;
 use crate::valueref::JSValueRef;
 use hirofa_utils::js_utils::JsError;
 use libquickjs_sys as q;
-use log::LevelFilter;
+use log::Level;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+thread_local! {
+    /// the timers started by console.time() per context
+    static CONSOLE_TIMERS: RefCell<HashMap<String, HashMap<String, Instant>>> = RefCell::new(HashMap::new());
+}
 
 pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    q_js_rt.add_context_drop_hook(|q_ctx| {
+        CONSOLE_TIMERS.with(|rc| rc.borrow_mut().remove(&q_ctx.id));
+    });
     q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| init_ctx(q_ctx))
 }
 
@@ -65,12 +82,159 @@ pub(crate) fn init_ctx(q_ctx: &QuickJsContext) -> Result<(), JsError> {
         .static_native_method("info", Some(console_info))
         .static_native_method("warn", Some(console_warn))
         .static_native_method("error", Some(console_error))
-        //.static_native_method("assert", Some(console_assert)) // todo
+        .static_native_method("assert", Some(console_assert))
         .static_native_method("debug", Some(console_debug))
+        .static_native_method("time", Some(console_time))
+        .static_native_method("timeEnd", Some(console_time_end))
+        .static_native_method("table", Some(console_table))
         .install(q_ctx, true)
         .map(|_| {})
 }
 
+/// the level of a console message, console.log() uses [ConsoleLevel::Log] which is logged at info level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleLevel {
+    Trace,
+    Debug,
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+impl ConsoleLevel {
+    pub fn get_log_level(&self) -> Level {
+        match self {
+            ConsoleLevel::Trace => Level::Trace,
+            ConsoleLevel::Debug => Level::Debug,
+            ConsoleLevel::Log => Level::Info,
+            ConsoleLevel::Info => Level::Info,
+            ConsoleLevel::Warn => Level::Warn,
+            ConsoleLevel::Error => Level::Error,
+        }
+    }
+}
+
+/// a handler which receives all console messages instead of the log crate
+pub type ConsoleHandler = dyn Fn(ConsoleLevel, &str) + Send + Sync;
+
+/// a handler which receives the arguments of all console calls as EsValueFacades instead of a formatted string
+/// this may be used to ship structured logging data (e.g. serialized to JSON)
+pub type StructuredConsoleHandler = dyn Fn(ConsoleLevel, Vec<EsValueFacade>) + Send + Sync;
+
+/// set the handler for console messages of the QuickJsRuntime, returns the previous handler
+pub(crate) fn set_console_handler(
+    q_js_rt: &QuickJsRuntime,
+    handler: Option<Arc<ConsoleHandler>>,
+) -> Option<Arc<ConsoleHandler>> {
+    q_js_rt.console_handler.replace(handler)
+}
+
+/// collects console messages until it is dropped, when dropped the previous console handler is restored
+/// see [EsRuntime::capture_console_output]
+pub struct ConsoleCapture {
+    rt: WeakEsRuntime,
+    messages: Arc<Mutex<Vec<(ConsoleLevel, String)>>>,
+    previous_handler: Option<Arc<ConsoleHandler>>,
+}
+
+impl ConsoleCapture {
+    pub(crate) fn new(es_rt: &Arc<EsRuntime>) -> Self {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let messages2 = messages.clone();
+        let handler: Arc<ConsoleHandler> = Arc::new(move |level, message: &str| {
+            messages2.lock().unwrap().push((level, message.to_string()));
+        });
+        let previous_handler = es_rt
+            .exe_rt_task_in_event_loop(move |q_js_rt| set_console_handler(q_js_rt, Some(handler)));
+        Self {
+            rt: es_rt.weak_ref(),
+            messages,
+            previous_handler,
+        }
+    }
+    /// get and remove all messages which were collected so far
+    pub fn take_messages(&self) -> Vec<(ConsoleLevel, String)> {
+        std::mem::take(&mut *self.messages.lock().unwrap())
+    }
+}
+
+impl Drop for ConsoleCapture {
+    fn drop(&mut self) {
+        let previous_handler = self.previous_handler.take();
+        self.rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            set_console_handler(q_js_rt, previous_handler);
+        });
+    }
+}
+
+unsafe fn log_line(
+    ctx: *mut q::JSContext,
+    level: ConsoleLevel,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) {
+    let (handler, handled_structured) = QuickJsRuntime::do_with(|q_js_rt| {
+        let handled_structured = match &q_js_rt.structured_console_handler {
+            Some(structured_handler) => {
+                let q_ctx = q_js_rt.get_quickjs_context(ctx);
+                let values: Result<Vec<EsValueFacade>, JsError> = parse_args(ctx, argc, argv)
+                    .iter()
+                    .map(|arg| EsValueFacade::from_jsval(q_ctx, arg))
+                    .collect();
+                match values {
+                    Ok(values) => structured_handler(level, values),
+                    Err(e) => log::error!("could not convert console arguments: {}", e),
+                }
+                true
+            }
+            None => false,
+        };
+        (q_js_rt.console_handler.borrow().clone(), handled_structured)
+    });
+    if let Some(handler) = handler {
+        let args = parse_args(ctx, argc, argv);
+        handler(level, parse_line(ctx, args).as_str());
+    } else if !handled_structured && log::max_level() >= level.get_log_level() {
+        let args = parse_args(ctx, argc, argv);
+        log::log!(level.get_log_level(), "{}", parse_line(ctx, args));
+    }
+}
+
+/// log a message which was formatted in rust (e.g. the output of console.timeEnd())
+fn log_message(level: ConsoleLevel, message: String) {
+    let (handler, handled_structured) = QuickJsRuntime::do_with(|q_js_rt| {
+        let handled_structured = match &q_js_rt.structured_console_handler {
+            Some(structured_handler) => {
+                structured_handler(level, vec![message.clone().to_es_value_facade()]);
+                true
+            }
+            None => false,
+        };
+        (q_js_rt.console_handler.borrow().clone(), handled_structured)
+    });
+    if let Some(handler) = handler {
+        handler(level, message.as_str());
+    } else if !handled_structured {
+        log::log!(level.get_log_level(), "{}", message);
+    }
+}
+
+/// get the label of a console.time() call, the label defaults to "default"
+unsafe fn parse_timer_label(
+    ctx: *mut q::JSContext,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> String {
+    let args = parse_args(ctx, argc, argv);
+    match args.first() {
+        Some(label_ref) if !label_ref.is_undefined() => {
+            functions::call_to_string(ctx, label_ref).unwrap_or_else(|_| "default".to_string())
+        }
+        _ => "default".to_string(),
+    }
+}
+
 #[allow(clippy::or_fun_call)]
 unsafe fn parse_field_value(ctx: *mut q::JSContext, field: &str, value: &JSValueRef) -> String {
     // format ints
@@ -209,10 +373,7 @@ unsafe extern "C" fn console_log(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Info {
-        let args = parse_args(ctx, argc, argv);
-        log::info!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, ConsoleLevel::Log, argc, argv);
     quickjs_utils::new_null()
 }
 
@@ -222,10 +383,7 @@ unsafe extern "C" fn console_trace(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Trace {
-        let args = parse_args(ctx, argc, argv);
-        log::trace!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, ConsoleLevel::Trace, argc, argv);
     quickjs_utils::new_null()
 }
 
@@ -235,10 +393,7 @@ unsafe extern "C" fn console_debug(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Debug {
-        let args = parse_args(ctx, argc, argv);
-        log::debug!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, ConsoleLevel::Debug, argc, argv);
     quickjs_utils::new_null()
 }
 
@@ -248,10 +403,7 @@ unsafe extern "C" fn console_info(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Info {
-        let args = parse_args(ctx, argc, argv);
-        log::info!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, ConsoleLevel::Info, argc, argv);
     quickjs_utils::new_null()
 }
 
@@ -261,10 +413,7 @@ unsafe extern "C" fn console_warn(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Warn {
-        let args = parse_args(ctx, argc, argv);
-        log::warn!("{}", parse_line(ctx, args));
-    }
+    log_line(ctx, ConsoleLevel::Warn, argc, argv);
     quickjs_utils::new_null()
 }
 
@@ -274,9 +423,193 @@ unsafe extern "C" fn console_error(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    if log::max_level() >= LevelFilter::Error {
-        let args = parse_args(ctx, argc, argv);
-        log::error!("{}", parse_line(ctx, args));
+    log_line(ctx, ConsoleLevel::Error, argc, argv);
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_time(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let label = parse_timer_label(ctx, argc, argv);
+    let ctx_id = QuickJsContext::get_id(ctx).to_string();
+    let exists = CONSOLE_TIMERS.with(|rc| {
+        let timers = &mut *rc.borrow_mut();
+        let ctx_timers = timers.entry(ctx_id).or_default();
+        if ctx_timers.contains_key(&label) {
+            true
+        } else {
+            ctx_timers.insert(label.clone(), Instant::now());
+            false
+        }
+    });
+    if exists {
+        log_message(
+            ConsoleLevel::Warn,
+            format!("Timer '{}' already exists", label),
+        );
+    }
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_time_end(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let label = parse_timer_label(ctx, argc, argv);
+    let ctx_id = QuickJsContext::get_id(ctx);
+    let start_opt = CONSOLE_TIMERS.with(|rc| {
+        let timers = &mut *rc.borrow_mut();
+        timers
+            .get_mut(ctx_id)
+            .and_then(|ctx_timers| ctx_timers.remove(&label))
+    });
+    match start_opt {
+        Some(start) => log_message(
+            ConsoleLevel::Log,
+            format!("{}: {:.3}ms", label, start.elapsed().as_secs_f64() * 1000.0),
+        ),
+        None => log_message(
+            ConsoleLevel::Warn,
+            format!("Timer '{}' does not exist", label),
+        ),
+    }
+    quickjs_utils::new_null()
+}
+
+/// format the rows of an array (or the properties of an object) as an ASCII table, the columns are the properties
+/// of the rows, rows which are not objects are put in a Values column
+unsafe fn format_table(ctx: *mut q::JSContext, data_ref: &JSValueRef) -> Result<String, JsError> {
+    let mut rows: Vec<(String, JSValueRef)> = vec![];
+    if arrays::is_array(ctx, data_ref) {
+        for x in 0..arrays::get_length(ctx, data_ref)? {
+            rows.push((x.to_string(), arrays::get_element(ctx, data_ref, x)?));
+        }
+    } else {
+        for name in objects::get_property_names(ctx, data_ref)? {
+            let value_ref = objects::get_property(ctx, data_ref, name.as_str())?;
+            rows.push((name, value_ref));
+        }
+    }
+
+    let mut columns: Vec<String> = vec![];
+    let mut has_values_column = false;
+    let mut cells: Vec<HashMap<String, String>> = vec![];
+    for (_, row_ref) in &rows {
+        let mut row_cells = HashMap::new();
+        if row_ref.is_object() && !functions::is_function(ctx, row_ref) {
+            for name in objects::get_property_names(ctx, row_ref)? {
+                let cell_ref = objects::get_property(ctx, row_ref, name.as_str())?;
+                if !columns.contains(&name) {
+                    columns.push(name.clone());
+                }
+                row_cells.insert(name, functions::call_to_string(ctx, &cell_ref)?);
+            }
+        } else {
+            has_values_column = true;
+            row_cells.insert(
+                "Values".to_string(),
+                functions::call_to_string(ctx, row_ref)?,
+            );
+        }
+        cells.push(row_cells);
+    }
+    if has_values_column {
+        columns.push("Values".to_string());
+    }
+
+    let mut headers = vec!["(index)".to_string()];
+    headers.extend(columns.iter().cloned());
+    let table_rows: Vec<Vec<String>> = rows
+        .iter()
+        .zip(cells.iter())
+        .map(|((index, _), row_cells)| {
+            let mut table_row = vec![index.clone()];
+            for column in &columns {
+                table_row.push(row_cells.get(column).cloned().unwrap_or_default());
+            }
+            table_row
+        })
+        .collect();
+
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(x, header)| {
+            table_rows
+                .iter()
+                .map(|table_row| table_row[x].chars().count())
+                .chain(std::iter::once(header.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<String>>()
+        .join("+");
+    let separator = format!("+{}+", separator);
+    let format_row = |table_row: &[String]| {
+        let row = table_row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!(" {:width$} ", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("|");
+        format!("|{}|", row)
+    };
+
+    let mut lines = vec![separator.clone(), format_row(&headers), separator.clone()];
+    for table_row in &table_rows {
+        lines.push(format_row(table_row));
+    }
+    lines.push(separator);
+    Ok(lines.join("\n"))
+}
+
+unsafe extern "C" fn console_table(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let args = parse_args(ctx, argc, argv);
+    match args.first() {
+        Some(data_ref) if data_ref.is_object() => match format_table(ctx, data_ref) {
+            Ok(table) => log_message(ConsoleLevel::Log, table),
+            Err(e) => log::error!("could not format console.table: {}", e),
+        },
+        // like other environments, fall back to console.log for primitives
+        _ => log_line(ctx, ConsoleLevel::Log, argc, argv),
+    }
+    quickjs_utils::new_null()
+}
+
+unsafe extern "C" fn console_assert(
+    ctx: *mut q::JSContext,
+    _this_val: q::JSValue,
+    argc: ::std::os::raw::c_int,
+    argv: *mut q::JSValue,
+) -> q::JSValue {
+    let mut args = parse_args(ctx, argc, argv);
+    let passed = match args.first() {
+        Some(condition_ref) => primitives::is_truthy(ctx, condition_ref),
+        None => false,
+    };
+    if !passed {
+        let message = if args.len() > 1 {
+            args.remove(0);
+            format!("Assertion failed: {}", parse_line(ctx, args))
+        } else {
+            "Assertion failed".to_string()
+        };
+        log_message(ConsoleLevel::Error, message);
     }
     quickjs_utils::new_null()
 }
@@ -285,8 +618,10 @@ unsafe extern "C" fn console_error(
 pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntime::EsRuntime;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::features::console::ConsoleLevel;
     use hirofa_utils::js_utils::Script;
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     pub fn test_console() {
@@ -300,4 +635,148 @@ pub mod tests {
         .expect("test_console.es failed");
         log::info!("< test_console");
     }
+
+    #[test]
+    pub fn test_capture_console_output() {
+        let rt: Arc<EsRuntime> = init_test_rt();
+        let capture = rt.capture_console_output();
+        rt.eval_sync(Script::new(
+            "test_capture_console_output.es",
+            "console.log('hello'); console.error('oops %s', 1);",
+        ))
+        .ok()
+        .expect("script failed");
+        assert_eq!(
+            capture.take_messages(),
+            vec![
+                (ConsoleLevel::Log, "hello".to_string()),
+                (ConsoleLevel::Error, "oops 1".to_string())
+            ]
+        );
+        assert!(capture.take_messages().is_empty());
+        drop(capture);
+
+        rt.eval_sync(Script::new(
+            "test_capture_console_output2.es",
+            "console.log('not captured');",
+        ))
+        .ok()
+        .expect("script failed");
+        let has_handler =
+            rt.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.console_handler.borrow().is_some());
+        assert!(!has_handler);
+    }
+
+    #[test]
+    pub fn test_structured_console_handler() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let received2 = received.clone();
+        let rt = EsRuntimeBuilder::new()
+            .structured_console_handler(Box::new(move |level, values| {
+                let summary: Vec<String> = values
+                    .iter()
+                    .map(|value| {
+                        if value.is_object() {
+                            format!(
+                                "object:{}",
+                                value.get_object().ok().unwrap().get("a").unwrap().get_i32()
+                            )
+                        } else {
+                            value.get_str().to_string()
+                        }
+                    })
+                    .collect();
+                received2.lock().unwrap().push((level, summary));
+            }))
+            .build();
+        rt.eval_sync(Script::new(
+            "test_structured_console_handler.es",
+            "console.info('data', {a: 12});",
+        ))
+        .ok()
+        .expect("script failed");
+        let received = &*received.lock().unwrap();
+        assert_eq!(
+            received,
+            &vec![(
+                ConsoleLevel::Info,
+                vec!["data".to_string(), "object:12".to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    pub fn test_console_time() {
+        let rt = EsRuntimeBuilder::new().build();
+        let capture = rt.capture_console_output();
+        rt.eval_sync(Script::new(
+            "test_console_time.es",
+            "console.time('x'); let s = Date.now(); while (Date.now() - s < 50) {} console.timeEnd('x'); console.timeEnd('x');",
+        ))
+        .ok()
+        .expect("script failed");
+        let messages = capture.take_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, ConsoleLevel::Log);
+        let elapsed: f64 = messages[0]
+            .1
+            .strip_prefix("x: ")
+            .and_then(|m| m.strip_suffix("ms"))
+            .expect("unexpected message")
+            .parse()
+            .ok()
+            .expect("could not parse elapsed ms");
+        assert!(elapsed >= 50.0);
+        assert_eq!(
+            messages[1],
+            (ConsoleLevel::Warn, "Timer 'x' does not exist".to_string())
+        );
+    }
+
+    #[test]
+    pub fn test_console_table() {
+        let rt = EsRuntimeBuilder::new().build();
+        let capture = rt.capture_console_output();
+        rt.eval_sync(Script::new(
+            "test_console_table.es",
+            "console.table([{a: 1, b: 2}, {a: 3, b: 4}]);",
+        ))
+        .ok()
+        .expect("script failed");
+        let messages = capture.take_messages();
+        assert_eq!(messages.len(), 1);
+        let table = messages[0].1.as_str();
+        assert_eq!(
+            table,
+            "+---------+---+---+\n\
+             | (index) | a | b |\n\
+             +---------+---+---+\n\
+             | 0       | 1 | 2 |\n\
+             | 1       | 3 | 4 |\n\
+             +---------+---+---+"
+        );
+    }
+
+    #[test]
+    pub fn test_console_assert() {
+        let rt = EsRuntimeBuilder::new().build();
+        let capture = rt.capture_console_output();
+        rt.eval_sync(Script::new(
+            "test_console_assert.es",
+            "console.assert(true, 'ok'); console.assert(1, 'ok'); console.assert(false, 'fail!'); console.assert('', 'empty %s', 'string'); console.assert(0);",
+        ))
+        .ok()
+        .expect("script failed");
+        assert_eq!(
+            capture.take_messages(),
+            vec![
+                (ConsoleLevel::Error, "Assertion failed: fail!".to_string()),
+                (
+                    ConsoleLevel::Error,
+                    "Assertion failed: empty string".to_string()
+                ),
+                (ConsoleLevel::Error, "Assertion failed".to_string())
+            ]
+        );
+    }
 }
@@ -0,0 +1,540 @@
+//! provides the EventSource class for the runtime (server-sent events)
+//!
+//! the http connection is read by a dedicated thread per EventSource, events are dispatched in the event loop of the
+//! runtime, both to the on[event] handlers and to listeners added with addEventListener
+//! the on[event] handlers are stored as properties of the EventSource object itself
+//! please note that the connection is not re-established when it is closed by the server
+//!
+//! this feature is only available when the `event_source` feature is enabled
+//!
+//! # Example
+//! ```javascript
+//! let es = new EventSource('http://127.0.0.1:8080/events');
+//! es.onmessage = (evt) => {console.log('got %s', evt.data);};
+//! es.addEventListener('update', (evt) => {console.log('got update %s', evt.data);});
+//! ```
+
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use crate::reflection;
+use crate::reflection::eventtarget;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use tokio_util::sync::CancellationToken;
+
+const EVENT_SOURCE_PROXY_NAME: &str = "EventSource";
+
+const CONNECTING: u16 = 0;
+const OPEN: u16 = 1;
+const CLOSED: u16 = 2;
+
+const HANDLER_EVENT_TYPES: [&str; 3] = ["open", "message", "error"];
+
+/// an event parsed from an event stream
+#[derive(Debug, PartialEq)]
+pub struct SseEvent {
+    pub event_type: String,
+    pub data: String,
+    pub last_event_id: String,
+}
+
+/// parses an event stream line by line, see [the spec](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation)
+#[derive(Default)]
+pub struct EventStreamParser {
+    event_type: String,
+    data: String,
+    last_event_id: String,
+}
+
+impl EventStreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// feed a line (without the line ending) to the parser, returns an event when the line completes one
+    pub fn feed_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            let event_type = std::mem::take(&mut self.event_type);
+            let data = std::mem::take(&mut self.data);
+            if data.is_empty() {
+                return None;
+            }
+            return Some(SseEvent {
+                event_type: if event_type.is_empty() {
+                    "message".to_string()
+                } else {
+                    event_type
+                },
+                data: data.strip_suffix('\n').unwrap_or(&data).to_string(),
+                last_event_id: self.last_event_id.clone(),
+            });
+        }
+        if line.starts_with(':') {
+            // comment
+            return None;
+        }
+        let (field, value) = match line.find(':') {
+            Some(idx) => {
+                let value = &line[idx + 1..];
+                (&line[..idx], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" => self.last_event_id = value.to_string(),
+            _ => {}
+        }
+        None
+    }
+}
+
+enum EsEvent {
+    Open,
+    Event(SseEvent),
+    Error(String),
+}
+
+struct EventSourceState {
+    url: String,
+    ready_state: u16,
+    cancel: CancellationToken,
+}
+
+thread_local! {
+    static EVENT_SOURCES: RefCell<HashMap<usize, EventSourceState>> = RefCell::new(HashMap::new());
+}
+
+fn with_event_source_state<C, R>(instance_id: &usize, consumer: C) -> Option<R>
+where
+    C: FnOnce(&mut EventSourceState) -> R,
+{
+    EVENT_SOURCES.with(|sources_rc| {
+        let sources = &mut *sources_rc.borrow_mut();
+        sources.get_mut(instance_id).map(consumer)
+    })
+}
+
+pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    log::trace!("event_source::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| init_event_source_proxy(q_ctx))
+}
+
+/// close the EventSource, this drops the response so a read which is waiting for the server is ended as well
+fn close(instance_id: &usize) {
+    with_event_source_state(instance_id, |state| {
+        state.ready_state = CLOSED;
+        state.cancel.cancel();
+    });
+}
+
+fn init_event_source_proxy(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    reflection::Proxy::new()
+        .name(EVENT_SOURCE_PROXY_NAME)
+        .event_target()
+        .constructor(|q_ctx, instance_id, args| {
+            if args.is_empty() || !args[0].is_string() {
+                return Err(JsError::new_str("EventSource requires a url argument"));
+            }
+            let url = primitives::to_string_q(q_ctx, &args[0])?;
+
+            let es_rt = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref())
+                .ok_or_else(|| JsError::new_str("Runtime was dropped"))?;
+
+            let cancel = CancellationToken::new();
+
+            let weak_rt = es_rt.weak_ref();
+            let context_id = q_ctx.id.clone();
+            let thread_url = url.clone();
+            let thread_cancel = cancel.clone();
+            // the stream is read until the server closes it so it gets its own thread instead of a helper task
+            std::thread::Builder::new()
+                .name(format!("event_source-{}", instance_id))
+                .spawn(move || {
+                    read_event_stream(thread_url, thread_cancel, |event| {
+                        let context_id = context_id.clone();
+                        weak_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+                            dispatch(q_js_rt, context_id.as_str(), instance_id, event);
+                        })
+                    })
+                })
+                .map_err(|e| {
+                    JsError::new_string(format!("could not start EventSource thread: {}", e))
+                })?;
+
+            EVENT_SOURCES.with(|sources_rc| {
+                let sources = &mut *sources_rc.borrow_mut();
+                sources.insert(
+                    instance_id,
+                    EventSourceState {
+                        url,
+                        ready_state: CONNECTING,
+                        cancel,
+                    },
+                );
+            });
+            Ok(())
+        })
+        .getter_setter(
+            "url",
+            |q_ctx, instance_id| {
+                let url = with_event_source_state(instance_id, |state| state.url.clone())
+                    .unwrap_or_default();
+                primitives::from_string_q(q_ctx, url.as_str())
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .getter_setter(
+            "readyState",
+            |_q_ctx, instance_id| {
+                let ready_state = with_event_source_state(instance_id, |state| state.ready_state)
+                    .unwrap_or(CLOSED);
+                Ok(primitives::from_i32(ready_state as i32))
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .method("close", |_q_ctx, instance_id, _args| {
+            close(instance_id);
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .finalizer(|_q_ctx, instance_id| {
+            log::trace!("dropping EventSource {}", instance_id);
+            close(&instance_id);
+            EVENT_SOURCES.with(|sources_rc| {
+                let sources = &mut *sources_rc.borrow_mut();
+                sources.remove(&instance_id);
+            });
+        })
+        .install(q_ctx, true)
+        .map(|_| {})
+}
+
+/// dispatch an event in the event loop, this calls the on[event] handler and the event listeners
+fn dispatch(q_js_rt: &QuickJsRuntime, context_id: &str, instance_id: usize, event: EsEvent) {
+    if !q_js_rt.has_context(context_id) {
+        return;
+    }
+    let q_ctx = q_js_rt.get_context(context_id);
+
+    let event_type = match &event {
+        EsEvent::Open => "open".to_string(),
+        EsEvent::Event(sse_event) => sse_event.event_type.clone(),
+        EsEvent::Error(_) => "error".to_string(),
+    };
+
+    let open = with_event_source_state(&instance_id, |state| {
+        if state.ready_state == CLOSED {
+            return false;
+        }
+        match &event {
+            EsEvent::Open => state.ready_state = OPEN,
+            EsEvent::Error(_) => state.ready_state = CLOSED,
+            _ => {}
+        }
+        true
+    })
+    .unwrap_or(false);
+    // the EventSource was closed or finalized
+    let instance_ref = match reflection::get_proxy_instance_ref(q_ctx, instance_id) {
+        Some(instance_ref) if open => instance_ref,
+        _ => return,
+    };
+
+    let res = create_event(q_ctx, event_type.as_str(), event).and_then(|event_ref| {
+        // custom event types only go to the event listeners
+        if HANDLER_EVENT_TYPES.contains(&event_type.as_str()) {
+            let handler_name = format!("on{}", event_type);
+            let handler = objects::get_property_q(q_ctx, &instance_ref, handler_name.as_str())?;
+            if functions::is_function_q(q_ctx, &handler) {
+                functions::call_function_q(q_ctx, &handler, vec![event_ref.clone()], None)?;
+            }
+        }
+        let proxy = reflection::get_proxy(q_ctx, EVENT_SOURCE_PROXY_NAME)
+            .expect("EventSource proxy was not installed");
+        eventtarget::dispatch_event(q_ctx, &proxy, instance_id, event_type.as_str(), event_ref)
+    });
+    if let Err(e) = res {
+        log::error!("EventSource {} handler failed: {}", event_type, e);
+    }
+}
+
+fn create_event(
+    q_ctx: &QuickJsContext,
+    event_type: &str,
+    event: EsEvent,
+) -> Result<JSValueRef, JsError> {
+    let event_ref = objects::create_object_q(q_ctx)?;
+    objects::set_property_q(
+        q_ctx,
+        &event_ref,
+        "type",
+        &primitives::from_string_q(q_ctx, event_type)?,
+    )?;
+    match event {
+        EsEvent::Open => {}
+        EsEvent::Event(sse_event) => {
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "data",
+                &primitives::from_string_q(q_ctx, sse_event.data.as_str())?,
+            )?;
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "lastEventId",
+                &primitives::from_string_q(q_ctx, sse_event.last_event_id.as_str())?,
+            )?;
+        }
+        EsEvent::Error(message) => {
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "message",
+                &primitives::from_string_q(q_ctx, message.as_str())?,
+            )?;
+        }
+    }
+    Ok(event_ref)
+}
+
+/// read an event stream, this runs in its own thread until the stream ends or the EventSource is closed
+fn read_event_stream<P>(url: String, cancel: CancellationToken, post: P)
+where
+    P: Fn(EsEvent),
+{
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build();
+    let rt = match rt {
+        Ok(rt) => rt,
+        Err(e) => {
+            post(EsEvent::Error(format!(
+                "could not start reading {}: {}",
+                url, e
+            )));
+            return;
+        }
+    };
+    rt.block_on(async {
+        // when the EventSource is closed the pending read and the response are dropped
+        tokio::select! {
+            _ = cancel.cancelled() => {}
+            _ = read_event_stream_async(url.as_str(), &post) => {}
+        }
+    });
+}
+
+async fn read_event_stream_async<P>(url: &str, post: &P)
+where
+    P: Fn(EsEvent),
+{
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("Accept", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let mut response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            post(EsEvent::Error(format!(
+                "could not connect to {}: {}",
+                url, e
+            )));
+            return;
+        }
+    };
+    post(EsEvent::Open);
+
+    let mut parser = EventStreamParser::new();
+    let mut buffer: Vec<u8> = vec![];
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                buffer.extend_from_slice(&chunk);
+                while let Some(idx) = buffer.iter().position(|b| *b == b'\n') {
+                    let line_bytes: Vec<u8> = buffer.drain(..=idx).collect();
+                    let line = String::from_utf8_lossy(&line_bytes[..idx]);
+                    let line = line.strip_suffix('\r').unwrap_or(&line);
+                    if let Some(event) = parser.feed_line(line) {
+                        post(EsEvent::Event(event));
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                post(EsEvent::Error(format!(
+                    "reading event stream failed: {}",
+                    e
+                )));
+                return;
+            }
+        }
+    }
+    post(EsEvent::Error("event stream was closed".to_string()));
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::features::event_source::{EventStreamParser, SseEvent};
+    use hirofa_utils::js_utils::Script;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parser() {
+        let mut parser = EventStreamParser::new();
+        let mut events = vec![];
+        for line in &[
+            ": a comment",
+            "event: update",
+            "id: 1",
+            "data: first line",
+            "data:second line",
+            "",
+            "",
+            "data: plain",
+            "",
+        ] {
+            if let Some(event) = parser.feed_line(line) {
+                events.push(event);
+            }
+        }
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event_type: "update".to_string(),
+                    data: "first line\nsecond line".to_string(),
+                    last_event_id: "1".to_string(),
+                },
+                SseEvent {
+                    event_type: "message".to_string(),
+                    data: "plain".to_string(),
+                    last_event_id: "1".to_string(),
+                },
+            ]
+        );
+    }
+
+    /// start an http server which sends 3 events 50 ms apart to a single client, returns the port
+    fn start_sse_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind");
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _addr) = listener.accept().expect("accept failed");
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok() && line != "\r\n" {
+                line.clear();
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            for event in &[
+                "data: one\n\n",
+                "event: custom\ndata: two\n\n",
+                "data: three\n\n",
+            ] {
+                stream.write_all(event.as_bytes()).unwrap();
+                stream.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_event_source() {
+        let port = start_sse_server();
+        let rt = init_test_rt();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_event_source.es",
+                format!(
+                    "let es = new EventSource('http://127.0.0.1:{}/events');\n\
+                     new Promise((resolve, reject) => {{\n\
+                        let received = [];\n\
+                        let add = (evt) => {{\n\
+                            received.push(evt.type + ':' + evt.data);\n\
+                            if (received.length === 3) {{\n\
+                                es.close();\n\
+                                resolve(received.join(','));\n\
+                            }}\n\
+                        }};\n\
+                        es.onmessage = add;\n\
+                        es.addEventListener('custom', add);\n\
+                        es.onerror = (evt) => {{reject(evt.message);}};\n\
+                     }});",
+                    port
+                )
+                .as_str(),
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "message:one,custom:two,message:three");
+    }
+
+    #[test]
+    fn test_event_source_close_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind");
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            let (mut stream, _addr) = listener.accept().expect("accept failed");
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok() && line != "\r\n" {
+                line.clear();
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n")
+                .unwrap();
+            stream.flush().unwrap();
+            // never send an event, the read only ends when the client drops the connection
+            let mut buf = [0u8; 16];
+            let _ = stream.read(&mut buf);
+            tx.send(()).unwrap();
+        });
+
+        let rt = init_test_rt();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_event_source_close_silent_server.es",
+                format!(
+                    "let es2 = new EventSource('http://127.0.0.1:{}/events');\n\
+                     new Promise((resolve) => {{\n\
+                        es2.onopen = () => {{es2.close(); resolve(es2.readyState);}};\n\
+                     }});",
+                    port
+                )
+                .as_str(),
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_i32(), 2);
+        // closing the EventSource drops the connection even though the server never sent anything
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
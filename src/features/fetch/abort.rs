@@ -0,0 +1,340 @@
+//! AbortController and AbortSignal, an AbortSignal may be passed to fetch() in order to abort a pending request
+//! # Example
+//! ```javascript
+//! let controller = new AbortController();
+//! fetch('https://example.com', {signal: controller.signal}).catch((reason) => {console.log('fetch was aborted: %s', reason);});
+//! controller.abort();
+//! ```
+
+use crate::quickjs_utils;
+use crate::quickjs_utils::promises::{add_promise_reactions_q, new_promise_q};
+use crate::quickjs_utils::{arrays, errors, functions, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::reflection;
+use crate::reflection::eventtarget;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+
+const ABORT_CONTROLLER_PROXY_NAME: &str = "AbortController";
+const ABORT_SIGNAL_PROXY_NAME: &str = "AbortSignal";
+
+// the signal of a controller and the state of a signal are stored as non enumerable properties of the instances so
+// they are freed together with the instances
+const SIGNAL_PROP: &str = "__abortSignal";
+const ABORTED_PROP: &str = "__aborted";
+const REASON_PROP: &str = "__abortReason";
+
+fn get_instance_ref(q_ctx: &QuickJsContext, instance_id: usize) -> Result<JSValueRef, JsError> {
+    reflection::get_proxy_instance_ref(q_ctx, instance_id)
+        .ok_or_else(|| JsError::new_str("no such instance found"))
+}
+
+/// get a hidden property of an instance, instances of proxy classes return null for properties which were not set
+fn get_hidden_property(
+    q_ctx: &QuickJsContext,
+    instance_ref: &JSValueRef,
+    name: &str,
+) -> Result<Option<JSValueRef>, JsError> {
+    let prop_ref = objects::get_property_q(q_ctx, instance_ref, name)?;
+    if prop_ref.is_null() || prop_ref.is_undefined() {
+        Ok(None)
+    } else {
+        Ok(Some(prop_ref))
+    }
+}
+
+fn is_aborted(q_ctx: &QuickJsContext, signal_ref: &JSValueRef) -> Result<bool, JsError> {
+    match get_hidden_property(q_ctx, signal_ref, ABORTED_PROP)? {
+        Some(aborted_ref) => primitives::to_bool(&aborted_ref),
+        None => Ok(false),
+    }
+}
+
+fn get_reason(q_ctx: &QuickJsContext, signal_ref: &JSValueRef) -> Result<JSValueRef, JsError> {
+    if is_aborted(q_ctx, signal_ref)? {
+        objects::get_property_q(q_ctx, signal_ref, REASON_PROP)
+    } else {
+        Ok(quickjs_utils::new_undefined_ref())
+    }
+}
+
+fn new_abort_error(q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+    unsafe { errors::new_error(q_ctx.context, "AbortError", "The operation was aborted", "") }
+}
+
+/// abort a signal, this sets the reason of the signal and dispatches an abort event
+/// aborting an already aborted signal does nothing
+fn abort_signal(
+    q_ctx: &QuickJsContext,
+    signal_id: usize,
+    signal_ref: &JSValueRef,
+    reason: Option<JSValueRef>,
+) -> Result<(), JsError> {
+    if is_aborted(q_ctx, signal_ref)? {
+        return Ok(());
+    }
+    let reason = match reason {
+        Some(reason) if !reason.is_undefined() => reason,
+        _ => new_abort_error(q_ctx)?,
+    };
+    objects::set_property2_q(q_ctx, signal_ref, REASON_PROP, &reason, 0)?;
+    objects::set_property2_q(
+        q_ctx,
+        signal_ref,
+        ABORTED_PROP,
+        &primitives::from_bool(true),
+        0,
+    )?;
+
+    let event_ref = objects::create_object_q(q_ctx)?;
+    objects::set_property_q(
+        q_ctx,
+        &event_ref,
+        "type",
+        &primitives::from_string_q(q_ctx, "abort")?,
+    )?;
+    let proxy = reflection::get_proxy(q_ctx, ABORT_SIGNAL_PROXY_NAME)
+        .expect("AbortSignal proxy was not installed");
+    eventtarget::dispatch_event(q_ctx, &proxy, signal_id, "abort", event_ref).map(|_| ())
+}
+
+/// create a Promise which settles like the passed promise unless the signal is aborted first, in that case it is
+/// rejected with the reason of the signal
+/// the abort listener which is added to the signal is removed when the passed promise settles
+pub(crate) fn with_abort_signal(
+    q_ctx: &QuickJsContext,
+    signal_ref: &JSValueRef,
+    promise_ref: JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    let signal_id = match reflection::get_proxy_instance_id(signal_ref) {
+        Some((class_name, id)) if class_name == ABORT_SIGNAL_PROXY_NAME => id,
+        _ => return Err(JsError::new_str("signal is not an AbortSignal")),
+    };
+
+    let abort_promise = new_promise_q(q_ctx)?;
+    if is_aborted(q_ctx, signal_ref)? {
+        abort_promise.reject_q(q_ctx, get_reason(q_ctx, signal_ref)?)?;
+    } else {
+        let abort_promise2 = abort_promise.clone();
+        let listener_ref = functions::new_function_q(
+            q_ctx,
+            "onabort",
+            move |q_ctx, _this_ref, _args| {
+                let signal_ref = get_instance_ref(q_ctx, signal_id)?;
+                abort_promise2.reject_q(q_ctx, get_reason(q_ctx, &signal_ref)?)?;
+                Ok(quickjs_utils::new_undefined_ref())
+            },
+            1,
+        )?;
+        eventtarget::add_event_listener(
+            q_ctx,
+            ABORT_SIGNAL_PROXY_NAME,
+            "abort",
+            signal_id,
+            listener_ref.clone(),
+            quickjs_utils::new_undefined_ref(),
+        );
+
+        // the remover holds the signal so its listener map still exists when the promise settles
+        let signal_ref = signal_ref.clone();
+        let remover_ref = functions::new_function_q(
+            q_ctx,
+            "removeAbortListener",
+            move |q_ctx, _this_ref, _args| {
+                if let Some((_class_name, signal_id)) =
+                    reflection::get_proxy_instance_id(&signal_ref)
+                {
+                    eventtarget::remove_event_listener(
+                        q_ctx,
+                        ABORT_SIGNAL_PROXY_NAME,
+                        "abort",
+                        signal_id,
+                        &listener_ref,
+                    );
+                }
+                Ok(quickjs_utils::new_undefined_ref())
+            },
+            1,
+        )?;
+        add_promise_reactions_q(
+            q_ctx,
+            &promise_ref,
+            Some(remover_ref.clone()),
+            Some(remover_ref),
+            None,
+        )?;
+    }
+
+    let promises_ref = arrays::create_array_q(q_ctx)?;
+    arrays::set_element_q(q_ctx, &promises_ref, 0, promise_ref)?;
+    arrays::set_element_q(q_ctx, &promises_ref, 1, abort_promise.get_promise_obj_ref())?;
+    let promise_constructor_ref =
+        objects::get_property_q(q_ctx, &quickjs_utils::get_global_q(q_ctx), "Promise")?;
+    functions::invoke_member_function_q(q_ctx, &promise_constructor_ref, "race", vec![promises_ref])
+}
+
+/// get the signal of a controller, the signal is created when it is first used
+fn get_controller_signal(
+    q_ctx: &QuickJsContext,
+    controller_id: usize,
+) -> Result<JSValueRef, JsError> {
+    let controller_ref = get_instance_ref(q_ctx, controller_id)?;
+    if let Some(signal_ref) = get_hidden_property(q_ctx, &controller_ref, SIGNAL_PROP)? {
+        return Ok(signal_ref);
+    }
+    let (_signal_id, signal_ref) = reflection::new_instance(ABORT_SIGNAL_PROXY_NAME, q_ctx)?;
+    objects::set_property2_q(q_ctx, &controller_ref, SIGNAL_PROP, &signal_ref, 0)?;
+    Ok(signal_ref)
+}
+
+pub(crate) fn init_abort_proxies(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    reflection::Proxy::new()
+        .name(ABORT_SIGNAL_PROXY_NAME)
+        .event_target()
+        .getter_setter(
+            "aborted",
+            |q_ctx, instance_id| {
+                let signal_ref = get_instance_ref(q_ctx, *instance_id)?;
+                Ok(primitives::from_bool(is_aborted(q_ctx, &signal_ref)?))
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .getter_setter(
+            "reason",
+            |q_ctx, instance_id| {
+                let signal_ref = get_instance_ref(q_ctx, *instance_id)?;
+                get_reason(q_ctx, &signal_ref)
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .install(q_ctx, true)?;
+
+    reflection::Proxy::new()
+        .name(ABORT_CONTROLLER_PROXY_NAME)
+        .constructor(|_q_ctx, _instance_id, _args| Ok(()))
+        .getter_setter(
+            "signal",
+            |q_ctx, instance_id| get_controller_signal(q_ctx, *instance_id),
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .method("abort", |q_ctx, instance_id, args| {
+            let signal_ref = get_controller_signal(q_ctx, *instance_id)?;
+            let (_class_name, signal_id) = reflection::get_proxy_instance_id(&signal_ref)
+                .expect("signal is not an AbortSignal");
+            abort_signal(q_ctx, signal_id, &signal_ref, args.into_iter().next())?;
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .install(q_ctx, true)
+        .map(|_| {})
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::features::fetch::response::FetchResponse;
+    use hirofa_utils::js_utils::Script;
+    use std::time::{Duration, Instant};
+
+    struct SlowResponse {}
+    impl FetchResponse for SlowResponse {
+        fn get_http_status(&self) -> u16 {
+            200
+        }
+
+        fn get_header(&self, _name: &str) -> Option<&str> {
+            None
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_abort_fetch() {
+        let rt = EsRuntimeBuilder::new()
+            .fetch_response_provider(|_req| {
+                std::thread::sleep(Duration::from_secs(1));
+                Box::new(SlowResponse {})
+            })
+            .build();
+
+        let start = Instant::now();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_abort_fetch.es",
+                "let controller = new AbortController();\n\
+                 let events = [];\n\
+                 controller.signal.addEventListener('abort', (evt) => {events.push(evt.type);});\n\
+                 let p = fetch('https://slow.com', {signal: controller.signal}).then(() => 'resolved', (reason) => 'rejected: ' + reason);\n\
+                 controller.abort('too slow');\n\
+                 p;",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(res.get_str(), "rejected: too slow");
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_abort_fetch2.es",
+                "events.join(',') + ':' + controller.signal.aborted + ':' + controller.signal.reason;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "abort:true:too slow");
+
+        // without a reason the signal is aborted with an AbortError, and an aborted signal rejects immediately
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_abort_fetch3.es",
+                "let controller2 = new AbortController();\n\
+                 controller2.abort();\n\
+                 fetch('https://slow.com', {signal: controller2.signal}).catch((reason) => reason.name);",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "AbortError");
+    }
+
+    #[test]
+    fn test_abort_listener_removed() {
+        let rt = EsRuntimeBuilder::new()
+            .fetch_response_provider(|_req| Box::new(SlowResponse {}))
+            .build();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_abort_listener_removed.es",
+                "let controller = new AbortController();\n\
+                 fetch('https://fast.com', {signal: controller.signal}).then(() => 'resolved');",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "resolved");
+
+        let listener_count = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let listeners = &*q_ctx.proxy_event_listeners.borrow();
+            listeners.get("AbortSignal").map_or(0, |instances| {
+                instances
+                    .values()
+                    .flat_map(|events| events.values())
+                    .map(|event_listeners| event_listeners.len())
+                    .sum()
+            })
+        });
+        assert_eq!(listener_count, 0);
+    }
+}
@@ -0,0 +1,223 @@
+//! caching of fetch responses, when a FetchCache is set in the EsRuntimeBuilder fetch() will look for a CachedResponse
+//! before calling the FetchResponseProvider
+
+use crate::features::fetch::request::{FetchRequest, HttpMethod};
+use crate::features::fetch::response::FetchResponse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// the headers which are copied from a FetchResponse when it is converted to a CachedResponse
+const CACHED_HEADERS: [&str; 7] = [
+    "Cache-Control",
+    "Content-Type",
+    "Content-Length",
+    "Content-Encoding",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+];
+
+/// a cache for fetch responses, see [MemoryFetchCache] for an implementation which respects `Cache-Control: max-age`
+pub trait FetchCache {
+    fn get(&self, req: &FetchRequest) -> Option<CachedResponse>;
+    fn put(&self, req: &FetchRequest, resp: CachedResponse);
+}
+
+/// a fully read FetchResponse which can be stored in a FetchCache
+#[derive(Clone)]
+pub struct CachedResponse {
+    http_status: u16,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    created: Instant,
+    read_done: bool,
+}
+
+impl CachedResponse {
+    pub fn new(http_status: u16, headers: HashMap<String, String>, body: Vec<u8>) -> Self {
+        Self {
+            http_status,
+            headers,
+            body,
+            created: Instant::now(),
+            read_done: false,
+        }
+    }
+    /// read a FetchResponse till completion and copy its status and (common) headers
+    pub fn from_response(resp: &mut dyn FetchResponse) -> Self {
+        let mut headers = HashMap::new();
+        for name in CACHED_HEADERS.iter() {
+            if let Some(value) = resp.get_header(name) {
+                headers.insert(name.to_string(), value.to_string());
+            }
+        }
+        let mut body = vec![];
+        while let Some(mut buffer) = resp.read() {
+            body.append(&mut buffer);
+        }
+        Self::new(resp.get_http_status(), headers, body)
+    }
+    /// get the age of this response
+    pub fn get_age(&self) -> Duration {
+        self.created.elapsed()
+    }
+    /// get the max-age from the Cache-Control header, returns None if there is no max-age or if the response
+    /// should not be stored (no-store, no-cache)
+    pub fn get_max_age(&self) -> Option<Duration> {
+        let cache_control = self.get_header("Cache-Control")?;
+        let mut max_age = None;
+        for directive in cache_control.split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            if directive == "no-store" || directive == "no-cache" {
+                return None;
+            }
+            if let Some(secs) = directive.strip_prefix("max-age=") {
+                max_age = secs.trim().parse::<u64>().ok().map(Duration::from_secs);
+            }
+        }
+        max_age
+    }
+    /// check if the age of this response exceeds its max-age
+    pub fn is_expired(&self) -> bool {
+        match self.get_max_age() {
+            Some(max_age) => self.get_age() >= max_age,
+            None => true,
+        }
+    }
+}
+
+impl FetchResponse for CachedResponse {
+    fn get_http_status(&self) -> u16 {
+        self.http_status
+    }
+
+    fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _value)| key.eq_ignore_ascii_case(name))
+            .map(|(_key, value)| value.as_str())
+    }
+
+    fn read(&mut self) -> Option<Vec<u8>> {
+        if self.read_done {
+            None
+        } else {
+            self.read_done = true;
+            Some(self.body.clone())
+        }
+    }
+}
+
+/// a FetchCache which keeps GET responses in memory for as long as their `Cache-Control: max-age` allows
+#[derive(Default)]
+pub struct MemoryFetchCache {
+    responses: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl MemoryFetchCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FetchCache for MemoryFetchCache {
+    fn get(&self, req: &FetchRequest) -> Option<CachedResponse> {
+        if req.get_method() != HttpMethod::Get {
+            return None;
+        }
+        let responses = &mut *self.responses.lock().unwrap();
+        let expired = responses.get(req.get_url())?.is_expired();
+        if expired {
+            responses.remove(req.get_url());
+            None
+        } else {
+            responses.get(req.get_url()).cloned()
+        }
+    }
+
+    fn put(&self, req: &FetchRequest, resp: CachedResponse) {
+        if req.get_method() != HttpMethod::Get || resp.get_max_age().is_none() {
+            return;
+        }
+        let responses = &mut *self.responses.lock().unwrap();
+        responses.insert(req.get_url().to_string(), resp);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::features::fetch::cache::MemoryFetchCache;
+    use crate::features::fetch::response::FetchResponse;
+    use hirofa_utils::js_utils::Script;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CacheableResponse {
+        txt: Option<String>,
+    }
+    impl FetchResponse for CacheableResponse {
+        fn get_http_status(&self) -> u16 {
+            200
+        }
+
+        fn get_header(&self, name: &str) -> Option<&str> {
+            if name == "Cache-Control" {
+                Some("public, max-age=60")
+            } else {
+                None
+            }
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            self.txt.take().map(|s| s.into_bytes())
+        }
+    }
+
+    #[test]
+    fn test_fetch_cache() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls2 = calls.clone();
+        let rt = EsRuntimeBuilder::new()
+            .fetch_cache(Box::new(MemoryFetchCache::new()))
+            .fetch_response_provider(move |_req| {
+                let call = calls2.fetch_add(1, Ordering::SeqCst) + 1;
+                Box::new(CacheableResponse {
+                    txt: Some(format!("response {}", call)),
+                })
+            })
+            .build();
+
+        for _ in 0..2 {
+            let prom = rt
+                .eval_sync(Script::new(
+                    "test_fetch_cache.es",
+                    "fetch('https://test.com/cached').then((res) => res.text());",
+                ))
+                .ok()
+                .expect("script failed");
+            let res = prom
+                .get_promise_result_sync()
+                .ok()
+                .expect("promise was rejected");
+            assert_eq!(res.get_str(), "response 1");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // POST requests are never cached
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_fetch_cache2.es",
+                "fetch('https://test.com/cached', {method: 'POST'}).then((res) => res.text());",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "response 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
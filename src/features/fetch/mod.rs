@@ -1,16 +1,20 @@
 use crate::esruntime::EsRuntime;
 use crate::esruntime_utils::promises;
-use crate::features::fetch::request::FetchRequest;
+use crate::features::fetch::cache::CachedResponse;
+use crate::features::fetch::request::{FetchBody, FetchRequest, HttpMethod};
 use crate::features::fetch::response::FetchResponse;
 use crate::quickjs_utils;
-use crate::quickjs_utils::{functions, objects, parse_args, primitives};
+use crate::quickjs_utils::{functions, json, objects, parse_args, primitives, typedarrays};
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::QuickJsRuntime;
+use crate::valueref::JSValueRef;
 use hirofa_utils::js_utils::JsError;
 use libquickjs_sys as q;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+pub mod abort;
+pub mod cache;
 pub mod request;
 pub mod response;
 
@@ -32,11 +36,77 @@ pub(crate) fn init(es_rt: &EsRuntime) -> Result<(), JsError> {
                 )
             }?;
 
+            abort::init_abort_proxies(q_ctx)?;
             response::init_response_proxy(q_ctx)
         })
     })
 }
 
+/// create a FetchRequest from the url and the (optional) options object passed to fetch()
+fn parse_request(
+    q_ctx: &QuickJsContext,
+    url: &str,
+    options: Option<&JSValueRef>,
+) -> Result<FetchRequest, JsError> {
+    let options = match options {
+        Some(options) if options.is_object() => options,
+        _ => return Ok(FetchRequest::new(url, HashMap::new())),
+    };
+
+    let mut headers = HashMap::new();
+    let headers_ref = objects::get_property_q(q_ctx, options, "headers")?;
+    if headers_ref.is_object() {
+        for name in objects::get_property_names_q(q_ctx, &headers_ref)? {
+            let value_ref = objects::get_property_q(q_ctx, &headers_ref, name.as_str())?;
+            let value = functions::call_to_string_q(q_ctx, &value_ref)?;
+            headers.insert(name, vec![value]);
+        }
+    }
+
+    let mut request = FetchRequest::new(url, headers);
+
+    let method_ref = objects::get_property_q(q_ctx, options, "method")?;
+    if method_ref.is_string() {
+        let method = primitives::to_string_q(q_ctx, &method_ref)?;
+        request = request.with_method(HttpMethod::parse(method.as_str())?);
+    }
+
+    let body_ref = objects::get_property_q(q_ctx, options, "body")?;
+    if body_ref.is_string() {
+        request = request.with_body(FetchBody::Text(primitives::to_string_q(q_ctx, &body_ref)?));
+    } else if typedarrays::is_uint8_array_q(q_ctx, &body_ref) {
+        request = request.with_body(FetchBody::Bytes(typedarrays::typed_array_to_vec_q(
+            q_ctx, &body_ref,
+        )?));
+    } else if body_ref.is_object() {
+        let json_ref = json::stringify_q(q_ctx, &body_ref, None)?;
+        let json_str = primitives::to_string_q(q_ctx, &json_ref)?;
+        let value: serde_json::Value = serde_json::from_str(json_str.as_str())
+            .map_err(|e| JsError::new_string(format!("could not serialize body: {}", e)))?;
+        request = request.with_body(FetchBody::Json(value));
+    }
+
+    Ok(request)
+}
+
+/// get the AbortSignal from the (optional) options object passed to fetch()
+fn get_signal(
+    q_ctx: &QuickJsContext,
+    options: Option<&JSValueRef>,
+) -> Result<Option<JSValueRef>, JsError> {
+    match options {
+        Some(options) if options.is_object() => {
+            let signal_ref = objects::get_property_q(q_ctx, options, "signal")?;
+            if signal_ref.is_object() {
+                Ok(Some(signal_ref))
+            } else {
+                Ok(None)
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
 unsafe extern "C" fn fetch_func(
     ctx: *mut q::JSContext,
     _this_val: q::JSValue,
@@ -58,6 +128,15 @@ unsafe extern "C" fn fetch_func(
 
         let url = primitives::to_string(ctx, &url_arg).ok().unwrap();
 
+        let request = match parse_request(q_ctx, url.as_str(), args_vec.get(1)) {
+            Ok(request) => request,
+            Err(e) => return q_ctx.report_ex(e.get_message()),
+        };
+        let signal = match get_signal(q_ctx, args_vec.get(1)) {
+            Ok(signal) => signal,
+            Err(e) => return q_ctx.report_ex(e.get_message()),
+        };
+
         if let Some(rt_ref) = q_js_rt.get_rt_ref() {
             if rt_ref.get_fetch_response_provider().is_some() {
                 let rt_ref_weak = Arc::downgrade(&rt_ref);
@@ -72,7 +151,17 @@ unsafe extern "C" fn fetch_func(
                             .get_fetch_response_provider()
                             .expect("we really expected a fetch_response_provider here");
 
-                        let request = FetchRequest::new(url.as_str(), HashMap::new());
+                        if let Some(cache) = rt_ref.get_fetch_cache() {
+                            if let Some(cached) = cache.get(&request) {
+                                let result: Box<dyn FetchResponse + Send> = Box::new(cached);
+                                return Ok(result);
+                            }
+                            let mut response = provider(&request);
+                            let cached = CachedResponse::from_response(&mut *response);
+                            cache.put(&request, cached.clone());
+                            let result: Box<dyn FetchResponse + Send> = Box::new(cached);
+                            return Ok(result);
+                        }
 
                         let result: Box<dyn FetchResponse + Send> = provider(&request);
 
@@ -86,7 +175,11 @@ unsafe extern "C" fn fetch_func(
                 };
                 let es_rt = q_js_rt.get_rt_ref().unwrap();
 
-                let prom_res = promises::new_resolving_promise(q_ctx, producer, mapper, es_rt);
+                let prom_res = promises::new_resolving_promise(q_ctx, producer, mapper, es_rt)
+                    .and_then(|prom_ref| match signal {
+                        Some(signal) => abort::with_abort_signal(q_ctx, &signal, prom_ref),
+                        None => Ok(prom_ref),
+                    });
                 match prom_res {
                     Ok(prom_ref) => prom_ref.clone_value_incr_rc(),
                     Err(e) => q_ctx.report_ex(e.get_message()),
@@ -106,7 +199,10 @@ pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntime::EsRuntime;
     use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::features::fetch::request::FetchBody;
+    use crate::features::fetch::response::FetchBodyStream;
     use crate::features::fetch::FetchResponse;
+    use hirofa_utils::js_utils::JsError;
     use hirofa_utils::js_utils::Script;
     use std::sync::Arc;
     use std::time::Duration;
@@ -185,4 +281,184 @@ pub mod tests {
         log::trace!("test_fetch sleep");
         std::thread::sleep(Duration::from_secs(2));
     }
+
+    #[test]
+    fn test_fetch_body() {
+        let rt = EsRuntimeBuilder::new()
+            .fetch_response_provider(|req| {
+                let body = match req.get_body() {
+                    Some(FetchBody::Text(txt)) => format!("text {}", txt),
+                    Some(FetchBody::Bytes(bytes)) => {
+                        format!("bytes {}", String::from_utf8_lossy(bytes))
+                    }
+                    Some(FetchBody::Json(val)) => format!("json {}", val),
+                    None => "none".to_string(),
+                };
+                let method = req.get_method().as_str();
+                let content_type = req
+                    .get_headers()
+                    .get("Content-Type")
+                    .map(|v| v.join(","))
+                    .unwrap_or_default();
+                Box::new(TestResponse {
+                    txt: Some(format!("{}:{}:{}", method, content_type, body)),
+                })
+            })
+            .build();
+
+        let echo = |code: &str| {
+            let prom = rt
+                .eval_sync(Script::new("test_fetch_body.es", code))
+                .ok()
+                .expect("script failed");
+            prom.get_promise_result_sync()
+                .ok()
+                .expect("promise was rejected")
+                .get_str()
+                .to_string()
+        };
+
+        assert_eq!(
+            echo("fetch('https://test.com', {method: 'POST', body: 'hello', headers: {'Content-Type': 'text/plain'}}).then((res) => res.text());"),
+            "POST:text/plain:text hello"
+        );
+        assert_eq!(
+            echo("fetch('https://test.com', {method: 'PUT', body: JSON.stringify({a: 1}), headers: {'Content-Type': 'application/json'}}).then((res) => res.text());"),
+            "PUT:application/json:text {\"a\":1}"
+        );
+        assert_eq!(
+            echo("fetch('https://test.com', {method: 'patch', body: {a: [1, 2]}}).then((res) => res.text());"),
+            "PATCH::json {\"a\":[1,2]}"
+        );
+        assert_eq!(
+            echo("fetch('https://test.com', {method: 'POST', body: new Uint8Array([104, 105])}).then((res) => res.text());"),
+            "POST::bytes hi"
+        );
+        assert_eq!(
+            echo("fetch('https://test.com').then((res) => res.text());"),
+            "GET::none"
+        );
+    }
+
+    struct TestStreamResponse {
+        size: usize,
+        chunk_size: usize,
+        read_size: usize,
+    }
+    impl FetchResponse for TestStreamResponse {
+        fn get_http_status(&self) -> u16 {
+            200
+        }
+
+        fn get_header(&self, _name: &str) -> Option<&str> {
+            None
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            if self.read_size >= self.size {
+                return None;
+            }
+            let len = self.chunk_size.min(self.size - self.read_size);
+            self.read_size += len;
+            Some(vec![1u8; len])
+        }
+
+        fn stream_body(&mut self) -> FetchBodyStream {
+            let chunks: Vec<Result<Vec<u8>, JsError>> = (0..self.size / self.chunk_size)
+                .map(|_| Ok(vec![1u8; self.chunk_size]))
+                .collect();
+            Box::pin(futures::stream::iter(chunks))
+        }
+    }
+
+    #[test]
+    fn test_fetch_stream_body() {
+        let rt = EsRuntimeBuilder::new()
+            .fetch_response_provider(|_req| {
+                Box::new(TestStreamResponse {
+                    size: 1024 * 1024,
+                    chunk_size: 64 * 1024,
+                    read_size: 0,
+                })
+            })
+            .build();
+
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_fetch_stream_body.es",
+                "(async function() {\n\
+                 let res = await fetch('https://test.com');\n\
+                 let total = 0;\n\
+                 let chunks = 0;\n\
+                 for await (const chunk of res.body) {\n\
+                    total += chunk.length;\n\
+                    chunks++;\n\
+                 }\n\
+                 let same_body = res.body === res.body;\n\
+                 let readable = res.body instanceof ReadableStream;\n\
+                 return total + ':' + chunks + ':' + same_body + ':' + readable;\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "1048576:16:true:true");
+    }
+
+    struct TestFailingStreamResponse {}
+    impl FetchResponse for TestFailingStreamResponse {
+        fn get_http_status(&self) -> u16 {
+            200
+        }
+
+        fn get_header(&self, _name: &str) -> Option<&str> {
+            None
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn stream_body(&mut self) -> FetchBodyStream {
+            let chunks: Vec<Result<Vec<u8>, JsError>> = vec![
+                Ok(vec![1u8, 2u8]),
+                Err(JsError::new_str("connection reset")),
+            ];
+            Box::pin(futures::stream::iter(chunks))
+        }
+    }
+
+    #[test]
+    fn test_fetch_stream_body_error() {
+        let rt = EsRuntimeBuilder::new()
+            .fetch_response_provider(|_req| Box::new(TestFailingStreamResponse {}))
+            .build();
+
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_fetch_stream_body_error.es",
+                "(async function() {\n\
+                 let res = await fetch('https://test.com');\n\
+                 let reader = res.body.getReader();\n\
+                 let first = await reader.read();\n\
+                 try {\n\
+                    await reader.read();\n\
+                    return 'second read should have failed';\n\
+                 } catch(e) {\n\
+                    return first.value.length + ':' + e.message;\n\
+                 }\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert!(res.get_str().starts_with("2:reading body failed: "));
+        assert!(res.get_str().contains("connection reset"));
+    }
 }
@@ -1,21 +1,95 @@
+use hirofa_utils::js_utils::JsError;
 use std::collections::HashMap;
 
+/// the http method of a FetchRequest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HttpMethod {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+}
+
+impl HttpMethod {
+    /// parse a method name (case insensitive) like it is passed to fetch() in the options object
+    pub fn parse(method: &str) -> Result<Self, JsError> {
+        match method.to_ascii_uppercase().as_str() {
+            "GET" => Ok(HttpMethod::Get),
+            "HEAD" => Ok(HttpMethod::Head),
+            "POST" => Ok(HttpMethod::Post),
+            "PUT" => Ok(HttpMethod::Put),
+            "DELETE" => Ok(HttpMethod::Delete),
+            "PATCH" => Ok(HttpMethod::Patch),
+            "OPTIONS" => Ok(HttpMethod::Options),
+            _ => Err(JsError::new_string(format!(
+                "unsupported http method: {}",
+                method
+            ))),
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Options => "OPTIONS",
+        }
+    }
+}
+
+/// the body of a FetchRequest
+/// a string body is passed as Text, a Uint8Array as Bytes and any other object is passed as Json
+pub enum FetchBody {
+    Text(String),
+    Bytes(Vec<u8>),
+    Json(serde_json::Value),
+}
+
 pub struct FetchRequest {
     url: String,
+    method: HttpMethod,
     headers: HashMap<String, Vec<String>>,
+    body: Option<FetchBody>,
 }
 
 impl FetchRequest {
     pub fn new(url: &str, headers: HashMap<String, Vec<String>>) -> Self {
         Self {
             url: url.to_string(),
+            method: HttpMethod::Get,
             headers,
+            body: None,
         }
     }
+    /// set the http method, defaults to GET
+    pub fn with_method(mut self, method: HttpMethod) -> Self {
+        self.method = method;
+        self
+    }
+    /// set the body of the request
+    pub fn with_body(mut self, body: FetchBody) -> Self {
+        self.body = Some(body);
+        self
+    }
     pub fn get_url(&self) -> &str {
         self.url.as_str()
     }
+    pub fn get_method(&self) -> HttpMethod {
+        self.method
+    }
     pub fn get_header(&self, name: &str) -> &[String] {
         self.headers.get(name).unwrap().as_slice()
     }
+    pub fn get_headers(&self) -> &HashMap<String, Vec<String>> {
+        &self.headers
+    }
+    pub fn get_body(&self) -> Option<&FetchBody> {
+        self.body.as_ref()
+    }
 }
@@ -1,16 +1,21 @@
 use crate::esruntime_utils::promises::new_resolving_promise;
-use crate::quickjs_utils::{json, primitives};
+use crate::esvalue::EsValueConvertible;
+use crate::features::streams;
+use crate::quickjs_utils::{json, objects, primitives};
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::QuickJsRuntime;
 use crate::reflection;
 use crate::valueref::JSValueRef;
+use futures::{Stream, StreamExt};
 use hirofa_utils::js_utils::JsError;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 
 type FetchResponseType = Box<dyn FetchResponse + Send>;
 type FetchResponseMapType = Arc<Mutex<FetchResponseType>>;
+pub type FetchBodyStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, JsError>> + Send>>;
 
 thread_local! {
     static RESPONSES : RefCell<HashMap<usize, FetchResponseMapType>> = RefCell::new(HashMap::new());
@@ -20,10 +25,77 @@ pub trait FetchResponse {
     fn get_http_status(&self) -> u16;
     fn get_header(&self, name: &str) -> Option<&str>;
     fn read(&mut self) -> Option<Vec<u8>>;
+    /// get the body of the response as a Stream of chunks, this is used for the body property of the Response
+    /// the default impl reads the complete body with read(), implementors should override this to actually stream the body
+    fn stream_body(&mut self) -> FetchBodyStream {
+        let mut chunks = vec![];
+        while let Some(chunk) = self.read() {
+            chunks.push(Ok(chunk));
+        }
+        Box::pin(futures::stream::iter(chunks))
+    }
+}
+
+/// a chunk of the body of a response which is sent to the ReadableStream of the body, a failed read can not be
+/// converted and thus errors the stream
+enum BodyChunk {
+    Bytes(Vec<u8>),
+    Failed(JsError),
+}
+
+impl EsValueConvertible for BodyChunk {
+    fn as_js_value(&mut self, q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+        match self {
+            BodyChunk::Bytes(bytes) => bytes.as_js_value(q_ctx),
+            BodyChunk::Failed(e) => Err(JsError::new_string(format!("reading body failed: {}", e))),
+        }
+    }
 }
 
 const RESPONSE_PROXY_NAME: &str = "Response";
 
+fn get_response(instance_id: &usize) -> FetchResponseMapType {
+    RESPONSES.with(move |rrc| {
+        let responses_map = &*rrc.borrow();
+        responses_map
+            .get(instance_id)
+            .expect("no such response found")
+            .clone()
+    })
+}
+
+/// create a ReadableStream which is fed by the stream_body() of a FetchResponse, the stream is defined as own
+/// property of the response so every read of the body property returns the same stream
+/// the body is polled in a helper task, stream_body() is called there because it may read the complete body
+fn response_body(q_ctx: &QuickJsContext, instance_id: &usize) -> Result<JSValueRef, JsError> {
+    QuickJsRuntime::do_with(|q_js_rt| {
+        let es_rt = q_js_rt.get_rt_ref().unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let stream_ref = streams::channel_to_readable_stream_q(q_ctx, &es_rt, receiver)?;
+        let response_ref = reflection::get_proxy_instance_ref(q_ctx, *instance_id)
+            .ok_or_else(|| JsError::new_str("no such response found"))?;
+        objects::set_property2_q(q_ctx, &response_ref, "body", &stream_ref, 0)?;
+
+        let response = get_response(instance_id);
+        let _ = es_rt.add_runtime_helper_task_async(async move {
+            let mut body_stream = response.lock().unwrap().stream_body();
+            while let Some(chunk_res) = body_stream.next().await {
+                let (chunk, failed) = match chunk_res {
+                    Ok(bytes) => (BodyChunk::Bytes(bytes), false),
+                    Err(e) => (BodyChunk::Failed(e), true),
+                };
+                // stop reading when the stream was dropped or errored
+                if sender.send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(stream_ref)
+    })
+}
+
 fn response_text(
     q_ctx: &QuickJsContext,
     instance_id: &usize,
@@ -118,6 +190,7 @@ pub(crate) fn init_response_proxy(q_ctx: &QuickJsContext) -> Result<(), JsError>
             |_q_js_rt, _instance_id| Ok(primitives::from_bool(true)),
             |_q_js_rt, _instance_id, _val| Ok(()),
         )
+        .getter_setter("body", response_body, |_q_js_rt, _instance_id, _val| Ok(()))
         .getter_setter(
             "ok",
             //todo
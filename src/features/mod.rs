@@ -2,9 +2,16 @@ use crate::esruntime::EsRuntime;
 use hirofa_utils::js_utils::JsError;
 
 pub mod console;
+#[cfg(feature = "event_source")]
+pub mod event_source;
 pub mod fetch;
+pub mod polyfills;
 pub mod set_timeout;
 pub mod setimmediate;
+pub mod storage;
+pub mod streams;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 pub fn init(es_rt: &EsRuntime) -> Result<(), JsError> {
     log::trace!("features::init");
@@ -13,8 +20,14 @@ pub fn init(es_rt: &EsRuntime) -> Result<(), JsError> {
 
     es_rt.exe_rt_task_in_event_loop(move |q_js_rt| {
         console::init(q_js_rt)?;
+        #[cfg(feature = "event_source")]
+        event_source::init(q_js_rt)?;
         setimmediate::init(q_js_rt)?;
         set_timeout::init(q_js_rt)?;
+        storage::init(q_js_rt)?;
+        streams::init(q_js_rt)?;
+        #[cfg(feature = "websocket")]
+        websocket::init(q_js_rt)?;
         Ok(())
     })
 }
@@ -0,0 +1,154 @@
+//! provides polyfills for modern globals which are not part of QuickJS
+//!
+//! the polyfills are only installed when enabled with [EsRuntimeBuilder::install_std_polyfills](crate::esruntimebuilder::EsRuntimeBuilder::install_std_polyfills)
+//!
+//! * structuredClone(value), clones a value by serializing it to JSON and parsing it again, so only JSON compatible values are supported
+//! * queueMicrotask(callback), runs the callback as a promise reaction
+//! * performance.now(), the number of milliseconds (with fractions) since the context was created
+//!
+//! setTimeout, clearTimeout, setInterval and clearInterval are always installed by the set_timeout feature
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use hirofa_utils::js_utils::Script;
+//! let rt = EsRuntimeBuilder::new().install_std_polyfills().build();
+//! let res = rt.eval_sync(Script::new("polyfills.es", "structuredClone({a: 1}).a;")).ok().unwrap();
+//! assert_eq!(res.get_i32(), 1);
+//! ```
+
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, json, objects, primitives, promises};
+use crate::quickjsruntime::QuickJsRuntime;
+use hirofa_utils::js_utils::JsError;
+use std::time::Instant;
+
+pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    log::trace!("polyfills::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        let global = quickjs_utils::get_global_q(q_ctx);
+
+        let structured_clone_func = functions::new_function_q(
+            q_ctx,
+            "structuredClone",
+            |q_ctx, _this_ref, args| {
+                let value_ref = match args.first() {
+                    Some(value_ref) => value_ref,
+                    None => return Ok(quickjs_utils::new_undefined_ref()),
+                };
+                if !value_ref.is_object() {
+                    // primitives are immutable so they do not need to be copied
+                    return Ok(value_ref.clone());
+                }
+                if functions::is_function_q(q_ctx, value_ref) {
+                    return Err(JsError::new(
+                        "DataCloneError".to_string(),
+                        "functions can not be cloned".to_string(),
+                        "".to_string(),
+                    ));
+                }
+                let json_ref = json::stringify_q(q_ctx, value_ref, None)?;
+                let json_str = primitives::to_string_q(q_ctx, &json_ref)?;
+                json::parse_q(q_ctx, json_str.as_str())
+            },
+            1,
+        )?;
+        objects::set_property2_q(q_ctx, &global, "structuredClone", &structured_clone_func, 0)?;
+
+        let queue_microtask_func = functions::new_function_q(
+            q_ctx,
+            "queueMicrotask",
+            |q_ctx, _this_ref, args| {
+                let callback_ref = match args.first() {
+                    Some(callback_ref) if functions::is_function_q(q_ctx, callback_ref) => {
+                        callback_ref.clone()
+                    }
+                    _ => {
+                        return Err(JsError::new(
+                            "TypeError".to_string(),
+                            "queueMicrotask requires a function as first arg".to_string(),
+                            "".to_string(),
+                        ))
+                    }
+                };
+                let promise_ref = promises::new_promise_q(q_ctx)?;
+                promises::add_promise_reactions_q(
+                    q_ctx,
+                    &promise_ref.get_promise_obj_ref(),
+                    Some(callback_ref),
+                    None,
+                    None,
+                )?;
+                promise_ref.resolve_q(q_ctx, quickjs_utils::new_undefined_ref())?;
+                Ok(quickjs_utils::new_undefined_ref())
+            },
+            1,
+        )?;
+        objects::set_property2_q(q_ctx, &global, "queueMicrotask", &queue_microtask_func, 0)?;
+
+        let time_origin = Instant::now();
+        let now_func = functions::new_function_q(
+            q_ctx,
+            "now",
+            move |_q_ctx, _this_ref, _args| {
+                Ok(primitives::from_f64(
+                    time_origin.elapsed().as_secs_f64() * 1000.0,
+                ))
+            },
+            0,
+        )?;
+        let performance_ref = objects::create_object_q(q_ctx)?;
+        objects::set_property2_q(q_ctx, &performance_ref, "now", &now_func, 0)?;
+        objects::set_property2_q(q_ctx, &global, "performance", &performance_ref, 0)?;
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use hirofa_utils::js_utils::Script;
+
+    #[test]
+    fn test_polyfills() {
+        let rt = EsRuntimeBuilder::new().install_std_polyfills().build();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_polyfills.es",
+                "(async function() {\n\
+                 let results = [];\n\
+                 let orig = {a: 1, b: {c: [1, 2]}};\n\
+                 let clone = structuredClone(orig);\n\
+                 clone.b.c.push(3);\n\
+                 results.push(orig.b.c.length === 2 && clone.b.c.length === 3 && clone !== orig);\n\
+                 let order = [];\n\
+                 queueMicrotask(() => order.push('micro'));\n\
+                 order.push('sync');\n\
+                 await null;\n\
+                 results.push(order.join(',') === 'sync,micro');\n\
+                 await new Promise((resolve) => setTimeout(resolve, 10));\n\
+                 let interval_count = 0;\n\
+                 await new Promise((resolve) => {\n\
+                     let id = setInterval(() => {\n\
+                         if (++interval_count === 2) {clearInterval(id); resolve();}\n\
+                     }, 1);\n\
+                 });\n\
+                 results.push(interval_count === 2);\n\
+                 let t1 = performance.now();\n\
+                 let t2 = performance.now();\n\
+                 results.push(typeof t1 === 'number' && t1 >= 0 && t2 >= t1);\n\
+                 results.push(typeof clearTimeout === 'function');\n\
+                 return results.join(',');\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "true,true,true,true,true");
+    }
+}
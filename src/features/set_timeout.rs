@@ -1,10 +1,21 @@
 use crate::quickjs_utils;
 use crate::quickjs_utils::{functions, get_global, objects, parse_args, primitives};
 use crate::quickjsruntime::QuickJsRuntime;
+use crate::valueref::JSValueRef;
 use hirofa_utils::eventloop::EventLoop;
 use hirofa_utils::js_utils::JsError;
 use libquickjs_sys as q;
-use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// active intervals, maps the id of an interval to the id of the timeout for its next tick
+    static INTERVALS: RefCell<HashMap<i32, i32>> = RefCell::new(HashMap::new());
+    /// ids of the pending timeouts and active intervals per context, these are cancelled when the context is dropped
+    static CONTEXT_TIMERS: RefCell<HashMap<String, HashSet<i32>>> = RefCell::new(HashMap::new());
+}
 
 /// provides the setImmediate methods for the runtime
 /// # Example
@@ -43,9 +54,39 @@ pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
         objects::set_property2_q(q_ctx, &global, "clearInterval", &clear_interval_func, 0)?;
         Ok(())
     })?;
+    q_js_rt.add_context_drop_hook(|q_ctx| clear_context_timers(q_ctx.id.as_str()));
     Ok(())
 }
 
+fn register_timer(q_ctx_id: &str, id: i32) {
+    CONTEXT_TIMERS.with(|rc| {
+        let map = &mut *rc.borrow_mut();
+        map.entry(q_ctx_id.to_string()).or_default().insert(id);
+    });
+}
+
+fn unregister_timer(id: i32) {
+    CONTEXT_TIMERS.with(|rc| {
+        let map = &mut *rc.borrow_mut();
+        for ids in map.values_mut() {
+            ids.remove(&id);
+        }
+    });
+}
+
+/// cancel all pending timeouts and intervals of a context, called when a context is dropped
+fn clear_context_timers(q_ctx_id: &str) {
+    let ids = CONTEXT_TIMERS.with(|rc| rc.borrow_mut().remove(q_ctx_id));
+    if let Some(ids) = ids {
+        log::trace!("clearing {} timers of context {}", ids.len(), q_ctx_id);
+        for id in ids {
+            if !clear_interval_by_id(id) {
+                EventLoop::clear_timeout(id);
+            }
+        }
+    }
+}
+
 unsafe extern "C" fn set_timeout(
     context: *mut q::JSContext,
     _this_val: q::JSValue,
@@ -81,10 +122,16 @@ unsafe extern "C" fn set_timeout(
         };
 
         let q_ctx_id = q_ctx.id.clone();
+        let id_cell = Rc::new(Cell::new(0));
+        let id_cell2 = id_cell.clone();
 
         let id = EventLoop::add_timeout(
             move || {
+                unregister_timer(id_cell2.get());
                 QuickJsRuntime::do_with(|q_js_rt| {
+                    if !q_js_rt.has_context(q_ctx_id.as_str()) {
+                        return;
+                    }
                     let mut args = args.clone();
                     let func = args.remove(0);
                     let q_ctx = q_js_rt.get_context(q_ctx_id.as_str());
@@ -99,6 +146,8 @@ unsafe extern "C" fn set_timeout(
             },
             Duration::from_millis(delay_ms),
         );
+        id_cell.set(id);
+        register_timer(q_ctx.id.as_str(), id);
         log::trace!("set_timeout: {}", id);
         primitives::from_i32(id).clone_value_incr_rc()
     })
@@ -114,7 +163,7 @@ unsafe extern "C" fn set_interval(
 
     let mut args = parse_args(context, argc, argv);
 
-    QuickJsRuntime::do_with(|q_js_rt| {
+    QuickJsRuntime::do_with(move |q_js_rt| {
         let q_ctx = q_js_rt.get_quickjs_context(context);
         if args.is_empty() {
             return q_ctx.report_ex("setInterval requires at least one argument");
@@ -139,33 +188,95 @@ unsafe extern "C" fn set_interval(
         };
 
         let q_ctx_id = q_ctx.id.clone();
+        let interval = Duration::from_millis(delay_ms);
 
-        let id = EventLoop::add_interval(
-            move || {
-                QuickJsRuntime::do_with(|q_js_rt| {
-                    let q_ctx = q_js_rt.get_context(q_ctx_id.as_str());
-                    let mut args = args.clone();
-
-                    let func = args.remove(0);
-
-                    match functions::call_function_q(q_ctx, &func, args, None) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            log::error!("setInterval func failed: {}", e);
-                        }
-                    };
-
-                    q_js_rt.run_pending_jobs_if_any();
-                })
-            },
-            Duration::from_millis(delay_ms),
-            Duration::from_millis(delay_ms),
-        );
+        let id = schedule_interval_tick(None, q_ctx_id, args, Instant::now() + interval, interval);
         log::trace!("set_interval: {}", id);
         primitives::from_i32(id).clone_value_incr_rc()
     })
 }
 
+/// schedule the next tick of an interval as a timeout at an absolute Instant
+/// the tick after that is scheduled relative to this Instant and not to the moment the callback completes, so the
+/// time it takes to run the callback does not accumulate as drift
+/// if a callback takes longer than the interval the next tick fires immediately
+/// returns the id of the interval
+fn schedule_interval_tick(
+    interval_id: Option<i32>,
+    q_ctx_id: String,
+    args: Vec<JSValueRef>,
+    next_tick: Instant,
+    interval: Duration,
+) -> i32 {
+    let id_cell = Rc::new(Cell::new(interval_id.unwrap_or(0)));
+    let id_cell2 = id_cell.clone();
+    let delay = next_tick.saturating_duration_since(Instant::now());
+    let registration_ctx_id = q_ctx_id.clone();
+
+    let timeout_id = EventLoop::add_timeout(
+        move || {
+            let interval_id = id_cell2.get();
+            if !INTERVALS.with(|rc| rc.borrow().contains_key(&interval_id)) {
+                return;
+            }
+            let has_context = QuickJsRuntime::do_with(|q_js_rt| {
+                if !q_js_rt.has_context(q_ctx_id.as_str()) {
+                    return false;
+                }
+                let q_ctx = q_js_rt.get_context(q_ctx_id.as_str());
+                let mut args = args.clone();
+
+                let func = args.remove(0);
+
+                match functions::call_function_q(q_ctx, &func, args, None) {
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!("setInterval func failed: {}", e);
+                    }
+                };
+
+                q_js_rt.run_pending_jobs_if_any();
+                true
+            });
+            if !has_context {
+                clear_interval_by_id(interval_id);
+                return;
+            }
+            // the callback may have cleared the interval
+            if INTERVALS.with(|rc| rc.borrow().contains_key(&interval_id)) {
+                let now = Instant::now();
+                let next_tick = std::cmp::max(next_tick + interval, now);
+                schedule_interval_tick(Some(interval_id), q_ctx_id, args, next_tick, interval);
+            }
+        },
+        delay,
+    );
+
+    // the id of the first timeout is used as the id of the interval
+    let interval_id = match interval_id {
+        Some(interval_id) => interval_id,
+        None => {
+            register_timer(registration_ctx_id.as_str(), timeout_id);
+            timeout_id
+        }
+    };
+    id_cell.set(interval_id);
+    INTERVALS.with(|rc| rc.borrow_mut().insert(interval_id, timeout_id));
+    interval_id
+}
+
+/// clear an interval which was added by setInterval, returns false if there was no such interval
+fn clear_interval_by_id(id: i32) -> bool {
+    match INTERVALS.with(|rc| rc.borrow_mut().remove(&id)) {
+        Some(timeout_id) => {
+            EventLoop::clear_timeout(timeout_id);
+            unregister_timer(id);
+            true
+        }
+        None => false,
+    }
+}
+
 unsafe extern "C" fn clear_interval(
     context: *mut q::JSContext,
     _this_val: q::JSValue,
@@ -185,7 +296,10 @@ unsafe extern "C" fn clear_interval(
         }
         let id = primitives::to_i32(&args[0]).ok().unwrap();
         log::trace!("clear_interval: {}", id);
-        EventLoop::clear_interval(id);
+        if !clear_interval_by_id(id) {
+            EventLoop::clear_timeout(id);
+            unregister_timer(id);
+        }
         quickjs_utils::new_null()
     })
 }
@@ -211,7 +325,10 @@ unsafe extern "C" fn clear_timeout(
         let id = primitives::to_i32(&args[0]).ok().unwrap();
         log::trace!("clear_timeout: {}", id);
 
-        EventLoop::clear_timeout(id);
+        if !clear_interval_by_id(id) {
+            EventLoop::clear_timeout(id);
+            unregister_timer(id);
+        }
 
         quickjs_utils::new_null()
     })
@@ -221,10 +338,14 @@ unsafe extern "C" fn clear_timeout(
 pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntime::EsRuntime;
-    use crate::quickjs_utils::get_global_q;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::quickjs_utils::functions::new_function_q;
     use crate::quickjs_utils::objects::get_property_q;
+    use crate::quickjs_utils::objects::set_property_q;
     use crate::quickjs_utils::primitives::to_i32;
+    use crate::quickjs_utils::{get_global_q, new_undefined_ref};
     use hirofa_utils::js_utils::Script;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
     use std::time::Duration;
 
@@ -373,4 +494,82 @@ pub mod tests {
 
         rt.gc_sync();
     }
+
+    #[test]
+    fn test_set_interval_no_drift() {
+        let rt = EsRuntimeBuilder::new().build();
+        let esvf = rt
+            .eval_sync(Script::new(
+                "test_set_interval_no_drift.es",
+                "new Promise((resolve) => {\
+                     let start = Date.now();\
+                     let count = 0;\
+                     let id = setInterval(() => {\
+                         let s = Date.now();\
+                         while (Date.now() - s < 10) {}\
+                         if (++count === 10) {\
+                             clearInterval(id);\
+                             resolve((Date.now() - start) | 0);\
+                         }\
+                     }, 50);\
+                 });",
+            ))
+            .ok()
+            .expect("script failed");
+        let elapsed = esvf
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected")
+            .get_i32();
+        // 10 ticks of 50 ms plus the 10 ms of the last callback, without drift this is not near 600 ms
+        assert!(elapsed >= 500, "elapsed was {}", elapsed);
+        assert!(elapsed < 580, "elapsed was {}", elapsed);
+    }
+
+    #[test]
+    fn test_timers_cleared_on_context_drop() {
+        let rt = EsRuntimeBuilder::new().build();
+        rt.create_context("timer_ctx")
+            .ok()
+            .expect("could not create context");
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticks2 = ticks.clone();
+        rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_context("timer_ctx");
+            let tick_func = new_function_q(
+                q_ctx,
+                "tick",
+                move |_q_ctx, _this_ref, _args| {
+                    ticks2.fetch_add(1, Ordering::SeqCst);
+                    Ok(new_undefined_ref())
+                },
+                0,
+            )
+            .ok()
+            .expect("could not create function");
+            let global = get_global_q(q_ctx);
+            set_property_q(q_ctx, &global, "tick", &tick_func)
+                .ok()
+                .expect("could not set tick");
+            q_ctx
+                .eval(Script::new(
+                    "test_timers_cleared_on_context_drop.es",
+                    "setInterval(tick, 100); setTimeout(tick, 120);",
+                ))
+                .ok()
+                .expect("script failed");
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        rt.drop_context("timer_ctx");
+        let ticks_at_drop = ticks.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(ticks_at_drop, 0);
+        assert_eq!(ticks.load(Ordering::SeqCst), ticks_at_drop);
+        // the runtime is still usable
+        let res = rt
+            .eval_sync(Script::new("test_timers_cleared2.es", "1 + 1;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 2);
+    }
 }
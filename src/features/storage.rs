@@ -0,0 +1,254 @@
+//! provides localStorage and sessionStorage for the runtime
+//!
+//! both storages are shared by all contexts of an EsRuntime, sessionStorage lives as long as the EsRuntime and
+//! localStorage uses a [StorageBackend] which may be set with [EsRuntimeBuilder::local_storage_backend](crate::esruntimebuilder::EsRuntimeBuilder::local_storage_backend)
+//! in order to persist its items
+//! please note that items can only be accessed with the methods of the Storage interface (getItem, setItem, etc.) and not as properties
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use hirofa_utils::js_utils::Script;
+//! let rt = EsRuntimeBuilder::new().build();
+//! rt.eval_sync(Script::new("storage.es", "localStorage.setItem('a', 'b');")).ok().expect("script failed");
+//! let item = rt.eval_sync(Script::new("storage.es", "localStorage.getItem('a');")).ok().expect("script failed");
+//! assert_eq!(item.get_str(), "b");
+//! ```
+
+use crate::esruntime::EsRuntime;
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use crate::reflection;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const STORAGE_PROXY_NAME: &str = "Storage";
+
+/// the backend of a Storage, all values are strings
+pub trait StorageBackend {
+    fn get_item(&self, key: &str) -> Option<String>;
+    fn set_item(&self, key: &str, value: String);
+    fn remove_item(&self, key: &str);
+    fn clear(&self);
+    /// get all keys in the order they were added
+    fn keys(&self) -> Vec<String>;
+}
+
+/// a StorageBackend which keeps all items in memory
+#[derive(Default)]
+pub struct MemoryStorageBackend {
+    items: Mutex<HashMap<String, String>>,
+    keys: Mutex<Vec<String>>,
+}
+
+impl MemoryStorageBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorageBackend {
+    fn get_item(&self, key: &str) -> Option<String> {
+        let items = &*self.items.lock().unwrap();
+        items.get(key).cloned()
+    }
+
+    fn set_item(&self, key: &str, value: String) {
+        let items = &mut *self.items.lock().unwrap();
+        if items.insert(key.to_string(), value).is_none() {
+            self.keys.lock().unwrap().push(key.to_string());
+        }
+    }
+
+    fn remove_item(&self, key: &str) {
+        let items = &mut *self.items.lock().unwrap();
+        if items.remove(key).is_some() {
+            self.keys.lock().unwrap().retain(|k| k != key);
+        }
+    }
+
+    fn clear(&self) {
+        let items = &mut *self.items.lock().unwrap();
+        items.clear();
+        self.keys.lock().unwrap().clear();
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.keys.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum StorageKind {
+    Local,
+    Session,
+}
+
+thread_local! {
+    static STORAGES: RefCell<HashMap<usize, StorageKind>> = RefCell::new(HashMap::new());
+}
+
+fn with_backend<C, R>(instance_id: &usize, consumer: C) -> Result<R, JsError>
+where
+    C: FnOnce(&dyn StorageBackend) -> R,
+{
+    let kind = STORAGES
+        .with(|storages_rc| storages_rc.borrow().get(instance_id).cloned())
+        .ok_or_else(|| JsError::new_str("no such Storage"))?;
+    let es_rt: Arc<EsRuntime> = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref())
+        .ok_or_else(|| JsError::new_str("Runtime was dropped"))?;
+    Ok(match kind {
+        StorageKind::Local => consumer(es_rt.get_local_storage()),
+        StorageKind::Session => consumer(es_rt.get_session_storage()),
+    })
+}
+
+fn get_key_arg(
+    q_ctx: &QuickJsContext,
+    args: &[JSValueRef],
+    method: &str,
+) -> Result<String, JsError> {
+    match args.first() {
+        Some(key_ref) => functions::call_to_string_q(q_ctx, key_ref),
+        None => Err(JsError::new_string(format!(
+            "{} requires a key argument",
+            method
+        ))),
+    }
+}
+
+pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    log::trace!("storage::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        init_storage_proxy(q_ctx)?;
+        let global_ref = quickjs_utils::get_global_q(q_ctx);
+        for (name, kind) in &[
+            ("localStorage", StorageKind::Local),
+            ("sessionStorage", StorageKind::Session),
+        ] {
+            let (instance_id, instance_ref) = reflection::new_instance(STORAGE_PROXY_NAME, q_ctx)?;
+            STORAGES.with(|storages_rc| {
+                let storages = &mut *storages_rc.borrow_mut();
+                storages.insert(instance_id, *kind);
+            });
+            objects::set_property2_q(q_ctx, &global_ref, name, &instance_ref, 0)?;
+        }
+        Ok(())
+    })
+}
+
+fn init_storage_proxy(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    reflection::Proxy::new()
+        .name(STORAGE_PROXY_NAME)
+        .method("getItem", |q_ctx, instance_id, args| {
+            let key = get_key_arg(q_ctx, &args, "getItem")?;
+            match with_backend(instance_id, |backend| backend.get_item(key.as_str()))? {
+                Some(value) => primitives::from_string_q(q_ctx, value.as_str()),
+                None => Ok(quickjs_utils::new_null_ref()),
+            }
+        })
+        .method("setItem", |q_ctx, instance_id, args| {
+            let key = get_key_arg(q_ctx, &args, "setItem")?;
+            let value = match args.get(1) {
+                Some(value_ref) => functions::call_to_string_q(q_ctx, value_ref)?,
+                None => "undefined".to_string(),
+            };
+            with_backend(instance_id, |backend| backend.set_item(key.as_str(), value))?;
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .method("removeItem", |q_ctx, instance_id, args| {
+            let key = get_key_arg(q_ctx, &args, "removeItem")?;
+            with_backend(instance_id, |backend| backend.remove_item(key.as_str()))?;
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .method("clear", |_q_ctx, instance_id, _args| {
+            with_backend(instance_id, |backend| backend.clear())?;
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .method("key", |q_ctx, instance_id, args| {
+            let index = match args.first() {
+                Some(index_ref) if index_ref.is_i32() => primitives::to_i32(index_ref)?,
+                Some(index_ref) if index_ref.is_f64() => primitives::to_f64(index_ref)? as i32,
+                _ => 0,
+            };
+            let keys = with_backend(instance_id, |backend| backend.keys())?;
+            match keys.get(index as usize) {
+                Some(key) if index >= 0 => primitives::from_string_q(q_ctx, key.as_str()),
+                _ => Ok(quickjs_utils::new_null_ref()),
+            }
+        })
+        .getter_setter(
+            "length",
+            |_q_ctx, instance_id| {
+                let len = with_backend(instance_id, |backend| backend.keys().len())?;
+                Ok(primitives::from_i32(len as i32))
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .finalizer(|_q_ctx, instance_id| {
+            STORAGES.with(|storages_rc| {
+                let storages = &mut *storages_rc.borrow_mut();
+                storages.remove(&instance_id);
+            });
+        })
+        .install(q_ctx, false)
+        .map(|_| {})
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::quickjs_utils::primitives;
+    use hirofa_utils::js_utils::Script;
+
+    #[test]
+    fn test_storage() {
+        let rt = EsRuntimeBuilder::new().build();
+        let res = rt
+            .eval_sync(Script::new(
+                "test_storage.es",
+                "localStorage.setItem('a', 'one');\n\
+                 localStorage.setItem('b', 2);\n\
+                 sessionStorage.setItem('s', 'session');\n\
+                 localStorage.removeItem('b');\n\
+                 localStorage.length + ':' + localStorage.key(0) + ':' + localStorage.getItem('b') + ':' + sessionStorage.length;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1:a:null:1");
+
+        rt.create_context("storage_ctx")
+            .ok()
+            .expect("could not create context");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("storage_ctx");
+            let res_ref = q_ctx
+                .eval(Script::new(
+                    "test_storage2.es",
+                    "localStorage.getItem('a') + ':' + sessionStorage.getItem('s');",
+                ))
+                .ok()
+                .expect("script failed");
+            primitives::to_string_q(q_ctx, &res_ref)
+                .ok()
+                .expect("not a string")
+        });
+        assert_eq!(res, "one:session");
+        rt.drop_context("storage_ctx");
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_storage3.es",
+                "sessionStorage.clear(); sessionStorage.length + ':' + localStorage.length;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "0:1");
+    }
+}
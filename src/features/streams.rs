@@ -0,0 +1,538 @@
+//! provides a minimal ReadableStream for the runtime
+//!
+//! the ReadableStream supports an underlying source with start, pull and cancel methods, readers (getReader) and
+//! async iteration (`for await (const chunk of stream)`), byte streams, tee() and pipes are not supported
+//!
+//! Rust channels can be converted to a ReadableStream with [EsRuntime::channel_to_readable_stream](crate::esruntime::EsRuntime::channel_to_readable_stream)
+//! and a ReadableStream can be consumed as a rust Stream with [EsRuntime::readable_stream_to_rust](crate::esruntime::EsRuntime::readable_stream_to_rust)
+//!
+//! # Example
+//! ```javascript
+//! let stream = new ReadableStream({
+//!     start(controller) {
+//!         controller.enqueue('a');
+//!         controller.enqueue('b');
+//!         controller.close();
+//!     }
+//! });
+//! for await (const chunk of stream) {
+//!     console.log(chunk);
+//! }
+//! ```
+
+use crate::esruntime::EsRuntime;
+use crate::esvalue::{EsValueConvertible, EsValueFacade};
+use crate::quickjs_utils;
+use crate::quickjs_utils::{errors, functions, objects, primitives, promises};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use crate::valueref::JSValueRef;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::Stream;
+use hirofa_utils::js_utils::{JsError, Script};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+const READABLE_STREAM_SRC: &str = r#"
+(function() {
+    class ReadableStreamDefaultController {
+        constructor(stream) {
+            this._stream = stream;
+        }
+        get desiredSize() {
+            return 1 - this._stream._queue.length;
+        }
+        enqueue(chunk) {
+            this._stream._enqueue(chunk);
+        }
+        close() {
+            this._stream._close();
+        }
+        error(e) {
+            this._stream._error(e);
+        }
+    }
+    class ReadableStreamDefaultReader {
+        constructor(stream) {
+            if (stream._locked) {
+                throw new TypeError('ReadableStream is locked');
+            }
+            stream._locked = true;
+            this._stream = stream;
+        }
+        read() {
+            if (!this._stream) {
+                return Promise.reject(new TypeError('reader was released'));
+            }
+            return this._stream._read();
+        }
+        cancel(reason) {
+            if (!this._stream) {
+                return Promise.reject(new TypeError('reader was released'));
+            }
+            return this._stream._cancel(reason);
+        }
+        releaseLock() {
+            if (this._stream) {
+                this._stream._locked = false;
+                this._stream = null;
+            }
+        }
+    }
+    class ReadableStream {
+        constructor(source) {
+            this._source = source || {};
+            this._queue = [];
+            this._reads = [];
+            this._state = 'readable';
+            this._locked = false;
+            this._started = false;
+            this._pulling = false;
+            this._controller = new ReadableStreamDefaultController(this);
+            let started = this._source.start ? this._source.start(this._controller) : undefined;
+            Promise.resolve(started).then(() => {
+                this._started = true;
+                this._pull();
+            }, (e) => this._error(e));
+        }
+        get locked() {
+            return this._locked;
+        }
+        getReader() {
+            return new ReadableStreamDefaultReader(this);
+        }
+        cancel(reason) {
+            if (this._locked) {
+                return Promise.reject(new TypeError('ReadableStream is locked'));
+            }
+            return this._cancel(reason);
+        }
+        async *values() {
+            const reader = this.getReader();
+            try {
+                while (true) {
+                    const {value, done} = await reader.read();
+                    if (done) {
+                        return;
+                    }
+                    yield value;
+                }
+            } finally {
+                reader.releaseLock();
+            }
+        }
+        [Symbol.asyncIterator]() {
+            return this.values();
+        }
+        _cancel(reason) {
+            this._queue = [];
+            this._close();
+            let cancelled = this._source.cancel ? this._source.cancel(reason) : undefined;
+            return Promise.resolve(cancelled).then(() => undefined);
+        }
+        _enqueue(chunk) {
+            if (this._state !== 'readable') {
+                throw new TypeError('ReadableStream is not readable');
+            }
+            if (this._reads.length > 0) {
+                this._reads.shift().resolve({value: chunk, done: false});
+            } else {
+                this._queue.push(chunk);
+            }
+        }
+        _close() {
+            if (this._state !== 'readable') {
+                return;
+            }
+            this._state = 'closed';
+            for (const read of this._reads.splice(0)) {
+                read.resolve({value: undefined, done: true});
+            }
+        }
+        _error(e) {
+            if (this._state !== 'readable') {
+                return;
+            }
+            this._state = 'errored';
+            this._storedError = e;
+            this._queue = [];
+            for (const read of this._reads.splice(0)) {
+                read.reject(e);
+            }
+        }
+        _read() {
+            if (this._queue.length > 0) {
+                const value = this._queue.shift();
+                this._pull();
+                return Promise.resolve({value, done: false});
+            }
+            if (this._state === 'closed') {
+                return Promise.resolve({value: undefined, done: true});
+            }
+            if (this._state === 'errored') {
+                return Promise.reject(this._storedError);
+            }
+            return new Promise((resolve, reject) => {
+                this._reads.push({resolve, reject});
+                this._pull();
+            });
+        }
+        _pull() {
+            if (!this._started || this._pulling || this._state !== 'readable' || !this._source.pull
+                || this._queue.length > 0) {
+                return;
+            }
+            this._pulling = true;
+            Promise.resolve(this._source.pull(this._controller)).then(() => {
+                this._pulling = false;
+                if (this._reads.length > 0) {
+                    this._pull();
+                }
+            }, (e) => this._error(e));
+        }
+    }
+    Object.defineProperty(globalThis, 'ReadableStream', {value: ReadableStream, writable: true, configurable: true});
+})();
+"#;
+
+pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    log::trace!("streams::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| {
+        q_ctx
+            .eval(Script::new("streams.es", READABLE_STREAM_SRC))
+            .map(|_| ())
+    })
+}
+
+/// create a new ReadableStream with a start function which stores the controller of the stream in the object cache
+/// of the context (so it is dropped with the context), returns the cache id of the controller and the stream
+fn new_channel_stream(q_ctx: &QuickJsContext) -> Result<(i32, JSValueRef), JsError> {
+    let controller_id_cell = Rc::new(RefCell::new(None));
+    let controller_id_cell2 = controller_id_cell.clone();
+    let start_ref = functions::new_function_q(
+        q_ctx,
+        "start",
+        move |q_ctx, _this_ref, args| {
+            let controller_ref = args
+                .first()
+                .cloned()
+                .ok_or_else(|| JsError::new_str("no controller passed to start"))?;
+            let id = q_ctx.cache_object(controller_ref);
+            controller_id_cell2.replace(Some(id));
+            Ok(quickjs_utils::new_undefined_ref())
+        },
+        1,
+    )?;
+    let source_ref = objects::create_object_q(q_ctx)?;
+    objects::set_property_q(q_ctx, &source_ref, "start", &start_ref)?;
+    let constructor_ref =
+        objects::get_property_q(q_ctx, &quickjs_utils::get_global_q(q_ctx), "ReadableStream")?;
+    let stream_ref = functions::call_constructor_q(q_ctx, &constructor_ref, &[source_ref])?;
+    let controller_id = controller_id_cell
+        .borrow_mut()
+        .take()
+        .ok_or_else(|| JsError::new_str("ReadableStream did not call start"))?;
+    Ok((controller_id, stream_ref))
+}
+
+fn with_channel_controller(
+    q_ctx: &QuickJsContext,
+    controller_id: i32,
+    method: &str,
+    arguments: Vec<JSValueRef>,
+    remove: bool,
+) {
+    let controller_ref = if remove {
+        q_ctx.consume_cached_obj(controller_id)
+    } else {
+        q_ctx.with_cached_obj(controller_id, |controller_ref| controller_ref)
+    };
+    if let Err(e) = functions::invoke_member_function_q(q_ctx, &controller_ref, method, arguments) {
+        log::error!("ReadableStream controller.{} failed: {}", method, e);
+    }
+}
+
+/// enqueue an item received from a channel, when the item can not be converted the stream is errored
+fn enqueue_channel_item<T>(q_ctx: &QuickJsContext, controller_id: i32, mut item: T)
+where
+    T: EsValueConvertible,
+{
+    match item.as_js_value(q_ctx) {
+        Ok(item_ref) => {
+            with_channel_controller(q_ctx, controller_id, "enqueue", vec![item_ref], false)
+        }
+        Err(e) => {
+            log::error!("could not convert item of channel: {}", e);
+            match unsafe {
+                errors::new_error(q_ctx.context, e.get_name(), e.get_message(), e.get_stack())
+            } {
+                Ok(err_ref) => {
+                    with_channel_controller(q_ctx, controller_id, "error", vec![err_ref], false)
+                }
+                Err(e) => log::error!("could not create error for channel: {}", e),
+            }
+        }
+    }
+}
+
+/// create a ReadableStream in the main context which enqueues all items received from a channel, the stream is
+/// closed when all senders of the channel are dropped
+pub(crate) fn channel_to_readable_stream<T>(
+    es_rt: &Arc<EsRuntime>,
+    receiver: Receiver<T>,
+) -> Result<EsValueFacade, JsError>
+where
+    T: EsValueConvertible + Send + 'static,
+{
+    let stream_rt = es_rt.clone();
+    es_rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+        let q_ctx = q_js_rt.get_main_context();
+        let stream_ref = channel_to_readable_stream_q(q_ctx, &stream_rt, receiver)?;
+        EsValueFacade::from_jsval(q_ctx, &stream_ref)
+    })
+}
+
+/// create a ReadableStream in the given context which enqueues all items received from a channel, the stream is
+/// closed when all senders of the channel are dropped and errored when an item can not be converted
+pub(crate) fn channel_to_readable_stream_q<T>(
+    q_ctx: &QuickJsContext,
+    es_rt: &Arc<EsRuntime>,
+    receiver: Receiver<T>,
+) -> Result<JSValueRef, JsError>
+where
+    T: EsValueConvertible + Send + 'static,
+{
+    let (controller_id, stream_ref) = new_channel_stream(q_ctx)?;
+
+    // receiving blocks until the channel is closed so it gets a thread of its own instead of a helper thread
+    let weak_rt = Arc::downgrade(es_rt);
+    let context_id = q_ctx.id.clone();
+    let spawn_res = std::thread::Builder::new()
+        .name(format!("readable-stream-channel-{}", controller_id))
+        .spawn(move || {
+            while let Ok(item) = receiver.recv() {
+                match weak_rt.upgrade() {
+                    Some(es_rt) => {
+                        let context_id = context_id.clone();
+                        es_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+                            // the context may have been dropped while the item was queued
+                            if let Some(q_ctx) = q_js_rt.opt_context(context_id.as_str()) {
+                                enqueue_channel_item(q_ctx, controller_id, item);
+                            }
+                        });
+                    }
+                    None => return,
+                }
+            }
+            if let Some(es_rt) = weak_rt.upgrade() {
+                es_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+                    if let Some(q_ctx) = q_js_rt.opt_context(context_id.as_str()) {
+                        with_channel_controller(q_ctx, controller_id, "close", vec![], true);
+                    }
+                });
+            }
+        });
+    if let Err(e) = spawn_res {
+        q_ctx.remove_cached_obj_if_present(controller_id);
+        return Err(JsError::new_string(format!(
+            "could not start thread for channel: {}",
+            e
+        )));
+    }
+
+    Ok(stream_ref)
+}
+
+/// read the next chunk from a reader and pass it to the sender, reads the next chunk when the sender is still open
+fn read_next_chunk<T, C>(
+    q_ctx: &QuickJsContext,
+    reader_ref: JSValueRef,
+    converter: Rc<C>,
+    sender: UnboundedSender<T>,
+) -> Result<(), JsError>
+where
+    T: 'static,
+    C: Fn(EsValueFacade) -> T + 'static,
+{
+    let prom_ref = functions::invoke_member_function_q(q_ctx, &reader_ref, "read", vec![])?;
+
+    let then_sender = sender.clone();
+    let then_ref = functions::new_function_q(
+        q_ctx,
+        "then",
+        move |q_ctx, _this_ref, args| {
+            let result_ref = args
+                .first()
+                .ok_or_else(|| JsError::new_str("no result passed to then"))?;
+            let done_ref = objects::get_property_q(q_ctx, result_ref, "done")?;
+            if done_ref.is_bool() && primitives::to_bool(&done_ref)? {
+                then_sender.close_channel();
+                functions::invoke_member_function_q(q_ctx, &reader_ref, "releaseLock", vec![])?;
+            } else {
+                let value_ref = objects::get_property_q(q_ctx, result_ref, "value")?;
+                let value = converter(EsValueFacade::from_jsval(q_ctx, &value_ref)?);
+                if then_sender.unbounded_send(value).is_ok() {
+                    read_next_chunk(
+                        q_ctx,
+                        reader_ref.clone(),
+                        converter.clone(),
+                        then_sender.clone(),
+                    )?;
+                } else {
+                    // rust Stream was dropped, stop reading
+                    functions::invoke_member_function_q(q_ctx, &reader_ref, "cancel", vec![])?;
+                }
+            }
+            Ok(quickjs_utils::new_undefined_ref())
+        },
+        1,
+    )?;
+
+    let catch_ref = functions::new_function_q(
+        q_ctx,
+        "catch",
+        move |q_ctx, _this_ref, args| {
+            let reason = match args.first() {
+                Some(reason_ref) => functions::call_to_string_q(q_ctx, reason_ref)?,
+                None => "unknown".to_string(),
+            };
+            log::error!("reading ReadableStream failed: {}", reason);
+            sender.close_channel();
+            Ok(quickjs_utils::new_undefined_ref())
+        },
+        1,
+    )?;
+
+    promises::add_promise_reactions_q(q_ctx, &prom_ref, Some(then_ref), Some(catch_ref), None)
+}
+
+/// read all chunks of a ReadableStream in the main context and convert them to a rust Stream
+pub(crate) fn readable_stream_to_rust<T, C>(
+    es_rt: &EsRuntime,
+    stream_esvf: EsValueFacade,
+    converter: C,
+) -> impl Stream<Item = T>
+where
+    T: Send + 'static,
+    C: Fn(EsValueFacade) -> T + Send + 'static,
+{
+    let (sender, receiver) = unbounded();
+
+    es_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+        let q_ctx = q_js_rt.get_main_context();
+        let mut stream_esvf = stream_esvf;
+        let res = stream_esvf.as_js_value(q_ctx).and_then(|stream_ref| {
+            let reader_ref =
+                functions::invoke_member_function_q(q_ctx, &stream_ref, "getReader", vec![])?;
+            read_next_chunk(q_ctx, reader_ref, Rc::new(converter), sender.clone())
+        });
+        if let Err(e) = res {
+            log::error!("could not read ReadableStream: {}", e);
+            sender.close_channel();
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use hirofa_utils::js_utils::Script;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_readable_stream() {
+        let rt = EsRuntimeBuilder::new().build();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_readable_stream.es",
+                "(async function() {\n\
+                 let count = 0;\n\
+                 let stream = new ReadableStream({\n\
+                     start(controller) {controller.enqueue('a');},\n\
+                     pull(controller) {\n\
+                         count++;\n\
+                         if (count > 2) {controller.close();} else {controller.enqueue('p' + count);}\n\
+                     }\n\
+                 });\n\
+                 let chunks = [];\n\
+                 for await (const chunk of stream) {chunks.push(chunk);}\n\
+                 let reader = stream.getReader();\n\
+                 let last = await reader.read();\n\
+                 return chunks.join(',') + ':' + last.done + ':' + stream.locked;\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "a,p1,p2:true:true");
+    }
+
+    #[test]
+    fn test_channel_to_readable_stream() {
+        let rt = EsRuntimeBuilder::new().build();
+        let (sender, receiver) = channel();
+        let stream = rt
+            .channel_to_readable_stream(receiver)
+            .ok()
+            .expect("could not create stream");
+        let consume = rt
+            .eval_sync(Script::new(
+                "test_channel_stream.es",
+                "(async function(stream) {\n\
+                 let values = [];\n\
+                 for await (const x of stream) {values.push(x);}\n\
+                 return values.join(',');\n\
+                 });",
+            ))
+            .ok()
+            .expect("script failed");
+        let prom = consume
+            .invoke_function_sync(vec![stream])
+            .ok()
+            .expect("function failed");
+        std::thread::spawn(move || {
+            for x in 1..=5 {
+                sender.send(x).expect("send failed");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "1,2,3,4,5");
+    }
+
+    #[test]
+    fn test_readable_stream_to_rust() {
+        let rt = EsRuntimeBuilder::new().build();
+        let stream = rt
+            .eval_sync(Script::new(
+                "test_stream_to_rust.es",
+                "new ReadableStream({\n\
+                 start(controller) {\n\
+                     controller.enqueue('a');\n\
+                     controller.enqueue('b');\n\
+                     controller.enqueue('c');\n\
+                     controller.close();\n\
+                 }\n\
+                 });",
+            ))
+            .ok()
+            .expect("script failed");
+        let chunks = rt.readable_stream_to_rust(stream, |chunk| chunk.get_str().to_string());
+        let chunks: Vec<String> = block_on(chunks.collect());
+        assert_eq!(chunks, vec!["a", "b", "c"]);
+    }
+}
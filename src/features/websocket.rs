@@ -0,0 +1,395 @@
+//! provides the WebSocket class for the runtime
+//!
+//! the connection is managed by a dedicated thread per WebSocket, events (open, message, error and close) are
+//! dispatched in the event loop of the runtime, both to the on[event] handlers and to listeners added with
+//! addEventListener
+//! the on[event] handlers are stored as properties of the WebSocket object itself
+//!
+//! this feature is only available when the `websocket` feature is enabled
+//!
+//! # Example
+//! ```javascript
+//! let ws = new WebSocket('ws://127.0.0.1:9001');
+//! ws.onopen = () => {ws.send('hello');};
+//! ws.onmessage = (evt) => {console.log('got %s', evt.data); ws.close();};
+//! ```
+
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, objects, primitives, typedarrays};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use crate::reflection;
+use crate::reflection::eventtarget;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::Duration;
+use tungstenite::Message;
+
+const WEBSOCKET_PROXY_NAME: &str = "WebSocket";
+
+const CONNECTING: u16 = 0;
+const OPEN: u16 = 1;
+const CLOSING: u16 = 2;
+const CLOSED: u16 = 3;
+
+/// the interval in which the connection thread checks for messages to send while waiting for incoming messages
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum WsCommand {
+    Send(Message),
+    Close,
+}
+
+enum WsEvent {
+    Open,
+    Message(Message),
+    Error(String),
+    Close(u16, String),
+}
+
+impl WsEvent {
+    fn get_type(&self) -> &'static str {
+        match self {
+            WsEvent::Open => "open",
+            WsEvent::Message(_) => "message",
+            WsEvent::Error(_) => "error",
+            WsEvent::Close(_, _) => "close",
+        }
+    }
+}
+
+struct WebSocketState {
+    ready_state: u16,
+    sender: Sender<WsCommand>,
+}
+
+thread_local! {
+    static SOCKETS: RefCell<HashMap<usize, WebSocketState>> = RefCell::new(HashMap::new());
+}
+
+fn with_socket_state<C, R>(instance_id: &usize, consumer: C) -> Option<R>
+where
+    C: FnOnce(&mut WebSocketState) -> R,
+{
+    SOCKETS.with(|sockets_rc| {
+        let sockets = &mut *sockets_rc.borrow_mut();
+        sockets.get_mut(instance_id).map(consumer)
+    })
+}
+
+pub fn init(q_js_rt: &QuickJsRuntime) -> Result<(), JsError> {
+    log::trace!("websocket::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, q_ctx| init_websocket_proxy(q_ctx))
+}
+
+fn init_websocket_proxy(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    reflection::Proxy::new()
+        .name(WEBSOCKET_PROXY_NAME)
+        .event_target()
+        .constructor(|q_ctx, instance_id, args| {
+            if args.is_empty() || !args[0].is_string() {
+                return Err(JsError::new_str("WebSocket requires a url argument"));
+            }
+            let url = primitives::to_string_q(q_ctx, &args[0])?;
+
+            let es_rt = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref())
+                .ok_or_else(|| JsError::new_str("Runtime was dropped"))?;
+
+            let (sender, receiver) = channel();
+
+            let weak_rt = es_rt.weak_ref();
+            let context_id = q_ctx.id.clone();
+            // the connection blocks while reading so it gets its own thread instead of a helper task
+            std::thread::Builder::new()
+                .name(format!("websocket-{}", instance_id))
+                .spawn(move || {
+                    run_connection(url, receiver, |event| {
+                        let context_id = context_id.clone();
+                        weak_rt.add_rt_task_to_event_loop_void(move |q_js_rt| {
+                            dispatch(q_js_rt, context_id.as_str(), instance_id, event);
+                        })
+                    })
+                })
+                .map_err(|e| {
+                    JsError::new_string(format!("could not start WebSocket thread: {}", e))
+                })?;
+
+            SOCKETS.with(|sockets_rc| {
+                let sockets = &mut *sockets_rc.borrow_mut();
+                sockets.insert(
+                    instance_id,
+                    WebSocketState {
+                        ready_state: CONNECTING,
+                        sender,
+                    },
+                );
+            });
+            Ok(())
+        })
+        .getter_setter(
+            "readyState",
+            |_q_ctx, instance_id| {
+                let ready_state =
+                    with_socket_state(instance_id, |state| state.ready_state).unwrap_or(CLOSED);
+                Ok(primitives::from_i32(ready_state as i32))
+            },
+            |_q_ctx, _instance_id, _val| Ok(()),
+        )
+        .method("send", |q_ctx, instance_id, args| {
+            if args.is_empty() {
+                return Err(JsError::new_str("send requires a data argument"));
+            }
+            let message = if args[0].is_string() {
+                Message::Text(primitives::to_string_q(q_ctx, &args[0])?)
+            } else if typedarrays::is_uint8_array_q(q_ctx, &args[0]) {
+                Message::Binary(typedarrays::typed_array_to_vec_q(q_ctx, &args[0])?)
+            } else {
+                Message::Text(functions::call_to_string_q(q_ctx, &args[0])?)
+            };
+            with_socket_state(instance_id, |state| {
+                if state.ready_state != OPEN {
+                    return Err(JsError::new_str("WebSocket is not open"));
+                }
+                state
+                    .sender
+                    .send(WsCommand::Send(message))
+                    .map_err(|_| JsError::new_str("WebSocket connection was closed"))
+            })
+            .unwrap_or_else(|| Err(JsError::new_str("no such WebSocket")))?;
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .method("close", |_q_ctx, instance_id, _args| {
+            with_socket_state(instance_id, |state| {
+                if state.ready_state < CLOSING {
+                    state.ready_state = CLOSING;
+                    let _ = state.sender.send(WsCommand::Close);
+                }
+            });
+            Ok(quickjs_utils::new_undefined_ref())
+        })
+        .finalizer(|_q_ctx, instance_id| {
+            log::trace!("dropping WebSocket {}", instance_id);
+            // dropping the sender ends the connection thread
+            SOCKETS.with(|sockets_rc| {
+                let sockets = &mut *sockets_rc.borrow_mut();
+                sockets.remove(&instance_id);
+            });
+        })
+        .install(q_ctx, true)
+        .map(|_| {})
+}
+
+/// dispatch an event in the event loop, this calls the on[event] handler and the event listeners
+fn dispatch(q_js_rt: &QuickJsRuntime, context_id: &str, instance_id: usize, event: WsEvent) {
+    if !q_js_rt.has_context(context_id) {
+        return;
+    }
+    let q_ctx = q_js_rt.get_context(context_id);
+    let event_type = event.get_type();
+
+    let updated = with_socket_state(&instance_id, |state| match &event {
+        WsEvent::Open => state.ready_state = OPEN,
+        WsEvent::Close(_, _) => state.ready_state = CLOSED,
+        _ => {}
+    });
+    // the WebSocket was finalized
+    let instance_ref = match (updated, reflection::get_proxy_instance_ref(q_ctx, instance_id)) {
+        (Some(()), Some(instance_ref)) => instance_ref,
+        _ => return,
+    };
+
+    let res = create_event(q_ctx, event).and_then(|event_ref| {
+        let handler_name = format!("on{}", event_type);
+        let handler = objects::get_property_q(q_ctx, &instance_ref, handler_name.as_str())?;
+        if functions::is_function_q(q_ctx, &handler) {
+            functions::call_function_q(q_ctx, &handler, vec![event_ref.clone()], None)?;
+        }
+        let proxy = reflection::get_proxy(q_ctx, WEBSOCKET_PROXY_NAME)
+            .expect("WebSocket proxy was not installed");
+        eventtarget::dispatch_event(q_ctx, &proxy, instance_id, event_type, event_ref)
+    });
+    if let Err(e) = res {
+        log::error!("WebSocket {} handler failed: {}", event_type, e);
+    }
+}
+
+fn create_event(q_ctx: &QuickJsContext, event: WsEvent) -> Result<JSValueRef, JsError> {
+    let event_ref = objects::create_object_q(q_ctx)?;
+    objects::set_property_q(
+        q_ctx,
+        &event_ref,
+        "type",
+        &primitives::from_string_q(q_ctx, event.get_type())?,
+    )?;
+    match event {
+        WsEvent::Open => {}
+        WsEvent::Message(message) => {
+            let data_ref = match message {
+                Message::Binary(bytes) => typedarrays::new_uint8_array_q(q_ctx, bytes.as_slice())?,
+                message => primitives::from_string_q(q_ctx, message.to_text().unwrap_or(""))?,
+            };
+            objects::set_property_q(q_ctx, &event_ref, "data", &data_ref)?;
+        }
+        WsEvent::Error(message) => {
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "message",
+                &primitives::from_string_q(q_ctx, message.as_str())?,
+            )?;
+        }
+        WsEvent::Close(code, reason) => {
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "code",
+                &primitives::from_i32(code as i32),
+            )?;
+            objects::set_property_q(
+                q_ctx,
+                &event_ref,
+                "reason",
+                &primitives::from_string_q(q_ctx, reason.as_str())?,
+            )?;
+        }
+    }
+    Ok(event_ref)
+}
+
+/// manage a connection, this runs in its own thread until the connection is closed or the WebSocket is finalized
+fn run_connection<P>(url: String, receiver: Receiver<WsCommand>, post: P)
+where
+    P: Fn(WsEvent),
+{
+    let mut socket = match tungstenite::connect(url.as_str()) {
+        Ok((socket, _response)) => socket,
+        Err(e) => {
+            post(WsEvent::Error(format!(
+                "could not connect to {}: {}",
+                url, e
+            )));
+            post(WsEvent::Close(1006, "".to_string()));
+            return;
+        }
+    };
+    // use a read timeout so we can check for messages to send while waiting for incoming messages
+    // without a tls feature the stream of tungstenite 0.14 is a plain TcpStream
+    if let Err(e) = socket.get_mut().set_read_timeout(Some(POLL_INTERVAL)) {
+        log::error!("could not set read timeout for {}: {}", url, e);
+    }
+    post(WsEvent::Open);
+
+    let mut close_frame = (1005, "".to_string());
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(WsCommand::Send(message)) => {
+                    if let Err(e) = socket.write_message(message) {
+                        post(WsEvent::Error(format!("send failed: {}", e)));
+                    }
+                }
+                Ok(WsCommand::Close) => {
+                    let _ = socket.close(None);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // the WebSocket was finalized, nobody is listening anymore
+                    let _ = socket.close(None);
+                    let _ = socket.write_pending();
+                    return;
+                }
+            }
+        }
+
+        match socket.read_message() {
+            Ok(Message::Close(frame)) => {
+                if let Some(frame) = frame {
+                    close_frame = (u16::from(frame.code), frame.reason.to_string());
+                }
+            }
+            Ok(message @ Message::Text(_)) | Ok(message @ Message::Binary(_)) => {
+                post(WsEvent::Message(message));
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(tungstenite::Error::ConnectionClosed) | Err(tungstenite::Error::AlreadyClosed) => {
+                post(WsEvent::Close(close_frame.0, close_frame.1));
+                return;
+            }
+            Err(e) => {
+                post(WsEvent::Error(format!("connection failed: {}", e)));
+                post(WsEvent::Close(1006, "".to_string()));
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use hirofa_utils::js_utils::Script;
+    use std::net::TcpListener;
+    use tungstenite::Message;
+
+    /// start a ws server which echoes all messages for a single connection, returns the port
+    fn start_echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind");
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _addr) = listener.accept().expect("accept failed");
+            let mut socket = tungstenite::accept(stream).expect("handshake failed");
+            loop {
+                match socket.read_message() {
+                    Ok(message @ Message::Text(_)) | Ok(message @ Message::Binary(_)) => {
+                        socket.write_message(message).expect("echo failed");
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_websocket_echo() {
+        let port = start_echo_server();
+        let rt = init_test_rt();
+        let prom = rt
+            .eval_sync(Script::new(
+                "test_websocket_echo.es",
+                format!(
+                    "let ws = new WebSocket('ws://127.0.0.1:{}');\n\
+                     let events = [];\n\
+                     ws.addEventListener('close', (evt) => {{events.push(evt.type);}});\n\
+                     new Promise((resolve, reject) => {{\n\
+                        ws.onopen = (evt) => {{events.push(evt.type); ws.send('hello ws');}};\n\
+                        ws.onmessage = (evt) => {{events.push(evt.type); resolve(evt.data); ws.close();}};\n\
+                        ws.onerror = (evt) => {{reject(evt.message);}};\n\
+                     }});",
+                    port
+                )
+                .as_str(),
+            ))
+            .ok()
+            .expect("script failed");
+        let res = prom
+            .get_promise_result_sync()
+            .ok()
+            .expect("promise was rejected");
+        assert_eq!(res.get_str(), "hello ws");
+
+        let events = rt
+            .eval_sync(Script::new("test_websocket_echo2.es", "events.join(',');"))
+            .ok()
+            .expect("script failed");
+        assert!(events.get_str().starts_with("open,message"));
+    }
+}
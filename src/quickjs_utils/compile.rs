@@ -123,7 +123,8 @@ pub unsafe fn run_compiled_function(
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn to_bytecode(context: *mut q::JSContext, compiled_func: &JSValueRef) -> Vec<u8> {
-    assert!(compiled_func.is_compiled_function());
+    // compiled modules (see modules::compile_module) can be written as well
+    assert!(compiled_func.is_compiled_function() || compiled_func.is_module());
 
     let mut len = 0;
 
@@ -0,0 +1,350 @@
+//! utils for the debug hook of a QuickJsRuntime, see [QuickJsRuntime::set_debug_hook](crate::quickjsruntime::QuickJsRuntime::set_debug_hook)
+//!
+//! the bundled QuickJS has no debugger api so when a debug hook is set scripts are instrumented before they are
+//! evaluated, a checkpoint call is inserted at the start of every line which (probably) starts a statement
+//! the checkpoint is inserted on the same line so line numbers stay the same (columns do not)
+//!
+//! the script is tokenized (so strings, template literals, regular expressions and comments are skipped) and a line
+//! is considered to start a statement when its first token is directly in a block (the top level of the script, a
+//! function body or e.g. `if (a) {`, so not in a class body, an object literal or between parentheses) and the
+//! previous token is `;`, `}` or the `{` of that block. Lines which may continue an expression (e.g. starting with
+//! `.`, `+` or `else`) are never instrumented and neither is a `'use strict'` directive.
+//! This means breakpoints only work on lines which start a statement
+
+use crate::quickjs_utils::tokenizer::{tokenize, Token, TokenKind};
+use crate::quickjs_utils::{functions, get_global_q, new_undefined_ref, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use hirofa_utils::js_utils::JsError;
+
+const CHECKPOINT_FUNCTION_NAME: &str = "__qjs_debug_checkpoint__";
+
+/// an event passed to the debug hook of a QuickJsRuntime
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugEvent {
+    /// a line with a breakpoint is about to be executed
+    Breakpoint { filename: String, line: u32 },
+    /// a line is about to be executed while stepping is enabled, see [QuickJsRuntime::set_debug_stepping]
+    Step { filename: String, line: u32 },
+}
+
+pub type DebugHook = dyn Fn(&DebugEvent) + Send + Sync;
+
+/// install the checkpoint function in a context
+pub(crate) fn init_ctx(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    let func_ref = functions::new_function_q(
+        q_ctx,
+        CHECKPOINT_FUNCTION_NAME,
+        |q_ctx, _this_ref, args| {
+            if args.len() == 2 && args[0].is_string() && args[1].is_i32() {
+                let filename = primitives::to_string_q(q_ctx, &args[0])?;
+                let line = primitives::to_i32(&args[1])?;
+                QuickJsRuntime::do_with(|q_js_rt| q_js_rt.debug_checkpoint(filename, line as u32));
+            }
+            Ok(new_undefined_ref())
+        },
+        2,
+    )?;
+    objects::set_property2_q(
+        q_ctx,
+        &get_global_q(q_ctx),
+        CHECKPOINT_FUNCTION_NAME,
+        &func_ref,
+        0,
+    )
+}
+
+/// the kind of code between a pair of braces
+#[derive(Clone, Copy, PartialEq)]
+enum BraceKind {
+    /// a block of statements (this includes the top level of the script and function bodies)
+    Block,
+    ClassBody,
+    /// an object literal or a destructuring pattern
+    Object,
+    /// a template literal substitution (`${...}`)
+    Substitution,
+}
+
+struct BraceFrame {
+    kind: BraceKind,
+    /// number of unclosed parentheses and brackets
+    parens: usize,
+    /// number of `?` which did not get their `:` yet
+    conditionals: usize,
+    /// true if the last `:` was the one of a conditional expression
+    conditional_colon: bool,
+    /// the paren depth at which a `class` keyword was seen whose body did not start yet
+    class_pending: Option<usize>,
+}
+
+impl BraceFrame {
+    fn new(kind: BraceKind) -> Self {
+        Self {
+            kind,
+            parens: 0,
+            conditionals: 0,
+            conditional_colon: false,
+            class_pending: None,
+        }
+    }
+}
+
+/// keywords which can never continue the expression on the previous line, a line starting with one of these starts
+/// a statement even when the previous line does not end with `;` (automatic semicolon insertion)
+const STATEMENT_KEYWORDS: [&str; 14] = [
+    "break", "class", "const", "continue", "debugger", "for", "function", "if", "let", "return",
+    "switch", "throw", "try", "var",
+];
+
+/// keywords which continue a statement (or expression) of a previous line
+const CONTINUATION_KEYWORDS: [&str; 7] = [
+    "case",
+    "catch",
+    "default",
+    "else",
+    "finally",
+    "in",
+    "instanceof",
+];
+
+/// insert checkpoints in a script, see the module docs for which lines are instrumented
+pub(crate) fn instrument(path: &str, code: &str) -> String {
+    let path_literal = serde_json::to_string(path).expect("could not serialize path");
+    let tokens = tokenize(code);
+    let mut instrumented = String::with_capacity(code.len() * 2);
+    let mut copied = 0;
+    let mut frames = vec![BraceFrame::new(BraceKind::Block)];
+    for (idx, token) in tokens.iter().enumerate() {
+        let prev = if idx > 0 { tokens.get(idx - 1) } else { None };
+        let frame = frames.last_mut().expect("no brace frame");
+
+        if token.line_start && is_statement_start(frame, prev, token) {
+            let line_start = code[..token.start].rfind('\n').map_or(0, |pos| pos + 1);
+            instrumented.push_str(&code[copied..line_start]);
+            instrumented.push_str(
+                format!(
+                    "{}({}, {});",
+                    CHECKPOINT_FUNCTION_NAME, path_literal, token.line
+                )
+                .as_str(),
+            );
+            copied = line_start;
+        }
+
+        match (token.kind, token.text) {
+            (TokenKind::Punct, "(") | (TokenKind::Punct, "[") => frame.parens += 1,
+            (TokenKind::Punct, ")") | (TokenKind::Punct, "]") => {
+                frame.parens = frame.parens.saturating_sub(1)
+            }
+            (TokenKind::Punct, "?") => frame.conditionals += 1,
+            (TokenKind::Punct, ":") => {
+                frame.conditional_colon = frame.conditionals > 0;
+                frame.conditionals = frame.conditionals.saturating_sub(1);
+            }
+            (TokenKind::Punct, ";") => {
+                frame.conditionals = 0;
+                frame.class_pending = None;
+            }
+            (TokenKind::Ident, "class") => frame.class_pending = Some(frame.parens),
+            (TokenKind::Punct, "{") => {
+                let kind = brace_kind(frame, prev);
+                if kind == BraceKind::ClassBody {
+                    frame.class_pending = None;
+                }
+                frames.push(BraceFrame::new(kind));
+            }
+            (TokenKind::Punct, "${") => frames.push(BraceFrame::new(BraceKind::Substitution)),
+            (TokenKind::Punct, "}") => {
+                if frames.len() > 1 {
+                    frames.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+    instrumented.push_str(&code[copied..]);
+    instrumented
+}
+
+/// determine what a `{` opens based on the token before it
+fn brace_kind(frame: &BraceFrame, prev: Option<&Token>) -> BraceKind {
+    if frame.class_pending == Some(frame.parens) {
+        return BraceKind::ClassBody;
+    }
+    let statement_context = frame.kind == BraceKind::Block && frame.parens == 0;
+    match prev {
+        None => BraceKind::Block,
+        Some(prev) => match (prev.kind, prev.text) {
+            // if (..) {, function(..) {, a => {, } {
+            (TokenKind::Punct, ")") | (TokenKind::Punct, "=>") | (TokenKind::Punct, "}") => {
+                BraceKind::Block
+            }
+            (TokenKind::Punct, ";") | (TokenKind::Punct, "{") if statement_context => {
+                BraceKind::Block
+            }
+            // a label or a case, but not the `:` of a conditional
+            (TokenKind::Punct, ":") if statement_context && !frame.conditional_colon => {
+                BraceKind::Block
+            }
+            (TokenKind::Ident, "do")
+            | (TokenKind::Ident, "else")
+            | (TokenKind::Ident, "finally")
+            | (TokenKind::Ident, "try") => BraceKind::Block,
+            _ => BraceKind::Object,
+        },
+    }
+}
+
+/// see if a token which is the first on its line starts a statement which may be preceded by a checkpoint call
+fn is_statement_start(frame: &BraceFrame, prev: Option<&Token>, token: &Token) -> bool {
+    if frame.kind != BraceKind::Block || frame.parens > 0 {
+        return false;
+    }
+    // tokens which may continue an expression on the previous line
+    let continues_expression = match token.kind {
+        TokenKind::Punct => !matches!(token.text, "(" | "[" | "{" | "!" | "~"),
+        TokenKind::Ident => CONTINUATION_KEYWORDS.contains(&token.text),
+        // a directive must stay the first statement of its script or function
+        TokenKind::Str => token.text == "'use strict'" || token.text == "\"use strict\"",
+        TokenKind::Template | TokenKind::Number | TokenKind::Regex => false,
+    };
+    if continues_expression {
+        return false;
+    }
+    match prev {
+        None => true,
+        Some(prev) if prev.is_punct(";") => true,
+        // the `{` of the block this token is in (a block because the frame is one)
+        Some(prev) if prev.is_punct("{") => true,
+        // after a `}` a line starting with `(`, `[` or a template may continue an expression
+        // (e.g. `let f = function() {}\n(a)` calls the function)
+        // and a `while` may be the end of a `do {} while ()` loop
+        Some(prev) if prev.is_punct("}") => {
+            !(token.is_punct("(")
+                || token.is_punct("[")
+                || token.kind == TokenKind::Template
+                || token.is_ident("while"))
+        }
+        Some(prev) => {
+            prev.line < token.line
+                && token.kind == TokenKind::Ident
+                && STATEMENT_KEYWORDS.contains(&token.text)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::debugger::{instrument, DebugEvent};
+    use hirofa_utils::js_utils::Script;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_instrument() {
+        let code = "let a = {\n  b: 1\n};\nif (a.b) {\n  a.b++;\n}\nfoo()\n  .bar();";
+        let instrumented = instrument("test.es", code);
+        let lines: Vec<&str> = instrumented.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert!(lines[0].starts_with("__qjs_debug_checkpoint__(\"test.es\", 1);"));
+        assert_eq!(lines[1], "  b: 1");
+        assert_eq!(lines[2], "};");
+        assert!(lines[3].starts_with("__qjs_debug_checkpoint__(\"test.es\", 4);"));
+        assert!(lines[4].starts_with("__qjs_debug_checkpoint__(\"test.es\", 5);"));
+        assert!(lines[6].starts_with("__qjs_debug_checkpoint__(\"test.es\", 7);"));
+        assert_eq!(lines[7], "  .bar();");
+    }
+
+    #[test]
+    fn test_instrument_skips_literals_and_class_bodies() {
+        let code = "class A {\n  foo() {}\n  bar() {\n    return 1;\n  }\n}\nlet s = `a\nb${ {c: 1}.c }\nd`;\nlet r = /}\\//;\nlet o = {\n  e: true ? {\n    f: 1\n  } : 2\n};";
+        let instrumented = instrument("test.es", code);
+        let lines: Vec<&str> = instrumented.lines().collect();
+        assert_eq!(lines.len(), 15);
+        let instrumented_lines: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.contains("__qjs_debug_checkpoint__"))
+            .map(|(idx, _)| idx + 1)
+            .collect();
+        assert_eq!(instrumented_lines, vec![1, 4, 7, 10, 11]);
+        assert_eq!(lines[7], "b${ {c: 1}.c }");
+        assert_eq!(lines[8], "d`;");
+
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .set_debug_hook(Box::new(|_event| {}))
+                .ok()
+                .expect("could not set debug hook");
+        });
+        let res = rt
+            .eval_sync(Script::new(
+                "test_instrument_literals.es",
+                format!("{}\n`${{new A().bar()}}${{s}}${{o.e.f}}`", code).as_str(),
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1a\nb1\nd1");
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let rt = init_test_rt();
+        let events = Arc::new(Mutex::new(vec![]));
+        let events2 = events.clone();
+        rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt
+                .set_debug_hook(Box::new(move |event| {
+                    events2.lock().unwrap().push(event.clone());
+                }))
+                .ok()
+                .expect("could not set debug hook");
+        });
+        rt.add_breakpoint("test_breakpoint.es", 3);
+
+        let res = rt
+            .eval_sync(Script::new(
+                "test_breakpoint.es",
+                "let a = 1;\nlet b = 2;\nlet c = a + b;\nc;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3);
+        assert_eq!(
+            &*events.lock().unwrap(),
+            &vec![DebugEvent::Breakpoint {
+                filename: "test_breakpoint.es".to_string(),
+                line: 3
+            }]
+        );
+
+        rt.remove_breakpoint("test_breakpoint.es", 3);
+        rt.eval_sync(Script::new(
+            "test_breakpoint.es",
+            "let d = 1;\nlet e = 2;\nlet f = d + e;",
+        ))
+        .ok()
+        .expect("script failed");
+        assert_eq!(events.lock().unwrap().len(), 1);
+
+        rt.exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.set_debug_stepping(true));
+        rt.eval_sync(Script::new("test_step.es", "let g = 1;\nlet h = 2;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(
+            events.lock().unwrap()[1..],
+            [
+                DebugEvent::Step {
+                    filename: "test_step.es".to_string(),
+                    line: 1
+                },
+                DebugEvent::Step {
+                    filename: "test_step.es".to_string(),
+                    line: 2
+                }
+            ]
+        );
+    }
+}
@@ -1,10 +1,26 @@
 //! utils for getting and reporting exceptions
 
-use crate::quickjs_utils::{objects, primitives};
+use crate::quickjs_utils::{functions, objects, primitives, sourcemaps};
 use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
 use crate::valueref::{JSValueRef, TAG_EXCEPTION};
 use hirofa_utils::js_utils::JsError;
+use hirofa_utils::js_utils::Script;
 use libquickjs_sys as q;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+thread_local! {
+    // names of the error classes created with define_custom_error_class per context
+    static CUSTOM_ERROR_CLASSES: RefCell<HashMap<String, HashSet<String>>> = RefCell::new(HashMap::new());
+}
+
+/// add the context drop hook which forgets the custom error classes of a dropped context
+pub(crate) fn init(q_js_rt: &QuickJsRuntime) {
+    q_js_rt.add_context_drop_hook(|q_ctx| {
+        CUSTOM_ERROR_CLASSES.with(|rc| rc.borrow_mut().remove(&q_ctx.id));
+    });
+}
 
 /// Get the last exception from the runtime, and if present, convert it to an JsError.
 /// # Safety
@@ -28,10 +44,44 @@ pub unsafe fn get_exception(context: *mut q::JSContext) -> Option<JsError> {
     }
 }
 
+/// Get the last exception from the runtime, and if present, convert it to a String.
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn get_exception_string(context: *mut q::JSContext) -> Option<String> {
+    let exception_val = q::JS_GetException(context);
+    let exception_ref = JSValueRef::new(
+        context,
+        exception_val,
+        false,
+        true,
+        "errors::get_exception_string",
+    );
+
+    if exception_ref.is_null() {
+        None
+    } else {
+        match functions::call_to_string(context, &exception_ref) {
+            Ok(s) => Some(s),
+            Err(e) => Some(format!("Could not convert exception to string: {}", e)),
+        }
+    }
+}
+
 /// convert an instance of Error to JsError
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn error_to_js_error(context: *mut q::JSContext, exception_ref: &JSValueRef) -> JsError {
+    error_to_js_error_with_causes(context, exception_ref, &mut HashSet::new())
+}
+
+/// convert an Error to JsError, seen contains the addresses of the errors which are already in the cause chain
+/// so cyclic causes (e.g. `e.cause = e`) end the chain instead of recursing forever
+unsafe fn error_to_js_error_with_causes(
+    context: *mut q::JSContext,
+    exception_ref: &JSValueRef,
+    seen: &mut HashSet<usize>,
+) -> JsError {
+    seen.insert(exception_ref.borrow_value().u.ptr as usize);
     let name_ref = objects::get_property(context, exception_ref, "name")
         .ok()
         .unwrap();
@@ -43,16 +93,58 @@ pub unsafe fn error_to_js_error(context: *mut q::JSContext, exception_ref: &JSVa
     let stack_ref = objects::get_property(context, exception_ref, "stack")
         .ok()
         .unwrap();
-    let stack_string;
+    let mut stack_string;
     if stack_ref.is_string() {
-        stack_string = primitives::to_string(context, &stack_ref).ok().unwrap();
+        let raw_stack_string = primitives::to_string(context, &stack_ref).ok().unwrap();
+        stack_string = sourcemaps::map_stack_trace(raw_stack_string.as_str());
     } else {
         stack_string = "".to_string();
     }
 
+    // JsError has no cause field so the cause chain is appended to the stack, like java does
+    if let Some(cause) = get_cause_with_seen(context, exception_ref, seen) {
+        stack_string.push_str(
+            format!(
+                "Caused by: {}: {}\n{}",
+                cause.get_name(),
+                cause.get_message(),
+                cause.get_stack()
+            )
+            .as_str(),
+        );
+    }
+
     JsError::new(name_string, message_string, stack_string)
 }
 
+/// get the cause of an Error (the `cause` property, e.g. `new Error('outer', {cause: inner})`) as JsError
+/// the stack of the returned JsError contains the causes of the cause
+pub fn get_cause_q(q_ctx: &QuickJsContext, error_ref: &JSValueRef) -> Option<JsError> {
+    unsafe { get_cause(q_ctx.context, error_ref) }
+}
+
+/// get the cause of an Error
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn get_cause(context: *mut q::JSContext, error_ref: &JSValueRef) -> Option<JsError> {
+    let mut seen = HashSet::new();
+    seen.insert(error_ref.borrow_value().u.ptr as usize);
+    get_cause_with_seen(context, error_ref, &mut seen)
+}
+
+unsafe fn get_cause_with_seen(
+    context: *mut q::JSContext,
+    error_ref: &JSValueRef,
+    seen: &mut HashSet<usize>,
+) -> Option<JsError> {
+    let cause_ref = objects::get_property(context, error_ref, "cause").ok()?;
+    if is_error(context, &cause_ref) && !seen.contains(&(cause_ref.borrow_value().u.ptr as usize)) {
+        Some(error_to_js_error_with_causes(context, &cause_ref, seen))
+    } else {
+        None
+    }
+}
+
 /// Create a new Error object
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -91,6 +183,134 @@ pub unsafe fn new_error(
     Ok(obj_ref)
 }
 
+/// create a class which extends Error, instances of the class have the name of the class as their name
+/// the class is returned and not added to the global scope
+/// # Example
+/// ```rust
+/// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+/// use quickjs_runtime::quickjs_utils::{errors, get_global_q, objects};
+/// let rt = EsRuntimeBuilder::new().build();
+/// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+///     let q_ctx = q_js_rt.get_main_context();
+///     let class_ref = errors::define_custom_error_class_q(q_ctx, "ValidationError").ok().unwrap();
+///     objects::set_property_q(q_ctx, &get_global_q(q_ctx), "ValidationError", &class_ref).ok().unwrap();
+///     let err_ref = errors::new_custom_error_q(q_ctx, "ValidationError", "invalid input").ok().unwrap();
+///     assert!(errors::is_error_q(q_ctx, &err_ref));
+/// });
+/// ```
+pub fn define_custom_error_class_q(
+    q_ctx: &QuickJsContext,
+    name: &str,
+) -> Result<JSValueRef, JsError> {
+    unsafe { define_custom_error_class(q_ctx.context, name) }
+}
+
+/// create a class which extends Error
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn define_custom_error_class(
+    context: *mut q::JSContext,
+    name: &str,
+) -> Result<JSValueRef, JsError> {
+    let valid_name = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        .unwrap_or(false)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if !valid_name {
+        return Err(JsError::new_string(format!(
+            "invalid error class name: {}",
+            name
+        )));
+    }
+    let src = format!(
+        "(class {name} extends Error {{\n\
+         constructor(message, options) {{\n\
+         super(message, options);\n\
+         this.name = '{name}';\n\
+         }}\n\
+         }});",
+        name = name
+    );
+    let class_ref = QuickJsContext::eval_ctx(
+        context,
+        Script::new(
+            format!("define_error_class_{}.es", name).as_str(),
+            src.as_str(),
+        ),
+    )?;
+    let context_id = QuickJsContext::get_id(context);
+    CUSTOM_ERROR_CLASSES.with(|rc| {
+        let classes = &mut *rc.borrow_mut();
+        classes
+            .entry(context_id.to_string())
+            .or_insert_with(HashSet::new)
+            .insert(name.to_string());
+    });
+    Ok(class_ref)
+}
+
+/// check if a class with this name was created with define_custom_error_class in this context
+pub fn is_custom_error_class_q(q_ctx: &QuickJsContext, name: &str) -> bool {
+    unsafe { is_custom_error_class(q_ctx.context, name) }
+}
+
+/// check if a class with this name was created with define_custom_error_class in this context
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn is_custom_error_class(context: *mut q::JSContext, name: &str) -> bool {
+    let context_id = QuickJsContext::get_id(context);
+    CUSTOM_ERROR_CLASSES.with(|rc| {
+        rc.borrow()
+            .get(context_id)
+            .map(|classes| classes.contains(name))
+            .unwrap_or(false)
+    })
+}
+
+/// create an instance of an error class which was created with define_custom_error_class, the class is looked up
+/// by name in the global scope
+pub fn new_custom_error_q(
+    q_ctx: &QuickJsContext,
+    class_name: &str,
+    message: &str,
+) -> Result<JSValueRef, JsError> {
+    unsafe { new_custom_error(q_ctx.context, class_name, message) }
+}
+
+/// create an instance of a custom error class
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn new_custom_error(
+    context: *mut q::JSContext,
+    class_name: &str,
+    message: &str,
+) -> Result<JSValueRef, JsError> {
+    let global_ref = crate::quickjs_utils::get_global(context);
+    let class_ref = objects::get_property(context, &global_ref, class_name)?;
+    if !functions::is_function(context, &class_ref) {
+        return Err(JsError::new_string(format!(
+            "no error class named {} found",
+            class_name
+        )));
+    }
+    let err_ref = functions::call_constructor(
+        context,
+        &class_ref,
+        &[primitives::from_string(context, message)?],
+    )?;
+    if !is_error(context, &err_ref) {
+        return Err(JsError::new_string(format!(
+            "{} is not an error class",
+            class_name
+        )));
+    }
+    Ok(err_ref)
+}
+
 /// See if a JSValueRef is an Error object
 pub fn is_error_q(q_ctx: &QuickJsContext, obj_ref: &JSValueRef) -> bool {
     unsafe { is_error(q_ctx.context, obj_ref) }
@@ -125,11 +345,136 @@ pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntime::EsRuntime;
     use crate::esvalue::EsValueConvertible;
+    use crate::quickjs_utils::errors::{
+        define_custom_error_class_q, error_to_js_error, get_cause_q, is_custom_error_class_q,
+        new_custom_error_q,
+    };
     use crate::quickjs_utils::functions;
-    use hirofa_utils::js_utils::Script;
+    use hirofa_utils::js_utils::{JsError, Script};
     use std::sync::Arc;
     use std::time::Duration;
 
+    #[test]
+    fn test_custom_error_class() {
+        let rt = init_test_rt();
+        rt.define_error_class("DatabaseError")
+            .ok()
+            .expect("could not define error class");
+        rt.set_function(vec!["com", "test"], "query", |_q_ctx, _args| {
+            Err(JsError::new(
+                "DatabaseError".to_string(),
+                "connection lost".to_string(),
+                "".to_string(),
+            ))
+        })
+        .ok()
+        .expect("could not set function");
+        let res = rt
+            .eval_sync(Script::new(
+                "test_custom_error_class.es",
+                "(function() {\n\
+                 try {\n\
+                     com.test.query();\n\
+                 } catch(err) {\n\
+                     return [err instanceof DatabaseError, err instanceof Error, err.name, err.message].join(',');\n\
+                 }\n\
+                 })();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "true,true,DatabaseError,connection lost");
+
+        let ok = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let err_ref = new_custom_error_q(q_ctx, "DatabaseError", "from rust")?;
+            let err = unsafe { error_to_js_error(q_ctx.context, &err_ref) };
+            assert!(define_custom_error_class_q(q_ctx, "not a name").is_err());
+            Ok::<_, JsError>(err.get_name() == "DatabaseError" && err.get_message() == "from rust")
+        });
+        assert!(ok.ok().expect("could not create custom error"));
+    }
+
+    #[test]
+    fn test_custom_error_class_per_context() {
+        let rt = init_test_rt();
+        rt.create_context("error_class_ctx")
+            .ok()
+            .expect("could not create context");
+        let (in_ctx, in_main) = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("error_class_ctx");
+            define_custom_error_class_q(q_ctx, "ContextError")
+                .ok()
+                .expect("could not define error class");
+            (
+                is_custom_error_class_q(q_ctx, "ContextError"),
+                is_custom_error_class_q(q_js_rt.get_main_context(), "ContextError"),
+            )
+        });
+        assert!(in_ctx);
+        assert!(!in_main);
+
+        // a new context with the same id does not inherit the classes of the dropped context
+        rt.drop_context("error_class_ctx");
+        rt.create_context("error_class_ctx")
+            .ok()
+            .expect("could not create context");
+        let in_new_ctx = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            is_custom_error_class_q(q_js_rt.get_context("error_class_ctx"), "ContextError")
+        });
+        assert!(!in_new_ctx);
+    }
+
+    #[test]
+    fn test_error_cause() {
+        let rt = init_test_rt();
+        let res = rt.eval_sync(Script::new(
+            "test_error_cause.es",
+            "let outer = new Error('outer');\n\
+             outer.cause = new TypeError('inner');\n\
+             throw outer;",
+        ));
+        let err = res.err().expect("script should have failed");
+        assert_eq!(err.get_message(), "outer");
+        assert!(err.get_stack().contains("Caused by: TypeError: inner"));
+
+        let cause = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let err_ref = q_ctx
+                .eval(Script::new(
+                    "test_error_cause2.es",
+                    "let e = new Error('outer');\n\
+                     e.cause = new Error('middle');\n\
+                     e.cause.cause = new Error('inner');\n\
+                     e;",
+                ))
+                .ok()
+                .expect("script failed");
+            get_cause_q(q_ctx, &err_ref)
+        });
+        let cause = cause.expect("no cause found");
+        assert_eq!(cause.get_message(), "middle");
+        assert!(cause.get_stack().contains("Caused by: Error: inner"));
+
+        // cyclic causes end the chain
+        let res = rt.eval_sync(Script::new(
+            "test_error_cause3.es",
+            "let self_caused = new Error('self');\n\
+             self_caused.cause = self_caused;\n\
+             throw self_caused;",
+        ));
+        let err = res.err().expect("script should have failed");
+        assert!(!err.get_stack().contains("Caused by"));
+        let res = rt.eval_sync(Script::new(
+            "test_error_cause4.es",
+            "let c1 = new Error('c1');\n\
+             c1.cause = new Error('c2');\n\
+             c1.cause.cause = c1;\n\
+             throw c1;",
+        ));
+        let err = res.err().expect("script should have failed");
+        assert_eq!(err.get_stack().matches("Caused by").count(), 1);
+    }
+
     #[test]
     fn test_ex() {
         // check if stacktrace is preserved when invoking native methods
@@ -213,4 +558,33 @@ pub mod tests {
 
         std::thread::sleep(Duration::from_secs(1));
     }
+
+    #[test]
+    fn test_get_exception_string() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            assert!(q_js_rt.get_exception_string().is_none());
+
+            let code = "throw Error('exception as string');";
+            let code_c = std::ffi::CString::new(code).unwrap();
+            let file_c = std::ffi::CString::new("test_get_exception_string.es").unwrap();
+            let res = unsafe {
+                libquickjs_sys::JS_Eval(
+                    q_ctx.context,
+                    code_c.as_ptr(),
+                    code.len() as _,
+                    file_c.as_ptr(),
+                    libquickjs_sys::JS_EVAL_TYPE_GLOBAL as i32,
+                )
+            };
+            assert_eq!(res.tag, crate::valueref::TAG_EXCEPTION);
+
+            let ex_str = q_js_rt
+                .get_exception_string()
+                .expect("no pending exception");
+            assert!(ex_str.contains("exception as string"));
+            assert!(q_ctx.get_exception_string().is_none());
+        });
+    }
 }
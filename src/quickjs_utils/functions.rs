@@ -1,7 +1,7 @@
 //! utils to create and invoke functions
 
 use crate::quickjs_utils::{atoms, errors, objects, parse_args, primitives};
-use crate::quickjscontext::QuickJsContext;
+use crate::quickjscontext::{DeadlineGuard, QuickJsContext};
 use crate::quickjsruntime::{make_cstring, QuickJsRuntime};
 use crate::valueref::JSValueRef;
 use hirofa_utils::auto_id_map::AutoIdMap;
@@ -103,6 +103,8 @@ pub unsafe fn call_function(
         this_val = crate::quickjs_utils::new_null();
     }
 
+    let deadline_guard = DeadlineGuard::enter(context);
+
     let res = q::JS_Call(
         context,
         *function_ref.borrow_value(),
@@ -113,15 +115,33 @@ pub unsafe fn call_function(
 
     let res_ref = JSValueRef::new(context, res, false, true, "call_function result");
 
+    deadline_result(
+        &deadline_guard,
+        context,
+        res_ref,
+        "function invocation failed but could not get ex",
+    )
+}
+
+/// convert the result of a call to a Result, a call which exceeded the eval timeout of its context fails with a
+/// TimeoutError, see [QuickJsRuntime::set_context_eval_timeout](crate::quickjsruntime::QuickJsRuntime::set_context_eval_timeout)
+unsafe fn deadline_result(
+    deadline_guard: &DeadlineGuard,
+    context: *mut q::JSContext,
+    res_ref: JSValueRef,
+    no_ex_msg: &str,
+) -> Result<JSValueRef, JsError> {
     if res_ref.is_exception() {
-        if let Some(ex) = QuickJsContext::get_exception(context) {
+        let ex_opt = QuickJsContext::get_exception(context);
+        if let Some(timeout_err) = deadline_guard.timeout_error() {
+            Err(timeout_err)
+        } else if let Some(ex) = ex_opt {
             Err(ex)
         } else {
-            Err(JsError::new_str(
-                "function invocation failed but could not get ex",
-            ))
+            Err(JsError::new_str(no_ex_msg))
         }
     } else {
+        deadline_guard.run_pending_jobs()?;
         Ok(res_ref)
     }
 }
@@ -167,6 +187,8 @@ pub unsafe fn invoke_member_function(
         .map(|a| *a.borrow_value())
         .collect::<Vec<_>>();
 
+    let deadline_guard = DeadlineGuard::enter(context);
+
     let res_val = q::JS_Invoke(
         context,
         *obj_ref.borrow_value(),
@@ -183,17 +205,12 @@ pub unsafe fn invoke_member_function(
         format!("functions::invoke_member_function res: {}", function_name).as_str(),
     );
 
-    if res_ref.is_exception() {
-        if let Some(ex) = QuickJsContext::get_exception(context) {
-            Err(ex)
-        } else {
-            Err(JsError::new_str(
-                "invoke_member_function failed but could not get ex",
-            ))
-        }
-    } else {
-        Ok(res_ref)
-    }
+    deadline_result(
+        &deadline_guard,
+        context,
+        res_ref,
+        "invoke_member_function failed but could not get ex",
+    )
 }
 
 /// call an objects to_String method or convert a value to string
@@ -908,6 +925,14 @@ unsafe extern "C" fn callback_function(
             match callback_res {
                 Ok(res) => res.clone_value_incr_rc(),
                 Err(e) => {
+                    // errors named after a custom error class are thrown as an instance of that class
+                    if errors::is_custom_error_class(ctx, e.get_name()) {
+                        if let Ok(err) =
+                            errors::new_custom_error(ctx, e.get_name(), e.get_message())
+                        {
+                            return errors::throw(ctx, err);
+                        }
+                    }
                     let message =
                         format!("\n{} at\nnative_code\n{}", e.get_message(), e.get_stack());
                     let err = errors::new_error(ctx, e.get_name(), message.as_str(), e.get_stack())
@@ -18,12 +18,18 @@ pub(crate) fn init(q_js_rt: &QuickJsRuntime) {
 
 unsafe extern "C" fn interrupt_handler(_rt: *mut q::JSRuntime, _opaque: *mut c_void) -> c_int {
     QuickJsRuntime::do_with(|q_js_rt| {
-        let handler = q_js_rt.interrupt_handler.as_ref().unwrap();
-        if handler(q_js_rt) {
-            1
-        } else {
-            0 // do not interrupt, return 1 to interrupt
+        if q_js_rt.is_cancelled()
+            || q_js_rt.is_sandbox_limit_exceeded()
+            || q_js_rt.is_any_eval_deadline_exceeded()
+        {
+            return 1;
         }
+        if let Some(handler) = q_js_rt.interrupt_handler.as_ref() {
+            if handler(q_js_rt) {
+                return 1;
+            }
+        }
+        0 // do not interrupt, return 1 to interrupt
     })
 }
 
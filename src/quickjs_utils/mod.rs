@@ -5,6 +5,7 @@ pub mod atoms;
 pub mod bigints;
 pub mod compile;
 pub mod dates;
+pub mod debugger;
 pub mod errors;
 pub mod functions;
 pub mod interrupthandler;
@@ -14,9 +15,13 @@ pub mod maps;
 pub mod modules;
 pub mod objects;
 pub mod primitives;
+pub mod profiler;
 pub mod promises;
 pub mod properties;
+pub mod reflect;
 pub mod sets;
+pub mod sourcemaps;
+pub(crate) mod tokenizer;
 pub mod typedarrays;
 
 use crate::quickjs_utils::atoms::JSAtomRef;
@@ -37,6 +42,13 @@ pub fn gc(q_js_rt: &QuickJsRuntime) {
     log::trace!("GC done");
 }
 
+/// get memory usage statistics of a runtime
+pub fn memory_usage(q_js_rt: &QuickJsRuntime) -> q::JSMemoryUsage {
+    let mut usage: q::JSMemoryUsage = unsafe { std::mem::zeroed() };
+    unsafe { q::JS_ComputeMemoryUsage(q_js_rt.runtime, &mut usage) };
+    usage
+}
+
 pub fn new_undefined_ref() -> JSValueRef {
     JSValueRef::new_no_context(
         q::JSValue {
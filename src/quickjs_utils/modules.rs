@@ -2,6 +2,7 @@
 
 use crate::quickjs_utils::atoms;
 use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::objects;
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::QuickJsRuntime;
 use crate::valueref::JSValueRef;
@@ -9,8 +10,77 @@ use core::ptr;
 use hirofa_utils::js_utils::JsError;
 use hirofa_utils::js_utils::Script;
 use libquickjs_sys as q;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::ffi::{CStr, CString};
 
+thread_local! {
+    /// the modules which are currently being loaded by js_module_loader, a module which imports one of these is part
+    /// of a circular dependency, the set only lives as long as the (nested) loader calls of a single resolution
+    static LOADING_MODULES: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// get the namespace object (the object containing all exports) of a module
+/// if the module was not loaded yet it will be loaded (and evaluated) by the module loaders
+/// the namespace is looked up by compiling and evaluating a small helper module which imports it, this does not run
+/// any pending jobs, the namespace is cached per context so the helper module is only created once per module
+pub fn get_module_namespace_q(
+    q_ctx: &QuickJsContext,
+    module_name: &str,
+) -> Result<JSValueRef, JsError> {
+    if let Some(ns_ref) = q_ctx.module_namespaces.borrow().get(module_name) {
+        return Ok(ns_ref.clone());
+    }
+
+    // quickjs looks up import.meta by the name of the module so every helper module needs a unique name
+    let helper_name = format!(
+        "get_module_namespace_{}.es",
+        q_ctx.module_namespaces.borrow().len()
+    );
+    let quoted_name = module_name
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    let code = format!(
+        "import * as ns from \"{}\";\nimport.meta.namespace = ns;",
+        quoted_name
+    );
+    let module_ref = unsafe { compile_module(q_ctx.context, Script::new(&helper_name, &code))? };
+    let module_def = get_module_def(&module_ref);
+    // JS_EvalFunction consumes the module value, when evaluation fails quickjs frees the module def
+    let module_val = module_ref.clone_value_incr_rc();
+    drop(module_ref);
+
+    // the dependencies of the helper module were resolved while compiling so it is evaluated synchronously
+    let eval_res = JSValueRef::new(
+        q_ctx.context,
+        unsafe { q::JS_EvalFunction(q_ctx.context, module_val) },
+        false,
+        true,
+        "get_module_namespace_q eval result",
+    );
+    if eval_res.is_exception() {
+        return Err(q_ctx.get_exception_ctx().unwrap_or_else(|| {
+            JsError::new_str("could not evaluate get_module_namespace helper")
+        }));
+    }
+
+    let meta_ref = JSValueRef::new(
+        q_ctx.context,
+        unsafe { q::JS_GetImportMeta(q_ctx.context, module_def) },
+        false,
+        true,
+        "get_module_namespace_q import.meta",
+    );
+    let ns_ref = objects::get_property_q(q_ctx, &meta_ref, "namespace")?;
+    q_ctx
+        .module_namespaces
+        .borrow_mut()
+        .insert(module_name.to_string(), ns_ref.clone());
+    Ok(ns_ref)
+}
+
 /// compile a module, used for module loading
 /// # Safety
 /// please ensure the corresponding QuickJSContext is still valid
@@ -55,6 +125,44 @@ pub unsafe fn compile_module(
     }
 }
 
+/// the name of the module which is imported by modules which are validated, see [validate_module]
+const VALIDATE_MODULE_MARKER: &str = "quickjs_runtime::validate_module";
+
+/// check a module for syntax errors without resolving its imports and without keeping the compiled module
+///
+/// quickjs resolves the imports of a module while compiling it and keeps compiled modules until the context is
+/// freed, so a marker import is prepended which is always the first import to be resolved. the module normalizer
+/// fails for the marker so quickjs frees the module def before any of the other imports are loaded
+/// # Safety
+/// please ensure the corresponding QuickJSContext is still valid
+pub unsafe fn validate_module(context: *mut q::JSContext, script: Script) -> Result<(), JsError> {
+    let code = script.get_code();
+    // keep the line numbers of syntax errors intact, a shebang must stay on the first line
+    let insert_idx = if code.starts_with("#!") {
+        code.find('\n')
+            .map(|idx| idx + 1)
+            .unwrap_or_else(|| code.len())
+    } else {
+        0
+    };
+    let marked_code = format!(
+        "{}import \"{}\";{}",
+        &code[..insert_idx],
+        VALIDATE_MODULE_MARKER,
+        &code[insert_idx..]
+    );
+    match compile_module(
+        context,
+        Script::new(script.get_path(), marked_code.as_str()),
+    ) {
+        Ok(_) => Err(JsError::new_str(
+            "validate_module: module was compiled, this should not happen",
+        )),
+        Err(err) if err.get_message().contains(VALIDATE_MODULE_MARKER) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
 // get the ModuleDef obj from a JSValue, this is used for module loading
 pub fn get_module_def(value: &JSValueRef) -> *mut q::JSModuleDef {
     assert!(value.is_module());
@@ -167,18 +275,40 @@ unsafe extern "C" fn js_module_normalize(
         name_str
     );
 
+    if name_str == VALIDATE_MODULE_MARKER {
+        // the module is being validated, fail so quickjs frees it without loading its imports
+        QuickJsContext::report_ex_ctx(ctx, VALIDATE_MODULE_MARKER);
+        return ptr::null_mut();
+    }
+
     QuickJsRuntime::do_with(|q_js_rt| {
         let q_ctx = q_js_rt.get_quickjs_context(ctx);
-
-        if let Some(res) = q_js_rt.with_all_module_loaders(|loader| {
-            if let Some(normalized_path) = loader.normalize_path(q_ctx, base_str, name_str) {
-                let c_absolute_path = CString::new(normalized_path.as_str()).expect("fail");
-                Some(c_absolute_path.into_raw())
-            } else {
-                None
-            }
+        let name_string = match &q_js_rt.module_specifier_normalizer {
+            Some(normalizer) => normalizer(base_str, name_str),
+            None => name_str.to_string(),
+        };
+        let name_string = q_js_rt.resolve_module_path(base_str, name_string.as_str());
+        let name_str = name_string.as_str();
+
+        if let Some(res) = q_js_rt.with_context_module_loaders(q_ctx, |loader| {
+            loader.normalize_path(q_ctx, base_str, name_str)
         }) {
-            res
+            if LOADING_MODULES.with(|rc| rc.borrow().contains(res.as_str())) {
+                q_ctx.report_ex(
+                    format!(
+                        "circular dependency detected: {} imports {} which is still being loaded",
+                        base_str, res
+                    )
+                    .as_str(),
+                );
+                return ptr::null_mut();
+            }
+            let c_absolute_path = CString::new(res.as_str()).expect("fail");
+            c_absolute_path.into_raw()
+        } else if q_ctx.evaluated_modules.borrow().contains(name_str) {
+            // the module was evaluated directly so quickjs will find it by its name
+            let c_absolute_path = CString::new(name_str).expect("fail");
+            c_absolute_path.into_raw()
         } else {
             q_ctx.report_ex(format!("Module {} was not found", name_str).as_str());
             ptr::null_mut()
@@ -198,9 +328,22 @@ unsafe extern "C" fn js_module_loader(
 
     log::trace!("js_module_loader called: {}", module_name);
 
-    QuickJsRuntime::do_with(|q_js_rt| {
+    LOADING_MODULES.with(|rc| rc.borrow_mut().insert(module_name.to_string()));
+    let res = QuickJsRuntime::do_with(|q_js_rt| {
         QuickJsContext::with_context(ctx, |q_ctx| {
-            if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
+            if let Some(cached_res) = q_js_rt.load_cached_module(q_ctx, module_name) {
+                return match cached_res {
+                    Ok(module_def) => module_def,
+                    Err(e) => {
+                        let err =
+                            format!("Module load failed for {} because of: {}", module_name, e);
+                        log::error!("{}", err);
+                        q_ctx.report_ex(err.as_str());
+                        std::ptr::null_mut()
+                    }
+                };
+            }
+            if let Some(res) = q_js_rt.with_context_module_loaders(q_ctx, |module_loader| {
                 if module_loader.has_module(q_ctx, module_name) {
                     let mod_val_res = module_loader.load_module(q_ctx, module_name);
                     match mod_val_res {
@@ -223,16 +366,65 @@ unsafe extern "C" fn js_module_loader(
                 std::ptr::null_mut()
             }
         })
-    })
+    });
+    LOADING_MODULES.with(|rc| rc.borrow_mut().remove(module_name));
+    res
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::esruntime::tests::init_test_rt;
+    use crate::esruntime::EsRuntime;
     use crate::quickjs_utils::modules::detect_module;
+    use crate::quickjsruntime::ScriptModuleLoader;
     use hirofa_utils::js_utils::Script;
     use std::time::Duration;
 
+    struct CircularModuleLoader {}
+
+    impl ScriptModuleLoader for CircularModuleLoader {
+        fn normalize_path(&self, _ref_path: &str, path: &str) -> Option<String> {
+            Some(path.to_string())
+        }
+
+        fn load_module(&self, absolute_path: &str) -> String {
+            match absolute_path {
+                "circ_a.mes" => "import {b} from 'circ_b.mes';\nexport const a = 'a' + b;",
+                "circ_b.mes" => "import {a} from 'circ_a.mes';\nexport const b = 'b';",
+                "dia_a.mes" => "import {c} from 'circ_c.mes';\nimport {d} from 'dia_d.mes';\nexport const a = c + d;",
+                "dia_d.mes" => "import {c} from 'circ_c.mes';\nexport const d = 'd' + c;",
+                _ => "export const c = 'c';",
+            }
+            .to_string()
+        }
+    }
+
+    #[test]
+    fn test_circular_dependency() {
+        let rt = EsRuntime::builder()
+            .script_module_loader(Box::new(CircularModuleLoader {}))
+            .build();
+        let res = rt.eval_module_sync(Script::new(
+            "test_circular_dependency.es",
+            "import {a} from 'circ_a.mes';\nconsole.log(a);",
+        ));
+        let err = res.err().expect("circular import should have failed");
+        assert!(err.get_message().contains("circular dependency"));
+
+        // non circular imports still work, also when the same module is imported twice
+        rt.eval_module_sync(Script::new(
+            "test_circular_dependency2.es",
+            "import {a} from 'dia_a.mes';\nglobalThis.dia_res = a;",
+        ))
+        .ok()
+        .expect("diamond import failed");
+        let res = rt
+            .eval_sync(Script::new("test_circular_dependency3.es", "dia_res;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "cdc");
+    }
+
     #[test]
     fn test_native_modules() {
         let rt = init_test_rt();
@@ -1,12 +1,16 @@
 //! Utils for working with objects
 
+use crate::quickjs_utils;
 use crate::quickjs_utils::properties::JSPropertyEnumRef;
-use crate::quickjs_utils::{atoms, functions, get_constructor, get_global};
+use crate::quickjs_utils::{
+    arrays, atoms, dates, functions, get_constructor, get_global, maps, primitives, sets,
+};
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::{make_cstring, QuickJsRuntime};
 use crate::valueref::JSValueRef;
 use hirofa_utils::js_utils::JsError;
 use libquickjs_sys as q;
+use std::collections::HashMap;
 
 /// get a namespace object
 /// this is used to get nested object properties which are used as namespaces
@@ -117,6 +121,53 @@ pub unsafe fn create_object(context: *mut q::JSContext) -> Result<JSValueRef, Js
     Ok(obj_ref)
 }
 
+/// create a new object with the given prototype, e.g. `let obj = Object.create(proto);`
+pub fn create_object_with_prototype_q(
+    q_ctx: &QuickJsContext,
+    proto: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    unsafe { create_object_with_prototype(q_ctx.context, proto) }
+}
+
+/// create a new object with the given prototype, e.g. `let obj = Object.create(proto);`
+/// # Safety
+/// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
+pub unsafe fn create_object_with_prototype(
+    context: *mut q::JSContext,
+    proto: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    let obj = q::JS_NewObjectProto(context, *proto.borrow_value());
+    let obj_ref = JSValueRef::new(
+        context,
+        obj,
+        false,
+        true,
+        "objects::create_object_with_prototype",
+    );
+    if obj_ref.is_exception() {
+        return Err(JsError::new_str("Could not create object"));
+    }
+    Ok(obj_ref)
+}
+
+/// create a new object without a prototype, e.g. `let obj = Object.create(null);`
+/// this is useful for dictionary-like objects which should not inherit props like `hasOwnProperty`
+pub fn create_object_no_proto_q(q_ctx: &QuickJsContext) -> Result<JSValueRef, JsError> {
+    unsafe { create_object_no_proto(q_ctx.context) }
+}
+
+/// create a new object without a prototype, e.g. `let obj = Object.create(null);`
+/// # Safety
+/// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
+pub unsafe fn create_object_no_proto(context: *mut q::JSContext) -> Result<JSValueRef, JsError> {
+    let obj = q::JS_NewObjectProto(context, quickjs_utils::new_null());
+    let obj_ref = JSValueRef::new(context, obj, false, true, "objects::create_object_no_proto");
+    if obj_ref.is_exception() {
+        return Err(JsError::new_str("Could not create object"));
+    }
+    Ok(obj_ref)
+}
+
 /// set a property in an object, like `obj[propName] = val;`
 pub fn set_property_q(
     q_ctx: &QuickJsContext,
@@ -369,6 +420,37 @@ pub unsafe fn get_property(
     Ok(prop_ref)
 }
 
+/// delete a property from an object by name, returns false if the property could not be deleted (e.g. because it
+/// is not configurable)
+pub fn delete_property_q(
+    q_ctx: &QuickJsContext,
+    obj_ref: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    unsafe { delete_property(q_ctx.context, obj_ref, prop_name) }
+}
+
+/// delete a property from an object by name
+/// # Safety
+/// when passing a context please ensure the corresponding QuickJsContext is still valid
+pub unsafe fn delete_property(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    log::trace!("objects::delete_property {}", prop_name);
+
+    let atom_ref = atoms::from_string(context, prop_name)?;
+    let ret = q::JS_DeleteProperty(context, *obj_ref.borrow_value(), atom_ref.get_atom(), 0);
+    if ret < 0 {
+        return Err(JsError::new_string(format!(
+            "could not delete property {}",
+            prop_name
+        )));
+    }
+    Ok(ret != 0)
+}
+
 /// get the property names of an object
 pub fn get_own_property_names_q(
     q_ctx: &QuickJsContext,
@@ -430,6 +512,11 @@ pub unsafe fn get_property_names(
     Ok(names)
 }
 
+/// traverse the properties of an object in the order in which they were added
+/// please note that, like in `Object.keys()`, integer like keys (e.g. array indexes) are always visited first in
+/// ascending order, followed by the other string keys in insertion order and then the symbol keys in insertion order
+/// use [traverse_properties_sorted_q] to visit the properties sorted by their names or
+/// [traverse_properties_insertion_order_q] to make the dependency on the insertion order explicit
 pub fn traverse_properties_q<V, R>(
     q_ctx: &QuickJsContext,
     obj_ref: &JSValueRef,
@@ -441,6 +528,7 @@ where
     unsafe { traverse_properties(q_ctx.context, obj_ref, visitor) }
 }
 
+/// traverse the properties of an object in the order in which they were added, see [traverse_properties_q]
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn traverse_properties<V, R>(
@@ -451,12 +539,90 @@ pub unsafe fn traverse_properties<V, R>(
 where
     V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
 {
+    // JS_GetOwnPropertyNames lists integer like keys (sorted), then strings and then symbols, strings and symbols
+    // are listed in the order of the shape of the object which is the order in which they were added
     let enum_ref = get_own_property_names(context, obj_ref)?;
+    let atoms = (0..enum_ref.len())
+        .map(|index| enum_ref.get_atom_raw(index) as q::JSAtom)
+        .collect::<Vec<_>>();
+    visit_properties(context, obj_ref, atoms, visitor)
+}
 
-    let mut result = vec![];
+/// traverse the properties of an object sorted by their names, this visits properties in the same order on every
+/// call regardless of the order in which they were added
+pub fn traverse_properties_sorted_q<V, R>(
+    q_ctx: &QuickJsContext,
+    obj_ref: &JSValueRef,
+    visitor: V,
+) -> Result<Vec<R>, JsError>
+where
+    V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
+{
+    unsafe { traverse_properties_sorted(q_ctx.context, obj_ref, visitor) }
+}
 
+/// traverse the properties of an object sorted by their names
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn traverse_properties_sorted<V, R>(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+    visitor: V,
+) -> Result<Vec<R>, JsError>
+where
+    V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
+{
+    let enum_ref = get_own_property_names(context, obj_ref)?;
+    let mut named_atoms = vec![];
     for index in 0..enum_ref.len() {
         let atom = enum_ref.get_atom_raw(index) as q::JSAtom;
+        named_atoms.push((atoms::to_string2(context, &atom)?, atom));
+    }
+    named_atoms.sort_by(|a, b| a.0.cmp(&b.0));
+    let atoms = named_atoms.into_iter().map(|(_name, atom)| atom).collect();
+    visit_properties(context, obj_ref, atoms, visitor)
+}
+
+/// traverse the properties of an object in the order in which they were added
+/// please note that, like in `Object.keys()`, integer like keys (e.g. array indexes) are always visited first in
+/// ascending order, followed by the other string keys in insertion order and then the symbol keys in insertion order
+pub fn traverse_properties_insertion_order_q<V, R>(
+    q_ctx: &QuickJsContext,
+    obj_ref: &JSValueRef,
+    visitor: V,
+) -> Result<Vec<R>, JsError>
+where
+    V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
+{
+    unsafe { traverse_properties_insertion_order(q_ctx.context, obj_ref, visitor) }
+}
+
+/// traverse the properties of an object in the order in which they were added
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn traverse_properties_insertion_order<V, R>(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+    visitor: V,
+) -> Result<Vec<R>, JsError>
+where
+    V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
+{
+    traverse_properties(context, obj_ref, visitor)
+}
+
+unsafe fn visit_properties<V, R>(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+    atoms: Vec<q::JSAtom>,
+    visitor: V,
+) -> Result<Vec<R>, JsError>
+where
+    V: Fn(&str, &JSValueRef) -> Result<R, JsError>,
+{
+    let mut result = vec![];
+
+    for atom in atoms {
         let prop_name = atoms::to_str(context, &atom)?;
 
         let raw_value = q::JS_GetPropertyInternal(
@@ -485,6 +651,377 @@ where
     Ok(result)
 }
 
+/// create a shallow copy of an object, like `let copy = Object.assign({}, obj);`
+/// arrays are copied to a new array
+pub fn clone_shallow_q(
+    q_ctx: &QuickJsContext,
+    obj_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    unsafe { clone_shallow(q_ctx.context, obj_ref) }
+}
+
+/// create a shallow copy of an object, like `let copy = Object.assign({}, obj);`
+/// arrays are copied to a new array
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn clone_shallow(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    if !obj_ref.is_object() {
+        return Err(JsError::new_str("clone_shallow: value is not an object"));
+    }
+    let clone_ref = create_clone_target(context, obj_ref)?;
+    for prop_name in get_property_names(context, obj_ref)? {
+        let prop_ref = get_property(context, obj_ref, prop_name.as_str())?;
+        set_property(context, &clone_ref, prop_name.as_str(), &prop_ref)?;
+    }
+    Ok(clone_ref)
+}
+
+/// create a deep copy of an object, nested values are cloned recursively
+///
+/// supported are plain objects (with Object.prototype or null as prototype), arrays, Date, RegExp, Map, Set (keys and
+/// values are cloned), ArrayBuffer and typed arrays (their bytes are copied)
+/// functions are not cloned but copied by reference, circular references are preserved in the clone
+/// for other objects (e.g. instances of classes, Errors or Promises) an Err is returned
+pub fn clone_deep_q(q_ctx: &QuickJsContext, obj_ref: &JSValueRef) -> Result<JSValueRef, JsError> {
+    unsafe { clone_deep(q_ctx.context, obj_ref) }
+}
+
+/// create a deep copy of an object, see [clone_deep_q](fn.clone_deep_q.html) for the supported types
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn clone_deep(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    if !obj_ref.is_object() {
+        return Err(JsError::new_str("clone_deep: value is not an object"));
+    }
+    let mut seen = HashMap::new();
+    clone_deep2(context, obj_ref, &mut seen)
+}
+
+/// the typed arrays which are copied by clone_deep
+const TYPED_ARRAY_CONSTRUCTORS: [&str; 11] = [
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "Float32Array",
+    "Float64Array",
+    "BigInt64Array",
+    "BigUint64Array",
+];
+
+unsafe fn clone_deep_value(
+    context: *mut q::JSContext,
+    value_ref: JSValueRef,
+    seen: &mut HashMap<usize, JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    if value_ref.is_object() {
+        clone_deep2(context, &value_ref, seen)
+    } else {
+        Ok(value_ref)
+    }
+}
+
+unsafe fn clone_deep2(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+    seen: &mut HashMap<usize, JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    let address = obj_ref.borrow_value().u.ptr as usize;
+    if let Some(clone_ref) = seen.get(&address) {
+        return Ok(clone_ref.clone());
+    }
+    if functions::is_function(context, obj_ref) {
+        return Ok(obj_ref.clone());
+    }
+
+    if dates::is_date(context, obj_ref) {
+        let clone_ref = dates::new_date(context)?;
+        dates::set_time(context, &clone_ref, dates::get_time(context, obj_ref)?)?;
+        seen.insert(address, clone_ref.clone());
+        return Ok(clone_ref);
+    }
+    if is_instance_of_by_name(context, obj_ref, "RegExp")? {
+        let constructor_ref = get_constructor(context, "RegExp")?;
+        let clone_ref = functions::call_constructor(context, &constructor_ref, &[obj_ref.clone()])?;
+        seen.insert(address, clone_ref.clone());
+        return Ok(clone_ref);
+    }
+    if maps::is_map(context, obj_ref)? {
+        let clone_ref = maps::new_map(context)?;
+        seen.insert(address, clone_ref.clone());
+        for (key_ref, value_ref) in maps::entries(context, obj_ref, |key_ref, value_ref| {
+            Ok((key_ref, value_ref))
+        })? {
+            let key_clone_ref = clone_deep_value(context, key_ref, seen)?;
+            let value_clone_ref = clone_deep_value(context, value_ref, seen)?;
+            maps::set(context, &clone_ref, key_clone_ref, value_clone_ref)?;
+        }
+        return Ok(clone_ref);
+    }
+    if sets::is_set(context, obj_ref)? {
+        let clone_ref = sets::new_set(context)?;
+        seen.insert(address, clone_ref.clone());
+        for value_ref in sets::values(context, obj_ref, Ok)? {
+            let value_clone_ref = clone_deep_value(context, value_ref, seen)?;
+            sets::add(context, &clone_ref, value_clone_ref)?;
+        }
+        return Ok(clone_ref);
+    }
+    if is_instance_of_by_name(context, obj_ref, "ArrayBuffer")? {
+        let clone_ref = functions::invoke_member_function(context, obj_ref, "slice", vec![])?;
+        seen.insert(address, clone_ref.clone());
+        return Ok(clone_ref);
+    }
+    for constructor_name in TYPED_ARRAY_CONSTRUCTORS.iter() {
+        if is_instance_of_by_name(context, obj_ref, constructor_name)? {
+            // a typed array constructor copies the elements of a typed array which is passed as argument
+            let constructor_ref = get_constructor(context, constructor_name)?;
+            let clone_ref =
+                functions::call_constructor(context, &constructor_ref, &[obj_ref.clone()])?;
+            seen.insert(address, clone_ref.clone());
+            return Ok(clone_ref);
+        }
+    }
+
+    if !arrays::is_array(context, obj_ref) && !is_plain_object(context, obj_ref)? {
+        return Err(JsError::new_string(format!(
+            "clone_deep: objects of type {} can not be cloned",
+            get_constructor_name(context, obj_ref)
+        )));
+    }
+
+    let clone_ref = create_clone_target(context, obj_ref)?;
+    seen.insert(address, clone_ref.clone());
+    for prop_name in get_property_names(context, obj_ref)? {
+        let prop_ref = get_property(context, obj_ref, prop_name.as_str())?;
+        let prop_clone_ref = clone_deep_value(context, prop_ref, seen)?;
+        set_property(context, &clone_ref, prop_name.as_str(), &prop_clone_ref)?;
+    }
+    Ok(clone_ref)
+}
+
+/// check if an object was created with an object literal or Object.create(null)
+unsafe fn is_plain_object(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+) -> Result<bool, JsError> {
+    // JS_GetPrototype returns a new reference which is freed when proto_ref is dropped
+    let proto_ref = JSValueRef::new(
+        context,
+        q::JS_GetPrototype(context, *obj_ref.borrow_value()),
+        false,
+        true,
+        "objects::is_plain_object proto",
+    );
+    if proto_ref.is_exception() {
+        return Err(QuickJsContext::get_exception(context)
+            .unwrap_or_else(|| JsError::new_str("could not get prototype")));
+    }
+    if proto_ref.is_null() {
+        return Ok(true);
+    }
+    let object_constructor_ref = get_constructor(context, "Object")?;
+    let object_proto_ref = get_property(context, &object_constructor_ref, "prototype")?;
+    Ok(proto_ref == object_proto_ref)
+}
+
+/// get the name of the constructor of an object for error messages
+unsafe fn get_constructor_name(context: *mut q::JSContext, obj_ref: &JSValueRef) -> String {
+    get_property(context, obj_ref, "constructor")
+        .and_then(|constructor_ref| {
+            if constructor_ref.is_object() {
+                get_property(context, &constructor_ref, "name")
+            } else {
+                Ok(constructor_ref)
+            }
+        })
+        .ok()
+        .filter(|name_ref| name_ref.is_string())
+        .and_then(|name_ref| primitives::to_string(context, &name_ref).ok())
+        .unwrap_or_else(|| "Object".to_string())
+}
+
+unsafe fn create_clone_target(
+    context: *mut q::JSContext,
+    obj_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    if arrays::is_array(context, obj_ref) {
+        arrays::create_array(context)
+    } else {
+        create_object(context)
+    }
+}
+
+/// copy a value to another context of the same runtime by serializing it with JS_WriteObject and reading it in the
+/// other context with JS_ReadObject, this works for primitives, plain objects, arrays and typed arrays but fails for
+/// functions and instances of classes
+pub fn clone_to_context_q(
+    src_q_ctx: &QuickJsContext,
+    dst_q_ctx: &QuickJsContext,
+    value_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    unsafe { clone_to_context(src_q_ctx.context, dst_q_ctx.context, value_ref) }
+}
+
+/// copy a value to another context, see clone_to_context_q
+/// # Safety
+/// When passing context pointers please make sure the corresponding QuickJsContexts are still valid
+pub unsafe fn clone_to_context(
+    src_context: *mut q::JSContext,
+    dst_context: *mut q::JSContext,
+    value_ref: &JSValueRef,
+) -> Result<JSValueRef, JsError> {
+    let mut len = 0;
+    let buf = q::JS_WriteObject(src_context, &mut len, *value_ref.borrow_value(), 0);
+    if buf.is_null() {
+        return Err(QuickJsContext::get_exception(src_context)
+            .unwrap_or_else(|| JsError::new_str("clone_to_context: could not write object")));
+    }
+    let raw = q::JS_ReadObject(dst_context, buf, len as _, 0);
+    q::js_free(src_context, buf as *mut std::ffi::c_void);
+    let clone_ref = JSValueRef::new(dst_context, raw, false, true, "clone_to_context result");
+    if clone_ref.is_exception() {
+        return Err(QuickJsContext::get_exception(dst_context)
+            .unwrap_or_else(|| JsError::new_str("clone_to_context: could not read object")));
+    }
+    Ok(clone_ref)
+}
+
+/// copy all own enumerable properties of the sources to the target, like `Object.assign(target, ...sources);`
+/// sources are processed in order so later sources overwrite properties set by earlier sources
+pub fn merge_q(
+    q_ctx: &QuickJsContext,
+    target: &JSValueRef,
+    sources: &[&JSValueRef],
+) -> Result<(), JsError> {
+    unsafe { merge(q_ctx.context, target, sources) }
+}
+
+/// copy all own enumerable properties of the sources to the target, like `Object.assign(target, ...sources);`
+/// sources are processed in order so later sources overwrite properties set by earlier sources
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn merge(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+    sources: &[&JSValueRef],
+) -> Result<(), JsError> {
+    if !target.is_object() {
+        return Err(JsError::new_str("merge: target is not an object"));
+    }
+    for source in sources {
+        if !source.is_object() {
+            return Err(JsError::new_str("merge: source is not an object"));
+        }
+        for prop_name in get_property_names(context, source)? {
+            let prop_ref = get_property(context, source, prop_name.as_str())?;
+            set_property(context, target, prop_name.as_str(), &prop_ref)?;
+        }
+    }
+    Ok(())
+}
+
+/// the differences between two objects as produced by [diff_q](fn.diff_q.html)
+pub struct ObjectDiff {
+    /// properties which are present in b but not in a, with their value in b
+    pub added: HashMap<String, JSValueRef>,
+    /// names of properties which are present in a but not in b
+    pub removed: Vec<String>,
+    /// properties which are present in both objects but have a different value, as (value in a, value in b)
+    pub changed: HashMap<String, (JSValueRef, JSValueRef)>,
+}
+
+/// compare the own enumerable properties of two objects
+/// values are compared like `===`, so numbers are compared by value and nested objects are only considered unchanged
+/// if they are the same instance
+pub fn diff_q(
+    q_ctx: &QuickJsContext,
+    a: &JSValueRef,
+    b: &JSValueRef,
+) -> Result<ObjectDiff, JsError> {
+    unsafe { diff(q_ctx.context, a, b) }
+}
+
+/// compare the own enumerable properties of two objects
+/// values are compared like `===`, so numbers are compared by value and nested objects are only considered unchanged
+/// if they are the same instance
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn diff(
+    context: *mut q::JSContext,
+    a: &JSValueRef,
+    b: &JSValueRef,
+) -> Result<ObjectDiff, JsError> {
+    if !a.is_object() || !b.is_object() {
+        return Err(JsError::new_str("diff: value is not an object"));
+    }
+
+    let a_names = get_property_names(context, a)?;
+    let b_names = get_property_names(context, b)?;
+
+    let mut result = ObjectDiff {
+        added: HashMap::new(),
+        removed: vec![],
+        changed: HashMap::new(),
+    };
+
+    for name in &a_names {
+        if !b_names.contains(name) {
+            result.removed.push(name.clone());
+        }
+    }
+
+    for name in b_names {
+        let b_val = get_property(context, b, name.as_str())?;
+        if a_names.contains(&name) {
+            let a_val = get_property(context, a, name.as_str())?;
+            if !values_strict_equal(context, &a_val, &b_val)? {
+                result.changed.insert(name, (a_val, b_val));
+            }
+        } else {
+            result.added.insert(name, b_val);
+        }
+    }
+
+    Ok(result)
+}
+
+unsafe fn values_strict_equal(
+    context: *mut q::JSContext,
+    a: &JSValueRef,
+    b: &JSValueRef,
+) -> Result<bool, JsError> {
+    if a.is_string() && b.is_string() {
+        Ok(primitives::to_string(context, a)? == primitives::to_string(context, b)?)
+    } else if is_number(a) && is_number(b) {
+        // quickjs stores a number as either an int or a float64, so 1 and 1.0 may have a different tag
+        Ok(number_to_f64(a)? == number_to_f64(b)?)
+    } else {
+        Ok(a == b)
+    }
+}
+
+fn is_number(value_ref: &JSValueRef) -> bool {
+    value_ref.is_i32() || value_ref.is_f64()
+}
+
+fn number_to_f64(value_ref: &JSValueRef) -> Result<f64, JsError> {
+    if value_ref.is_i32() {
+        Ok(primitives::to_i32(value_ref)? as f64)
+    } else {
+        primitives::to_f64(value_ref)
+    }
+}
+
 pub fn is_instance_of_q(
     q_ctx: &QuickJsContext,
     obj_ref: &JSValueRef,
@@ -555,10 +1092,13 @@ pub unsafe fn is_instance_of_by_name(
 pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::quickjs_utils::objects::{
-        create_object_q, get_property_names_q, get_property_q, set_property_q,
+        clone_deep_q, clone_shallow_q, create_object_no_proto_q, create_object_q,
+        create_object_with_prototype_q, diff_q, get_property_names_q, get_property_q,
+        is_instance_of_q, merge_q, set_property_q, traverse_properties_insertion_order_q,
+        traverse_properties_q, traverse_properties_sorted_q,
     };
     use crate::quickjs_utils::primitives::{from_i32, to_i32};
-    use crate::quickjs_utils::{get_global_q, primitives};
+    use crate::quickjs_utils::{functions, get_global_q, primitives};
     use hirofa_utils::js_utils::Script;
 
     #[test]
@@ -703,4 +1243,250 @@ pub mod tests {
 
         log::info!("< test_set_prop");
     }
+
+    #[test]
+    fn test_create_object_with_prototype() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let constructor_ref = q_ctx
+                .eval(Script::new(
+                    "test_create_object_with_prototype.es",
+                    "(function Animal(){});",
+                ))
+                .ok()
+                .expect("could not get constructor");
+            let proto_ref = get_property_q(q_ctx, &constructor_ref, "prototype")
+                .ok()
+                .expect("could not get prototype");
+            let obj_ref = create_object_with_prototype_q(q_ctx, &proto_ref)
+                .ok()
+                .expect("could not create object");
+            assert!(is_instance_of_q(q_ctx, &obj_ref, &constructor_ref));
+
+            let no_proto_ref = create_object_no_proto_q(q_ctx)
+                .ok()
+                .expect("could not create object");
+            assert!(no_proto_ref.is_object());
+            let hop_ref = get_property_q(q_ctx, &no_proto_ref, "hasOwnProperty")
+                .ok()
+                .expect("could not get prop");
+            assert!(hop_ref.is_undefined());
+            let plain_ref = create_object_q(q_ctx).ok().unwrap();
+            let hop_ref = get_property_q(q_ctx, &plain_ref, "hasOwnProperty")
+                .ok()
+                .expect("could not get prop");
+            assert!(functions::is_function_q(q_ctx, &hop_ref));
+        });
+    }
+
+    #[test]
+    fn test_clone() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let obj_ref = q_ctx
+                .eval(Script::new(
+                    "test_clone.es",
+                    "this.test_clone_orig = {a: 1, arr: [1, 2, [3, 4]], sub: {b: 2}}; this.test_clone_orig.self = this.test_clone_orig; (this.test_clone_orig);",
+                ))
+                .ok()
+                .expect("could not get test obj");
+
+            let shallow_ref = clone_shallow_q(q_ctx, &obj_ref)
+                .ok()
+                .expect("could not clone");
+            let deep_ref = clone_deep_q(q_ctx, &obj_ref).ok().expect("could not clone");
+
+            let global_ref = get_global_q(q_ctx);
+            set_property_q(q_ctx, &global_ref, "test_clone_shallow", &shallow_ref)
+                .ok()
+                .unwrap();
+            set_property_q(q_ctx, &global_ref, "test_clone_deep", &deep_ref)
+                .ok()
+                .unwrap();
+
+            let res = q_ctx
+                .eval(Script::new(
+                    "test_clone2.es",
+                    "test_clone_deep.a = 2; test_clone_deep.arr[2].push(5); test_clone_deep.sub.b = 3; \
+                     test_clone_shallow.a = 3; \
+                     JSON.stringify([\
+                        test_clone_orig.a, test_clone_orig.arr, test_clone_orig.sub.b, \
+                        Array.isArray(test_clone_deep.arr[2]), test_clone_deep.self === test_clone_deep, \
+                        test_clone_shallow.arr === test_clone_orig.arr\
+                     ]);",
+                ))
+                .ok()
+                .expect("script failed");
+            let res_str = primitives::to_string_q(q_ctx, &res).ok().unwrap();
+            assert_eq!(res_str, "[1,[1,2,[3,4]],2,true,true,true]");
+
+            let typed_ref = q_ctx
+                .eval(Script::new(
+                    "test_clone3.es",
+                    "this.test_clone_typed = {\
+                        date: new Date(1000), re: /a+/gi, map: new Map([['k', {v: 1}]]), set: new Set([1, 'a']), \
+                        bytes: new Uint8Array([1, 2, 3]), buf: new ArrayBuffer(4), fn: function() {return 1;}\
+                     }; (this.test_clone_typed);",
+                ))
+                .ok()
+                .expect("could not get typed test obj");
+            let typed_clone_ref = clone_deep_q(q_ctx, &typed_ref)
+                .ok()
+                .expect("could not clone");
+            set_property_q(q_ctx, &global_ref, "test_clone_typed_deep", &typed_clone_ref)
+                .ok()
+                .unwrap();
+            let res = q_ctx
+                .eval(Script::new(
+                    "test_clone4.es",
+                    "let tco = test_clone_typed; let tcc = test_clone_typed_deep; \
+                     tcc.map.get('k').v = 2; tcc.bytes[0] = 9; \
+                     JSON.stringify([\
+                        tcc.date instanceof Date && tcc.date !== tco.date && tcc.date.getTime(), \
+                        tcc.re instanceof RegExp && tcc.re !== tco.re && tcc.re.source + '/' + tcc.re.flags, \
+                        tcc.map instanceof Map && tco.map.get('k').v, \
+                        tcc.set instanceof Set && tcc.set !== tco.set && [...tcc.set], \
+                        tcc.bytes instanceof Uint8Array && [...tco.bytes], \
+                        tcc.buf instanceof ArrayBuffer && tcc.buf !== tco.buf && tcc.buf.byteLength, \
+                        tcc.fn === tco.fn\
+                     ]);",
+                ))
+                .ok()
+                .expect("script failed");
+            let res_str = primitives::to_string_q(q_ctx, &res).ok().unwrap();
+            assert_eq!(res_str, "[1000,\"a+/gi\",1,[1,\"a\"],[1,2,3],4,true]");
+
+            // instances of classes can not be cloned
+            let instance_ref = q_ctx
+                .eval(Script::new(
+                    "test_clone5.es",
+                    "class TestCloneClass {}; ({inst: new TestCloneClass()});",
+                ))
+                .ok()
+                .expect("could not get instance");
+            let err = clone_deep_q(q_ctx, &instance_ref)
+                .err()
+                .expect("clone of a class instance should fail");
+            assert!(err.get_message().contains("TestCloneClass"));
+        });
+    }
+
+    #[test]
+    fn test_merge() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let target_ref = q_ctx
+                .eval(Script::new("test_merge.es", "({a: 1, b: 1});"))
+                .ok()
+                .expect("could not get target");
+            let source_a = q_ctx
+                .eval(Script::new("test_merge.es", "({b: 2, c: 2});"))
+                .ok()
+                .expect("could not get source");
+            let source_b = q_ctx
+                .eval(Script::new("test_merge.es", "({c: 3, d: 3});"))
+                .ok()
+                .expect("could not get source");
+            merge_q(q_ctx, &target_ref, &[&source_a, &source_b])
+                .ok()
+                .expect("merge failed");
+
+            let mut prop_names = get_property_names_q(q_ctx, &target_ref).ok().unwrap();
+            prop_names.sort();
+            assert_eq!(prop_names, vec!["a", "b", "c", "d"]);
+            for (name, expected) in &[("a", 1), ("b", 2), ("c", 3), ("d", 3)] {
+                let prop_ref = get_property_q(q_ctx, &target_ref, name).ok().unwrap();
+                assert_eq!(to_i32(&prop_ref).ok().unwrap(), *expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_diff() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let a_ref = q_ctx
+                .eval(Script::new(
+                    "test_diff.es",
+                    "this.test_diff_sub = {}; ({same: 1, str: 'abc', sub: test_diff_sub, gone: true, chg: 1});",
+                ))
+                .ok()
+                .expect("could not get a");
+            let b_ref = q_ctx
+                .eval(Script::new(
+                    "test_diff.es",
+                    "({same: 0.5 + 0.5, str: 'ab' + 'c', sub: test_diff_sub, chg: 2, new1: 'x', new2: 'y'});",
+                ))
+                .ok()
+                .expect("could not get b");
+            let diff = diff_q(q_ctx, &a_ref, &b_ref).ok().expect("diff failed");
+
+            assert_eq!(diff.added.len(), 2);
+            assert!(diff.added.contains_key("new1"));
+            assert!(diff.added.contains_key("new2"));
+            assert_eq!(diff.removed, vec!["gone"]);
+            assert_eq!(diff.changed.len(), 1);
+            let (old_val, new_val) = diff.changed.get("chg").expect("chg not changed");
+            assert_eq!(to_i32(old_val).ok().unwrap(), 1);
+            assert_eq!(to_i32(new_val).ok().unwrap(), 2);
+
+            // 1 and 1.0 are the same number, NaN is never equal to itself like with ===
+            let c_ref = q_ctx
+                .eval(Script::new(
+                    "test_diff.es",
+                    "({int: 1, float: 2.5, nan: NaN});",
+                ))
+                .ok()
+                .expect("could not get c");
+            let d_ref = q_ctx
+                .eval(Script::new(
+                    "test_diff.es",
+                    "({int: 0.5 + 0.5, float: 2.5, nan: NaN});",
+                ))
+                .ok()
+                .expect("could not get d");
+            assert!(get_property_q(q_ctx, &d_ref, "int")
+                .ok()
+                .expect("no int")
+                .is_f64());
+            let diff = diff_q(q_ctx, &c_ref, &d_ref).ok().expect("diff failed");
+            assert!(diff.added.is_empty());
+            assert!(diff.removed.is_empty());
+            assert_eq!(diff.changed.len(), 1);
+            assert!(diff.changed.contains_key("nan"));
+        });
+    }
+
+    #[test]
+    fn test_traverse_properties_ordered() {
+        let rt = init_test_rt();
+        let (sorted, inserted, traversed) = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let obj_ref = q_ctx
+                .eval(Script::new("test_traverse.es", "({z: 1, a: 2, m: 3});"))
+                .ok()
+                .expect("script failed");
+            let sorted = traverse_properties_sorted_q(q_ctx, &obj_ref, |name, val_ref| {
+                Ok(format!("{}{}", name, to_i32(val_ref)?))
+            })
+            .ok()
+            .expect("traverse failed");
+            let inserted = traverse_properties_insertion_order_q(q_ctx, &obj_ref, |name, _| {
+                Ok(name.to_string())
+            })
+            .ok()
+            .expect("traverse failed");
+            let traversed = traverse_properties_q(q_ctx, &obj_ref, |name, _| Ok(name.to_string()))
+                .ok()
+                .expect("traverse failed");
+            (sorted, inserted, traversed)
+        });
+        assert_eq!(sorted, vec!["a2", "m3", "z1"]);
+        assert_eq!(inserted, vec!["z", "a", "m"]);
+        assert_eq!(traversed, inserted);
+    }
 }
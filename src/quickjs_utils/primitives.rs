@@ -16,6 +16,21 @@ pub fn to_bool(value_ref: &JSValueRef) -> Result<bool, JsError> {
     }
 }
 
+/// check if a value is truthy (e.g. a non empty string or a non zero number), unlike [to_bool] this accepts values
+/// of any type
+pub fn is_truthy_q(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> bool {
+    unsafe { is_truthy(q_ctx.context, value_ref) }
+}
+
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn is_truthy(context: *mut q::JSContext, value_ref: &JSValueRef) -> bool {
+    if value_ref.is_bool() {
+        return to_bool(value_ref).unwrap_or(false);
+    }
+    q::JS_ToBool(context, *value_ref.borrow_value()) > 0
+}
+
 pub fn from_bool(b: bool) -> JSValueRef {
     let raw = unsafe { q::JS_NewBool(ptr::null_mut(), b) };
     JSValueRef::new_no_context(raw, "primitives::from_bool")
@@ -51,6 +66,7 @@ pub fn from_i32(i: i32) -> JSValueRef {
     JSValueRef::new_no_context(raw, "primitives::from_i32")
 }
 
+/// convert a string value to a rust String using the context the value belongs to
 pub fn to_string_q(q_ctx: &QuickJsContext, value_ref: &JSValueRef) -> Result<String, JsError> {
     unsafe { to_string(q_ctx.context, value_ref) }
 }
@@ -88,6 +104,7 @@ pub unsafe fn to_string(
     Ok(s)
 }
 
+/// create a new string value in the given context
 pub fn from_string_q(q_ctx: &QuickJsContext, s: &str) -> Result<JSValueRef, JsError> {
     unsafe { from_string(q_ctx.context, s) }
 }
@@ -102,3 +119,32 @@ pub unsafe fn from_string(context: *mut q::JSContext, s: &str) -> Result<JSValue
 
     Ok(ret)
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::primitives::{from_string_q, to_string_q};
+
+    #[test]
+    fn test_string_multi_ctx() {
+        let rt = init_test_rt();
+        rt.create_context("test_string_multi_ctx")
+            .ok()
+            .expect("could not create context");
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let main_ctx = q_js_rt.get_main_context();
+            let other_ctx = q_js_rt.get_context("test_string_multi_ctx");
+
+            let main_str = from_string_q(main_ctx, "hello").ok().unwrap();
+            let other_str = from_string_q(other_ctx, "hello").ok().unwrap();
+
+            assert_eq!(main_str.context, main_ctx.context);
+            assert_eq!(other_str.context, other_ctx.context);
+            assert!(main_str != other_str);
+
+            assert_eq!(to_string_q(main_ctx, &main_str).ok().unwrap(), "hello");
+            assert_eq!(to_string_q(other_ctx, &other_str).ok().unwrap(), "hello");
+        });
+        rt.drop_context("test_string_multi_ctx");
+    }
+}
@@ -0,0 +1,366 @@
+//! utils for profiling scripts, see [EsRuntime::start_profiling](crate::esruntime::EsRuntime::start_profiling)
+//!
+//! the bundled QuickJS has no profiling api so while profiling scripts are instrumented before they are evaluated,
+//! the body of every `function` is wrapped in a try/finally block which records the call and its duration
+//! no newlines are inserted so line numbers stay the same (columns do not)
+//!
+//! the try block starts after the directive prologue of a function so a `'use strict'` directive keeps working
+//!
+//! only functions declared with the function keyword are profiled (so no arrow functions or methods), and only
+//! scripts which are evaluated after profiling was started. For async functions and generators only the time until
+//! the first await or yield is measured. Note that `toString()` of a profiled function returns the instrumented code
+
+use crate::esruntime::EsRuntime;
+use crate::quickjs_utils::tokenizer::{tokenize, Token, TokenKind};
+use crate::quickjs_utils::{functions, get_global_q, new_undefined_ref, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::quickjsruntime::QuickJsRuntime;
+use hirofa_utils::js_utils::JsError;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+const ENTER_FUNCTION_NAME: &str = "__qjs_profile_enter__";
+const EXIT_FUNCTION_NAME: &str = "__qjs_profile_exit__";
+const CALL_ID_NAME: &str = "__qjs_profile_id__";
+
+/// the profile of a single function
+#[derive(Clone, Debug)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub file: String,
+    /// the total time spent in the function (including the functions it called) in milliseconds
+    pub total_ms: f64,
+    pub call_count: u32,
+}
+
+/// the result of profiling, functions are sorted by total_ms, slowest first
+#[derive(Clone, Debug, Default)]
+pub struct ProfileReport {
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl ProfileReport {
+    /// get the profile of a function by name
+    pub fn get_function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.iter().find(|f| f.name.as_str() == name)
+    }
+}
+
+/// a handle to a running profiling session, profiling stops when the handle is stopped or dropped
+pub struct ProfilingHandle {
+    es_rt: Arc<EsRuntime>,
+    stopped: bool,
+}
+
+impl ProfilingHandle {
+    pub(crate) fn new(es_rt: Arc<EsRuntime>) -> Self {
+        Self {
+            es_rt,
+            stopped: false,
+        }
+    }
+
+    /// stop profiling and get the report
+    pub fn stop(mut self) -> ProfileReport {
+        self.stopped = true;
+        self.es_rt
+            .exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.stop_profiling())
+    }
+}
+
+impl Drop for ProfilingHandle {
+    fn drop(&mut self) {
+        if !self.stopped {
+            self.es_rt.exe_rt_task_in_event_loop(|q_js_rt| {
+                let _ = q_js_rt.stop_profiling();
+            });
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Profiler {
+    next_call_id: i32,
+    // call id -> (index of function, start)
+    running_calls: HashMap<i32, (usize, Instant)>,
+    functions: Vec<FunctionProfile>,
+    function_indexes: HashMap<(String, String), usize>,
+}
+
+impl Profiler {
+    fn enter(&mut self, name: String, file: String) -> i32 {
+        let key = (name, file);
+        let index = match self.function_indexes.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = self.functions.len();
+                self.functions.push(FunctionProfile {
+                    name: key.0.clone(),
+                    file: key.1.clone(),
+                    total_ms: 0.0,
+                    call_count: 0,
+                });
+                self.function_indexes.insert(key, index);
+                index
+            }
+        };
+        self.functions[index].call_count += 1;
+        self.next_call_id = self.next_call_id.wrapping_add(1);
+        self.running_calls
+            .insert(self.next_call_id, (index, Instant::now()));
+        self.next_call_id
+    }
+
+    fn exit(&mut self, call_id: i32) {
+        if let Some((index, start)) = self.running_calls.remove(&call_id) {
+            self.functions[index].total_ms += start.elapsed().as_secs_f64() * 1000.0;
+        }
+    }
+
+    pub(crate) fn into_report(self) -> ProfileReport {
+        let mut functions = self.functions;
+        functions.sort_by(|a, b| {
+            b.total_ms
+                .partial_cmp(&a.total_ms)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        ProfileReport { functions }
+    }
+}
+
+/// install the enter and exit functions in a context
+pub(crate) fn init_ctx(q_ctx: &QuickJsContext) -> Result<(), JsError> {
+    let global_ref = get_global_q(q_ctx);
+    let enter_ref = functions::new_function_q(
+        q_ctx,
+        ENTER_FUNCTION_NAME,
+        |q_ctx, _this_ref, args| {
+            if args.len() != 2 {
+                return Err(JsError::new_str("enter requires 2 arguments"));
+            }
+            let name = primitives::to_string_q(q_ctx, &args[0])?;
+            let file = primitives::to_string_q(q_ctx, &args[1])?;
+            let call_id = QuickJsRuntime::do_with(|q_js_rt| {
+                q_js_rt.with_profiler(|profiler| profiler.enter(name, file))
+            });
+            Ok(primitives::from_i32(call_id.unwrap_or(0)))
+        },
+        2,
+    )?;
+    objects::set_property2_q(q_ctx, &global_ref, ENTER_FUNCTION_NAME, &enter_ref, 0)?;
+    let exit_ref = functions::new_function_q(
+        q_ctx,
+        EXIT_FUNCTION_NAME,
+        |_q_ctx, _this_ref, args| {
+            if let Some(call_id_ref) = args.first() {
+                if call_id_ref.is_i32() {
+                    let call_id = primitives::to_i32(call_id_ref)?;
+                    QuickJsRuntime::do_with(|q_js_rt| {
+                        q_js_rt.with_profiler(|profiler| profiler.exit(call_id))
+                    });
+                }
+            }
+            Ok(new_undefined_ref())
+        },
+        1,
+    )?;
+    objects::set_property2_q(q_ctx, &global_ref, EXIT_FUNCTION_NAME, &exit_ref, 0)
+}
+
+/// parse the head of a function (the tokens after the function keyword), returns the name and the index of the
+/// opening brace of the body
+fn parse_function_head(tokens: &[Token], mut idx: usize) -> Option<(String, usize)> {
+    if tokens.get(idx)?.is_punct("*") {
+        idx += 1;
+    }
+    let name = if tokens.get(idx)?.kind == TokenKind::Ident {
+        idx += 1;
+        tokens[idx - 1].text.to_string()
+    } else {
+        "<anonymous>".to_string()
+    };
+    if !tokens.get(idx)?.is_punct("(") {
+        return None;
+    }
+    let mut paren_depth = 0;
+    while let Some(token) = tokens.get(idx) {
+        if token.is_punct("(") {
+            paren_depth += 1;
+        } else if token.is_punct(")") {
+            paren_depth -= 1;
+            if paren_depth == 0 {
+                break;
+            }
+        }
+        idx += 1;
+    }
+    if tokens.get(idx + 1)?.is_punct("{") {
+        Some((name, idx + 1))
+    } else {
+        None
+    }
+}
+
+/// find the end of the directive prologue (e.g. `'use strict';`) of a function body, returns the position after it
+/// and whether the last directive still needs a semicolon
+fn directive_prologue_end(tokens: &[Token], body_start: usize) -> (usize, bool) {
+    let mut end = (tokens[body_start].end, false);
+    let mut idx = body_start + 1;
+    while let Some(token) = tokens.get(idx) {
+        if token.kind != TokenKind::Str {
+            break;
+        }
+        match tokens.get(idx + 1) {
+            Some(next) if next.is_punct(";") => {
+                end = (next.end, false);
+                idx += 2;
+            }
+            Some(next)
+                if next.is_punct("}")
+                    || (next.line > token.line
+                        && matches!(next.kind, TokenKind::Ident | TokenKind::Str)) =>
+            {
+                end = (token.end, true);
+                idx += 1;
+            }
+            // the string is not a directive but the start of an expression
+            _ => break,
+        }
+    }
+    end
+}
+
+/// wrap the bodies of all functions in a script, see the module docs for which functions are instrumented
+pub(crate) fn instrument(path: &str, code: &str) -> String {
+    let path_literal = serde_json::to_string(path).expect("could not serialize path");
+    let tokens = tokenize(code);
+    let mut insertions: Vec<(usize, String)> = vec![];
+    let mut body_starts: HashSet<usize> = HashSet::new();
+    // for every unclosed brace whether it is the start of an instrumented function body
+    let mut open_braces: Vec<bool> = vec![];
+    for (idx, token) in tokens.iter().enumerate() {
+        match (token.kind, token.text) {
+            (TokenKind::Punct, "{") | (TokenKind::Punct, "${") => {
+                open_braces.push(body_starts.contains(&idx))
+            }
+            (TokenKind::Punct, "}") => {
+                if open_braces.pop() == Some(true) {
+                    insertions.push((
+                        token.start,
+                        format!("}} finally {{ {}({}); }}", EXIT_FUNCTION_NAME, CALL_ID_NAME),
+                    ));
+                }
+            }
+            (TokenKind::Ident, "function") if idx == 0 || !tokens[idx - 1].is_punct(".") => {
+                if let Some((name, body_start)) = parse_function_head(&tokens, idx + 1) {
+                    let name_literal =
+                        serde_json::to_string(name.as_str()).expect("could not serialize name");
+                    body_starts.insert(body_start);
+                    let (pos, needs_semicolon) = directive_prologue_end(&tokens, body_start);
+                    insertions.push((
+                        pos,
+                        format!(
+                            "{} const {} = {}({}, {}); try {{",
+                            if needs_semicolon { ";" } else { "" },
+                            CALL_ID_NAME,
+                            ENTER_FUNCTION_NAME,
+                            name_literal,
+                            path_literal
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    insertions.sort_by_key(|(pos, _)| *pos);
+    let mut instrumented = String::with_capacity(code.len() * 2);
+    let mut last_pos = 0;
+    for (pos, insertion) in insertions {
+        instrumented.push_str(&code[last_pos..pos]);
+        instrumented.push_str(insertion.as_str());
+        last_pos = pos;
+    }
+    instrumented.push_str(&code[last_pos..]);
+    instrumented
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::profiler::instrument;
+    use hirofa_utils::js_utils::Script;
+
+    #[test]
+    fn test_instrument() {
+        let code = "function a(x) {\n  return '}' + x; // }\n}\nlet b = function() { return {}; };";
+        let instrumented = instrument("test.es", code);
+        assert_eq!(instrumented.lines().count(), 4);
+        assert_eq!(
+            instrumented,
+            "function a(x) { const __qjs_profile_id__ = __qjs_profile_enter__(\"a\", \"test.es\"); try {\n  return '}' + x; // }\n} finally { __qjs_profile_exit__(__qjs_profile_id__); }}\n\
+             let b = function() { const __qjs_profile_id__ = __qjs_profile_enter__(\"<anonymous>\", \"test.es\"); try { return {}; } finally { __qjs_profile_exit__(__qjs_profile_id__); }};"
+        );
+    }
+
+    #[test]
+    fn test_instrument_directive_and_literals() {
+        let code = "function a() {\n  'use strict';\n  return /}/.test(`${ {b: '}'}.b }`);\n}";
+        let instrumented = instrument("test.es", code);
+        assert_eq!(
+            instrumented,
+            "function a() {\n  'use strict'; const __qjs_profile_id__ = __qjs_profile_enter__(\"a\", \"test.es\"); try {\n  return /}/.test(`${ {b: '}'}.b }`);\n} finally { __qjs_profile_exit__(__qjs_profile_id__); }}"
+        );
+
+        let code = "function b() { \"use strict\"\n  return 1; }";
+        let instrumented = instrument("test.es", code);
+        assert_eq!(
+            instrumented,
+            "function b() { \"use strict\"; const __qjs_profile_id__ = __qjs_profile_enter__(\"b\", \"test.es\"); try {\n  return 1; } finally { __qjs_profile_exit__(__qjs_profile_id__); }}"
+        );
+    }
+
+    #[test]
+    fn test_profiling() {
+        let rt = init_test_rt();
+        let handle = rt.start_profiling();
+        let res = rt
+            .eval_sync(Script::new(
+                "test_profiling.es",
+                "function add(a, b) {\n  return a + b;\n}\n\
+                 function sum(arr) {\n  let total = 0;\n  for (let x of arr) {\n    total = add(total, x);\n  }\n  return total;\n}\n\
+                 sum([1, 2, 3]) + sum([4, 5]);",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 15);
+        let res = rt
+            .eval_sync(Script::new(
+                "test_profiling_strict.es",
+                "function isStrict() {\n  'use strict';\n  return this === undefined;\n}\nisStrict();",
+            ))
+            .ok()
+            .expect("script failed");
+        assert!(res.get_boolean());
+        let report = handle.stop();
+        assert_eq!(report.functions.len(), 3);
+        let add = report.get_function("add").expect("add was not profiled");
+        assert_eq!(add.call_count, 5);
+        assert_eq!(add.file.as_str(), "test_profiling.es");
+        let sum = report.get_function("sum").expect("sum was not profiled");
+        assert_eq!(sum.call_count, 2);
+        assert!(sum.total_ms >= add.total_ms);
+
+        // scripts evaluated after profiling stopped are no longer instrumented
+        let res = rt
+            .eval_sync(Script::new(
+                "test_profiling2.es",
+                "function mul(a, b) {\n  return a * b;\n}\nmul.toString().indexOf('__qjs_profile_enter__');",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), -1);
+    }
+}
@@ -0,0 +1,298 @@
+//! Reflect utils, these methods call the JavaScript Reflect API from rust
+//! see [MDN](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Reflect) for more on Reflect
+
+use crate::quickjs_utils::{arrays, functions, get_global, objects, primitives};
+use crate::quickjscontext::QuickJsContext;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+use libquickjs_sys as q;
+
+unsafe fn get_reflect(context: *mut q::JSContext) -> Result<JSValueRef, JsError> {
+    let global_ref = get_global(context);
+    objects::get_property(context, &global_ref, "Reflect")
+}
+
+unsafe fn to_array(
+    context: *mut q::JSContext,
+    values: Vec<JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    let arr_ref = arrays::create_array(context)?;
+    for (index, value) in values.into_iter().enumerate() {
+        arrays::set_element(context, &arr_ref, index as u32, value)?;
+    }
+    Ok(arr_ref)
+}
+
+/// get a property of an object, like `Reflect.get(target, propName);`
+pub fn get_q(
+    q_ctx: &QuickJsContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<JSValueRef, JsError> {
+    unsafe { get(q_ctx.context, target, prop_name) }
+}
+
+/// get a property of an object, like `Reflect.get(target, propName);`
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn get(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<JSValueRef, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let prop_name_ref = primitives::from_string(context, prop_name)?;
+    functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "get",
+        vec![target.clone(), prop_name_ref],
+    )
+}
+
+/// set a property of an object, like `Reflect.set(target, propName, value);`
+/// returns true if the property was set
+pub fn set_q(
+    q_ctx: &QuickJsContext,
+    target: &JSValueRef,
+    prop_name: &str,
+    value: JSValueRef,
+) -> Result<bool, JsError> {
+    unsafe { set(q_ctx.context, target, prop_name, value) }
+}
+
+/// set a property of an object, like `Reflect.set(target, propName, value);`
+/// returns true if the property was set
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn set(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+    prop_name: &str,
+    value: JSValueRef,
+) -> Result<bool, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let prop_name_ref = primitives::from_string(context, prop_name)?;
+    let res = functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "set",
+        vec![target.clone(), prop_name_ref, value],
+    )?;
+    primitives::to_bool(&res)
+}
+
+/// check if an object has a property, like `Reflect.has(target, propName);`
+pub fn has_q(
+    q_ctx: &QuickJsContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    unsafe { has(q_ctx.context, target, prop_name) }
+}
+
+/// check if an object has a property, like `Reflect.has(target, propName);`
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn has(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let prop_name_ref = primitives::from_string(context, prop_name)?;
+    let res = functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "has",
+        vec![target.clone(), prop_name_ref],
+    )?;
+    primitives::to_bool(&res)
+}
+
+/// delete a property of an object, like `Reflect.deleteProperty(target, propName);`
+/// returns true if the property was deleted
+pub fn delete_property_q(
+    q_ctx: &QuickJsContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    unsafe { delete_property(q_ctx.context, target, prop_name) }
+}
+
+/// delete a property of an object, like `Reflect.deleteProperty(target, propName);`
+/// returns true if the property was deleted
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn delete_property(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+    prop_name: &str,
+) -> Result<bool, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let prop_name_ref = primitives::from_string(context, prop_name)?;
+    let res = functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "deleteProperty",
+        vec![target.clone(), prop_name_ref],
+    )?;
+    primitives::to_bool(&res)
+}
+
+/// get the own property keys of an object including non-enumerable and symbol keys, like `Reflect.ownKeys(target);`
+pub fn own_keys_q(q_ctx: &QuickJsContext, target: &JSValueRef) -> Result<Vec<JSValueRef>, JsError> {
+    unsafe { own_keys(q_ctx.context, target) }
+}
+
+/// get the own property keys of an object including non-enumerable and symbol keys, like `Reflect.ownKeys(target);`
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn own_keys(
+    context: *mut q::JSContext,
+    target: &JSValueRef,
+) -> Result<Vec<JSValueRef>, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let keys_ref =
+        functions::invoke_member_function(context, &reflect_ref, "ownKeys", vec![target.clone()])?;
+    let len = arrays::get_length(context, &keys_ref)?;
+    let mut keys = vec![];
+    for index in 0..len {
+        keys.push(arrays::get_element(context, &keys_ref, index)?);
+    }
+    Ok(keys)
+}
+
+/// call a function with a specific this, like `Reflect.apply(func, thisArg, args);`
+pub fn apply_q(
+    q_ctx: &QuickJsContext,
+    function_ref: &JSValueRef,
+    this_ref: &JSValueRef,
+    arguments: Vec<JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    unsafe { apply(q_ctx.context, function_ref, this_ref, arguments) }
+}
+
+/// call a function with a specific this, like `Reflect.apply(func, thisArg, args);`
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn apply(
+    context: *mut q::JSContext,
+    function_ref: &JSValueRef,
+    this_ref: &JSValueRef,
+    arguments: Vec<JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let args_ref = to_array(context, arguments)?;
+    functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "apply",
+        vec![function_ref.clone(), this_ref.clone(), args_ref],
+    )
+}
+
+/// construct a new instance of a constructor, like `Reflect.construct(constructor, args);`
+pub fn construct_q(
+    q_ctx: &QuickJsContext,
+    constructor_ref: &JSValueRef,
+    arguments: Vec<JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    unsafe { construct(q_ctx.context, constructor_ref, arguments) }
+}
+
+/// construct a new instance of a constructor, like `Reflect.construct(constructor, args);`
+/// # Safety
+/// please ensure the passed JSContext is still valid
+pub unsafe fn construct(
+    context: *mut q::JSContext,
+    constructor_ref: &JSValueRef,
+    arguments: Vec<JSValueRef>,
+) -> Result<JSValueRef, JsError> {
+    let reflect_ref = get_reflect(context)?;
+    let args_ref = to_array(context, arguments)?;
+    functions::invoke_member_function(
+        context,
+        &reflect_ref,
+        "construct",
+        vec![constructor_ref.clone(), args_ref],
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::{functions, objects, primitives, reflect};
+    use hirofa_utils::js_utils::Script;
+
+    #[test]
+    fn test_apply() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let func_ref = q_ctx
+                .eval(Script::new(
+                    "test_reflect_apply.es",
+                    "(function(a) {return this.base + a;});",
+                ))
+                .ok()
+                .expect("script failed");
+            let this_ref = q_ctx
+                .eval(Script::new("test_reflect_apply2.es", "({base: 10});"))
+                .ok()
+                .expect("script failed");
+
+            let res = reflect::apply_q(q_ctx, &func_ref, &this_ref, vec![primitives::from_i32(5)])
+                .ok()
+                .expect("apply failed");
+            let expected = functions::call_function_q(
+                q_ctx,
+                &func_ref,
+                vec![primitives::from_i32(5)],
+                Some(&this_ref),
+            )
+            .ok()
+            .expect("call failed");
+            assert_eq!(primitives::to_i32(&res).ok().unwrap(), 15);
+            assert_eq!(
+                primitives::to_i32(&res).ok().unwrap(),
+                primitives::to_i32(&expected).ok().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn test_props() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let obj_ref = objects::create_object_q(q_ctx).ok().unwrap();
+            assert!(
+                reflect::set_q(q_ctx, &obj_ref, "a", primitives::from_i32(1))
+                    .ok()
+                    .unwrap()
+            );
+            assert!(reflect::has_q(q_ctx, &obj_ref, "a").ok().unwrap());
+            let a_ref = reflect::get_q(q_ctx, &obj_ref, "a").ok().unwrap();
+            assert_eq!(primitives::to_i32(&a_ref).ok().unwrap(), 1);
+            assert_eq!(reflect::own_keys_q(q_ctx, &obj_ref).ok().unwrap().len(), 1);
+            assert!(reflect::delete_property_q(q_ctx, &obj_ref, "a")
+                .ok()
+                .unwrap());
+            assert!(!reflect::has_q(q_ctx, &obj_ref, "a").ok().unwrap());
+
+            let date_constructor = q_ctx
+                .eval(Script::new("test_reflect_construct.es", "(Date);"))
+                .ok()
+                .expect("script failed");
+            let date_ref = reflect::construct_q(q_ctx, &date_constructor, vec![])
+                .ok()
+                .expect("construct failed");
+            assert!(objects::is_instance_of_q(
+                q_ctx,
+                &date_ref,
+                &date_constructor
+            ));
+        });
+    }
+}
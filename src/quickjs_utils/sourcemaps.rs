@@ -0,0 +1,377 @@
+//! utils for working with (inline) source maps
+//!
+//! source maps are registered in the QuickJsRuntime per script path, when an Error is converted to a JsError
+//! the stack trace is rewritten to point to the original sources
+//!
+//! the runtime keeps at most [MAX_SOURCE_MAPS] source maps, when more are registered the oldest ones are removed
+
+use crate::quickjsruntime::QJS_RT;
+use hirofa_utils::js_utils::{JsError, Script};
+use std::collections::{HashMap, VecDeque};
+
+const INLINE_MAP_PREFIX: &str = "//# sourceMappingURL=data:";
+const INLINE_JSON_MAP_PREFIX: &str = "//# sourceMappingURL=data:application/json;base64,";
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// the maximum number of source maps which are kept by a runtime
+pub const MAX_SOURCE_MAPS: usize = 1024;
+
+/// adds a source map to a Script
+pub trait SourceMapScript {
+    /// add a source map (json) to the script as an inline `//# sourceMappingURL=data:...` comment, the map is
+    /// registered for the path of the script when it is evaluated
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::quickjs_utils::sourcemaps::SourceMapScript;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let script = Script::new("with_map.es", "let a = 1;\nthrow Error('mapped');")
+    ///     .with_source_map("{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA;AASA\"}");
+    /// let err = rt.eval_sync(script).err().expect("script should have failed");
+    /// assert!(err.get_stack().contains("original.ts:10"));
+    /// ```
+    fn with_source_map(self, source_map_json: &str) -> Script;
+}
+
+impl SourceMapScript for Script {
+    fn with_source_map(self, source_map_json: &str) -> Script {
+        let code = format!(
+            "{}\n{}{}",
+            self.get_code(),
+            INLINE_JSON_MAP_PREFIX,
+            base64::encode(source_map_json)
+        );
+        Script::new(self.get_path(), code.as_str())
+    }
+}
+
+/// the source maps of a runtime per script path, when more than [MAX_SOURCE_MAPS] maps are added the oldest are removed
+#[derive(Default)]
+pub(crate) struct SourceMapRegistry {
+    maps: HashMap<String, SourceMap>,
+    // the paths in the order in which their maps were added
+    order: VecDeque<String>,
+}
+
+impl SourceMapRegistry {
+    pub(crate) fn insert(&mut self, path: &str, source_map: SourceMap) {
+        if self.maps.insert(path.to_string(), source_map).is_some() {
+            self.order.retain(|p| p != path);
+        }
+        self.order.push_back(path.to_string());
+        while self.order.len() > MAX_SOURCE_MAPS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.maps.remove(&oldest);
+            }
+        }
+    }
+    pub(crate) fn remove(&mut self, path: &str) -> bool {
+        self.order.retain(|p| p != path);
+        self.maps.remove(path).is_some()
+    }
+    pub(crate) fn get(&self, path: &str) -> Option<&SourceMap> {
+        self.maps.get(path)
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+}
+
+/// a single mapping segment, generated column to original source/line/column
+struct Segment {
+    gen_col: u32,
+    source: u32,
+    orig_line: u32,
+}
+
+/// a parsed version 3 source map
+pub struct SourceMap {
+    sources: Vec<String>,
+    // one entry per generated line, segments are sorted by gen_col
+    lines: Vec<Vec<Segment>>,
+}
+
+impl SourceMap {
+    /// parse a source map from its json representation
+    pub fn parse(json: &str) -> Result<Self, JsError> {
+        let map: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| JsError::new_string(format!("invalid source map: {}", e)))?;
+        let mappings = map
+            .get("mappings")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| JsError::new_str("source map does not contain mappings"))?;
+        let sources = map
+            .get("sources")
+            .and_then(|s| s.as_array())
+            .map(|sources| {
+                sources
+                    .iter()
+                    .map(|source| source.as_str().unwrap_or("").to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut lines = vec![];
+        let mut source: i64 = 0;
+        let mut orig_line: i64 = 0;
+
+        for line_str in mappings.split(';') {
+            let mut segments = vec![];
+            let mut gen_col: i64 = 0;
+            for segment_str in line_str.split(',') {
+                if segment_str.is_empty() {
+                    continue;
+                }
+                let fields = decode_vlq(segment_str)?;
+                gen_col += fields[0];
+                if fields.len() >= 4 {
+                    source += fields[1];
+                    orig_line += fields[2];
+                    segments.push(Segment {
+                        gen_col: gen_col as u32,
+                        source: source as u32,
+                        orig_line: orig_line as u32,
+                    });
+                }
+            }
+            lines.push(segments);
+        }
+
+        Ok(Self { sources, lines })
+    }
+
+    /// get the original source and (1-based) line for a (1-based) generated line and optional column
+    pub fn lookup(&self, line: u32, col: Option<u32>) -> Option<(&str, u32)> {
+        if line == 0 {
+            return None;
+        }
+        let segments = self.lines.get((line - 1) as usize)?;
+        let segment = match col {
+            Some(col) => segments
+                .iter()
+                .rev()
+                .find(|s| s.gen_col < col)
+                .or_else(|| segments.first()),
+            None => segments.first(),
+        }?;
+        let source = self
+            .sources
+            .get(segment.source as usize)
+            .map(|s| s.as_str())
+            .unwrap_or("");
+        Some((source, segment.orig_line + 1))
+    }
+}
+
+/// detect an inline (base64 data uri) source map in a script, returns the code without the sourceMappingURL comment and the decoded map
+pub fn extract_inline_source_map(code: &str) -> Option<(String, String)> {
+    let idx = code.rfind(INLINE_MAP_PREFIX)?;
+    let rest = &code[idx + INLINE_MAP_PREFIX.len()..];
+    let end = rest.find('\n').unwrap_or(rest.len());
+    let data_uri = rest[..end].trim();
+    let comma_idx = data_uri.find(',')?;
+    if !data_uri[..comma_idx].ends_with(";base64") {
+        return None;
+    }
+    let decoded = base64::decode(data_uri[comma_idx + 1..].trim()).ok()?;
+    let json = String::from_utf8(decoded).ok()?;
+
+    let mut stripped = code[..idx].to_string();
+    stripped.push_str(&rest[end..]);
+    Some((stripped, json))
+}
+
+/// rewrite the locations in a stack trace with the source maps registered in the current QuickJsRuntime
+pub fn map_stack_trace(stack: &str) -> String {
+    QJS_RT.with(|rc| {
+        if let Ok(opt) = rc.try_borrow() {
+            if let Some(q_js_rt) = &*opt {
+                return q_js_rt.with_source_maps(|maps| {
+                    if maps.is_empty() {
+                        stack.to_string()
+                    } else {
+                        stack
+                            .lines()
+                            .map(|line| map_stack_line(line, |path| maps.get(path)))
+                            .collect::<Vec<String>>()
+                            .join("\n")
+                    }
+                });
+            }
+        }
+        stack.to_string()
+    })
+}
+
+/// rewrite a single stack line like `    at func (file.es:3)` or `    at func (file.es:3:7)`
+fn map_stack_line<'a, F>(line: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<&'a SourceMap>,
+{
+    if let (Some(open), Some(close)) = (line.rfind('('), line.rfind(')')) {
+        if open < close {
+            let location = &line[open + 1..close];
+            let parts: Vec<&str> = location.rsplitn(3, ':').collect();
+            // parts are reversed, either [line, path] or [col, line, path]
+            let (path, line_no, col) = match parts.len() {
+                3 => match (parts[1].parse::<u32>(), parts[0].parse::<u32>()) {
+                    (Ok(l), Ok(c)) => (parts[2], l, Some(c)),
+                    _ => (
+                        location.rsplitn(2, ':').last().unwrap_or(""),
+                        parts[0].parse::<u32>().unwrap_or(0),
+                        None,
+                    ),
+                },
+                2 => (parts[1], parts[0].parse::<u32>().unwrap_or(0), None),
+                _ => return line.to_string(),
+            };
+            if let Some(map) = lookup(path) {
+                if let Some((source, orig_line)) = map.lookup(line_no, col) {
+                    let source = if source.is_empty() { path } else { source };
+                    return format!(
+                        "{}({}:{}){}",
+                        &line[..open],
+                        source,
+                        orig_line,
+                        &line[close + 1..]
+                    );
+                }
+            }
+        }
+    }
+    line.to_string()
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>, JsError> {
+    let mut res = vec![];
+    let mut value: i64 = 0;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = BASE64_CHARS
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| JsError::new_str("invalid char in source map mappings"))?
+            as i64;
+        value += (digit & 31) << shift;
+        if digit & 32 == 0 {
+            let negate = value & 1 == 1;
+            value >>= 1;
+            res.push(if negate { -value } else { value });
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 5;
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::sourcemaps::{
+        extract_inline_source_map, SourceMap, SourceMapRegistry, SourceMapScript, MAX_SOURCE_MAPS,
+    };
+    use hirofa_utils::js_utils::Script;
+
+    // {"version":3,"sources":["original.ts"],"names":[],"mappings":"AAAA;AASA"}
+    const MAP_B64: &str = "eyJ2ZXJzaW9uIjozLCJzb3VyY2VzIjpbIm9yaWdpbmFsLnRzIl0sIm5hbWVzIjpbXSwibWFwcGluZ3MiOiJBQUFBO0FBU0EifQ==";
+
+    #[test]
+    fn test_parse_map() {
+        let map = SourceMap::parse(
+            "{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA;AASA\"}",
+        )
+        .ok()
+        .expect("parse failed");
+        assert_eq!(map.lookup(1, None), Some(("original.ts", 1)));
+        assert_eq!(map.lookup(2, None), Some(("original.ts", 10)));
+        assert_eq!(map.lookup(3, None), None);
+        assert!(SourceMap::parse("{\"mappings\": ").is_err());
+    }
+
+    #[test]
+    fn test_registry_is_bounded() {
+        let json =
+            "{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA\"}";
+        let mut registry = SourceMapRegistry::default();
+        for i in 0..MAX_SOURCE_MAPS + 10 {
+            let map = SourceMap::parse(json).ok().expect("parse failed");
+            registry.insert(format!("script_{}.es", i).as_str(), map);
+        }
+        assert_eq!(registry.order.len(), MAX_SOURCE_MAPS);
+        assert!(registry.get("script_9.es").is_none());
+        assert!(registry.get("script_10.es").is_some());
+        assert!(registry.remove("script_10.es"));
+        assert!(!registry.remove("script_10.es"));
+        assert_eq!(registry.maps.len(), MAX_SOURCE_MAPS - 1);
+    }
+
+    #[test]
+    fn test_extract() {
+        let code = format!(
+            "let a = 1;\n//# sourceMappingURL=data:application/json;base64,{}\n",
+            MAP_B64
+        );
+        let (stripped, json) = extract_inline_source_map(code.as_str()).expect("no map found");
+        assert_eq!(stripped, "let a = 1;\n\n");
+        assert!(json.contains("\"mappings\":\"AAAA;AASA\""));
+        assert!(extract_inline_source_map("let a = 1;").is_none());
+    }
+
+    #[test]
+    fn test_mapped_error() {
+        let rt = init_test_rt();
+        let code = format!(
+            "let smA = 1;\nthrow Error('mapped');\n//# sourceMappingURL=data:application/json;base64,{}",
+            MAP_B64
+        );
+        let res = rt.eval_sync(Script::new("test_mapped_error.es", code.as_str()));
+        let err = res.err().expect("script should have failed");
+        assert!(err.get_message().contains("mapped"));
+        assert!(err.get_stack().contains("original.ts:10"));
+    }
+
+    #[test]
+    fn test_registered_map() {
+        let rt = init_test_rt();
+        rt.add_source_map(
+            "test_registered_map.es",
+            "{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA;AASA\"}",
+        )
+        .ok()
+        .expect("add_source_map failed");
+        let res = rt.eval_sync(Script::new(
+            "test_registered_map.es",
+            "let smB = 1;\nthrow Error('mapped');",
+        ));
+        let err = res.err().expect("script should have failed");
+        assert!(err.get_stack().contains("original.ts:10"));
+
+        assert!(rt.remove_source_map("test_registered_map.es"));
+        let res = rt.eval_sync(Script::new(
+            "test_registered_map.es",
+            "let smC = 1;\nthrow Error('mapped');",
+        ));
+        let err = res.err().expect("script should have failed");
+        assert!(!err.get_stack().contains("original.ts"));
+    }
+
+    #[test]
+    fn test_with_source_map() {
+        let rt = init_test_rt();
+        let script = Script::new(
+            "test_with_source_map.es",
+            "let smD = 1;\nthrow Error('mapped');",
+        )
+        .with_source_map(
+            "{\"version\":3,\"sources\":[\"original.ts\"],\"names\":[],\"mappings\":\"AAAA;AASA\"}",
+        );
+        let err = rt
+            .eval_sync(script)
+            .err()
+            .expect("script should have failed");
+        assert!(err.get_stack().contains("original.ts:10"));
+    }
+}
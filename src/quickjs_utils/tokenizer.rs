@@ -0,0 +1,280 @@
+//! a minimal JavaScript tokenizer used to instrument scripts for the debugger and the profiler
+//!
+//! it only knows enough of the syntax to find the boundaries of string, template and regular expression literals
+//! and of comments, so braces, keywords and newlines inside those are never mistaken for code
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TokenKind {
+    /// an identifier or a keyword
+    Ident,
+    Number,
+    Str,
+    /// (a part of) a template literal, a template with substitutions is split into several parts, the `${` which
+    /// starts a substitution and the `}` which ends it are Punct tokens
+    Template,
+    Regex,
+    /// a punctuator like `{`, `=>` or `${`
+    Punct,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// byte offset of the start of the token
+    pub start: usize,
+    /// byte offset of the end of the token
+    pub end: usize,
+    /// the (1 based) line on which the token starts
+    pub line: usize,
+    /// true if there is only whitespace between the start of the line and the token
+    pub line_start: bool,
+}
+
+impl Token<'_> {
+    pub fn is_punct(&self, text: &str) -> bool {
+        self.kind == TokenKind::Punct && self.text == text
+    }
+    pub fn is_ident(&self, text: &str) -> bool {
+        self.kind == TokenKind::Ident && self.text == text
+    }
+}
+
+/// keywords after which a `/` starts a regular expression instead of being a division
+const REGEX_PREFIX_KEYWORDS: [&str; 15] = [
+    "await",
+    "case",
+    "delete",
+    "do",
+    "else",
+    "in",
+    "instanceof",
+    "new",
+    "of",
+    "return",
+    "throw",
+    "typeof",
+    "void",
+    "yield",
+    "extends",
+];
+
+/// multi character punctuators which matter to the users of the tokens, all others are split per character
+const MULTI_CHAR_PUNCTUATORS: [&str; 3] = ["=>", "?.", "??"];
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b == b'\\' || b >= 0x80
+}
+
+fn regex_allowed(prev: Option<&Token>) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => match prev.kind {
+            TokenKind::Number | TokenKind::Str | TokenKind::Template | TokenKind::Regex => false,
+            TokenKind::Ident => REGEX_PREFIX_KEYWORDS.contains(&prev.text),
+            TokenKind::Punct => prev.text != ")" && prev.text != "]",
+        },
+    }
+}
+
+/// split a script into tokens, comments and whitespace are skipped
+/// unterminated literals simply end at the end of the code, the tokenizer never fails
+pub(crate) fn tokenize(code: &str) -> Vec<Token<'_>> {
+    let bytes = code.as_bytes();
+    let mut tokens: Vec<Token> = vec![];
+    // the brace depth at which each currently open template substitution was started
+    let mut substitution_depths: Vec<usize> = vec![];
+    let mut brace_depth = 0;
+    let mut line = 1;
+    let mut line_start = true;
+    let mut idx = 0;
+
+    let count_lines =
+        |from: usize, to: usize| bytes[from..to].iter().filter(|b| **b == b'\n').count();
+
+    while idx < bytes.len() {
+        let b = bytes[idx];
+        if b == b'\n' {
+            line += 1;
+            line_start = true;
+            idx += 1;
+            continue;
+        }
+        if b.is_ascii_whitespace() {
+            idx += 1;
+            continue;
+        }
+        if b == b'/' && bytes.get(idx + 1) == Some(&b'/') {
+            while idx < bytes.len() && bytes[idx] != b'\n' {
+                idx += 1;
+            }
+            continue;
+        }
+        if b == b'/' && bytes.get(idx + 1) == Some(&b'*') {
+            let end = code[idx + 2..]
+                .find("*/")
+                .map(|pos| idx + 2 + pos + 2)
+                .unwrap_or_else(|| bytes.len());
+            line += count_lines(idx, end);
+            line_start = false;
+            idx = end;
+            continue;
+        }
+
+        let mut start = idx;
+        let kind;
+        if b == b'\'' || b == b'"' {
+            kind = TokenKind::Str;
+            idx += 1;
+            while idx < bytes.len() && bytes[idx] != b && bytes[idx] != b'\n' {
+                if bytes[idx] == b'\\' {
+                    idx += 1;
+                }
+                idx += 1;
+            }
+            idx = (idx + 1).min(bytes.len());
+        } else if b == b'`' {
+            kind = TokenKind::Template;
+            idx = scan_template_part(bytes, idx + 1);
+        } else if b.is_ascii_digit()
+            || (b == b'.' && bytes.get(idx + 1).map_or(false, |n| n.is_ascii_digit()))
+        {
+            kind = TokenKind::Number;
+            while idx < bytes.len() && (is_ident_byte(bytes[idx]) || bytes[idx] == b'.') {
+                idx += 1;
+            }
+        } else if is_ident_byte(b) {
+            kind = TokenKind::Ident;
+            while idx < bytes.len() && is_ident_byte(bytes[idx]) {
+                idx += 1;
+            }
+        } else if b == b'/' && regex_allowed(tokens.last()) {
+            kind = TokenKind::Regex;
+            idx = scan_regex(bytes, idx + 1);
+        } else if b == b'}'
+            && brace_depth > 0
+            && substitution_depths.last() == Some(&(brace_depth - 1))
+        {
+            // the end of a template substitution, emit the brace and continue with the template
+            substitution_depths.pop();
+            brace_depth -= 1;
+            tokens.push(Token {
+                kind: TokenKind::Punct,
+                text: &code[idx..idx + 1],
+                start: idx,
+                end: idx + 1,
+                line,
+                line_start,
+            });
+            line_start = false;
+            kind = TokenKind::Template;
+            start = idx + 1;
+            idx = scan_template_part(bytes, start);
+        } else {
+            kind = TokenKind::Punct;
+            idx += MULTI_CHAR_PUNCTUATORS
+                .iter()
+                .find(|p| code[idx..].starts_with(*p))
+                // `a?.5:1` is a conditional, not an optional chain
+                .filter(|p| **p != "?." || !bytes.get(idx + 2).map_or(false, u8::is_ascii_digit))
+                .map_or(1, |p| p.len());
+            match b {
+                b'{' => brace_depth += 1,
+                b'}' => brace_depth = brace_depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let token_line = line;
+        line += count_lines(start, idx);
+
+        // a template part which ends with `${` is emitted without it, the `${` is a token of its own
+        let (end, substitution) = if kind == TokenKind::Template && code[..idx].ends_with("${") {
+            (idx - 2, true)
+        } else {
+            (idx, false)
+        };
+        tokens.push(Token {
+            kind,
+            text: &code[start..end],
+            start,
+            end,
+            line: token_line,
+            line_start,
+        });
+        if substitution {
+            tokens.push(Token {
+                kind: TokenKind::Punct,
+                text: &code[end..idx],
+                start: end,
+                end: idx,
+                line,
+                line_start: false,
+            });
+            substitution_depths.push(brace_depth);
+            brace_depth += 1;
+        }
+        line_start = false;
+    }
+    tokens
+}
+
+/// scan the rest of a template part, returns the offset after the closing backtick or after the `${` which starts a substitution
+fn scan_template_part(bytes: &[u8], mut idx: usize) -> usize {
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'`' => return idx + 1,
+            b'$' if bytes.get(idx + 1) == Some(&b'{') => return idx + 2,
+            _ => idx += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// scan the rest of a regular expression literal, returns the offset after its flags
+fn scan_regex(bytes: &[u8], mut idx: usize) -> usize {
+    let mut in_class = false;
+    while idx < bytes.len() && bytes[idx] != b'\n' {
+        match bytes[idx] {
+            b'\\' => idx += 1,
+            b'[' => in_class = true,
+            b']' => in_class = false,
+            b'/' if !in_class => {
+                idx += 1;
+                break;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    while idx < bytes.len() && is_ident_byte(bytes[idx]) {
+        idx += 1;
+    }
+    idx.min(bytes.len())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::quickjs_utils::tokenizer::{tokenize, TokenKind};
+
+    #[test]
+    fn test_tokenize() {
+        let code = "let a = `x${ {b: '}'}.b }\ny` / 2; // }\nlet r = /[/}]+/g.test(a) /* { */;";
+        let tokens = tokenize(code);
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "let", "a", "=", "`x", "${", "{", "b", ":", "'}'", "}", ".", "b", "}", "\ny`", "/",
+                "2", ";", "let", "r", "=", "/[/}]+/g", ".", "test", "(", "a", ")", ";"
+            ]
+        );
+        assert_eq!(tokens[3].kind, TokenKind::Template);
+        assert_eq!(tokens[13].kind, TokenKind::Template);
+        assert_eq!(tokens[20].kind, TokenKind::Regex);
+        assert_eq!(tokens[17].line, 3);
+        assert!(tokens[17].line_start);
+        assert!(!tokens[14].line_start);
+    }
+}
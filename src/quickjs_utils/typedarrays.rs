@@ -1 +1,138 @@
+//! Utils for working with ArrayBuffers and typed arrays
 
+use crate::quickjs_utils;
+use crate::quickjs_utils::{functions, objects};
+use crate::quickjscontext::QuickJsContext;
+use crate::valueref::JSValueRef;
+use hirofa_utils::js_utils::JsError;
+use libquickjs_sys as q;
+
+/// create a new ArrayBuffer containing a copy of the given bytes
+pub fn new_array_buffer_q(q_ctx: &QuickJsContext, bytes: &[u8]) -> Result<JSValueRef, JsError> {
+    unsafe { new_array_buffer(q_ctx.context, bytes) }
+}
+
+/// create a new ArrayBuffer containing a copy of the given bytes
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn new_array_buffer(
+    context: *mut q::JSContext,
+    bytes: &[u8],
+) -> Result<JSValueRef, JsError> {
+    let raw = q::JS_NewArrayBufferCopy(context, bytes.as_ptr(), bytes.len() as _);
+    let buffer_ref = JSValueRef::new(context, raw, false, true, "typedarrays::new_array_buffer");
+    if buffer_ref.is_exception() {
+        return Err(JsError::new_str("Could not create ArrayBuffer"));
+    }
+    Ok(buffer_ref)
+}
+
+/// create a new Uint8Array containing a copy of the given bytes
+pub fn new_uint8_array_q(q_ctx: &QuickJsContext, bytes: &[u8]) -> Result<JSValueRef, JsError> {
+    unsafe { new_uint8_array(q_ctx.context, bytes) }
+}
+
+/// create a new Uint8Array containing a copy of the given bytes
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn new_uint8_array(
+    context: *mut q::JSContext,
+    bytes: &[u8],
+) -> Result<JSValueRef, JsError> {
+    let buffer_ref = new_array_buffer(context, bytes)?;
+    let constructor = quickjs_utils::get_constructor(context, "Uint8Array")?;
+    functions::call_constructor(context, &constructor, &[buffer_ref])
+}
+
+/// check if a JSValueRef is an instance of Uint8Array
+pub fn is_uint8_array_q(q_ctx: &QuickJsContext, obj_ref: &JSValueRef) -> bool {
+    unsafe { is_uint8_array(q_ctx.context, obj_ref) }
+}
+
+/// check if a JSValueRef is an instance of Uint8Array
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn is_uint8_array(context: *mut q::JSContext, obj_ref: &JSValueRef) -> bool {
+    objects::is_instance_of_by_name(context, obj_ref, "Uint8Array").unwrap_or(false)
+}
+
+/// copy the bytes of a Uint8Array (or any other typed array) to a Vec
+pub fn typed_array_to_vec_q(
+    q_ctx: &QuickJsContext,
+    arr_ref: &JSValueRef,
+) -> Result<Vec<u8>, JsError> {
+    unsafe { typed_array_to_vec(q_ctx.context, arr_ref) }
+}
+
+/// copy the bytes of a Uint8Array (or any other typed array) to a Vec
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn typed_array_to_vec(
+    context: *mut q::JSContext,
+    arr_ref: &JSValueRef,
+) -> Result<Vec<u8>, JsError> {
+    let mut offset = 0;
+    let mut len = 0;
+    let mut bytes_per_element = 0;
+    let raw_buffer = q::JS_GetTypedArrayBuffer(
+        context,
+        *arr_ref.borrow_value(),
+        &mut offset,
+        &mut len,
+        &mut bytes_per_element,
+    );
+    let buffer_ref = JSValueRef::new(
+        context,
+        raw_buffer,
+        false,
+        true,
+        "typedarrays::typed_array_to_vec buffer",
+    );
+    // both JS_GetTypedArrayBuffer and JS_GetArrayBuffer throw on failure, the exception is cleared by returning it
+    if buffer_ref.is_exception() {
+        return Err(QuickJsContext::get_exception(context)
+            .unwrap_or_else(|| JsError::new_str("value is not a typed array")));
+    }
+    let mut size = 0;
+    let ptr = q::JS_GetArrayBuffer(context, &mut size, *buffer_ref.borrow_value());
+    if ptr.is_null() {
+        return Err(QuickJsContext::get_exception(context)
+            .unwrap_or_else(|| JsError::new_str("could not get ArrayBuffer of typed array")));
+    }
+    let bytes = std::slice::from_raw_parts(ptr.add(offset as usize), len as usize);
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
+    use crate::quickjs_utils::typedarrays::{
+        is_uint8_array_q, new_uint8_array_q, typed_array_to_vec_q,
+    };
+    use crate::quickjs_utils::{arrays, primitives};
+
+    #[test]
+    fn test_uint8_array() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let arr_ref = new_uint8_array_q(q_ctx, &[0, 1, 255])
+                .ok()
+                .expect("could not create Uint8Array");
+            assert!(is_uint8_array_q(q_ctx, &arr_ref));
+            let el = arrays::get_element_q(q_ctx, &arr_ref, 2)
+                .ok()
+                .expect("could not get element");
+            assert_eq!(primitives::to_i32(&el).ok().expect("not an i32"), 255);
+            let bytes = typed_array_to_vec_q(q_ctx, &arr_ref)
+                .ok()
+                .expect("could not read bytes");
+            assert_eq!(bytes, vec![0, 1, 255]);
+
+            // the exception of a failed conversion is returned and not left pending
+            let not_an_array = primitives::from_i32(1);
+            assert!(typed_array_to_vec_q(q_ctx, &not_an_array).is_err());
+            assert!(q_ctx.get_exception_ctx().is_none());
+        });
+    }
+}
@@ -1,6 +1,7 @@
+use crate::esruntime::EsRuntime;
 use crate::quickjs_utils::primitives::{from_bool, from_f64, from_i32, from_string_q};
 use crate::quickjs_utils::promises::PromiseRef;
-use crate::quickjs_utils::{arrays, errors, functions, new_null_ref, objects};
+use crate::quickjs_utils::{arrays, errors, functions, modules, new_null_ref, objects};
 use crate::quickjsruntime::{make_cstring, QuickJsRuntime};
 use crate::reflection::{Proxy, ProxyInstanceInfo};
 use crate::valueref::{JSValueRef, TAG_EXCEPTION};
@@ -10,11 +11,13 @@ use hirofa_utils::js_utils::adapters::JsRealmAdapter;
 use hirofa_utils::js_utils::JsError;
 use hirofa_utils::js_utils::Script;
 use libquickjs_sys as q;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 type ProxyEventListenerMaps = HashMap<
     String, /*proxy_class_name*/
@@ -27,17 +30,175 @@ type ProxyEventListenerMaps = HashMap<
     >,
 >;
 
+/// the way a script is evaluated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvalType {
+    /// evaluate as a script in the global scope
+    Global,
+    /// evaluate as an ES module, this allows import and export statements
+    Module,
+}
+
+/// options for [QuickJsContext::eval_with_options] and [EsRuntime::eval_sync_with_options](../esruntime/struct.EsRuntime.html#method.eval_sync_with_options)
+#[derive(Clone, Debug)]
+pub struct EvalOptions {
+    pub eval_type: EvalType,
+    /// force strict mode
+    pub strict: bool,
+    /// strip debug info (e.g. source lines for stack traces) from the compiled code
+    pub strip: bool,
+    /// only compile the script, the script is not run
+    pub compile_only: bool,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            eval_type: EvalType::Global,
+            strict: false,
+            strip: false,
+            compile_only: false,
+        }
+    }
+}
+
+impl EvalOptions {
+    fn get_flags(&self) -> i32 {
+        let mut flags = match self.eval_type {
+            EvalType::Global => q::JS_EVAL_TYPE_GLOBAL,
+            EvalType::Module => q::JS_EVAL_TYPE_MODULE,
+        };
+        if self.strict {
+            flags |= q::JS_EVAL_FLAG_STRICT;
+        }
+        if self.strip {
+            flags |= q::JS_EVAL_FLAG_STRIP;
+        }
+        if self.compile_only {
+            flags |= q::JS_EVAL_FLAG_COMPILE_ONLY;
+        }
+        flags as i32
+    }
+}
+
 pub struct QuickJsContext {
     object_cache: RefCell<AutoIdMap<JSValueRef>>,
     pub(crate) proxy_instance_id_mappings: RefCell<HashMap<usize, Box<ProxyInstanceInfo>>>,
     pub(crate) proxy_registry: RefCell<HashMap<String, Rc<Proxy>>>, // todo is this Rc needed or can we just borrow the Proxy when needed?
     pub(crate) proxy_event_listeners: RefCell<ProxyEventListenerMaps>,
+    /// the names of the modules which were evaluated directly (not loaded by a module loader) so they can be resolved
+    /// by name without a module loader
+    pub(crate) evaluated_modules: RefCell<HashSet<String>>,
+    /// the namespaces which were looked up by get_module_namespace
+    pub(crate) module_namespaces: RefCell<HashMap<String, JSValueRef>>,
     pub id: String,
     pub context: *mut q::JSContext,
 }
 
 thread_local! {
     static ID_REGISTRY: RefCell<HashMap<String, Box<String>>> = RefCell::new(HashMap::new());
+    /// the number of evals currently running on this thread, evals are nested when a native function which was called
+    /// from script evaluates another script
+    static EVAL_DEPTH: Cell<usize> = Cell::new(0);
+    /// the number of evals and function calls currently running on this thread
+    static RUNNING_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/// decrements EVAL_DEPTH when an eval completes
+struct EvalGuard {
+    deadline: DeadlineGuard,
+}
+
+impl EvalGuard {
+    fn enter(context: *mut q::JSContext) -> Result<Self, JsError> {
+        let depth = EVAL_DEPTH.with(|c| c.get()) + 1;
+        if let Some(max_depth) = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.max_eval_depth) {
+            if depth > max_depth {
+                return Err(JsError::new(
+                    "RangeError".to_string(),
+                    format!("maximum eval depth of {} exceeded", max_depth),
+                    "".to_string(),
+                ));
+            }
+        }
+        EVAL_DEPTH.with(|c| c.set(depth));
+        Ok(Self {
+            deadline: DeadlineGuard::enter(context),
+        })
+    }
+}
+
+impl Drop for EvalGuard {
+    fn drop(&mut self) {
+        EVAL_DEPTH.with(|c| c.set(c.get() - 1));
+    }
+}
+
+/// starts the deadline of an eval or function call in a context with an eval timeout (see
+/// [QuickJsRuntime::set_context_eval_timeout]) and ends it when dropped
+pub(crate) struct DeadlineGuard {
+    context: *mut q::JSContext,
+    timeout: Option<Duration>,
+    /// true if no other eval or function call was running when this one started
+    outermost: bool,
+}
+
+impl DeadlineGuard {
+    pub(crate) fn enter(context: *mut q::JSContext) -> Self {
+        let outermost = RUNNING_DEPTH.with(|c| {
+            let depth = c.get();
+            c.set(depth + 1);
+            depth == 0
+        });
+        let ctx_id = unsafe { QuickJsContext::get_id(context) };
+        let timeout = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.start_eval_deadline(ctx_id));
+        Self {
+            context,
+            timeout,
+            outermost,
+        }
+    }
+
+    /// run the pending jobs before the deadline ends so the promise reactions which were added by the eval or call
+    /// are limited as well, this is only done for the outermost eval or call because jobs should not run while
+    /// another script is running
+    /// returns a TimeoutError if the jobs exceeded the deadline
+    pub(crate) fn run_pending_jobs(&self) -> Result<(), JsError> {
+        if self.timeout.is_some() && self.outermost {
+            QuickJsRuntime::do_with(|q_js_rt| q_js_rt.run_pending_jobs_if_any());
+            if let Some(timeout_err) = self.timeout_error() {
+                return Err(timeout_err);
+            }
+        }
+        Ok(())
+    }
+
+    /// returns a TimeoutError if this eval or call has a deadline which has passed
+    pub(crate) fn timeout_error(&self) -> Option<JsError> {
+        let timeout = self.timeout?;
+        if QuickJsRuntime::do_with(|q_js_rt| q_js_rt.is_eval_deadline_exceeded()) {
+            Some(JsError::new(
+                "TimeoutError".to_string(),
+                format!(
+                    "script exceeded the eval timeout of {} ms of context {}",
+                    timeout.as_millis(),
+                    unsafe { QuickJsContext::get_id(self.context) }
+                ),
+                "".to_string(),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        RUNNING_DEPTH.with(|c| c.set(c.get() - 1));
+        if self.timeout.is_some() {
+            QuickJsRuntime::do_with(|q_js_rt| q_js_rt.end_eval_deadline());
+        }
+    }
 }
 
 impl QuickJsContext {
@@ -56,6 +217,12 @@ impl QuickJsContext {
             let proxy_event_listeners = &mut *self.proxy_event_listeners.borrow_mut();
             proxy_event_listeners.clear();
         }
+        {
+            let module_namespaces = &mut *self.module_namespaces.borrow_mut();
+            module_namespaces.clear();
+            let evaluated_modules = &mut *self.evaluated_modules.borrow_mut();
+            evaluated_modules.clear();
+        }
 
         unsafe { q::JS_FreeContext(self.context) };
         log::trace!("after QuickJsContext:free {}", self.id);
@@ -86,6 +253,8 @@ impl QuickJsContext {
             proxy_instance_id_mappings: RefCell::new(Default::default()),
             proxy_registry: RefCell::new(Default::default()),
             proxy_event_listeners: RefCell::new(Default::default()),
+            evaluated_modules: RefCell::new(Default::default()),
+            module_namespaces: RefCell::new(Default::default()),
         }
     }
     /// get the id of a QuickJsContext from a JSContext
@@ -96,6 +265,19 @@ impl QuickJsContext {
         let info: &mut String = &mut *(info_ptr as *mut String);
         info
     }
+    /// get the EsRuntime this context belongs to
+    pub fn get_runtime_ref(&self) -> Option<Arc<EsRuntime>> {
+        QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_rt_ref())
+    }
+    /// get the global object (globalThis) of this context
+    pub fn get_global_this(&self) -> Result<JSValueRef, JsError> {
+        let global_ref = crate::quickjs_utils::get_global_q(self);
+        if global_ref.is_object() {
+            Ok(global_ref)
+        } else {
+            Err(JsError::new_str("could not get globalThis"))
+        }
+    }
     /// call a function by namespace and name
     pub fn call_function(
         &self,
@@ -115,44 +297,22 @@ impl QuickJsContext {
     /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
     pub unsafe fn eval_ctx(
         context: *mut q::JSContext,
-        mut script: Script,
+        script: Script,
     ) -> Result<JSValueRef, JsError> {
-        log::debug!("q_js_rt.eval file {}", script.get_path());
-
-        script = QuickJsRuntime::pre_process(script)?;
-
-        let filename_c = make_cstring(script.get_path())?;
-        let code_c = make_cstring(script.get_code())?;
-
-        let value_raw = q::JS_Eval(
-            context,
-            code_c.as_ptr(),
-            script.get_code().len() as _,
-            filename_c.as_ptr(),
-            q::JS_EVAL_TYPE_GLOBAL as i32,
-        );
-
-        log::trace!("after eval, checking error");
+        let options = EvalOptions {
+            strict: QuickJsRuntime::do_with(|q_js_rt| q_js_rt.default_strict_mode),
+            ..Default::default()
+        };
+        Self::eval_with_options_ctx(context, script, &options)
+    }
 
-        // check for error
-        let ret = JSValueRef::new(
-            context,
-            value_raw,
-            false,
-            true,
-            format!("eval result of {}", script.get_path()).as_str(),
-        );
-        if ret.is_exception() {
-            let ex_opt = Self::get_exception(context);
-            if let Some(ex) = ex_opt {
-                log::debug!("eval_ctx failed: {}", ex);
-                Err(ex)
-            } else {
-                Err(JsError::new_str("eval failed and could not get exception"))
-            }
-        } else {
-            Ok(ret)
-        }
+    /// evaluate a script in strict mode
+    pub fn eval_strict(&self, script: Script) -> Result<JSValueRef, JsError> {
+        let options = EvalOptions {
+            strict: true,
+            ..Default::default()
+        };
+        self.eval_with_options(script, &options)
     }
 
     /// evaluate a Module
@@ -163,10 +323,39 @@ impl QuickJsContext {
     /// # Safety
     /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
     pub unsafe fn eval_module_ctx(
+        context: *mut q::JSContext,
+        script: Script,
+    ) -> Result<JSValueRef, JsError> {
+        let options = EvalOptions {
+            eval_type: EvalType::Module,
+            ..Default::default()
+        };
+        Self::eval_with_options_ctx(context, script, &options)
+    }
+
+    /// evaluate a script or module with specific options
+    pub fn eval_with_options(
+        &self,
+        script: Script,
+        options: &EvalOptions,
+    ) -> Result<JSValueRef, JsError> {
+        unsafe { Self::eval_with_options_ctx(self.context, script, options) }
+    }
+
+    /// # Safety
+    /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
+    pub unsafe fn eval_with_options_ctx(
         context: *mut q::JSContext,
         mut script: Script,
+        options: &EvalOptions,
     ) -> Result<JSValueRef, JsError> {
-        log::debug!("q_js_rt.eval_module file {}", script.get_path());
+        log::debug!(
+            "q_js_rt.eval_with_options file {} as {:?}",
+            script.get_path(),
+            options.eval_type
+        );
+
+        let eval_guard = EvalGuard::enter(context)?;
 
         script = QuickJsRuntime::pre_process(script)?;
 
@@ -178,35 +367,45 @@ impl QuickJsContext {
             code_c.as_ptr(),
             script.get_code().len() as _,
             filename_c.as_ptr(),
-            q::JS_EVAL_TYPE_MODULE as i32,
+            options.get_flags(),
         );
 
+        log::trace!("after eval, checking error");
+
+        // check for error
         let ret = JSValueRef::new(
             context,
             value_raw,
             false,
             true,
-            format!("eval_module result of {}", script.get_path()).as_str(),
+            format!("eval result of {}", script.get_path()).as_str(),
         );
-
-        log::trace!("evalled module yielded a {}", ret.borrow_value().tag);
-
-        // check for error
-
         if ret.is_exception() {
             let ex_opt = Self::get_exception(context);
-            if let Some(ex) = ex_opt {
-                log::debug!("eval_module_ctx failed: {}", ex);
+            if let Some(timeout_err) = eval_guard.deadline.timeout_error() {
+                Err(timeout_err)
+            } else if let Some(ex) = ex_opt {
+                log::debug!("eval_with_options_ctx failed: {}", ex);
                 Err(ex)
             } else {
-                Err(JsError::new_str(
-                    "eval_module failed and could not get exception",
-                ))
+                Err(JsError::new_str("eval failed and could not get exception"))
             }
         } else {
+            if options.eval_type == EvalType::Module && !options.compile_only {
+                Self::with_context(context, |q_ctx| {
+                    let evaluated_modules = &mut *q_ctx.evaluated_modules.borrow_mut();
+                    evaluated_modules.insert(script.get_path().to_string());
+                });
+            }
+            eval_guard.deadline.run_pending_jobs()?;
             Ok(ret)
         }
     }
+    /// get the namespace object (containing all exports) of a module, modules which were already loaded are not
+    /// evaluated again
+    pub fn get_module_namespace(&self, module_name: &str) -> Result<JSValueRef, JsError> {
+        modules::get_module_namespace_q(self, module_name)
+    }
     /// throw an internal error to quickjs and create a new ex obj
     pub fn report_ex(&self, err: &str) -> q::JSValue {
         unsafe { Self::report_ex_ctx(self.context, err) }
@@ -228,6 +427,11 @@ impl QuickJsContext {
         unsafe { errors::get_exception(self.context) }
     }
 
+    /// Get the last exception from the runtime, and if present, convert it to a String.
+    pub fn get_exception_string(&self) -> Option<String> {
+        unsafe { errors::get_exception_string(self.context) }
+    }
+
     /// Get the last exception from the runtime, and if present, convert it to a JsError.
     /// # Safety
     /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -235,6 +439,24 @@ impl QuickJsContext {
         errors::get_exception(context)
     }
 
+    /// take the pending exception (if any) from the context, this leaves the context in a clean state for the next call
+    /// returns None if there was no pending exception
+    pub fn clear_exception(&self) -> Option<JSValueRef> {
+        let exception_val = unsafe { q::JS_GetException(self.context) };
+        let exception_ref = JSValueRef::new(
+            self.context,
+            exception_val,
+            false,
+            true,
+            "QuickJsContext::clear_exception",
+        );
+        if exception_ref.is_null() {
+            None
+        } else {
+            Some(exception_ref)
+        }
+    }
+
     pub fn cache_object(&self, obj: JSValueRef) -> i32 {
         let cache_map = &mut *self.object_cache.borrow_mut();
         let id = cache_map.insert(obj) as i32;
@@ -573,9 +795,58 @@ pub mod tests {
     use crate::esruntime::tests::init_test_rt;
     use crate::esruntimebuilder::EsRuntimeBuilder;
     use crate::quickjs_utils;
-    use crate::quickjs_utils::primitives::to_i32;
+    use crate::quickjs_utils::primitives::{from_i32, to_i32};
     use crate::quickjs_utils::{functions, get_global_q, objects};
     use hirofa_utils::js_utils::Script;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_max_eval_depth() {
+        let rt = EsRuntimeBuilder::new().max_eval_depth(3).build();
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let rust_eval_func = functions::new_function_q(
+                q_ctx,
+                "rustEval",
+                |q_ctx, _this_ref, args| {
+                    let depth = to_i32(&args[0])?;
+                    q_ctx.eval(Script::new(
+                        "test_max_eval_depth_nested.es",
+                        format!("jsFn({});", depth + 1).as_str(),
+                    ))
+                },
+                1,
+            )
+            .ok()
+            .expect("could not create function");
+            let global = get_global_q(q_ctx);
+            objects::set_property_q(q_ctx, &global, "rustEval", &rust_eval_func)
+                .ok()
+                .expect("could not set prop");
+            let res = q_ctx
+                .eval(Script::new(
+                    "test_max_eval_depth.es",
+                    "function jsFn(d) {try {return rustEval(d);} catch(e) {return e.name + ':' + d;}}; jsFn(1);",
+                ))
+                .ok()
+                .expect("script failed");
+            let first = quickjs_utils::primitives::to_string_q(q_ctx, &res)
+                .ok()
+                .expect("could not convert to string");
+            // the depth is reset after the nested evals completed
+            let res = q_ctx
+                .eval(Script::new("test_max_eval_depth2.es", "jsFn(2);"))
+                .ok()
+                .expect("script failed");
+            let second = quickjs_utils::primitives::to_string_q(q_ctx, &res)
+                .ok()
+                .expect("could not convert to string");
+            (first, second)
+        });
+        // the 4th nested eval fails, jsFn(2) starts one level deeper so it fails one call later
+        assert_eq!(res.0.as_str(), "RangeError:3");
+        assert_eq!(res.1.as_str(), "RangeError:4");
+    }
 
     #[test]
     fn test_eval() {
@@ -598,6 +869,28 @@ pub mod tests {
         });
     }
 
+    #[test]
+    fn test_eval_strict() {
+        let rt = init_test_rt();
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            q_ctx
+                .eval_strict(Script::new("test_strict.es", "strict_undeclared = 1;"))
+                .err()
+                .map(|e| e.get_name().to_string())
+        });
+        assert_eq!(res, Some("ReferenceError".to_string()));
+        let res = rt.eval_sync(Script::new("test_strict2.es", "sloppy_undeclared = 1;"));
+        assert!(res.is_ok());
+
+        let rt = EsRuntimeBuilder::new().default_strict_mode(true).build();
+        let res = rt.eval_sync(Script::new("test_strict3.es", "strict_undeclared = 1;"));
+        assert_eq!(
+            res.err().expect("script did not fail").get_name(),
+            "ReferenceError"
+        );
+    }
+
     #[test]
     fn test_multi_ctx() {
         let rt = EsRuntimeBuilder::new().build();
@@ -691,4 +984,84 @@ pub mod tests {
             q_js_rt.gc();
         });
     }
+
+    #[test]
+    fn test_get_global_this() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            q_ctx
+                .eval(Script::new("test_get_global_this.es", "this.gt_a = 123;"))
+                .ok()
+                .expect("script failed");
+            let global_this = q_ctx.get_global_this().ok().expect("no globalThis");
+            assert!(global_this.is_object());
+            let a = objects::get_property_q(q_ctx, &global_this, "gt_a")
+                .ok()
+                .expect("could not get gt_a");
+            assert_eq!(to_i32(&a).ok().expect("not an i32"), 123);
+            objects::set_property_q(q_ctx, &global_this, "gt_b", &from_i32(456))
+                .ok()
+                .expect("could not set gt_b");
+            let b = q_ctx
+                .eval(Script::new("test_get_global_this2.es", "(gt_b);"))
+                .ok()
+                .expect("script failed");
+            assert_eq!(to_i32(&b).ok().expect("not an i32"), 456);
+        });
+    }
+
+    #[test]
+    fn test_clear_exception() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            assert!(q_ctx.clear_exception().is_none());
+
+            let func_ref = q_ctx
+                .eval(Script::new(
+                    "test_clear_exception.es",
+                    "(function(){throw Error('clear me');});",
+                ))
+                .ok()
+                .expect("script failed");
+            // call the function with the raw api so the exception stays pending in the context
+            let res = unsafe {
+                libquickjs_sys::JS_Call(
+                    q_ctx.context,
+                    *func_ref.borrow_value(),
+                    quickjs_utils::new_null(),
+                    0,
+                    std::ptr::null_mut(),
+                )
+            };
+            assert_eq!(res.tag, crate::valueref::TAG_EXCEPTION);
+
+            let ex = q_ctx.clear_exception().expect("no pending exception");
+            assert!(quickjs_utils::errors::is_error_q(q_ctx, &ex));
+            assert!(q_ctx.clear_exception().is_none());
+
+            let v = q_ctx
+                .eval(Script::new("test_clear_exception2.es", "(1 + 2);"))
+                .ok()
+                .expect("script failed");
+            assert_eq!(to_i32(&v).ok().expect("not an i32"), 3);
+        });
+    }
+
+    #[test]
+    fn test_get_runtime_ref() {
+        let rt = init_test_rt();
+        rt.create_context("rt_ref_ctx")
+            .ok()
+            .expect("could not create context");
+        let rt2 = rt.clone();
+        let same = rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            let q_ctx = q_js_rt.get_context("rt_ref_ctx");
+            let rt_ref = q_ctx.get_runtime_ref().expect("no runtime ref");
+            Arc::ptr_eq(&rt_ref, &rt2)
+        });
+        assert!(same);
+        rt.drop_context("rt_ref_ctx");
+    }
 }
@@ -1,12 +1,19 @@
 // store in thread_local
 
 use crate::esruntime::EsRuntime;
+use crate::features::console::{ConsoleHandler, StructuredConsoleHandler};
+use crate::quickjs_utils::debugger::{DebugEvent, DebugHook};
 use crate::quickjs_utils::modules::{
     add_module_export, compile_module, get_module_def, get_module_name, new_module,
     set_module_export,
 };
+use crate::quickjs_utils::profiler::{ProfileReport, Profiler};
 use crate::quickjs_utils::promises::PromiseRef;
-use crate::quickjs_utils::{gc, interrupthandler, modules, promises};
+use crate::quickjs_utils::sourcemaps::{SourceMap, SourceMapRegistry};
+use crate::quickjs_utils::{
+    compile, debugger, errors, functions, gc, get_global_q, interrupthandler, memory_usage,
+    modules, objects, primitives, profiler, promises, sourcemaps,
+};
 use crate::quickjscontext::QuickJsContext;
 use crate::valueref::JSValueRef;
 use hirofa_utils::js_utils::adapters::JsRuntimeAdapter;
@@ -14,12 +21,15 @@ use hirofa_utils::js_utils::JsError;
 use hirofa_utils::js_utils::Script;
 use hirofa_utils::js_utils::ScriptPreProcessor;
 use libquickjs_sys as q;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::os::raw::c_int;
 use std::panic;
-use std::sync::{Arc, Weak};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 
 /// this is the internal abstract loader which is used to actually load the modules
 pub trait ModuleLoader {
@@ -82,6 +92,101 @@ impl ModuleLoader for ScriptModuleLoaderAdapter {
         script = QuickJsRuntime::pre_process(script)?;
 
         let compiled_module = unsafe { compile_module(q_ctx.context, script)? };
+        QuickJsRuntime::do_with(|q_js_rt| {
+            if let Some(cache) = &q_js_rt.module_cache {
+                let bytecode = unsafe { compile::to_bytecode(q_ctx.context, &compiled_module) };
+                cache.put(absolute_path, bytecode);
+            }
+        });
+        Ok(get_module_def(&compiled_module))
+    }
+
+    fn has_module(&self, q_ctx: &QuickJsContext, absolute_path: &str) -> bool {
+        self.normalize_path(q_ctx, absolute_path, absolute_path)
+            .is_some()
+    }
+
+    unsafe fn init_module(
+        &self,
+        _q_ctx: &QuickJsContext,
+        _module: *mut q::JSModuleDef,
+    ) -> Result<(), JsError> {
+        Ok(())
+    }
+}
+
+/// a cache for the bytecode of compiled script modules, modules which are in the cache are not loaded again by the
+/// ScriptModuleLoaders, not even when they are imported in a new context
+pub trait ModuleCache {
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+    fn put(&self, name: &str, bytecode: Vec<u8>);
+}
+
+/// a ModuleCache which keeps all bytecode in memory
+#[derive(Default)]
+pub struct MemoryModuleCache {
+    modules: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryModuleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ModuleCache for MemoryModuleCache {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        let modules = &*self.modules.lock().unwrap();
+        modules.get(name).cloned()
+    }
+
+    fn put(&self, name: &str, bytecode: Vec<u8>) {
+        let modules = &mut *self.modules.lock().unwrap();
+        modules.insert(name.to_string(), bytecode);
+    }
+}
+
+/// a module loader which provides modules as precompiled bytecode (see compile::to_bytecode)
+/// compiled module loaders are used before script module loaders
+pub trait CompiledModuleLoader {
+    fn normalize_path(&self, q_ctx: &QuickJsContext, ref_path: &str, path: &str) -> Option<String>;
+    fn load_module(&self, q_ctx: &QuickJsContext, absolute_path: &str) -> Result<Vec<u8>, JsError>;
+}
+
+pub struct CompiledModuleLoaderAdapter {
+    inner: Box<dyn CompiledModuleLoader>,
+}
+
+impl CompiledModuleLoaderAdapter {
+    pub fn new(loader: Box<dyn CompiledModuleLoader>) -> Self {
+        Self { inner: loader }
+    }
+}
+
+impl ModuleLoader for CompiledModuleLoaderAdapter {
+    fn normalize_path(&self, q_ctx: &QuickJsContext, ref_path: &str, path: &str) -> Option<String> {
+        self.inner.normalize_path(q_ctx, ref_path, path)
+    }
+
+    fn load_module(
+        &self,
+        q_ctx: &QuickJsContext,
+        absolute_path: &str,
+    ) -> Result<*mut q::JSModuleDef, JsError> {
+        let bytecode = self.inner.load_module(q_ctx, absolute_path)?;
+        if bytecode.is_empty() {
+            return Err(JsError::new_string(format!(
+                "no bytecode for module {}",
+                absolute_path
+            )));
+        }
+        let compiled_module = unsafe { compile::from_bytecode(q_ctx.context, bytecode)? };
+        if !compiled_module.is_module() {
+            return Err(JsError::new_string(format!(
+                "bytecode for {} is not a module",
+                absolute_path
+            )));
+        }
         Ok(get_module_def(&compiled_module))
     }
 
@@ -168,7 +273,7 @@ unsafe extern "C" fn native_module_init(
 
     QuickJsRuntime::do_with(|q_js_rt| {
         QuickJsContext::with_context(ctx, |q_ctx| {
-            if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
+            if let Some(res) = q_js_rt.with_context_module_loaders(q_ctx, |module_loader| {
                 if module_loader.has_module(q_ctx, module_name.as_str()) {
                     match module_loader.init_module(q_ctx, module) {
                         Ok(_) => {
@@ -205,6 +310,10 @@ pub trait NativeModuleLoader {
         q_ctx: &QuickJsContext,
         module_name: &str,
     ) -> Vec<(&str, JSValueRef)>;
+    /// list the names of the modules this loader can provide, used to inspect which native modules exist
+    fn list_modules(&self) -> Vec<String> {
+        vec![]
+    }
 }
 
 thread_local! {
@@ -218,19 +327,125 @@ thread_local! {
 
 }
 
+/// the default maximum number of pending jobs which are run after a task in the event queue
+pub const DEFAULT_MAX_JOBS_PER_TICK: usize = 1024;
+
+/// the default maximum stack size of a runtime (JS_DEFAULT_STACK_SIZE)
+pub const DEFAULT_MAX_STACK_SIZE: u64 = 256 * 1024;
+
+/// resource limits for a sandboxed eval, see [QuickJsRuntime::with_sandbox] and
+/// [EsRuntime::eval_sandboxed](crate::esruntime::EsRuntime::eval_sandboxed), limits which are None are not applied
+#[derive(Clone, Debug, Default)]
+pub struct SandboxConfig {
+    /// the number of bytes which may be allocated on top of what the runtime already uses
+    pub memory_bytes: Option<u64>,
+    /// the maximum stack size
+    pub stack_bytes: Option<u64>,
+    /// the maximum time the script may run
+    pub timeout_ms: Option<u64>,
+    /// the maximum number of objects in the runtime, please note that checking this requires computing the memory
+    /// usage of the runtime which is expensive for large heaps
+    pub max_objects: Option<u64>,
+}
+
+struct SandboxLimits {
+    start: Instant,
+    timeout_ms: Option<u64>,
+    max_objects: Option<u64>,
+    violation: Option<String>,
+}
+
+/// a frame of the call stack, see [QuickJsRuntime::get_call_stack]
+/// the column is 0 when it is not known
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallFrame {
+    pub function_name: String,
+    pub filename: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl CallFrame {
+    /// parse a line of a stack trace like `    at func (file.es:3)`, `    at func (file.es:3:7)` or `    at func (native)`
+    fn parse(line: &str) -> Option<Self> {
+        let frame = line.trim().strip_prefix("at ")?;
+        let (function_name, location) = match (frame.find(" ("), frame.ends_with(')')) {
+            (Some(idx), true) => (&frame[..idx], &frame[idx + 2..frame.len() - 1]),
+            _ => ("", frame),
+        };
+        let parts: Vec<&str> = location.rsplitn(3, ':').collect();
+        // parts are reversed, either [path], [line, path] or [col, line, path]
+        let (filename, line, column) = match parts.as_slice() {
+            [col, line, path] => match (line.parse::<u32>(), col.parse::<u32>()) {
+                (Ok(line), Ok(col)) => (path.to_string(), line, col),
+                _ => (
+                    format!("{}:{}", path, line),
+                    col.parse::<u32>().unwrap_or(0),
+                    0,
+                ),
+            },
+            [line, path] => match line.parse::<u32>() {
+                Ok(line) => (path.to_string(), line, 0),
+                Err(_) => (location.to_string(), 0, 0),
+            },
+            _ => (location.to_string(), 0, 0),
+        };
+        Some(Self {
+            function_name: function_name.to_string(),
+            filename,
+            line,
+            column,
+        })
+    }
+}
+
 pub type ContextInitHooks =
     Vec<Box<dyn Fn(&QuickJsRuntime, &QuickJsContext) -> Result<(), JsError>>>;
 
+pub type ContextDropHooks = Vec<Box<dyn Fn(&QuickJsContext)>>;
+
+/// a function which normalizes a module specifier (as used in an import statement) to a canonical module name
+/// the first argument is the name of the importing module, the second is the specifier
+pub type ModuleSpecifierNormalizer = dyn Fn(&str, &str) -> String + Send + Sync;
+
+/// a function which transpiles TypeScript source to JavaScript, see
+/// [EsRuntimeBuilder::typescript_transpiler](crate::esruntimebuilder::EsRuntimeBuilder::typescript_transpiler)
+pub type TypeScriptTranspiler = dyn Fn(&str) -> Result<String, JsError> + Send + Sync;
+
 pub struct QuickJsRuntime {
     pub(crate) runtime: *mut q::JSRuntime,
     contexts: HashMap<String, QuickJsContext>,
     es_rt_ref: Option<Weak<EsRuntime>>,
     id: String,
     context_init_hooks: RefCell<ContextInitHooks>,
+    context_drop_hooks: RefCell<ContextDropHooks>,
     script_module_loaders: Vec<ScriptModuleLoaderAdapter>,
+    compiled_module_loaders: Vec<CompiledModuleLoaderAdapter>,
     native_module_loaders: Vec<NativeModuleLoaderAdapter>,
+    context_native_module_loaders: HashMap<String, Vec<NativeModuleLoaderAdapter>>,
     pub(crate) script_pre_processors: Vec<Box<dyn ScriptPreProcessor + Send>>,
     pub(crate) interrupt_handler: Option<Box<dyn Fn(&QuickJsRuntime) -> bool>>,
+    pub(crate) module_cache: Option<Box<dyn ModuleCache + Send + Sync>>,
+    source_maps: RefCell<SourceMapRegistry>,
+    cancel_flags: RefCell<Vec<Arc<AtomicBool>>>,
+    pub(crate) max_jobs_per_tick: usize,
+    pub(crate) default_strict_mode: bool,
+    pub(crate) max_eval_depth: Option<usize>,
+    pub(crate) module_base_path: Option<PathBuf>,
+    pub(crate) module_specifier_normalizer: Option<Box<ModuleSpecifierNormalizer>>,
+    pub(crate) typescript_transpiler: Option<Box<TypeScriptTranspiler>>,
+    /// the current max stack size of the runtime, QuickJS has no getter for it so it is tracked here
+    pub(crate) max_stack_size: Cell<u64>,
+    sandbox_limits: RefCell<Option<SandboxLimits>>,
+    context_eval_timeouts: RefCell<HashMap<String, Duration>>,
+    eval_deadlines: RefCell<Vec<Instant>>,
+    pub(crate) console_handler: RefCell<Option<Arc<ConsoleHandler>>>,
+    pub(crate) structured_console_handler: Option<Box<StructuredConsoleHandler>>,
+    debug_hook: RefCell<Option<Arc<DebugHook>>>,
+    breakpoints: RefCell<HashSet<(String, u32)>>,
+    debug_stepping: Cell<bool>,
+    profiler: RefCell<Option<Profiler>>,
+    profiler_installed: Cell<bool>,
 }
 
 impl QuickJsRuntime {
@@ -241,15 +456,74 @@ impl QuickJsRuntime {
         })
     }
 
-    pub(crate) fn pre_process(mut script: Script) -> Result<Script, JsError> {
+    pub(crate) fn pre_process(script: Script) -> Result<Script, JsError> {
+        Self::pre_process_opt(script, true)
+    }
+
+    /// pre process a script which is only compiled to check it for errors, the inline source map is stripped but not
+    /// registered and the script is not instrumented for the profiler or the debugger
+    /// the ScriptPreProcessors are called like for any other script so they should not have side effects
+    pub(crate) fn pre_process_for_validation(script: Script) -> Result<Script, JsError> {
+        Self::pre_process_opt(script, false)
+    }
+
+    fn pre_process_opt(mut script: Script, for_eval: bool) -> Result<Script, JsError> {
         Self::do_with(|q_js_rt| {
+            if let Some(transpiler) = q_js_rt.typescript_transpiler.as_ref() {
+                let path = script.get_path();
+                if path.ends_with(".ts") || path.ends_with(".tsx") {
+                    let code = transpiler(script.get_code())?;
+                    script = Script::new(path, code.as_str());
+                }
+            }
             for pp in &q_js_rt.script_pre_processors {
                 pp.process(&mut script)?;
             }
+            if let Some((code, map_json)) = sourcemaps::extract_inline_source_map(script.get_code())
+            {
+                if for_eval {
+                    q_js_rt.add_source_map(script.get_path(), map_json.as_str())?;
+                }
+                script = Script::new(script.get_path(), code.as_str());
+            }
+            if !for_eval {
+                return Ok(script);
+            }
+            if q_js_rt.profiler.borrow().is_some() {
+                let code = profiler::instrument(script.get_path(), script.get_code());
+                script = Script::new(script.get_path(), code.as_str());
+            }
+            if q_js_rt.debug_hook.borrow().is_some() {
+                let code = debugger::instrument(script.get_path(), script.get_code());
+                script = Script::new(script.get_path(), code.as_str());
+            }
             Ok(script)
         })
     }
 
+    /// register a source map (json) for a script path, stack traces of errors thrown from that script will be mapped to the original sources
+    /// at most [MAX_SOURCE_MAPS](crate::quickjs_utils::sourcemaps::MAX_SOURCE_MAPS) maps are kept, the oldest are removed first
+    pub fn add_source_map(&self, path: &str, source_map_json: &str) -> Result<(), JsError> {
+        let source_map = SourceMap::parse(source_map_json)?;
+        let maps = &mut *self.source_maps.borrow_mut();
+        maps.insert(path, source_map);
+        Ok(())
+    }
+
+    /// remove the source map of a script path, returns false if no source map was registered for the path
+    pub fn remove_source_map(&self, path: &str) -> bool {
+        let maps = &mut *self.source_maps.borrow_mut();
+        maps.remove(path)
+    }
+
+    pub(crate) fn with_source_maps<C, R>(&self, consumer: C) -> R
+    where
+        C: FnOnce(&SourceMapRegistry) -> R,
+    {
+        let maps = &*self.source_maps.borrow();
+        consumer(maps)
+    }
+
     pub fn add_context_init_hook<H>(&self, hook: H) -> Result<(), JsError>
     where
         H: Fn(&QuickJsRuntime, &QuickJsContext) -> Result<(), JsError> + 'static,
@@ -262,6 +536,17 @@ impl QuickJsRuntime {
         hooks.push(Box::new(hook));
         Ok(())
     }
+
+    /// add a hook which is called just before a context is dropped (see [remove_context](QuickJsRuntime::remove_context)),
+    /// this may be used to clean up resources (like cached objects) of a context while it is still valid
+    /// the hook is called for every context which is dropped after it was added
+    pub fn add_context_drop_hook<H>(&self, hook: H)
+    where
+        H: Fn(&QuickJsContext) + 'static,
+    {
+        let hooks = &mut *self.context_drop_hooks.borrow_mut();
+        hooks.push(Box::new(hook));
+    }
     // todo, this needs to be static, create a context, then borrowmut and add it (do not borrow mut while instantiating context)
     // so actually needs to be called in a plain job to inner.TaskManager and not by add_to_esEventquueue
     // EsRuntime should have a util to do that
@@ -285,19 +570,60 @@ impl QuickJsRuntime {
             Ok(())
         })
     }
+    /// create a new context and copy all own enumerable properties of the global object of an existing context to it
+    /// values are copied with [objects::clone_to_context](crate::quickjs_utils::objects::clone_to_context) so
+    /// changes in the new context do not affect the source context, properties which can not be copied (like
+    /// functions) and properties which already exist in the new context (like features) are skipped
+    pub fn clone_context(src_id: &str, dst_id: &str) -> Result<(), JsError> {
+        if !Self::do_with(|q_js_rt| q_js_rt.has_context(src_id)) {
+            return Err(JsError::new_string(format!("no such context: {}", src_id)));
+        }
+        Self::create_context(dst_id)?;
+
+        Self::do_with(|q_js_rt| {
+            let src_ctx = q_js_rt.get_context(src_id);
+            let dst_ctx = q_js_rt.get_context(dst_id);
+            let src_global_ref = get_global_q(src_ctx);
+            let dst_global_ref = get_global_q(dst_ctx);
+            objects::traverse_properties_q(src_ctx, &src_global_ref, |name, value_ref| {
+                let existing_ref = objects::get_property_q(dst_ctx, &dst_global_ref, name)?;
+                if !existing_ref.is_undefined() {
+                    return Ok(());
+                }
+                match objects::clone_to_context_q(src_ctx, dst_ctx, value_ref) {
+                    Ok(clone_ref) => {
+                        objects::set_property_q(dst_ctx, &dst_global_ref, name, &clone_ref)
+                    }
+                    Err(e) => {
+                        log::debug!("clone_context: skipping global {}: {}", name, e);
+                        Ok(())
+                    }
+                }
+            })?;
+            Ok(())
+        })
+    }
+
     pub fn remove_context(id: &str) {
         log::debug!("QuickJsRuntime::drop_context: {}", id);
 
         QuickJsRuntime::do_with(|rt| {
             let q_ctx = rt.get_context(id);
+            let hooks = &*rt.context_drop_hooks.borrow();
+            for hook in hooks {
+                hook(q_ctx);
+            }
             log::trace!("QuickJsRuntime::q_ctx.free: {}", id);
             q_ctx.free();
             log::trace!("after QuickJsRuntime::q_ctx.free: {}", id);
             rt.gc();
         });
 
-        let ctx =
-            QuickJsRuntime::do_with_mut(|m_rt| m_rt.contexts.remove(id).expect("no such context"));
+        let ctx = QuickJsRuntime::do_with_mut(|m_rt| {
+            m_rt.context_native_module_loaders.remove(id);
+            m_rt.context_eval_timeouts.get_mut().remove(id);
+            m_rt.contexts.remove(id).expect("no such context")
+        });
 
         drop(ctx);
     }
@@ -354,14 +680,38 @@ impl QuickJsRuntime {
             es_rt_ref: None,
             id,
             context_init_hooks: RefCell::new(vec![]),
+            context_drop_hooks: RefCell::new(vec![]),
             script_module_loaders: vec![],
+            compiled_module_loaders: vec![],
             native_module_loaders: vec![],
+            context_native_module_loaders: HashMap::new(),
+            module_cache: None,
             script_pre_processors: vec![],
             interrupt_handler: None,
+            source_maps: RefCell::new(SourceMapRegistry::default()),
+            cancel_flags: RefCell::new(vec![]),
+            max_jobs_per_tick: DEFAULT_MAX_JOBS_PER_TICK,
+            default_strict_mode: false,
+            max_eval_depth: None,
+            module_base_path: None,
+            module_specifier_normalizer: None,
+            typescript_transpiler: None,
+            max_stack_size: Cell::new(DEFAULT_MAX_STACK_SIZE),
+            sandbox_limits: RefCell::new(None),
+            context_eval_timeouts: RefCell::new(HashMap::new()),
+            eval_deadlines: RefCell::new(vec![]),
+            console_handler: RefCell::new(None),
+            structured_console_handler: None,
+            debug_hook: RefCell::new(None),
+            breakpoints: RefCell::new(HashSet::new()),
+            debug_stepping: Cell::new(false),
+            profiler: RefCell::new(None),
+            profiler_installed: Cell::new(false),
         };
 
         modules::set_module_loader(&q_rt);
         promises::init_promise_rejection_tracker(&q_rt);
+        errors::init(&q_rt);
 
         let main_ctx = QuickJsContext::new("__main__".to_string(), &q_rt);
         q_rt.contexts.insert("__main__".to_string(), main_ctx);
@@ -378,19 +728,335 @@ impl QuickJsRuntime {
         self
     }
 
+    /// set a hook which is called when a breakpoint is hit or, while stepping is enabled, a line is executed
+    /// only scripts which are evaluated after the hook was set can be debugged, see [debugger](crate::quickjs_utils::debugger) for the limitations
+    /// of how scripts are instrumented
+    pub fn set_debug_hook(&self, hook: Box<DebugHook>) -> Result<(), JsError> {
+        let was_set = self
+            .debug_hook
+            .borrow_mut()
+            .replace(Arc::from(hook))
+            .is_some();
+        if !was_set {
+            self.add_context_init_hook(|_q_js_rt, q_ctx| debugger::init_ctx(q_ctx))?;
+        }
+        Ok(())
+    }
+
+    /// call the debug hook with a Step event for every line which is executed
+    pub fn set_debug_stepping(&self, stepping: bool) {
+        self.debug_stepping.set(stepping);
+    }
+
+    /// add a breakpoint, the debug hook is called when the line is about to be executed
+    pub fn add_breakpoint(&self, filename: &str, line: u32) {
+        let breakpoints = &mut *self.breakpoints.borrow_mut();
+        breakpoints.insert((filename.to_string(), line));
+    }
+
+    pub fn remove_breakpoint(&self, filename: &str, line: u32) {
+        let breakpoints = &mut *self.breakpoints.borrow_mut();
+        breakpoints.remove(&(filename.to_string(), line));
+    }
+
+    pub(crate) fn debug_checkpoint(&self, filename: String, line: u32) {
+        let hook = self.debug_hook.borrow().clone();
+        if let Some(hook) = hook {
+            let is_breakpoint = self
+                .breakpoints
+                .borrow()
+                .contains(&(filename.clone(), line));
+            if is_breakpoint {
+                hook(&DebugEvent::Breakpoint { filename, line });
+            } else if self.debug_stepping.get() {
+                hook(&DebugEvent::Step { filename, line });
+            }
+        }
+    }
+
+    /// start profiling, all scripts which are evaluated from now on are instrumented so the calls of their functions
+    /// are recorded, see [profiler](crate::quickjs_utils::profiler) for the limitations of the instrumentation
+    /// starting while already profiling discards what was recorded until now
+    pub fn start_profiling(&self) -> Result<(), JsError> {
+        if !self.profiler_installed.get() {
+            self.add_context_init_hook(|_q_js_rt, q_ctx| profiler::init_ctx(q_ctx))?;
+            self.profiler_installed.set(true);
+        }
+        self.profiler.borrow_mut().replace(Profiler::default());
+        Ok(())
+    }
+
+    /// stop profiling and get the report of what was recorded
+    pub fn stop_profiling(&self) -> ProfileReport {
+        match self.profiler.borrow_mut().take() {
+            Some(profiler) => profiler.into_report(),
+            None => ProfileReport::default(),
+        }
+    }
+
+    pub(crate) fn with_profiler<C, R>(&self, consumer: C) -> Option<R>
+    where
+        C: FnOnce(&mut Profiler) -> R,
+    {
+        let profiler_opt = &mut *self.profiler.borrow_mut();
+        profiler_opt.as_mut().map(consumer)
+    }
+
+    /// add a cancel flag, while the flag is registered running scripts will be interrupted as soon as the flag is set
+    pub(crate) fn add_cancel_flag(&self, flag: Arc<AtomicBool>) {
+        interrupthandler::init(self);
+        let flags = &mut *self.cancel_flags.borrow_mut();
+        flags.push(flag);
+    }
+
+    pub(crate) fn remove_cancel_flag(&self, flag: &Arc<AtomicBool>) {
+        let flags = &mut *self.cancel_flags.borrow_mut();
+        flags.retain(|f| !Arc::ptr_eq(f, flag));
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        let flags = &*self.cancel_flags.borrow();
+        flags.iter().any(|f| f.load(Ordering::SeqCst))
+    }
+
+    /// run a consumer (which evaluates scripts) with resource limits, the limits are removed when the consumer is done
+    /// and the pending jobs (e.g. promise reactions) it added have run, so those jobs are limited as well
+    /// timers and other tasks which are added to the event queue do run without the limits
+    /// when a limit was exceeded an Err with name SandboxError is returned
+    /// the memory limit is enforced by QuickJS in which case the script fails with an out of memory error
+    pub fn with_sandbox<C, R>(&self, config: &SandboxConfig, consumer: C) -> Result<R, JsError>
+    where
+        C: FnOnce() -> Result<R, JsError>,
+    {
+        let prev_memory_limit = memory_usage(self).malloc_limit;
+        // the previous limit may be the limit of an outer sandbox
+        let prev_stack_size = self.max_stack_size.get();
+        unsafe {
+            if let Some(memory_bytes) = config.memory_bytes {
+                let used = memory_usage(self).malloc_size as u64;
+                q::JS_SetMemoryLimit(self.runtime, (used + memory_bytes) as _);
+            }
+            if let Some(stack_bytes) = config.stack_bytes {
+                q::JS_SetMaxStackSize(self.runtime, stack_bytes as _);
+                self.max_stack_size.set(stack_bytes);
+            }
+        }
+        if config.timeout_ms.is_some() || config.max_objects.is_some() {
+            interrupthandler::init(self);
+        }
+        let prev_limits = self.sandbox_limits.replace(Some(SandboxLimits {
+            start: Instant::now(),
+            timeout_ms: config.timeout_ms,
+            max_objects: config.max_objects,
+            violation: None,
+        }));
+
+        let res = consumer();
+        self.run_pending_jobs_if_any();
+
+        let limits = self.sandbox_limits.replace(prev_limits);
+        unsafe {
+            q::JS_SetMemoryLimit(self.runtime, prev_memory_limit as _);
+            q::JS_SetMaxStackSize(self.runtime, prev_stack_size as _);
+        }
+        self.max_stack_size.set(prev_stack_size);
+        match (res, limits.and_then(|limits| limits.violation)) {
+            (_, Some(violation)) => Err(JsError::new(
+                "SandboxError".to_string(),
+                violation,
+                "".to_string(),
+            )),
+            (res, _) => res,
+        }
+    }
+
+    /// check the limits of the current sandbox, returns true if a limit was exceeded
+    pub(crate) fn is_sandbox_limit_exceeded(&self) -> bool {
+        let mut limits_opt = self.sandbox_limits.borrow_mut();
+        if let Some(limits) = limits_opt.as_mut() {
+            if limits.violation.is_some() {
+                return true;
+            }
+            if let Some(timeout_ms) = limits.timeout_ms {
+                if limits.start.elapsed() > Duration::from_millis(timeout_ms) {
+                    limits.violation =
+                        Some(format!("script exceeded the timeout of {} ms", timeout_ms));
+                    return true;
+                }
+            }
+            if let Some(max_objects) = limits.max_objects {
+                let obj_count = memory_usage(self).obj_count as u64;
+                if obj_count > max_objects {
+                    limits.violation = Some(format!(
+                        "script exceeded the maximum of {} objects",
+                        max_objects
+                    ));
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// set a timeout for every script which is evaluated and every function which is called (e.g. by a timer) in a
+    /// specific context, this may be used to give a sandboxed context a strict time limit while other contexts are
+    /// unrestricted
+    /// the promise jobs which are added by the script or function run within the same timeout
+    /// when the timeout is exceeded the script is interrupted and the eval fails with an Err with name TimeoutError
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use hirofa_utils::js_utils::Script;
+    /// use std::time::Duration;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.create_context("sandbox").ok().unwrap();
+    /// let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///     q_js_rt.set_context_eval_timeout("sandbox", Duration::from_millis(10));
+    ///     q_js_rt
+    ///         .get_context("sandbox")
+    ///         .eval(Script::new("loop.es", "while(true){}"))
+    ///         .map(|_| ())
+    /// });
+    /// assert_eq!(res.err().unwrap().get_name(), "TimeoutError");
+    /// ```
+    pub fn set_context_eval_timeout(&self, ctx_id: &str, timeout: Duration) {
+        interrupthandler::init(self);
+        let timeouts = &mut *self.context_eval_timeouts.borrow_mut();
+        timeouts.insert(ctx_id.to_string(), timeout);
+    }
+
+    /// start the deadline of an eval in a context which has an eval timeout, returns the timeout if a deadline was
+    /// started, in which case [end_eval_deadline](QuickJsRuntime::end_eval_deadline) should be called when the
+    /// eval is done
+    pub(crate) fn start_eval_deadline(&self, ctx_id: &str) -> Option<Duration> {
+        let timeout = *self.context_eval_timeouts.borrow().get(ctx_id)?;
+        let deadlines = &mut *self.eval_deadlines.borrow_mut();
+        deadlines.push(Instant::now() + timeout);
+        Some(timeout)
+    }
+
+    pub(crate) fn end_eval_deadline(&self) {
+        let deadlines = &mut *self.eval_deadlines.borrow_mut();
+        deadlines.pop();
+    }
+
+    /// check if the deadline of the innermost timed eval has passed
+    pub(crate) fn is_eval_deadline_exceeded(&self) -> bool {
+        let deadlines = &*self.eval_deadlines.borrow();
+        match deadlines.last() {
+            Some(deadline) => Instant::now() > *deadline,
+            None => false,
+        }
+    }
+
+    /// check if the deadline of any running timed eval has passed
+    pub(crate) fn is_any_eval_deadline_exceeded(&self) -> bool {
+        let now = Instant::now();
+        let deadlines = &*self.eval_deadlines.borrow();
+        deadlines.iter().any(|deadline| now > *deadline)
+    }
+
     pub fn add_script_module_loader(&mut self, sml: ScriptModuleLoaderAdapter) {
         self.script_module_loaders.push(sml);
     }
 
+    /// load a module from the ModuleCache, returns None if there is no cache or the module is not cached
+    pub(crate) fn load_cached_module(
+        &self,
+        q_ctx: &QuickJsContext,
+        absolute_path: &str,
+    ) -> Option<Result<*mut q::JSModuleDef, JsError>> {
+        let bytecode = self.module_cache.as_ref()?.get(absolute_path)?;
+        log::trace!("loading module {} from module cache", absolute_path);
+        Some(
+            unsafe { compile::from_bytecode(q_ctx.context, bytecode) }
+                .map(|compiled_module| get_module_def(&compiled_module)),
+        )
+    }
+
+    pub fn add_compiled_module_loader(&mut self, cml: CompiledModuleLoaderAdapter) {
+        self.compiled_module_loaders.push(cml);
+    }
+
     pub fn add_native_module_loader(&mut self, nml: NativeModuleLoaderAdapter) {
         self.native_module_loaders.push(nml);
     }
 
+    /// list the names of the modules provided by all native module loaders
+    pub fn list_native_modules(&self) -> Vec<String> {
+        self.native_module_loaders
+            .iter()
+            .flat_map(|loader| loader.inner.list_modules())
+            .collect()
+    }
+
+    /// get a snapshot of the current call stack, the innermost frame first
+    /// this is meant to be called from rust functions which are called from script, when no script is running an empty Vec is returned
+    pub fn get_call_stack(&self) -> Vec<CallFrame> {
+        let q_ctx = self.get_main_context();
+        let stack_res = objects::get_property_q(q_ctx, &get_global_q(q_ctx), "Error")
+            .and_then(|error_constructor| {
+                functions::call_constructor_q(q_ctx, &error_constructor, &[])
+            })
+            .and_then(|error_ref| objects::get_property_q(q_ctx, &error_ref, "stack"))
+            .and_then(|stack_ref| primitives::to_string_q(q_ctx, &stack_ref));
+        match stack_res {
+            Ok(stack) => stack
+                .lines()
+                .filter_map(CallFrame::parse)
+                // skip the frame of the Error constructor
+                .skip_while(|frame| frame.function_name == "Error" && frame.filename == "native")
+                .collect(),
+            Err(e) => {
+                log::error!("could not get call stack: {}", e);
+                vec![]
+            }
+        }
+    }
+
     pub fn get_main_context(&self) -> &QuickJsContext {
         // todo store this somewhere so we don't need a lookup in the map every time
         self.get_context("__main__")
     }
 
+    /// add a NativeModuleLoader which only provides modules for a single context, context loaders are used before the
+    /// loaders of the runtime
+    pub fn add_context_native_module_loader(
+        &mut self,
+        ctx_id: &str,
+        nml: NativeModuleLoaderAdapter,
+    ) -> Result<(), JsError> {
+        if !self.has_context(ctx_id) {
+            return Err(JsError::new_string(format!("no such context: {}", ctx_id)));
+        }
+        self.context_native_module_loaders
+            .entry(ctx_id.to_string())
+            .or_insert_with(Vec::new)
+            .push(nml);
+        Ok(())
+    }
+
+    /// run a consumer for the module loaders of a context and then for all module loaders of the runtime until it
+    /// returns Some
+    pub fn with_context_module_loaders<C, R>(
+        &self,
+        q_ctx: &QuickJsContext,
+        consumer: C,
+    ) -> Option<R>
+    where
+        C: Fn(&dyn ModuleLoader) -> Option<R>,
+    {
+        if let Some(loaders) = self.context_native_module_loaders.get(&q_ctx.id) {
+            for loader in loaders {
+                let res = consumer(loader);
+                if res.is_some() {
+                    return res;
+                }
+            }
+        }
+        self.with_all_module_loaders(consumer)
+    }
+
     pub fn with_all_module_loaders<C, R>(&self, consumer: C) -> Option<R>
     where
         C: Fn(&dyn ModuleLoader) -> Option<R>,
@@ -401,6 +1067,12 @@ impl QuickJsRuntime {
                 return res;
             }
         }
+        for loader in &self.compiled_module_loaders {
+            let res = consumer(loader);
+            if res.is_some() {
+                return res;
+            }
+        }
         for loader in &self.script_module_loaders {
             let res = consumer(loader);
             if res.is_some() {
@@ -415,6 +1087,11 @@ impl QuickJsRuntime {
         gc(self);
     }
 
+    /// get memory usage statistics of the runtime
+    pub fn memory_usage(&self) -> q::JSMemoryUsage {
+        memory_usage(self)
+    }
+
     pub fn do_with<C, R>(task: C) -> R
     where
         C: FnOnce(&QuickJsRuntime) -> R,
@@ -462,11 +1139,43 @@ impl QuickJsRuntime {
         }
     }
 
+    /// run at most max pending jobs, returns the number of jobs which were actually run
+    /// this can be used instead of run_pending_jobs_if_any to prevent a script which keeps adding microtasks from starving other tasks
+    pub fn run_pending_jobs_limit(&self, max: usize) -> usize {
+        log::trace!("quick_js_rt.run_pending_jobs_limit({})", max);
+        let mut count = 0;
+        while count < max && self.has_pending_jobs() {
+            if let Err(e) = self.run_pending_job() {
+                log::error!("run_pending_job failed: {}", e);
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// check if there are pending jobs (e.g. promise reactions) in the job queue of the runtime
+    /// QuickJS does not expose the length of its job queue, use run_pending_jobs_limit to run a bounded number of jobs
     pub fn has_pending_jobs(&self) -> bool {
         let flag = unsafe { q::JS_IsJobPending(self.runtime) };
         flag > 0
     }
 
+    /// get the approximate number of pending jobs and tasks
+    /// QuickJS does not expose the length of its job queue so a non empty job queue counts as 1, added to that is the
+    /// number of tasks in the event queue of the EsRuntime which have not completed yet (including the task which is
+    /// currently running)
+    pub fn pending_job_count(&self) -> usize {
+        let queued = match self.get_rt_ref() {
+            Some(rt) => rt.get_todo_count(),
+            None => 0,
+        };
+        if self.has_pending_jobs() {
+            queued + 1
+        } else {
+            queued
+        }
+    }
+
     pub fn run_pending_job(&self) -> Result<(), JsError> {
         let mut ctx: *mut q::JSContext = std::ptr::null_mut();
         let flag = unsafe {
@@ -481,10 +1190,41 @@ impl QuickJsRuntime {
         Ok(())
     }
 
+    /// Get the last exception from the main context, and if present, convert it to a String.
+    pub fn get_exception_string(&self) -> Option<String> {
+        self.get_main_context().get_exception_string()
+    }
+
+    /// get the namespace object of a module in the main context, see QuickJsContext::get_module_namespace
+    pub fn get_module_namespace(&self, module_name: &str) -> Result<JSValueRef, JsError> {
+        self.get_main_context().get_module_namespace(module_name)
+    }
+
     pub fn get_id(&self) -> &str {
         self.id.as_str()
     }
 
+    /// resolve a relative module path (starting with ./ or ../) to an absolute path, relative to the path of the
+    /// importing module if that is absolute, or else relative to the module_base_path of the runtime
+    /// `.` and `..` components are removed from the resolved path, a path can not go above the root directory
+    /// returns the path as is when no module_base_path was set or if the path is not relative
+    pub fn resolve_module_path(&self, ref_path: &str, path: &str) -> String {
+        if let Some(base_path) = &self.module_base_path {
+            if path.starts_with("./") || path.starts_with("../") {
+                let ref_path = Path::new(ref_path);
+                let dir = if ref_path.is_absolute() {
+                    ref_path.parent().unwrap_or(base_path.as_path())
+                } else {
+                    base_path.as_path()
+                };
+                return normalize_path(dir.join(path).as_path())
+                    .to_string_lossy()
+                    .to_string();
+            }
+        }
+        path.to_string()
+    }
+
     /// this method tries to load a module script using the runtimes script_module loaders
     pub fn load_module_script_opt(&self, ref_path: &str, path: &str) -> Option<Script> {
         for loader in &self.script_module_loaders {
@@ -499,6 +1239,21 @@ impl QuickJsRuntime {
     }
 }
 
+/// remove the `.` and `..` components of a path without accessing the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
+}
+
 impl Drop for QuickJsRuntime {
     fn drop(&mut self) {
         // drop contexts first, should be done when Dropping EsRuntime?
@@ -549,8 +1304,14 @@ pub(crate) fn make_cstring(value: &str) -> Result<CString, JsError> {
 
 #[cfg(test)]
 pub mod tests {
+    use crate::esruntime::tests::init_test_rt;
     use crate::esruntimebuilder::EsRuntimeBuilder;
-    use crate::quickjsruntime::ScriptModuleLoader;
+    use crate::esvalue::{EsValueConvertible, EsValueFacade};
+    use crate::quickjs_utils::primitives;
+    use crate::quickjsruntime::{CallFrame, QuickJsRuntime, ScriptModuleLoader};
+    use hirofa_utils::js_utils::{JsError, Script};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     struct FooScriptModuleLoader {}
     impl ScriptModuleLoader for FooScriptModuleLoader {
@@ -564,6 +1325,104 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_clone_context() {
+        let rt = init_test_rt();
+        rt.create_context("clone_src")
+            .ok()
+            .expect("could not create context");
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt
+                .get_context("clone_src")
+                .eval(Script::new(
+                    "test_clone_context.es",
+                    "globalThis.cfg = {name: 'sandbox', limits: [1, 2, 3]};\n\
+                     globalThis.count = 5;\n\
+                     globalThis.greet = function() {return 'hi';};",
+                ))
+                .ok()
+                .expect("script failed");
+        });
+        rt.clone_context("clone_src", "clone_dst")
+            .ok()
+            .expect("clone_context failed");
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let src_ctx = q_js_rt.get_context("clone_src");
+            let dst_ctx = q_js_rt.get_context("clone_dst");
+            let res_ref = dst_ctx.eval(Script::new(
+                "test_clone_context2.es",
+                "let before = cfg.name + ':' + cfg.limits.join(',') + ':' + count + ':' + typeof greet;\n\
+                 cfg.name = 'changed';\n\
+                 count++;\n\
+                 before;",
+            ))?;
+            let dst_res = primitives::to_string_q(dst_ctx, &res_ref)?;
+            let src_ref = src_ctx.eval(Script::new(
+                "test_clone_context3.es",
+                "cfg.name + ':' + count;",
+            ))?;
+            let src_res = primitives::to_string_q(src_ctx, &src_ref)?;
+            Ok::<_, JsError>((dst_res, src_res))
+        });
+        let (dst_res, src_res) = res.ok().expect("script failed");
+        assert_eq!(dst_res, "sandbox:1,2,3:5:undefined");
+        assert_eq!(src_res, "sandbox:5");
+        rt.drop_context("clone_src");
+        rt.drop_context("clone_dst");
+    }
+
+    #[test]
+    fn test_context_drop_hook() {
+        let rt = init_test_rt();
+        let events = Arc::new(Mutex::new(vec![]));
+        let init_events = events.clone();
+        let drop_events = events.clone();
+        rt.exe_rt_task_in_event_loop(move |q_js_rt| {
+            q_js_rt
+                .add_context_init_hook(move |_q_js_rt, q_ctx| {
+                    init_events
+                        .lock()
+                        .unwrap()
+                        .push(format!("init {}", q_ctx.id));
+                    q_ctx
+                        .eval(Script::new(
+                            "test_context_drop_hook.es",
+                            "globalThis.dropHookMarker = 'marked';",
+                        ))
+                        .map(|_| ())
+                })
+                .ok()
+                .expect("could not add init hook");
+            q_js_rt.add_context_drop_hook(move |q_ctx| {
+                let marker = q_ctx
+                    .eval(Script::new(
+                        "test_context_drop_hook2.es",
+                        "globalThis.dropHookMarker;",
+                    ))
+                    .and_then(|marker_ref| primitives::to_string_q(q_ctx, &marker_ref))
+                    .unwrap_or_else(|e| e.get_message().to_string());
+                drop_events
+                    .lock()
+                    .unwrap()
+                    .push(format!("drop {} {}", q_ctx.id, marker));
+            });
+        });
+        rt.create_context("drop_hook_ctx")
+            .ok()
+            .expect("could not create context");
+        rt.drop_context("drop_hook_ctx");
+
+        let events = events.lock().unwrap();
+        let ctx_events: Vec<&String> = events
+            .iter()
+            .filter(|e| e.contains("drop_hook_ctx"))
+            .collect();
+        assert_eq!(
+            ctx_events,
+            vec!["init drop_hook_ctx", "drop drop_hook_ctx marked"]
+        );
+    }
+
     #[test]
     fn test_script_load() {
         log::debug!("testing1");
@@ -577,4 +1436,132 @@ pub mod tests {
             log::debug!("tested");
         });
     }
+
+    #[test]
+    fn test_call_frame_parse() {
+        assert_eq!(
+            CallFrame::parse("    at foo (test.es:3)"),
+            Some(CallFrame {
+                function_name: "foo".to_string(),
+                filename: "test.es".to_string(),
+                line: 3,
+                column: 0
+            })
+        );
+        assert_eq!(
+            CallFrame::parse("    at <eval> (c:/test.es:4:7)"),
+            Some(CallFrame {
+                function_name: "<eval>".to_string(),
+                filename: "c:/test.es".to_string(),
+                line: 4,
+                column: 7
+            })
+        );
+        assert_eq!(CallFrame::parse("Error"), None);
+    }
+
+    #[test]
+    fn test_get_call_stack() {
+        let rt = init_test_rt();
+        let frames: Arc<Mutex<Vec<CallFrame>>> = Arc::new(Mutex::new(vec![]));
+        let frames2 = frames.clone();
+        rt.set_function(
+            vec!["com", "test"],
+            "captureStack",
+            move |_q_ctx, _args: Vec<EsValueFacade>| {
+                let stack = QuickJsRuntime::do_with(|q_js_rt| q_js_rt.get_call_stack());
+                *frames2.lock().unwrap() = stack;
+                Ok(true.to_es_value_facade())
+            },
+        )
+        .ok()
+        .expect("set_function failed");
+        rt.eval_sync(Script::new(
+            "test_get_call_stack.es",
+            "function level1() {level2();}\n\
+             function level2() {level3();}\n\
+             function level3() {com.test.captureStack();}\n\
+             level1();",
+        ))
+        .ok()
+        .expect("script failed");
+
+        let frames = &*frames.lock().unwrap();
+        let names: Vec<&str> = frames
+            .iter()
+            .map(|frame| frame.function_name.as_str())
+            .collect();
+        let idx3 = names
+            .iter()
+            .position(|n| *n == "level3")
+            .expect("no level3");
+        let idx2 = names
+            .iter()
+            .position(|n| *n == "level2")
+            .expect("no level2");
+        let idx1 = names
+            .iter()
+            .position(|n| *n == "level1")
+            .expect("no level1");
+        assert!(idx3 < idx2 && idx2 < idx1);
+        assert_eq!(frames[idx3].filename, "test_get_call_stack.es");
+        assert_eq!(frames[idx3].line, 3);
+        assert!(rt
+            .exe_rt_task_in_event_loop(|q_js_rt| q_js_rt.get_call_stack())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_context_eval_timeout() {
+        let rt = EsRuntimeBuilder::new().build();
+        rt.create_context("timeout_ctx")
+            .ok()
+            .expect("could not create context");
+        let start = Instant::now();
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            q_js_rt.set_context_eval_timeout("timeout_ctx", Duration::from_millis(100));
+            q_js_rt
+                .get_context("timeout_ctx")
+                .eval(Script::new("test_context_eval_timeout.es", "while(true){}"))
+                .map(|_| ())
+        });
+        let err = res.err().expect("infinite loop was not interrupted");
+        assert_eq!(err.get_name(), "TimeoutError");
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // function calls and the promise jobs they add are limited as well
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_context("timeout_ctx");
+            q_ctx.eval(Script::new(
+                "test_context_eval_timeout3.es",
+                "globalThis.spin = function() {while(true){}};\n\
+                 globalThis.spinLater = function() {Promise.resolve().then(spin);};",
+            ))?;
+            let call_err = q_ctx
+                .call_function(vec![], "spin", vec![])
+                .err()
+                .expect("function call was not interrupted");
+            let job_err = q_ctx
+                .call_function(vec![], "spinLater", vec![])
+                .err()
+                .expect("promise job was not interrupted");
+            Ok::<_, JsError>((
+                call_err.get_name().to_string(),
+                job_err.get_name().to_string(),
+            ))
+        });
+        let (call_err_name, job_err_name) = res.ok().expect("could not define functions");
+        assert_eq!(call_err_name, "TimeoutError");
+        assert_eq!(job_err_name, "TimeoutError");
+
+        // the main context has no timeout
+        let res = rt
+            .eval_sync(Script::new(
+                "test_context_eval_timeout2.es",
+                "let s = Date.now(); while (Date.now() - s < 200) {} 1 + 1;",
+            ))
+            .ok()
+            .expect("script in main context failed");
+        assert_eq!(res.get_i32(), 2);
+    }
 }
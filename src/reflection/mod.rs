@@ -1,10 +1,11 @@
+use crate::esvalue::EsValueFacade;
 use crate::quickjs_utils;
 use crate::quickjs_utils::functions::new_native_function_q;
 use crate::quickjs_utils::primitives::from_string;
 use crate::quickjs_utils::{atoms, errors, functions, objects, parse_args, primitives};
 use crate::quickjscontext::QuickJsContext;
 use crate::quickjsruntime::QuickJsRuntime;
-use crate::valueref::JSValueRef;
+use crate::valueref::{JSValueRef, TAG_OBJECT};
 use hirofa_utils::js_utils::JsError;
 use libquickjs_sys as q;
 use log::trace;
@@ -226,6 +227,8 @@ pub struct Proxy {
     static_native_methods: HashMap<String, ProxyStaticNativeMethod>,
     static_getters_setters: HashMap<String, (Box<ProxyStaticGetter>, Box<ProxyStaticSetter>)>,
     getters_setters: HashMap<String, (Box<ProxyGetter>, Box<ProxySetter>)>,
+    static_properties: Vec<(String, EsValueFacade)>,
+    extends: Option<String>,
     is_event_target: bool,
     is_static_event_target: bool,
 }
@@ -242,6 +245,41 @@ pub fn get_proxy(q_ctx: &QuickJsContext, class_name: &str) -> Option<Rc<Proxy>>
     registry.get(class_name).cloned()
 }
 
+/// a ClassDefinition describes a Proxy class which can be registered with an EsRuntimeBuilder
+/// because a Proxy needs to be installed in every context separately the definition holds a factory
+/// which creates a new Proxy for every context
+/// # Example
+/// ```rust
+/// use quickjs_runtime::reflection::{ClassDefinition, Proxy};
+/// use quickjs_runtime::quickjs_utils::primitives;
+/// let def = ClassDefinition::new(|| {
+///     Proxy::new()
+///         .name("Greeter")
+///         .constructor(|_q_ctx, _id, _args| Ok(()))
+///         .method("greet", |q_ctx, _id, _args| primitives::from_string_q(q_ctx, "hello"))
+/// });
+/// ```
+pub struct ClassDefinition {
+    factory: Box<dyn Fn() -> Proxy + Send + Sync + 'static>,
+}
+
+impl ClassDefinition {
+    /// create a new ClassDefinition, the factory is called once for every context the class is installed in
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn() -> Proxy + Send + Sync + 'static,
+    {
+        Self {
+            factory: Box::new(factory),
+        }
+    }
+
+    /// create a new Proxy for this definition
+    pub fn create_proxy(&self) -> Proxy {
+        (self.factory)()
+    }
+}
+
 impl Proxy {
     #[allow(dead_code)]
     pub fn new() -> Self {
@@ -256,6 +294,8 @@ impl Proxy {
             static_native_methods: Default::default(),
             static_getters_setters: Default::default(),
             getters_setters: Default::default(),
+            static_properties: vec![],
+            extends: None,
             is_event_target: false,
             is_static_event_target: false,
         }
@@ -324,6 +364,7 @@ impl Proxy {
         self
     }
     /// add a method to the Proxy class, this method will be available as a member of instances of the Proxy class
+    /// methods for well-known symbols are added by the description of the symbol, e.g. `Symbol.asyncIterator`
     pub fn method<M>(mut self, name: &str, method: M) -> Self
     where
         M: Fn(&QuickJsContext, &usize, Vec<JSValueRef>) -> Result<JSValueRef, JsError> + 'static,
@@ -361,6 +402,43 @@ impl Proxy {
             .insert(name.to_string(), (Box::new(getter), Box::new(setter)));
         self
     }
+    /// add a static read-only getter to the Proxy class
+    pub fn static_getter<G>(self, name: &str, getter: G) -> Self
+    where
+        G: Fn(&QuickJsContext) -> Result<JSValueRef, JsError> + 'static,
+    {
+        let prop_name = name.to_string();
+        self.static_getter_setter(name, getter, move |_q_ctx, _val| {
+            Err(JsError::new_string(format!(
+                "static property {} is read-only",
+                prop_name
+            )))
+        })
+    }
+    /// add a static property to the Proxy class, the value is set on the class constructor when the class is installed
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::esvalue::EsValueConvertible;
+    /// use quickjs_runtime::reflection::Proxy;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///     let q_ctx = q_js_rt.get_main_context();
+    ///     Proxy::new()
+    ///         .name("Config")
+    ///         .static_property("VERSION", 3.to_es_value_facade())
+    ///         .install(q_ctx, true)
+    ///         .ok()
+    ///         .expect("install failed");
+    /// });
+    /// let res = rt.eval_sync(Script::new("static_prop.es", "Config.VERSION;")).ok().expect("script failed");
+    /// assert_eq!(res.get_i32(), 3);
+    /// ```
+    pub fn static_property(mut self, name: &str, value: EsValueFacade) -> Self {
+        self.static_properties.push((name.to_string(), value));
+        self
+    }
     /// add a getter and setter to the Proxy class, these will be available as a member of an instance of this Proxy class
     pub fn getter_setter<G, S>(mut self, name: &str, getter: G, setter: S) -> Self
     where
@@ -371,6 +449,34 @@ impl Proxy {
             .insert(name.to_string(), (Box::new(getter), Box::new(setter)));
         self
     }
+    /// make the Proxy class extend an existing class, the parent class is looked up by its canonical name (e.g. "com.hirofa.Animal") when the Proxy class is installed
+    /// members which are not defined by the Proxy class are looked up in the prototype chain of the parent class
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use quickjs_runtime::quickjs_utils::primitives;
+    /// use quickjs_runtime::reflection::Proxy;
+    /// use hirofa_utils::js_utils::Script;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync(Script::new("animal.es", "this.Animal = class Animal {speak() {return 'speaking';}};")).ok().expect("script failed");
+    /// rt.exe_rt_task_in_event_loop(|q_js_rt| {
+    ///     let q_ctx = q_js_rt.get_main_context();
+    ///     Proxy::new()
+    ///         .name("Dog")
+    ///         .extends("Animal")
+    ///         .constructor(|_q_ctx, _id, _args| Ok(()))
+    ///         .method("fetch", |q_ctx, _id, _args| primitives::from_string_q(q_ctx, "fetching"))
+    ///         .install(q_ctx, true)
+    ///         .ok()
+    ///         .expect("install failed");
+    /// });
+    /// let res = rt.eval_sync(Script::new("dog.es", "let d = new Dog(); d.speak() + '_' + d.fetch();")).ok().expect("script failed");
+    /// assert_eq!(res.get_str(), "speaking_fetching");
+    /// ```
+    pub fn extends(mut self, parent_class_name: &str) -> Self {
+        self.extends = Some(parent_class_name.to_string());
+        self
+    }
     /// indicate the Proxy class should implement the EventTarget interface, this will result in the addEventListener, removeEventListener and dispatchEvent methods to be available on instances of the Proxy class
     pub fn event_target(mut self) -> Self {
         self.is_event_target = true;
@@ -405,6 +511,23 @@ impl Proxy {
         });
 
         let class_ref = self.install_class_prop(q_ctx, add_variable_to_global)?;
+        if let Some(parent_class_name) = &self.extends {
+            let parent_ref =
+                objects::get_namespace_q(q_ctx, parent_class_name.split('.').collect(), false)?;
+            let parent_proto_ref = objects::get_property_q(q_ctx, &parent_ref, "prototype")?;
+            if !parent_proto_ref.is_object() {
+                return Err(JsError::new_string(format!(
+                    "{} is not a class",
+                    parent_class_name
+                )));
+            }
+            let proto_ref = objects::create_object_with_prototype_q(q_ctx, &parent_proto_ref)?;
+            objects::set_property2_q(q_ctx, &class_ref, "prototype", &proto_ref, 0)?;
+        }
+        for (name, mut value) in self.static_properties.drain(..) {
+            let value_ref = value.as_js_value(q_ctx)?;
+            objects::set_property_q(q_ctx, &class_ref, name.as_str(), &value_ref)?;
+        }
         eventtarget::impl_event_target(self).install_move_to_registry(q_ctx);
 
         Ok(class_ref)
@@ -556,7 +679,7 @@ pub(crate) fn new_instance3(
     );
 
     if class_val_ref.is_exception() {
-        return if let Some(e) = q_ctx.get_exception_ctx() {
+        return if let Some(e) = q_ctx.get_exception_string() {
             Err(JsError::new_string(format!(
                 "could not create class:{} due to: {}",
                 class_name, e
@@ -576,6 +699,7 @@ pub(crate) fn new_instance3(
         id: instance_id,
         class_name: proxy.get_class_name(),
         context_id: q_ctx.id.clone(),
+        value: *class_val_ref.borrow_value(),
     });
 
     let ibp: &mut ProxyInstanceInfo = &mut *bx;
@@ -643,7 +767,11 @@ unsafe extern "C" fn constructor(
 
                 match constructor_res {
                     Ok(()) => {
-                        let instance_ref_res = new_instance3(proxy, instance_id, q_ctx);
+                        let instance_ref_res =
+                            new_instance3(proxy, instance_id, q_ctx).and_then(|instance_ref| {
+                                set_instance_prototype(context, &this_ref, &instance_ref)?;
+                                Ok(instance_ref)
+                            });
 
                         match instance_ref_res {
                             Ok(instance_ref) => instance_ref.clone_value_incr_rc(),
@@ -669,10 +797,38 @@ unsafe extern "C" fn constructor(
     })
 }
 
+/// set the prototype of a new instance of a Proxy class to the prototype of the constructor
+/// the constructor only has a prototype if the class extends another class or if members were added to the prototype later
+unsafe fn set_instance_prototype(
+    context: *mut q::JSContext,
+    constructor_ref: &JSValueRef,
+    instance_ref: &JSValueRef,
+) -> Result<(), JsError> {
+    let proto_ref = objects::get_property(context, constructor_ref, "prototype")?;
+    if !proto_ref.is_object() {
+        return Ok(());
+    }
+    let res = q::JS_SetPrototype(
+        context,
+        *instance_ref.borrow_value(),
+        *proto_ref.borrow_value(),
+    );
+    if res < 0 {
+        return if let Some(err) = QuickJsContext::get_exception(context) {
+            Err(err)
+        } else {
+            Err(JsError::new_str("could not set instance proto"))
+        };
+    }
+    Ok(())
+}
+
 pub(crate) struct ProxyInstanceInfo {
     id: usize,
     class_name: String, // todo use unsafe to make these &str?
     context_id: String, // todo use unsafe to make these &str?
+    // the instance itself, this does not hold a reference, the info is removed when the instance is finalized
+    value: q::JSValue,
 }
 
 fn get_proxy_instance_info(val: &q::JSValue) -> &ProxyInstanceInfo {
@@ -682,6 +838,34 @@ fn get_proxy_instance_info(val: &q::JSValue) -> &ProxyInstanceInfo {
     info
 }
 
+/// get the class name and instance id of an instance of a Proxy class
+/// returns None if the value is not an instance of a Proxy class
+pub fn get_proxy_instance_id(obj_ref: &JSValueRef) -> Option<(String, usize)> {
+    let class_id = PROXY_INSTANCE_CLASS_ID.with(|rc| *rc.borrow());
+    let info_ptr: *mut c_void = unsafe { q::JS_GetOpaque(*obj_ref.borrow_value(), class_id) };
+    if info_ptr.is_null() {
+        None
+    } else {
+        let info: &ProxyInstanceInfo = unsafe { &*(info_ptr as *mut ProxyInstanceInfo) };
+        Some((info.class_name.clone(), info.id))
+    }
+}
+
+/// get an instance of a Proxy class by its instance_id
+/// returns None if there is no such instance or if it was finalized
+pub fn get_proxy_instance_ref(q_ctx: &QuickJsContext, instance_id: usize) -> Option<JSValueRef> {
+    let mappings = &*q_ctx.proxy_instance_id_mappings.borrow();
+    mappings.get(&instance_id).map(|info| {
+        JSValueRef::new(
+            q_ctx.context,
+            info.value,
+            true,
+            true,
+            "reflection::get_proxy_instance_ref",
+        )
+    })
+}
+
 #[allow(dead_code)]
 unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
     //todo
@@ -818,6 +1002,39 @@ unsafe extern "C" fn proxy_static_get_prop(
 }
 
 #[allow(dead_code)]
+/// methods for well-known symbols are registered with the description of the symbol as name (e.g.
+/// `Symbol.asyncIterator`), this checks that a property atom is a symbol exactly when the method name is one, and that
+/// it is the well-known symbol and not a string or another symbol with the same description
+unsafe fn atom_matches_method_name(
+    context: *mut q::JSContext,
+    atom: q::JSAtom,
+    name: &str,
+) -> bool {
+    let atom_ref = JSValueRef::new(
+        context,
+        q::JS_AtomToValue(context, atom),
+        false,
+        true,
+        "reflection::atom_matches_method_name atom",
+    );
+    let is_symbol = atom_ref.get_tag() == q::JS_TAG_SYMBOL as i64;
+    if !name.starts_with("Symbol.") {
+        return !is_symbol;
+    }
+    if !is_symbol {
+        return false;
+    }
+    match objects::get_namespace(context, name.split('.').collect(), false) {
+        Ok(symbol_ref) => {
+            let symbol_atom = q::JS_ValueToAtom(context, *symbol_ref.borrow_value());
+            let matches = symbol_atom == atom;
+            q::JS_FreeAtom(context, symbol_atom);
+            matches
+        }
+        Err(_) => false,
+    }
+}
+
 unsafe extern "C" fn proxy_instance_get_prop(
     context: *mut q::JSContext,
     obj: q::JSValue,
@@ -857,7 +1074,9 @@ unsafe extern "C" fn proxy_instance_get_prop(
 
         let registry = &*q_ctx.proxy_registry.borrow();
         let proxy = registry.get(&info.class_name).unwrap();
-        if proxy.methods.contains_key(&prop_name) {
+        if proxy.methods.contains_key(&prop_name)
+            && atom_matches_method_name(context, atom, prop_name.as_str())
+        {
             trace!("found method for {}", prop_name);
 
             let function_data_ref = from_string(context, prop_name.as_str())
@@ -904,8 +1123,21 @@ unsafe extern "C" fn proxy_instance_get_prop(
                 }
             }
         } else {
-            // return null if nothing was returned
-            quickjs_utils::new_null()
+            // look up the prop in the prototype chain, instances only have a prototype if one was set by the constructor
+            // JS_GetPrototype returns a new reference which is freed when proto_ref is dropped
+            let proto_ref = JSValueRef::new(
+                context,
+                q::JS_GetPrototype(context, obj),
+                false,
+                true,
+                "proxy_instance_get_prop proto",
+            );
+            if proto_ref.is_object() {
+                q::JS_GetPropertyInternal(context, *proto_ref.borrow_value(), atom, receiver, 0)
+            } else {
+                // return null if nothing was returned
+                quickjs_utils::new_null()
+            }
         }
     })
 
@@ -1061,27 +1293,65 @@ unsafe extern "C" fn proxy_static_set_prop(
 }
 
 unsafe extern "C" fn proxy_instance_set_prop(
-    _context: *mut q::JSContext,
-    _obj: q::JSValue,
-    _atom: q::JSAtom,
-    _value: q::JSValue,
-    _receiver: q::JSValue,
+    context: *mut q::JSContext,
+    obj: q::JSValue,
+    atom: q::JSAtom,
+    value: q::JSValue,
+    receiver: q::JSValue,
     _flags: ::std::os::raw::c_int,
 ) -> ::std::os::raw::c_int {
     trace!("proxy_instance_set_prop");
-    0
+
+    QuickJsRuntime::do_with(|q_js_rt| {
+        let q_ctx = q_js_rt.get_quickjs_context(context);
+
+        let prop_name = atoms::to_string2(context, &atom)
+            .ok()
+            .expect("could not get name");
+        trace!("proxy_instance_set_prop: {}", prop_name);
+
+        let info = get_proxy_instance_info(&obj);
+
+        let registry = &*q_ctx.proxy_registry.borrow();
+        let proxy = registry.get(&info.class_name).unwrap();
+        if let Some(getter_setter) = proxy.getters_setters.get(&prop_name) {
+            // call the setter, value is freed by quickjs after we return so we need to incr the refcount
+            let setter = &getter_setter.1;
+            let value_ref = JSValueRef::new(
+                context,
+                value,
+                true,
+                true,
+                "reflection::proxy_instance_set_prop value",
+            );
+            match setter(q_ctx, &info.id, value_ref) {
+                Ok(()) => 1,
+                Err(e) => {
+                    let err = format!("proxy_instance_set_prop failed: {}", e);
+                    q_ctx.report_ex(err.as_str());
+                    -1
+                }
+            }
+        } else {
+            // define the prop on the receiver like an ordinary object would, JS_DefinePropertyValue takes ownership of value
+            q::JS_DupValue(context, value);
+            q::JS_DefinePropertyValue(context, receiver, atom, value, q::JS_PROP_C_W_E as i32)
+        }
+    })
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::esruntime::tests::init_test_rt;
+    use crate::esvalue::EsValueConvertible;
     use crate::quickjs_utils::{functions, primitives};
     use crate::reflection::Proxy;
     use hirofa_utils::js_utils::JsError;
     use hirofa_utils::js_utils::Script;
     use log::trace;
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
     use std::collections::HashMap;
+    use std::rc::Rc;
     use std::time::Duration;
 
     thread_local! {
@@ -1267,4 +1537,129 @@ pub mod tests {
 
         log::info!("< test_proxy");
     }
+
+    #[test]
+    pub fn test_static_props() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let count = Rc::new(Cell::new(0));
+            let count_constr = count.clone();
+            Proxy::new()
+                .name("StaticPropsTest")
+                .constructor(move |_q_ctx, _id, _args| {
+                    count_constr.set(count_constr.get() + 1);
+                    Ok(())
+                })
+                .static_getter("count", move |_q_ctx| Ok(primitives::from_i32(count.get())))
+                .static_property("label", "static".to_string().to_es_value_facade())
+                .install(q_ctx, true)
+                .ok()
+                .expect("install failed");
+        });
+        let res = rt
+            .eval_sync(Script::new(
+                "test_static_props.es",
+                "new StaticPropsTest(); new StaticPropsTest(); StaticPropsTest.label + '_' + StaticPropsTest.count;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "static_2");
+    }
+
+    #[test]
+    pub fn test_extends() {
+        let rt = init_test_rt();
+        rt.eval_sync(Script::new(
+            "test_extends.es",
+            "this.Animal = class Animal {speak() {return 'speaking';}};",
+        ))
+        .ok()
+        .expect("script failed");
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            Proxy::new()
+                .name("Dog")
+                .extends("Animal")
+                .constructor(|_q_ctx, _id, _args| Ok(()))
+                .method("fetch", |q_ctx, _id, _args| {
+                    primitives::from_string_q(q_ctx, "fetching")
+                })
+                .install(q_ctx, true)
+                .ok()
+                .expect("install failed");
+        });
+        let res = rt
+            .eval_sync(Script::new(
+                "test_extends2.es",
+                "let dog = new Dog(); [dog.speak(), dog.fetch(), dog instanceof Animal, dog instanceof Dog].join('_');",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "speaking_fetching_true_true");
+    }
+
+    #[test]
+    pub fn test_instance_set_prop() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_context();
+            let values: Rc<RefCell<HashMap<usize, i32>>> = Rc::new(RefCell::new(HashMap::new()));
+            let values_get = values.clone();
+            Proxy::new()
+                .name("SetPropTest")
+                .constructor(|_q_ctx, _id, _args| Ok(()))
+                .getter_setter(
+                    "val",
+                    move |_q_ctx, id| {
+                        let values = &*values_get.borrow();
+                        Ok(primitives::from_i32(*values.get(id).unwrap_or(&0)))
+                    },
+                    move |_q_ctx, id, val| {
+                        let val = primitives::to_i32(&val)?;
+                        if val < 0 {
+                            return Err(JsError::new_str("val may not be negative"));
+                        }
+                        values.borrow_mut().insert(*id, val);
+                        Ok(())
+                    },
+                )
+                .install(q_ctx, true)
+                .ok()
+                .expect("install failed");
+        });
+
+        // the setter is called
+        let res = rt
+            .eval_sync(Script::new(
+                "test_instance_set_prop.es",
+                "let spt = new SetPropTest(); spt.val = 12; spt.val;",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 12);
+
+        // an error of the setter is thrown as an exception
+        let res = rt.eval_sync(Script::new("test_instance_set_prop2.es", "spt.val = -1;"));
+        assert!(res.is_err());
+        assert_eq!(
+            res.err().unwrap().get_message(),
+            "proxy_instance_set_prop failed: val may not be negative"
+        );
+        let res = rt
+            .eval_sync(Script::new("test_instance_set_prop3.es", "spt.val;"))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 12);
+
+        // other props are defined on the instance
+        let res = rt
+            .eval_sync(Script::new(
+                "test_instance_set_prop4.es",
+                "spt.label = 'abc'; spt.label + '_' + Object.keys(spt).includes('label');",
+            ))
+            .ok()
+            .expect("script failed");
+        assert_eq!(res.get_str(), "abc_true");
+    }
 }